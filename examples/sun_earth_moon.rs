@@ -0,0 +1,81 @@
+use dashu_float::DBig;
+use planetsim_rs::sin_cos::{dbig_to_f64, f64_to_dbig};
+use planetsim_rs::{
+    au_to_meters, Body, BodyDynamics, DecimalVector3d, OrbitingBodyDynamics, Simulation,
+    SimulationError, StaticBodyDynamics,
+};
+
+fn body(name: &str, mass: f64, radius: f64, dynamics: BodyDynamics, satellites: Vec<Body>) -> Body {
+    Body {
+        name: String::from(name),
+        rotation_axis: DecimalVector3d::from_f64(0.0, 1.0, 0.0),
+        rotation_period: DBig::from(24 * 3600),
+        rotation_phase: DBig::ZERO,
+        resonance: None,
+        nutation: None,
+        libration: None,
+        mass: f64_to_dbig(mass),
+        mass_variation: None,
+        radius: f64_to_dbig(radius),
+        visual: None,
+        kind: None,
+        dynamics,
+        update_interval: None,
+        satellites,
+    }
+}
+
+fn orbit(radius: DBig, period_days: i64) -> BodyDynamics {
+    BodyDynamics::Orbiting(OrbitingBodyDynamics {
+        orbit_radius: radius,
+        orbit_plane_normal: DecimalVector3d::from_f64(0.0, 1.0, 0.0),
+        orbit_period: DBig::from(period_days * 24 * 3600),
+        mean_anomaly_at_epoch: DBig::ZERO,
+        ellipse: None,
+        drift: None,
+    })
+}
+
+fn main() -> Result<(), SimulationError> {
+    let moon = body(
+        "moon",
+        7.342e22,
+        1_737_400.0,
+        orbit(DBig::from(384_400_000), 27),
+        vec![],
+    );
+    let earth = body(
+        "earth",
+        5.97219e24,
+        6_371_000.0,
+        orbit(au_to_meters(f64_to_dbig(1.0)), 365),
+        vec![moon],
+    );
+    let sun = body(
+        "sun",
+        1.98847e30,
+        696_340_000.0,
+        BodyDynamics::Static(StaticBodyDynamics {
+            position: DecimalVector3d::zero(),
+        }),
+        vec![earth],
+    );
+
+    let mut sim = Simulation::new();
+    sim.add_hierarchy(sun, None)?;
+    for day in (0..=30).step_by(10) {
+        sim.update(&DBig::from(day * 24 * 3600));
+        for name in ["earth", "moon"] {
+            let position = sim.world_position(sim.get_body(name)?);
+            println!(
+                "day {:>2} {:>5} x {:>+.6e} y {:>+.6e} z {:>+.6e}",
+                day,
+                name,
+                dbig_to_f64(&position.x),
+                dbig_to_f64(&position.y),
+                dbig_to_f64(&position.z)
+            );
+        }
+    }
+    Ok(())
+}
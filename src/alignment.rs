@@ -0,0 +1,227 @@
+use crate::body::BodyDynamics;
+use crate::error::SimulationError;
+use crate::simulation::Simulation;
+use crate::sin_cos::{atan2, PI};
+use dashu_float::ops::{Abs, SquareRoot};
+use dashu_float::DBig;
+
+const PRECISION: usize = 40;
+const REFINE_ITERATIONS: usize = 32;
+const SAMPLES_PER_ORBIT: usize = 16;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlignmentKind {
+    Conjunction, // both in the same direction
+    Opposition,  // on opposite sides of the observer
+}
+
+#[derive(Debug, Clone)]
+pub struct Alignment {
+    pub kind: AlignmentKind,
+    pub time: DBig,
+    pub separation: DBig, // in radians, between the directions to the bodies at that time
+}
+
+impl Simulation {
+    /// angle between the directions from the observer to the two bodies, in radians within [0, pi]
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if any of the bodies isn't in the simulation.
+    pub fn angular_separation(
+        &self,
+        first_name: &str,
+        second_name: &str,
+        observer_name: &str,
+    ) -> Result<DBig, SimulationError> {
+        let observer = self.world_position(self.get_body(observer_name)?);
+        let a = self.world_position(self.get_body(first_name)?) - &observer;
+        let b = self.world_position(self.get_body(second_name)?) - &observer;
+        Ok(atan2(a.cross(&b).length(), a.dot(&b), 32))
+    }
+
+    /// closest approaches of the separation to 0 and to pi between `t0` and `t1` that get within
+    /// `tolerance` radians of it, orbits out of a shared plane never line up exactly. Searched on
+    /// a copy of the simulation, sampled 16 times along the shortest orbit of the bodies and the
+    /// ones above them, and refined by golden section
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if any of the bodies isn't in the simulation, `InvalidState` if one of them is
+    /// sleeping and `InvalidDynamics` if a body is named twice.
+    pub fn find_alignments(
+        &self,
+        first_name: &str,
+        second_name: &str,
+        observer_name: &str,
+        t0: &DBig,
+        t1: &DBig,
+        tolerance: &DBig,
+    ) -> Result<Vec<Alignment>, SimulationError> {
+        let names = [first_name, second_name, observer_name];
+        let mut periods = vec![];
+        for (i, name) in names.iter().enumerate() {
+            if names[i + 1..].contains(name) {
+                return Err(SimulationError::InvalidDynamics(format!(
+                    "{name}: can't be aligned with itself"
+                )));
+            }
+            let body = self.get_awake_body(name)?;
+            let mut chain = self.resolve_hierarchy_up(body);
+            chain.push(body);
+            for body in chain {
+                if let BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics) =
+                    &body.body.dynamics
+                {
+                    periods.push(lift(&dynamics.orbit_period).abs());
+                }
+            }
+        }
+        // nothing moves around anything, the separation never changes
+        let Some(period) = periods.into_iter().min() else {
+            return Ok(vec![]);
+        };
+        let step = period / DBig::from(SAMPLES_PER_ORBIT);
+
+        let mut sim = self.copy_bodies();
+        let mut separation_at = |time: &DBig| {
+            sim.update(time);
+            sim.angular_separation(first_name, second_name, observer_name)
+        };
+
+        let pi = &*PI;
+        let ratio = (lift(&DBig::from(5)).sqrt() - DBig::ONE) / DBig::from(2);
+        let tolerance = lift(tolerance);
+        let end = lift(t1);
+        let mut time = lift(t0);
+        let mut times = vec![time.clone()];
+        while time < end {
+            time = (&time + &step).min(end.clone());
+            times.push(time.clone());
+        }
+        let samples = times
+            .iter()
+            .map(&mut separation_at)
+            .collect::<Result<Vec<DBig>, SimulationError>>()?;
+
+        let mut result: Vec<Alignment> = vec![];
+        for i in 1..samples.len().saturating_sub(1) {
+            let (before, current, after) = (&samples[i - 1], &samples[i], &samples[i + 1]);
+            let kind = if current <= before && current < after {
+                AlignmentKind::Conjunction
+            } else if current >= before && current > after {
+                AlignmentKind::Opposition
+            } else {
+                continue;
+            };
+            // golden section on the bracket, towards the smaller separation for conjunctions
+            // and the larger one for oppositions
+            let closer = |a: &DBig, b: &DBig| match kind {
+                AlignmentKind::Conjunction => a < b,
+                AlignmentKind::Opposition => a > b,
+            };
+            let (mut low, mut high) = (times[i - 1].clone(), times[i + 1].clone());
+            let mut left = &high - (&high - &low) * &ratio;
+            let mut right = &low + (&high - &low) * &ratio;
+            let mut left_value = separation_at(&left)?;
+            let mut right_value = separation_at(&right)?;
+            for _ in 0..REFINE_ITERATIONS {
+                if closer(&left_value, &right_value) {
+                    high = right;
+                    right = left;
+                    right_value = left_value;
+                    left = &high - (&high - &low) * &ratio;
+                    left_value = separation_at(&left)?;
+                } else {
+                    low = left;
+                    left = right;
+                    left_value = right_value;
+                    right = &low + (&high - &low) * &ratio;
+                    right_value = separation_at(&right)?;
+                }
+            }
+            let time = (low + high) / DBig::from(2);
+            let separation = separation_at(&time)?;
+            let offset = match kind {
+                AlignmentKind::Conjunction => separation.clone(),
+                AlignmentKind::Opposition => pi - &separation,
+            };
+            if offset <= tolerance {
+                result.push(Alignment {
+                    kind,
+                    time,
+                    separation,
+                });
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alignment::AlignmentKind;
+    use crate::error::SimulationError;
+    use crate::lunar_phase::LunarPhase;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+    use std::str::FromStr;
+
+    #[test]
+    fn find_alignments_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let (start, end) = (DBig::from(24 * 3600), DBig::from(31 * 24 * 3600));
+        let tolerance = DBig::from_str("0.2").unwrap();
+        // new and full moon seen from the earth, the tilted orbit keeps them a few degrees apart
+        let alignments = sim
+            .find_alignments("sun", "moon", "earth", &start, &end, &tolerance)
+            .unwrap();
+        let kinds: Vec<AlignmentKind> = alignments.iter().map(|a| a.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![AlignmentKind::Conjunction, AlignmentKind::Opposition]
+        );
+        let calendar = sim
+            .lunar_phase_calendar(
+                "moon",
+                "sun",
+                "earth",
+                &start,
+                &end,
+                &DBig::from(2 * 24 * 3600),
+            )
+            .unwrap();
+        let phase_time = |phase: LunarPhase| {
+            dbig_to_f64(&calendar.iter().find(|e| e.phase == phase).unwrap().time)
+        };
+        let new_moon = dbig_to_f64(&alignments[0].time);
+        let full_moon = dbig_to_f64(&alignments[1].time);
+        assert!((new_moon - phase_time(LunarPhase::New)).abs() < 12.0 * 3600.0);
+        assert!((full_moon - phase_time(LunarPhase::Full)).abs() < 12.0 * 3600.0);
+        assert!(dbig_to_f64(&alignments[0].separation) < 0.2);
+        assert!(dbig_to_f64(&alignments[0].separation) > 0.0);
+        // the closest approach, the separation is larger a few hours away
+        let mut probe = sim.copy_bodies();
+        probe.update(&(&alignments[0].time + DBig::from(6 * 3600)));
+        assert!(
+            probe.angular_separation("sun", "moon", "earth").unwrap() > alignments[0].separation
+        );
+
+        // a tight tolerance leaves out the near misses of the tilted orbit
+        let exact = DBig::from_str("0.001").unwrap();
+        assert!(sim
+            .find_alignments("sun", "moon", "earth", &start, &end, &exact)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            sim.find_alignments("moon", "moon", "earth", &start, &end, &tolerance)
+                .unwrap_err(),
+            SimulationError::InvalidDynamics(String::from("moon: can't be aligned with itself"))
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use crate::body::{BodyDynamics, OrbitingBodyDynamics};
+use crate::error::SimulationError;
+use crate::kepler::{true_anomaly_from_mean, DEFAULT_KEPLER_TOLERANCE};
+use crate::simulation::Simulation;
+use crate::sin_cos::PIMUL2;
+use dashu_float::ops::Abs;
+use dashu_float::DBig;
+
+impl Simulation {
+    fn orbiting_dynamics(
+        &self,
+        body_name: &str,
+    ) -> Result<Option<&OrbitingBodyDynamics>, SimulationError> {
+        Ok(match &self.get_body(body_name)?.body.dynamics {
+            BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics) => {
+                Some(dynamics)
+            }
+            BodyDynamics::Static(_) | BodyDynamics::Formation(_) => None,
+        })
+    }
+
+    /// in rad/s, always positive, None for static bodies
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn mean_motion(&self, body_name: &str) -> Result<Option<DBig>, SimulationError> {
+        let Some(dynamics) = self.orbiting_dynamics(body_name)? else {
+            return Ok(None);
+        };
+        Ok(Some(&*PIMUL2 / dynamics.orbit_period.clone().abs()))
+    }
+
+    /// in radians within [0, 2pi), increasing along the motion. Eccentric orbits count from the
+    /// periapsis, circular ones have none and count from where the orbit would be at time zero
+    /// without its mean anomaly at epoch, including the apsidal precession of a secular drift
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn mean_anomaly(&self, body_name: &str) -> Result<Option<DBig>, SimulationError> {
+        let Some(dynamics) = self.orbiting_dynamics(body_name)? else {
+            return Ok(None);
+        };
+        let mut angle = dynamics.orbit_angle(&self.time);
+        if let (Some(drift), None) = (&dynamics.drift, &dynamics.ellipse) {
+            angle += &drift.apsidal_precession * &self.time;
+        }
+        if dynamics.orbit_period < DBig::ZERO {
+            angle = -angle;
+        }
+        let turns = (&angle / &*PIMUL2).floor();
+        Ok(Some(angle - turns * &*PIMUL2))
+    }
+
+    /// angle from the periapsis within [0, 2pi), equal to the mean anomaly on circular orbits
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn true_anomaly(&self, body_name: &str) -> Result<Option<DBig>, SimulationError> {
+        let Some(mean_anomaly) = self.mean_anomaly(body_name)? else {
+            return Ok(None);
+        };
+        let Some(ellipse) = self
+            .orbiting_dynamics(body_name)?
+            .and_then(|d| d.ellipse.as_ref())
+        else {
+            return Ok(Some(mean_anomaly));
+        };
+        let angle = true_anomaly_from_mean(
+            &mean_anomaly,
+            &ellipse.eccentricity,
+            &DEFAULT_KEPLER_TOLERANCE,
+        );
+        let turns = (&angle / &*PIMUL2).floor();
+        Ok(Some(angle - turns * &*PIMUL2))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::BodyDynamics;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn anomaly_works() {
+        let mut sim = prepare_sim();
+        let period = 27.0 * 24.0 * 3600.0;
+        sim.update(&DBig::from(27 * 24 * 3600 / 4));
+        let motion = dbig_to_f64(&sim.mean_motion("moon").unwrap().unwrap());
+        assert!((motion - 2.0 * std::f64::consts::PI / period).abs() < 1e-15);
+        let anomaly = dbig_to_f64(&sim.mean_anomaly("moon").unwrap().unwrap());
+        assert!((anomaly - std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+        assert_eq!(
+            sim.true_anomaly("moon").unwrap(),
+            sim.mean_anomaly("moon").unwrap()
+        );
+        assert!(sim.mean_anomaly("sun").unwrap().is_none());
+
+        // retrograde orbits still count up along the motion
+        if let BodyDynamics::Orbiting(dynamics) = &mut sim.get_body_mut("moon").unwrap().dynamics {
+            dynamics.orbit_period = -dynamics.orbit_period.clone();
+        }
+        let anomaly = dbig_to_f64(&sim.mean_anomaly("moon").unwrap().unwrap());
+        assert!((anomaly - std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+        assert!(approx_eq(
+            &sim.mean_motion("moon").unwrap().unwrap(),
+            &f64_to_dbig(motion),
+            &f64_to_dbig(1e-15)
+        ));
+    }
+}
@@ -0,0 +1,243 @@
+use crate::error::SimulationError;
+use crate::propagation::CraftState;
+use crate::simulation::Simulation;
+use crate::spacecraft::Spacecraft;
+use dashu_float::DBig;
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtmosphereCrossing {
+    Entry,
+    Exit,
+}
+
+#[derive(Debug, Clone)]
+pub struct AtmosphereEvent {
+    pub spacecraft: String,
+    pub body: String,
+    pub crossing: AtmosphereCrossing,
+    pub time: DBig,        // refined between the steps
+    pub state: CraftState, // at the crossing, relative to the body
+}
+
+pub type AtmosphereCallback = dyn Fn(&AtmosphereEvent) + Send + Sync;
+
+// there is no density model, only the altitude where the atmosphere starts to matter, like the
+// 120 km entry interface of the Earth
+#[derive(Debug, Clone)]
+pub(crate) struct AtmosphereInterface {
+    body: String,
+    altitude: DBig, // in meters above the body radius
+}
+
+#[derive(Default)]
+pub(crate) struct AtmosphereTracking {
+    interfaces: Vec<AtmosphereInterface>,
+    callback: Option<Arc<AtmosphereCallback>>,
+}
+
+impl fmt::Debug for AtmosphereTracking {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AtmosphereTracking({:?})", self.interfaces)
+    }
+}
+
+impl Simulation {
+    /// replaces the interface the body had
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn set_atmosphere_interface(
+        &mut self,
+        body_name: &str,
+        altitude: DBig,
+    ) -> Result<(), SimulationError> {
+        self.get_body(body_name)?;
+        let interfaces = &mut self.atmosphere_tracking.interfaces;
+        interfaces.retain(|interface| interface.body != body_name);
+        interfaces.push(AtmosphereInterface {
+            body: body_name.to_string(),
+            altitude,
+        });
+        Ok(())
+    }
+
+    pub fn remove_atmosphere_interface(&mut self, body_name: &str) {
+        let interfaces = &mut self.atmosphere_tracking.interfaces;
+        interfaces.retain(|interface| interface.body != body_name);
+    }
+
+    pub fn atmosphere_interface(&self, body_name: &str) -> Option<&DBig> {
+        let interfaces = &self.atmosphere_tracking.interfaces;
+        interfaces
+            .iter()
+            .find(|interface| interface.body == body_name)
+            .map(|interface| &interface.altitude)
+    }
+
+    // called for every spacecraft crossing an interface while stepping, in time order
+    pub fn set_atmosphere_callback(&mut self, callback: Option<Arc<AtmosphereCallback>>) {
+        self.atmosphere_tracking.callback = callback;
+    }
+
+    // whether the craft is below the interface, None when the body is gone
+    fn inside_atmosphere(
+        &self,
+        craft: &Spacecraft,
+        interface: &AtmosphereInterface,
+    ) -> Option<bool> {
+        let body = self.get_body(&interface.body).ok()?;
+        let limit = &body.body.radius + &interface.altitude;
+        Some(craft.state.position.distance_to(&self.world_position(body)) < limit)
+    }
+
+    // for every spacecraft and interface, empty while nobody listens
+    pub(crate) fn atmosphere_regions(&self) -> Vec<Vec<Option<bool>>> {
+        if self.atmosphere_tracking.callback.is_none() {
+            return vec![];
+        }
+        let interfaces = &self.atmosphere_tracking.interfaces;
+        self.spacecraft
+            .iter()
+            .map(|craft| {
+                interfaces
+                    .iter()
+                    .map(|interface| self.inside_atmosphere(craft, interface))
+                    .collect()
+            })
+            .collect()
+    }
+
+    // runs after a spacecraft step from `start`, with the regions and spacecraft from before it
+    pub(crate) fn check_atmosphere_crossings(
+        &mut self,
+        start: &DBig,
+        spacecraft: &[Spacecraft],
+        step: &DBig,
+        regions: Vec<Vec<Option<bool>>>,
+    ) {
+        let Some(callback) = self.atmosphere_tracking.callback.clone() else {
+            return;
+        };
+        let interfaces = self.atmosphere_tracking.interfaces.clone();
+        let mut probe: Option<Simulation> = None;
+        let mut events: Vec<AtmosphereEvent> = vec![];
+        for (i, inside) in regions.into_iter().enumerate() {
+            let name = &self.spacecraft[i].name;
+            for (interface, was_inside) in interfaces.iter().zip(inside) {
+                let Some(was_inside) = was_inside else {
+                    continue;
+                };
+                let now = self.inside_atmosphere(&self.spacecraft[i], interface);
+                if now.is_none_or(|now| now == was_inside) {
+                    continue;
+                }
+
+                let probe = probe.get_or_insert_with(|| self.copy_bodies());
+                let moving = Some((spacecraft, step));
+                let crossed = |probe: &Simulation| {
+                    // the probe carries the same spacecraft and bodies
+                    let craft = probe.get_spacecraft(name).unwrap();
+                    probe.inside_atmosphere(craft, interface) != Some(was_inside)
+                };
+                let time = self.refine_crossing(probe, start, moving, crossed);
+                self.move_probe(probe, &time, moving);
+                let craft = probe.get_spacecraft(name).unwrap();
+                events.push(AtmosphereEvent {
+                    spacecraft: name.clone(),
+                    body: interface.body.clone(),
+                    crossing: if was_inside {
+                        AtmosphereCrossing::Exit
+                    } else {
+                        AtmosphereCrossing::Entry
+                    },
+                    time,
+                    state: probe
+                        .relative_craft_state(&craft.state, &interface.body)
+                        .unwrap(),
+                });
+            }
+        }
+
+        events.sort_by(|a, b| a.time.cmp(&b.time));
+        for event in events {
+            callback(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atmosphere::{AtmosphereCrossing, AtmosphereEvent};
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::propagation::{CraftState, ThrustProfile};
+    use crate::spacecraft::Spacecraft;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn atmosphere_crossings_work() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let earth = sim.get_body("earth").unwrap();
+        let (earth_position, earth_velocity) =
+            (sim.world_position(earth), sim.world_velocity(earth));
+        let interface = dbig_to_f64(&earth.body.radius) + 120_000.0;
+        // the periapsis is about 100 km below the interface
+        let speed = (6.674e-11 * 5.97219e24 / 7e6f64).sqrt();
+        let periapsis = interface - 100_000.0;
+        let speed = speed * (2.0 * periapsis / (7e6 + periapsis)).sqrt();
+        sim.add_spacecraft(Spacecraft {
+            name: String::from("capsule"),
+            state: CraftState {
+                time: DBig::ZERO,
+                position: &earth_position + DecimalVector3d::from_f64(7e6, 0.0, 0.0),
+                velocity: &earth_velocity + DecimalVector3d::from_f64(0.0, 0.0, -speed),
+                propulsion: None,
+            },
+            thrust: ThrustProfile::Coast,
+            primary: Some(String::from("earth")),
+        })
+        .unwrap();
+        sim.set_atmosphere_interface("earth", DBig::from(120_000))
+            .unwrap();
+        assert_eq!(
+            sim.atmosphere_interface("earth"),
+            Some(&DBig::from(120_000))
+        );
+        assert_eq!(
+            sim.set_atmosphere_interface("pluto", DBig::from(1000)),
+            Err(SimulationError::UnknownBody(String::from("pluto")))
+        );
+        let events: Arc<Mutex<Vec<AtmosphereEvent>>> = Arc::new(Mutex::new(vec![]));
+        let record = {
+            let events = events.clone();
+            Arc::new(move |event: &AtmosphereEvent| events.lock().unwrap().push(event.clone()))
+        };
+        sim.set_atmosphere_callback(Some(record));
+        sim.step_spacecraft(&DBig::from(4000), &DBig::from(100))
+            .unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        let (entry, exit) = (&events[0], &events[1]);
+        assert_eq!(entry.crossing, AtmosphereCrossing::Entry);
+        assert_eq!(exit.crossing, AtmosphereCrossing::Exit);
+        assert_eq!(entry.body, "earth");
+        assert!(entry.time < exit.time);
+        for event in [entry, exit] {
+            let radius = dbig_to_f64(&event.state.position.length());
+            assert!((radius - interface).abs() < 10.0, "{}", radius - interface);
+        }
+        // going down on entry and up on exit
+        assert!(entry.state.position.dot(&entry.state.velocity) < DBig::ZERO);
+        assert!(exit.state.position.dot(&exit.state.velocity) > DBig::ZERO);
+
+        sim.remove_atmosphere_interface("earth");
+        assert_eq!(sim.atmosphere_interface("earth"), None);
+    }
+}
@@ -1,30 +1,329 @@
+use crate::decimal_matrix_3d::DecimalMatrix3d;
 use crate::decimal_vector_3d::DecimalVector3d;
+use crate::kepler::{eccentric_anomaly, DEFAULT_KEPLER_TOLERANCE};
+use crate::sin_cos::{cos, sin, PIMUL2};
+use dashu_float::ops::Abs;
+use dashu_float::ops::SquareRoot;
 use dashu_float::DBig;
+use std::fmt;
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct StaticBodyDynamics {
     pub position: DecimalVector3d,
 }
 
+// linear rates from time zero, evaluated analytically along with the orbit
+#[derive(Debug, Clone)]
+pub struct SecularDrift {
+    pub nodal_regression: DBig, // in rad/s, the whole orbit turns about the parent rotation axis
+    pub apsidal_precession: DBig, // in rad/s, turns the periapsis, or the phase of circular orbits
+    pub radius_rate: DBig,      // in m/s, semi-major axis decay when negative
+}
+
+// shape of an eccentric orbit, the mean anomaly counts from the periapsis
+#[derive(Debug, Clone)]
+pub struct OrbitEllipse {
+    pub eccentricity: DBig,          // within [0, 1)
+    pub argument_of_periapsis: DBig, // in radians from the ascending node along the motion
+}
+
+impl OrbitEllipse {
+    // offset from the focus, the orbit radius is the semi-major axis and the periapsis is turned
+    // from the ascending node on the ecliptic (north x normal, world +X for ecliptic orbits)
+    pub(crate) fn position(
+        &self,
+        normal: &DecimalVector3d,
+        semi_major_axis: &DBig,
+        mean_anomaly: &DBig,
+        periapsis_advance: &DBig,
+    ) -> DecimalVector3d {
+        let lift = |v: &DBig| v.clone().with_precision(40).value();
+        let normal =
+            DecimalVector3d::new(lift(&normal.x), lift(&normal.y), lift(&normal.z)).normalized();
+        let mut node = DecimalVector3d::new(normal.z.clone(), DBig::ZERO, -&normal.x);
+        if node.length_squared() == DBig::ZERO {
+            node.x = lift(&DBig::ONE);
+        }
+        let periapsis_angle = &self.argument_of_periapsis + periapsis_advance;
+        let periapsis =
+            DecimalMatrix3d::axis_angle(&normal, periapsis_angle).apply(&node.normalized());
+        let quarter = normal.cross(&periapsis);
+
+        let eccentricity = lift(&self.eccentricity);
+        let anomaly = eccentric_anomaly(mean_anomaly, &eccentricity, &DEFAULT_KEPLER_TOLERANCE);
+        let semi_major_axis = lift(semi_major_axis);
+        let semi_minor_axis = &semi_major_axis * (DBig::ONE - &eccentricity * &eccentricity).sqrt();
+        periapsis * (&semi_major_axis * (cos(anomaly.clone(), 40) - &eccentricity))
+            + quarter * (semi_minor_axis * sin(anomaly, 40))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct OrbitingBodyDynamics {
-    pub orbit_radius: DBig,
+    pub orbit_radius: DBig, // the semi-major axis of eccentric orbits
     pub orbit_plane_normal: DecimalVector3d,
-    pub orbit_period: DBig,
+    pub orbit_period: DBig, // in seconds, negative runs clockwise about the normal
+    pub mean_anomaly_at_epoch: DBig, // in radians along the motion, where the orbit is at time zero
+    pub ellipse: Option<OrbitEllipse>, // None for circular orbits starting on world +X
+    pub drift: Option<SecularDrift>,
 }
 
+impl OrbitingBodyDynamics {
+    // from classical elements relative to the ecliptic (the XZ plane with +Y north), node
+    // longitudes counted from +X towards -Z, angles in radians
+    pub fn from_elements(
+        semi_major_axis: DBig,
+        eccentricity: DBig,
+        inclination: &DBig,
+        ascending_node: &DBig,
+        argument_of_periapsis: DBig,
+        orbit_period: DBig,
+    ) -> Self {
+        let sin_inclination = sin(inclination.clone(), 40);
+        OrbitingBodyDynamics {
+            orbit_radius: semi_major_axis,
+            orbit_plane_normal: DecimalVector3d::new(
+                &sin_inclination * sin(ascending_node.clone(), 40),
+                cos(inclination.clone(), 40),
+                &sin_inclination * cos(ascending_node.clone(), 40),
+            ),
+            orbit_period,
+            mean_anomaly_at_epoch: DBig::ZERO,
+            ellipse: Some(OrbitEllipse {
+                eccentricity,
+                argument_of_periapsis,
+            }),
+            drift: None,
+        }
+    }
+
+    // mean anomaly at `time` as an angle about the plane normal, decreasing for negative periods,
+    // without the apsidal precession
+    pub(crate) fn orbit_angle(&self, time: &DBig) -> DBig {
+        let angle = &*PIMUL2 * (time / &self.orbit_period).fract();
+        if self.orbit_period < DBig::ZERO {
+            angle - &self.mean_anomaly_at_epoch
+        } else {
+            angle + &self.mean_anomaly_at_epoch
+        }
+    }
+
+    // direction of the orbital angular momentum, the normal flipped for negative periods
+    pub fn angular_momentum_direction(&self) -> DecimalVector3d {
+        if self.orbit_period < DBig::ZERO {
+            -&self.orbit_plane_normal
+        } else {
+            self.orbit_plane_normal.clone()
+        }
+    }
+
+    // rotation axis tilted by `obliquity` from the orbital angular momentum, obliquities above 90
+    // degrees spin backwards like Venus, see `tilted_axis`
+    pub fn tilted_axis(&self, obliquity: &DBig, azimuth: &DBig) -> DecimalVector3d {
+        tilted_axis(&self.angular_momentum_direction(), obliquity, azimuth)
+    }
+}
+
+// axis tilted by `obliquity` from `pole`, leaning towards `azimuth` measured around the pole from
+// world +X (+Z when the pole leans more towards X than Z) in the direction of a positive rotation,
+// both in radians
+pub fn tilted_axis(pole: &DecimalVector3d, obliquity: &DBig, azimuth: &DBig) -> DecimalVector3d {
+    let lift = |v: &DBig| v.clone().with_precision(32).value();
+    let pole = DecimalVector3d::new(lift(&pole.x), lift(&pole.y), lift(&pole.z)).normalized();
+    let reference = if pole.x.clone().abs() <= pole.z.clone().abs() {
+        DecimalVector3d::new(DBig::ONE, DBig::ZERO, DBig::ZERO)
+    } else {
+        DecimalVector3d::new(DBig::ZERO, DBig::ZERO, DBig::ONE)
+    };
+    let reference = (&reference - &pole * pole.dot(&reference)).normalized();
+    let quarter = pole.cross(&reference);
+    let lean = reference * cos(azimuth.clone(), 32) + quarter * sin(azimuth.clone(), 32);
+    &pole * cos(obliquity.clone(), 32) + lean * sin(obliquity.clone(), 32)
+}
+
+// axes a formation offset is given in, both turn along with the leader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormationFrame {
+    Orbital,   // of the leader's orbit, x radial, y along the motion and z along the orbit normal
+    BodyFixed, // of the leader's rotation, see Simulation::world_to_body_fixed
+}
+
+// holds a fixed offset from the parent, the leader, for trailing points and station keeping
+// fleets; an orbital frame needs an orbiting leader
+#[derive(Debug, Clone)]
+pub struct FormationBodyDynamics {
+    pub offset: DecimalVector3d, // in meters, in the frame axes
+    pub frame: FormationFrame,
+}
+
+// bodies are few and shared behind an Arc, boxing the orbit would only add indirection
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone)]
 pub enum BodyDynamics {
     Static(StaticBodyDynamics),
     Orbiting(OrbitingBodyDynamics),
+    Formation(FormationBodyDynamics),
+    // the orbit of the separation from the parent, like Charon around Pluto; the parent is held
+    // back by the mass share of the pair so both go around their center of mass, and the parent's
+    // own dynamics place that center
+    Barycentric(OrbitingBodyDynamics),
+}
+
+#[derive(Clone)]
+pub enum MassVariation {
+    Linear(DBig), // in kg/s from time zero, never below zero
+    Function(Arc<dyn Fn(&DBig) -> DBig + Send + Sync>), // mass at a time, can't be snapshotted
+}
+
+impl fmt::Debug for MassVariation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MassVariation::Linear(rate) => write!(f, "Linear({rate})"),
+            MassVariation::Function(_) => write!(f, "Function"),
+        }
+    }
+}
+
+// periodic wobble of the rotation axis, the leading term of a nutation series. The longitude term
+// turns the axis about the orbit pole (world +Y for static bodies), the obliquity term about the
+// line where the equator crosses the orbit plane
+#[derive(Debug, Clone)]
+pub struct Nutation {
+    pub longitude_amplitude: DBig, // in radians, scaled by sin(2 pi t / period)
+    pub obliquity_amplitude: DBig, // in radians, scaled by cos(2 pi t / period)
+    pub period: DBig,              // in seconds
+}
+
+impl Nutation {
+    // turns the mean axis into the nutated one at `time`, `pole` is the orbit pole
+    pub fn rotation(
+        &self,
+        time: &DBig,
+        axis: &DecimalVector3d,
+        pole: &DecimalVector3d,
+    ) -> DecimalMatrix3d {
+        let lift = |v: &DecimalVector3d| {
+            let lift = |v: &DBig| v.clone().with_precision(32).value();
+            DecimalVector3d::new(lift(&v.x), lift(&v.y), lift(&v.z))
+        };
+        let pole = lift(pole).normalized();
+        let phase = &*PIMUL2 * (time / &self.period).fract();
+        let longitude =
+            DecimalMatrix3d::axis_angle(&pole, &self.longitude_amplitude * sin(phase.clone(), 32));
+        let node = pole.cross(&lift(axis).normalized());
+        if node.length_squared() == DBig::ZERO {
+            return longitude;
+        }
+        let obliquity = DecimalMatrix3d::axis_angle(
+            &node.normalized(),
+            &self.obliquity_amplitude * cos(phase, 32),
+        );
+        &longitude * &obliquity
+    }
+}
+
+// optical libration in longitude of a tidally locked body on an eccentric orbit, for circular
+// orbits imitating one the spin is modulated by the equation of center, which moves the sub-parent
+// point the same way; latitude libration comes from tilting the axis off the orbit normal
+#[derive(Debug, Clone)]
+pub struct Libration {
+    pub eccentricity: DBig, // of the orbit being imitated, with its periapsis at time zero
+}
+
+impl Libration {
+    // true minus mean anomaly for the signed orbit angle, to second order in the eccentricity
+    pub fn equation_of_center(&self, mean_anomaly: &DBig) -> DBig {
+        let e = &self.eccentricity;
+        DBig::from(2) * e * sin(mean_anomaly.clone(), 32)
+            + DBig::from(5) / DBig::from(4) * e * e * sin(mean_anomaly * DBig::from(2), 32)
+    }
+}
+
+// `rotations` turns every `orbits` orbits, 1:1 is tidal locking and 3:2 is Mercury
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpinOrbitResonance {
+    pub rotations: u32,
+    pub orbits: u32,
+}
+
+// for renderers driven by exported state alone, the simulation itself never reads them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VisualHints {
+    pub color: Option<[u8; 3]>,  // mean color as seen from afar, in sRGB
+    pub texture: Option<String>, // texture or atlas key, meaningful to the renderer only
+    pub emissive: bool,          // shines by itself, like a star
+}
+
+// what the body is, for rendering and gameplay; the simulation itself never reads it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyKind {
+    Star,
+    Planet,
+    DwarfPlanet,
+    Moon,
+    Asteroid,
+    Comet,
+    ArtificialSatellite,
 }
 
 #[derive(Debug, Clone)]
 pub struct Body {
     pub name: String,
     pub rotation_axis: DecimalVector3d,
-    pub rotation_period: DBig, // in seconds
-    pub mass: DBig,            // in kg
+    pub rotation_period: DBig, // in seconds, negative spins clockwise about the axis
+    pub rotation_phase: DBig,  // in radians, turned about the axis at time zero
+    pub resonance: Option<SpinOrbitResonance>, // overrides the rotation period, orbiting bodies only
+    pub nutation: Option<Nutation>,
+    pub libration: Option<Libration>, // only for orbiting bodies
+    pub mass: DBig,                   // in kg, at time zero if it varies
+    pub mass_variation: Option<MassVariation>,
+    pub radius: DBig, // in meters
+    pub visual: Option<VisualHints>,
+    pub kind: Option<BodyKind>, // None when unclassified
     pub dynamics: BodyDynamics,
-    pub satellites: Vec<Body>,
+    pub update_interval: Option<DBig>, // in seconds, None means updated every time
+    pub satellites: Vec<Body>,         // only read by Simulation::add_hierarchy
+}
+
+impl Body {
+    // pole of the orbit, the reference for obliquity and nutation
+    pub fn orbit_pole(&self) -> DecimalVector3d {
+        match &self.dynamics {
+            BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics) => {
+                dynamics.angular_momentum_direction()
+            }
+            BodyDynamics::Static(_) | BodyDynamics::Formation(_) => {
+                DecimalVector3d::new(DBig::ZERO, DBig::ONE, DBig::ZERO)
+            }
+        }
+    }
+
+    // in rad/s, along the axis or against it for negative periods
+    pub fn angular_velocity(&self) -> DecimalVector3d {
+        &self.rotation_axis * (&*PIMUL2 / self.spin_period())
+    }
+
+    // the rotation period, or the one a resonance keeps with the current orbit period, in the
+    // direction of the orbit
+    pub fn spin_period(&self) -> DBig {
+        match (&self.resonance, &self.dynamics) {
+            (
+                Some(resonance),
+                BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics),
+            ) => {
+                &dynamics.orbit_period * DBig::from(resonance.orbits)
+                    / DBig::from(resonance.rotations)
+            }
+            _ => self.rotation_period.clone(),
+        }
+    }
+
+    pub fn mass_at(&self, time: &DBig) -> DBig {
+        match &self.mass_variation {
+            None => self.mass.clone(),
+            Some(MassVariation::Linear(rate)) => (&self.mass + rate * time).max(DBig::ZERO),
+            Some(MassVariation::Function(function)) => function(time),
+        }
+    }
 }
@@ -0,0 +1,158 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::simulation::Simulation;
+use dashu_float::DBig;
+
+// a named epoch, like "launch" or "flyby-1"
+#[derive(Debug, Clone)]
+pub struct Bookmark {
+    pub name: String,
+    pub time: DBig,
+}
+
+impl Simulation {
+    // replaces the bookmark with the same name, the bookmarks are kept in time order
+    pub fn add_bookmark(&mut self, name: &str, time: DBig) {
+        self.bookmarks.retain(|bookmark| bookmark.name != name);
+        let index = self
+            .bookmarks
+            .partition_point(|bookmark| bookmark.time <= time);
+        self.bookmarks.insert(
+            index,
+            Bookmark {
+                name: name.to_string(),
+                time,
+            },
+        );
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBookmark` if there is no bookmark of that name.
+    pub fn remove_bookmark(&mut self, name: &str) -> Result<(), SimulationError> {
+        let index = self
+            .bookmarks
+            .iter()
+            .position(|bookmark| bookmark.name == name)
+            .ok_or_else(|| SimulationError::UnknownBookmark(name.to_string()))?;
+        self.bookmarks.remove(index);
+        Ok(())
+    }
+
+    pub fn bookmarks(&self) -> &[Bookmark] {
+        &self.bookmarks
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBookmark` if there is no bookmark of that name.
+    pub fn bookmark_time(&self, name: &str) -> Result<&DBig, SimulationError> {
+        self.bookmarks
+            .iter()
+            .find(|bookmark| bookmark.name == name)
+            .map(|bookmark| &bookmark.time)
+            .ok_or_else(|| SimulationError::UnknownBookmark(name.to_string()))
+    }
+
+    /// a copy of the bodies updated to the bookmark, this simulation doesn't move; spacecraft
+    /// aren't copied, see `copy_bodies`
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBookmark` if there is no bookmark of that name.
+    pub fn at_bookmark(&self, name: &str) -> Result<Simulation, SimulationError> {
+        let time = self.bookmark_time(name)?.clone();
+        let mut sim = self.copy_bodies();
+        sim.update(&time);
+        Ok(sim)
+    }
+
+    /// world position and velocity of the body at the bookmark
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation and `UnknownBookmark` if the bookmark
+    /// isn't.
+    pub fn body_state_at_bookmark(
+        &self,
+        body_name: &str,
+        bookmark: &str,
+    ) -> Result<(DecimalVector3d, DecimalVector3d), SimulationError> {
+        self.get_body(body_name)?;
+        let sim = self.at_bookmark(bookmark)?;
+        let body = sim.get_body(body_name)?;
+        Ok((sim.world_position(body), sim.world_velocity(body)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::MassVariation;
+    use crate::error::SimulationError;
+    use crate::simulation::Simulation;
+    use crate::sin_cos::f64_to_dbig;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+    use std::sync::Arc;
+
+    #[test]
+    fn bookmarks_work() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        sim.add_bookmark("flyby-1", f64_to_dbig(86400.0));
+        sim.add_bookmark("launch", f64_to_dbig(3600.0));
+        sim.add_bookmark("flyby-2", f64_to_dbig(172_800.0));
+        let names: Vec<&str> = sim.bookmarks().iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, vec!["launch", "flyby-1", "flyby-2"]);
+
+        // replacing keeps one bookmark per name
+        sim.add_bookmark("launch", f64_to_dbig(7200.0));
+        assert_eq!(sim.bookmarks().len(), 3);
+        assert_eq!(sim.bookmark_time("launch").unwrap(), &f64_to_dbig(7200.0));
+
+        let (position, velocity) = sim.body_state_at_bookmark("moon", "flyby-1").unwrap();
+        assert_eq!(sim.time(), &DBig::ZERO);
+        let mut expected = prepare_sim();
+        expected.update(&f64_to_dbig(86400.0));
+        let moon = expected.get_body("moon").unwrap();
+        assert!(dbig_to_f64(&position.distance_to(&expected.world_position(moon))) < 1e-6);
+        assert!(dbig_to_f64(&velocity.distance_to(&expected.world_velocity(moon))) < 1e-9);
+
+        let mut buf = vec![];
+        sim.write_snapshot(&mut buf).unwrap();
+        let resumed = Simulation::read_snapshot(&mut buf.as_slice()).unwrap();
+        assert_eq!(resumed.bookmarks().len(), 3);
+        assert_eq!(
+            resumed.bookmark_time("flyby-2").unwrap(),
+            &f64_to_dbig(172_800.0)
+        );
+
+        sim.remove_bookmark("flyby-2").unwrap();
+        assert!(matches!(
+            sim.remove_bookmark("flyby-2"),
+            Err(SimulationError::UnknownBookmark(_))
+        ));
+        assert!(matches!(
+            sim.body_state_at_bookmark("pluto", "launch"),
+            Err(SimulationError::UnknownBody(_))
+        ));
+    }
+
+    #[test]
+    fn bookmarks_work_with_mass_functions() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        sim.get_body_mut("moon").unwrap().mass_variation =
+            Some(MassVariation::Function(Arc::new(|time: &DBig| {
+                DBig::from(1000) + time
+            })));
+        sim.add_bookmark("launch", f64_to_dbig(3600.0));
+        // the copy carries the function along, unlike a snapshot
+        let copy = sim.at_bookmark("launch").unwrap();
+        assert_eq!(
+            copy.get_body("moon").unwrap().body.mass_at(copy.time()),
+            DBig::from(4600)
+        );
+        assert!(sim.body_state_at_bookmark("moon", "launch").is_ok());
+    }
+}
@@ -0,0 +1,179 @@
+use crate::decimal_matrix_3d::DecimalMatrix3d;
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::simulation::Simulation;
+use crate::sin_cos::{dbig_to_f64, sin};
+use dashu_float::DBig;
+
+const PRECISION: usize = 40;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+fn lift_vector(v: &DecimalVector3d) -> DecimalVector3d {
+    DecimalVector3d::new(lift(&v.x), lift(&v.y), lift(&v.z))
+}
+
+// looks along its local -Z with +Y up like an Observer, the orientation rows are the camera axes
+// in world coordinates so applying it turns view directions into world ones
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub position: DecimalVector3d,
+    pub orientation: DecimalMatrix3d,
+}
+
+impl Camera {
+    // None when the target is at the position or straight along `up`
+    pub fn look_at(
+        position: &DecimalVector3d,
+        target: &DecimalVector3d,
+        up: &DecimalVector3d,
+    ) -> Option<Camera> {
+        let position = lift_vector(position);
+        let forward = lift_vector(target) - &position;
+        let right = forward.cross(&lift_vector(up));
+        if forward.length_squared() == DBig::ZERO || right.length_squared() == DBig::ZERO {
+            return None;
+        }
+        let forward = forward.normalized();
+        let right = right.normalized();
+        let up = right.cross(&forward);
+        Some(Camera {
+            position,
+            orientation: DecimalMatrix3d {
+                data: [
+                    [right.x, right.y, right.z],
+                    [up.x, up.y, up.z],
+                    [-forward.x, -forward.y, -forward.z],
+                ],
+            },
+        })
+    }
+
+    // the point relative to the camera in its axes, subtracted before the conversion so huge
+    // world coordinates keep their detail near the camera
+    pub fn view_position(&self, point: &DecimalVector3d) -> [f64; 3] {
+        let offset = point - &self.position;
+        self.orientation
+            .data
+            .clone()
+            .map(|[x, y, z]| dbig_to_f64(&offset.dot(&DecimalVector3d::new(x, y, z))))
+    }
+
+    // x, y, z, w turning the camera axes into the world ones, like a Three JS camera quaternion
+    pub fn export_orientation(&self) -> [f64; 4] {
+        self.orientation.as_quat().map(|v| dbig_to_f64(&v))
+    }
+}
+
+// distance from the center where a sphere of the radius spans the angular size, in radians
+// across; the whole sphere fits at any angle below half a turn
+pub fn framing_distance(radius: &DBig, angular_size: &DBig) -> DBig {
+    lift(radius) / sin(lift(angular_size) / DBig::from(2), 40)
+}
+
+impl Simulation {
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation, `InvalidDynamics` if the camera is at the
+    /// body center or looks along `up`.
+    pub fn camera_looking_at(
+        &self,
+        position: &DecimalVector3d,
+        body_name: &str,
+        up: &DecimalVector3d,
+    ) -> Result<Camera, SimulationError> {
+        let target = self.world_position(self.get_body(body_name)?);
+        Camera::look_at(position, &target, up).ok_or_else(|| {
+            SimulationError::InvalidDynamics(format!(
+                "{body_name}: the camera can't look along up or from the center"
+            ))
+        })
+    }
+
+    /// a camera coming from `direction` towards the body, far enough for the body to span the
+    /// angular size
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation, `InvalidDynamics` if `direction` is zero.
+    pub fn camera_framing(
+        &self,
+        body_name: &str,
+        direction: &DecimalVector3d,
+        angular_size: &DBig,
+        up: &DecimalVector3d,
+    ) -> Result<Camera, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let distance = framing_distance(&body.body.radius, angular_size);
+        let direction = lift_vector(direction);
+        if direction.length_squared() == DBig::ZERO {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{body_name}: the camera needs a direction to come from"
+            )));
+        }
+        let position = self.world_position(body) - direction.normalized() * distance;
+        self.camera_looking_at(&position, body_name, up)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::camera::{framing_distance, Camera};
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::sin_cos::f64_to_dbig;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn camera_works() {
+        let identity = Camera::look_at(
+            &DecimalVector3d::zero(),
+            &DecimalVector3d::from_f64(0.0, 0.0, -5.0),
+            &DecimalVector3d::from_f64(0.0, 1.0, 0.0),
+        )
+        .unwrap();
+        let [x, y, z, w] = identity.export_orientation();
+        assert!(x.abs() < 1e-12 && y.abs() < 1e-12 && z.abs() < 1e-12 && (w - 1.0).abs() < 1e-12);
+        // turned to look along +X, a quarter turn clockwise about +Y
+        let east = Camera::look_at(
+            &DecimalVector3d::zero(),
+            &DecimalVector3d::from_f64(5.0, 0.0, 0.0),
+            &DecimalVector3d::from_f64(0.0, 1.0, 0.0),
+        )
+        .unwrap();
+        let [x, y, z, w] = east.export_orientation();
+        let half = std::f64::consts::FRAC_1_SQRT_2;
+        assert!(x.abs() < 1e-12 && (y + half).abs() < 1e-12 && z.abs() < 1e-12);
+        assert!((w - half).abs() < 1e-12);
+
+        let distance = dbig_to_f64(&framing_distance(&DBig::from(6_371_000), &f64_to_dbig(0.1)));
+        assert!((distance - 6_371_000.0 / 0.05f64.sin()).abs() < 1e-6);
+
+        // the earth sits near 6.5e19 m, where f64 world coordinates are 8 km apart
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let up = DecimalVector3d::from_f64(0.0, 1.0, 0.0);
+        let camera = sim
+            .camera_framing(
+                "earth",
+                &DecimalVector3d::from_f64(1.0, 0.0, 0.0),
+                &f64_to_dbig(0.1),
+                &up,
+            )
+            .unwrap();
+        let earth = sim.world_position(sim.get_body("earth").unwrap());
+        let [x, y, z] = camera.view_position(&earth);
+        assert!(x.abs() < 1e-6 && y.abs() < 1e-6 && (z + distance).abs() < 1e-6);
+        let [_, y, _] =
+            camera.view_position(&(&earth + DecimalVector3d::from_f64(0.0, 6_371_000.0, 0.0)));
+        assert!((y - 6_371_000.0).abs() < 1e-6);
+
+        assert!(matches!(
+            sim.camera_looking_at(&(&earth + &up), "earth", &up),
+            Err(SimulationError::InvalidDynamics(_))
+        ));
+    }
+}
@@ -0,0 +1,267 @@
+use crate::body::BodyDynamics;
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::simulation::{SimulatedBody, Simulation, G_CONSTANT};
+use crate::vis_viva::vis_viva_speed;
+use dashu_float::ops::{Abs, SquareRoot};
+use dashu_float::DBig;
+use std::str::FromStr;
+
+const PRECISION: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct CaptureAnalysis {
+    pub eccentricity: DBig,
+    pub periapsis_radius: DBig, // in meters, from the target center
+    pub periapsis_speed: DBig,  // in m/s, relative to the target
+    pub capture_burn: DBig,     // in m/s, at the periapsis, into the final orbit
+    pub sphere_of_influence: Option<DBig>, // in meters, None for roots
+    pub ballistic_capture: bool, // already bound and never leaving the sphere of influence
+    pub impact: bool,           // periapsis is below the surface
+}
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+impl Simulation {
+    /// Laplace sphere of influence, a * (m / M)^(2/5)
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation, `InvalidDynamics` if its parent has no
+    /// mass.
+    pub fn sphere_of_influence(&self, body_name: &str) -> Result<Option<DBig>, SimulationError> {
+        self.body_sphere_of_influence(self.get_body(body_name)?)
+    }
+
+    pub(crate) fn body_sphere_of_influence(
+        &self,
+        body: &SimulatedBody,
+    ) -> Result<Option<DBig>, SimulationError> {
+        let Some(parent) = body.parent() else {
+            return Ok(None);
+        };
+        let parent = self
+            .get_body_by_id(parent)
+            .ok_or(SimulationError::MissingParent(parent))?;
+        let mass = lift(&body.body.mass_at(&self.time));
+        let parent_mass = lift(&parent.body.mass_at(&self.time));
+        if parent_mass <= DBig::ZERO {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{}: the parent has no mass",
+                body.body.name
+            )));
+        }
+        // a massless body doesn't pull anything away from its parent
+        if mass <= DBig::ZERO {
+            return Ok(Some(DBig::ZERO));
+        }
+        let distance = match &body.body.dynamics {
+            BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics) => {
+                lift(&dynamics.orbit_radius)
+            }
+            BodyDynamics::Static(_) | BodyDynamics::Formation(_) => self
+                .world_position(body)
+                .distance_to(&self.world_position(parent)),
+        };
+        let ratio = mass / parent_mass;
+        let exponent = DBig::from_str("0.4").unwrap();
+        Ok(Some(distance * (ratio.ln() * exponent).exp()))
+    }
+
+    /// two-body analysis of a craft at world `position` with world `velocity` (see `world_velocity`)
+    /// against the target at the current time, the final orbit keeps the approach periapsis and
+    /// has the given apoapsis radius. Radial approaches have no periapsis to analyse and the
+    /// apoapsis can't be below the periapsis, both are errors
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the target isn't in the simulation. `InvalidDynamics` if the target has no
+    /// mass, the craft is at its center or comes in radially, or the final apoapsis is below the
+    /// periapsis.
+    pub fn capture_analysis(
+        &self,
+        target_name: &str,
+        position: &DecimalVector3d,
+        velocity: &DecimalVector3d,
+        final_apoapsis: &DBig,
+    ) -> Result<CaptureAnalysis, SimulationError> {
+        let target = self.get_body(target_name)?;
+        let mu = &*G_CONSTANT * lift(&target.body.mass_at(&self.time));
+        let relative_position = position - self.world_position(target);
+        let relative_velocity = velocity - self.world_velocity(target);
+        let radius = lift(&relative_position.length());
+        if mu <= DBig::ZERO || radius == DBig::ZERO {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{target_name}: no capture without mass or from the center"
+            )));
+        }
+        let speed_squared = lift(&relative_velocity.length_squared());
+
+        let two = DBig::from(2);
+        let energy = &speed_squared / &two - &mu / &radius;
+        let momentum_squared = relative_position.cross(&relative_velocity).length_squared();
+        let eccentricity_squared = DBig::ONE + &two * &energy * &momentum_squared / (&mu * &mu);
+        let eccentricity = eccentricity_squared.max(DBig::ZERO).sqrt();
+        let periapsis_radius = &momentum_squared / (&mu * (DBig::ONE + &eccentricity));
+        if periapsis_radius == DBig::ZERO {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{target_name}: the approach is radial, there is no periapsis"
+            )));
+        }
+        let periapsis_speed = (&two * (&energy + &mu / &periapsis_radius)).sqrt();
+
+        let final_apoapsis = lift(final_apoapsis);
+        if final_apoapsis < periapsis_radius {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{target_name}: the final apoapsis is below the periapsis"
+            )));
+        }
+        let final_semi_major_axis = (&periapsis_radius + &final_apoapsis) / &two;
+        let final_speed = vis_viva_speed(&mu, &periapsis_radius, &final_semi_major_axis);
+        let capture_burn = (&periapsis_speed - final_speed).abs();
+
+        let sphere_of_influence = self.sphere_of_influence(target_name)?;
+        let ballistic_capture = energy < DBig::ZERO
+            && match &sphere_of_influence {
+                None => true,
+                Some(limit) => {
+                    let apoapsis = -&mu / &energy - &periapsis_radius;
+                    apoapsis < *limit
+                }
+            };
+        let impact = periapsis_radius < target.body.radius;
+
+        Ok(CaptureAnalysis {
+            eccentricity,
+            periapsis_radius,
+            periapsis_speed,
+            capture_burn,
+            sphere_of_influence,
+            ballistic_capture,
+            impact,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn capture_analysis_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::from(3600));
+        let moon = sim.get_body("moon").unwrap();
+        let position = sim.world_position(moon) + DecimalVector3d::from_f64(5_000_000.0, 0.0, 0.0);
+        let moon_velocity = sim.world_velocity(moon);
+        let mu = 6.67408e-11 * 0.073e24;
+        let circular_speed = (mu / 5_000_000.0_f64).sqrt();
+
+        // perpendicular to the radius and slower than escape, so this is the periapsis of a bound orbit
+        let velocity = &moon_velocity + DecimalVector3d::from_f64(0.0, 1200.0, 0.0);
+        let analysis = sim
+            .capture_analysis("moon", &position, &velocity, &DBig::from(5_000_000))
+            .unwrap();
+        assert!(approx_eq(
+            &analysis.periapsis_radius,
+            &f64_to_dbig(5_000_000.0),
+            &DBig::ONE
+        ));
+        assert!(approx_eq(
+            &analysis.eccentricity,
+            &f64_to_dbig(1200.0 * 1200.0 * 5_000_000.0 / mu - 1.0),
+            &f64_to_dbig(1e-6)
+        ));
+        assert!(approx_eq(
+            &analysis.capture_burn,
+            &f64_to_dbig(1200.0 - circular_speed),
+            &f64_to_dbig(1e-3)
+        ));
+        assert!(analysis.ballistic_capture);
+        assert!(!analysis.impact);
+
+        let velocity = &moon_velocity + DecimalVector3d::from_f64(0.0, 1600.0, 0.0);
+        let analysis = sim
+            .capture_analysis("moon", &position, &velocity, &DBig::from(5_000_000))
+            .unwrap();
+        assert!(dbig_to_f64(&analysis.eccentricity) > 1.0);
+        assert!(approx_eq(
+            &analysis.capture_burn,
+            &f64_to_dbig(1600.0 - circular_speed),
+            &f64_to_dbig(1e-3)
+        ));
+        assert!(!analysis.ballistic_capture);
+
+        // straight at the moon
+        let velocity = &moon_velocity + DecimalVector3d::from_f64(-1600.0, 10.0, 0.0);
+        let analysis = sim
+            .capture_analysis("moon", &position, &velocity, &DBig::from(5_000_000))
+            .unwrap();
+        assert!(analysis.impact);
+    }
+
+    #[test]
+    fn capture_analysis_errors() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::from(3600));
+        let moon = sim.get_body("moon").unwrap();
+        let position = sim.world_position(moon) + DecimalVector3d::from_f64(5_000_000.0, 0.0, 0.0);
+        let moon_velocity = sim.world_velocity(moon);
+
+        // falling straight in has no periapsis
+        let velocity = &moon_velocity + DecimalVector3d::from_f64(-1600.0, 0.0, 0.0);
+        assert_eq!(
+            sim.capture_analysis("moon", &position, &velocity, &DBig::from(5_000_000))
+                .unwrap_err(),
+            SimulationError::InvalidDynamics(String::from(
+                "moon: the approach is radial, there is no periapsis"
+            ))
+        );
+        let velocity = &moon_velocity + DecimalVector3d::from_f64(0.0, 1200.0, 0.0);
+        assert_eq!(
+            sim.capture_analysis("moon", &position, &velocity, &DBig::from(1_000_000))
+                .unwrap_err(),
+            SimulationError::InvalidDynamics(String::from(
+                "moon: the final apoapsis is below the periapsis"
+            ))
+        );
+        let center = sim.world_position(sim.get_body("moon").unwrap());
+        assert!(sim
+            .capture_analysis("moon", &center, &velocity, &DBig::from(5_000_000))
+            .is_err());
+    }
+
+    #[test]
+    fn massless_bodies_have_no_sphere_of_influence() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        sim.get_body_mut("moon").unwrap().mass = DBig::ZERO;
+        assert_eq!(sim.sphere_of_influence("moon").unwrap(), Some(DBig::ZERO));
+        let moon = sim.get_body("moon").unwrap();
+        let near_moon = sim.world_position(moon) + DecimalVector3d::from_f64(2e6, 0.0, 0.0);
+        let dominant = sim.find_dominant_body(&near_moon).unwrap().unwrap();
+        assert_eq!(dominant.body.name, "earth");
+        assert!(sim.calculate_gravity_flux(&near_moon).is_ok());
+    }
+
+    #[test]
+    fn massless_parent_is_an_error() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        sim.get_body_mut("sun").unwrap().mass = DBig::ZERO;
+        let error = SimulationError::InvalidDynamics(String::from("earth: the parent has no mass"));
+        assert_eq!(sim.sphere_of_influence("earth").unwrap_err(), error);
+        let earth = sim.get_body("earth").unwrap();
+        let point = sim.world_position(earth) + DecimalVector3d::from_f64(7e6, 0.0, 0.0);
+        assert_eq!(sim.find_dominant_body(&point).unwrap_err(), error);
+        assert_eq!(sim.calculate_gravity_flux(&point).unwrap_err(), error);
+        assert_eq!(sim.calculate_gravity_potential(&point).unwrap_err(), error);
+        assert!(sim.calculate_tidal_tensor(&point).is_err());
+    }
+}
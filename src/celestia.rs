@@ -0,0 +1,225 @@
+use crate::au::meters_to_au;
+use crate::body::{BodyDynamics, OrbitingBodyDynamics};
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::simulation::{SimulatedBody, Simulation};
+use crate::sin_cos::dbig_to_f64;
+use dashu_float::DBig;
+use std::io::{Result, Write};
+
+const EARTH_MASS_KG: f64 = 5.9722e24;
+const DAY_SECONDS: f64 = 86400.0;
+const YEAR_DAYS: f64 = 365.25;
+
+// Celestia angles are relative to the ecliptic, which is the XZ plane here with +Y as north and
+// +X towards the equinox, so the second ecliptic axis is -Z; returns the inclination and node
+// longitude in degrees and the ascending node direction
+fn ecliptic_angles(normal: &DecimalVector3d) -> (f64, f64, [f64; 3]) {
+    let n = [
+        dbig_to_f64(&normal.x),
+        dbig_to_f64(&normal.y),
+        dbig_to_f64(&normal.z),
+    ];
+    let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    let n = [n[0] / length, n[1] / length, n[2] / length];
+    let inclination = n[1].clamp(-1.0, 1.0).acos().to_degrees();
+    // ascending node lies along north x normal, undefined for orbits in the ecliptic
+    let node = [n[2], 0.0, -n[0]];
+    let node_length = (node[0] * node[0] + node[2] * node[2]).sqrt();
+    if node_length < 1e-12 {
+        return (inclination, 0.0, [1.0, 0.0, 0.0]);
+    }
+    let node = [node[0] / node_length, 0.0, node[2] / node_length];
+    let longitude = (-node[2]).atan2(node[0]).to_degrees().rem_euclid(360.0);
+    (inclination, longitude, node)
+}
+
+fn path(sim: &Simulation, body: &SimulatedBody) -> String {
+    let mut names: Vec<&str> = sim
+        .resolve_hierarchy_up(body)
+        .iter()
+        .map(|parent| parent.body.name.as_str())
+        .collect();
+    names.reverse();
+    names.join("/")
+}
+
+fn write_orbit<W: Write>(
+    writer: &mut W,
+    dynamics: &OrbitingBodyDynamics,
+    epoch_jd: f64,
+    planet_units: bool,
+    distance: impl Fn(&DBig) -> f64,
+) -> Result<()> {
+    // Celestia periods are always positive, retrograde orbits are inclined past 90 degrees
+    let normal = dynamics.angular_momentum_direction().normalized();
+    let (inclination, node_longitude, node) = ecliptic_angles(&normal);
+    // orbits start on +X at time zero, which is an angle from the node within the plane,
+    // the phase at epoch is counted along the motion in either direction
+    let in_plane = [
+        dbig_to_f64(&normal.y) * node[2] - dbig_to_f64(&normal.z) * node[1],
+        dbig_to_f64(&normal.z) * node[0] - dbig_to_f64(&normal.x) * node[2],
+        dbig_to_f64(&normal.x) * node[1] - dbig_to_f64(&normal.y) * node[0],
+    ];
+    let mut anomaly = in_plane[0].atan2(node[0]).to_degrees().rem_euclid(360.0);
+    // eccentric orbits count the mean anomaly from the periapsis, a flipped normal mirrors
+    // the argument of periapsis about the node
+    let mut eccentricity = 0.0;
+    let mut periapsis = 0.0;
+    if let Some(ellipse) = &dynamics.ellipse {
+        eccentricity = dbig_to_f64(&ellipse.eccentricity);
+        periapsis = dbig_to_f64(&ellipse.argument_of_periapsis).to_degrees();
+        if dynamics.orbit_period < DBig::ZERO {
+            periapsis = 180.0 - periapsis;
+        }
+        periapsis = periapsis.rem_euclid(360.0);
+        anomaly = 0.0;
+    }
+    let epoch_anomaly = dbig_to_f64(&dynamics.mean_anomaly_at_epoch).to_degrees();
+    let anomaly = (anomaly + epoch_anomaly).rem_euclid(360.0);
+    let mut period = dbig_to_f64(&dynamics.orbit_period).abs() / DAY_SECONDS;
+    if planet_units {
+        period /= YEAR_DAYS;
+    }
+
+    writeln!(writer, "    EllipticalOrbit {{")?;
+    writeln!(writer, "        Epoch {epoch_jd}")?;
+    writeln!(writer, "        Period {period}")?;
+    writeln!(
+        writer,
+        "        SemiMajorAxis {}",
+        distance(&dynamics.orbit_radius)
+    )?;
+    writeln!(writer, "        Eccentricity {eccentricity}")?;
+    writeln!(writer, "        Inclination {inclination}")?;
+    writeln!(writer, "        AscendingNode {node_longitude}")?;
+    writeln!(writer, "        ArgOfPericenter {periapsis}")?;
+    writeln!(writer, "        MeanAnomaly {anomaly}")?;
+    writeln!(writer, "    }}")?;
+    Ok(())
+}
+
+fn write_body<W: Write>(
+    writer: &mut W,
+    sim: &Simulation,
+    body: &SimulatedBody,
+    epoch_jd: f64,
+) -> Result<()> {
+    // distances are in AU and periods in years for bodies orbiting a root, in km and days otherwise
+    let parent = sim.get_body_by_id(body.parent().unwrap()).unwrap();
+    let planet_units = parent.parent().is_none();
+    let distance = |meters: &DBig| {
+        if planet_units {
+            dbig_to_f64(&meters_to_au(meters.clone()))
+        } else {
+            dbig_to_f64(meters) / 1000.0
+        }
+    };
+
+    writeln!(writer, "\"{}\" \"{}\"", body.body.name, path(sim, body))?;
+    writeln!(writer, "{{")?;
+    writeln!(
+        writer,
+        "    Radius {}",
+        dbig_to_f64(&body.body.radius) / 1000.0
+    )?;
+    writeln!(
+        writer,
+        "    Mass {}",
+        dbig_to_f64(&body.body.mass_at(&sim.time)) / EARTH_MASS_KG
+    )?;
+
+    match &body.body.dynamics {
+        BodyDynamics::Static(dynamics) => {
+            let offset = &dynamics.position - sim.world_position(parent);
+            writeln!(
+                writer,
+                "    FixedPosition [ {} {} {} ]",
+                distance(&offset.x),
+                distance(&offset.y),
+                distance(&offset.z)
+            )?;
+        }
+        BodyDynamics::Formation(_) => {
+            // Celestia has no frames that follow a leader, the offset is frozen as it is now
+            let offset = sim.world_position(body) - sim.world_position(parent);
+            writeln!(
+                writer,
+                "    FixedPosition [ {} {} {} ]",
+                distance(&offset.x),
+                distance(&offset.y),
+                distance(&offset.z)
+            )?;
+        }
+        BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics) => {
+            write_orbit(writer, dynamics, epoch_jd, planet_units, distance)?;
+        }
+    }
+
+    // negative rotation periods are retrograde in Celestia as well
+    let (obliquity, equator_node, _) = ecliptic_angles(&body.body.rotation_axis);
+    writeln!(
+        writer,
+        "    RotationPeriod {}",
+        dbig_to_f64(&body.body.spin_period()) / 3600.0
+    )?;
+    writeln!(writer, "    RotationEpoch {epoch_jd}")?;
+    writeln!(writer, "    Obliquity {obliquity}")?;
+    writeln!(writer, "    EquatorAscendingNode {equator_node}")?;
+    if let Some(visual) = &body.body.visual {
+        if let Some([r, g, b]) = visual.color {
+            let channel = |c: u8| f64::from(c) / 255.0;
+            let (r, g, b) = (channel(r), channel(g), channel(b));
+            writeln!(writer, "    Color [ {r} {g} {b} ]")?;
+        }
+        if let Some(texture) = &visual.texture {
+            writeln!(writer, "    Texture \"{texture}\"")?;
+        }
+        if visual.emissive {
+            writeln!(writer, "    Emissive true")?;
+        }
+    }
+    writeln!(writer, "}}")?;
+    writeln!(writer)
+}
+
+impl Simulation {
+    /// writes every body below a root as a Celestia solar system catalog entry, roots are expected
+    /// to exist in Celestia as stars of the same name, `epoch_jd` is the Julian date of time zero
+    ///
+    /// # Errors
+    ///
+    /// Any error of the writer.
+    pub fn write_celestia_ssc<W: Write>(&self, writer: &mut W, epoch_jd: &DBig) -> Result<()> {
+        let epoch_jd = dbig_to_f64(epoch_jd);
+        for root in self.bodies.iter().filter(|body| body.parent().is_none()) {
+            // parents have to be defined before their satellites
+            for body in self.resolve_hierarchy_down(root) {
+                write_body(writer, self, body, epoch_jd)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::prepare_sim;
+    use dashu_float::DBig;
+
+    #[test]
+    fn write_celestia_ssc_works() {
+        let sim = prepare_sim();
+        let mut buf: Vec<u8> = vec![];
+        sim.write_celestia_ssc(&mut buf, &DBig::from(2_451_545))
+            .unwrap();
+        let ssc = String::from_utf8(buf).unwrap();
+
+        // the sun is expected to be a Celestia star, parents come before their satellites
+        assert!(!ssc.contains("\"sun\" \""));
+        let earth = ssc.find("\"earth\" \"sun\"").unwrap();
+        let moon = ssc.find("\"moon\" \"sun/earth\"").unwrap();
+        assert!(earth < moon);
+        assert!(ssc.contains("SemiMajorAxis 384400"));
+        assert!(ssc.contains("Period 27\n"));
+    }
+}
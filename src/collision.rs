@@ -0,0 +1,243 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::{check_positive, SimulationError};
+use crate::simulation::Simulation;
+use crate::triggers::TriggerSubject;
+use dashu_float::DBig;
+
+const PRECISION: usize = 40;
+const REFINE_ITERATIONS: usize = 32;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+#[derive(Debug, Clone)]
+pub struct CollisionEvent {
+    pub subject: TriggerSubject, // spacecraft are points
+    pub body: String,
+    pub time: DBig,  // of the first contact, refined between the steps
+    pub speed: DBig, // in m/s, of the subject relative to the body at the contact
+}
+
+struct Pair {
+    subject: TriggerSubject,
+    body: String,
+    contact: DBig, // sum of the radii
+}
+
+impl Simulation {
+    // position and velocity of the subject relative to the body, None when either is gone
+    fn subject_offset(
+        &self,
+        subject: &TriggerSubject,
+        body_name: &str,
+    ) -> Option<(DecimalVector3d, DecimalVector3d)> {
+        let (position, velocity) = match subject {
+            TriggerSubject::Body(name) => {
+                let body = self.get_body(name).ok()?;
+                (self.world_position(body), self.world_velocity(body))
+            }
+            TriggerSubject::Spacecraft(name) => {
+                let state = &self.get_spacecraft(name).ok()?.state;
+                (state.position.clone(), state.velocity.clone())
+            }
+        };
+        let body = self.get_body(body_name).ok()?;
+        Some((
+            position - self.world_position(body),
+            velocity - self.world_velocity(body),
+        ))
+    }
+
+    /// first contacts of two bodies, or of a spacecraft and a body, closer than the sum of their
+    /// radii between the current time and `time`. Searched on a copy of the simulation moved every
+    /// `step`, with the spacecraft propagated along at that step; between the samples the relative
+    /// motion is taken as straight, so a pair can't pass through each other within a step unseen.
+    /// Pairs already in contact at the start and sleeping bodies are left out, in time order
+    ///
+    /// # Errors
+    ///
+    /// `InvalidArgument` if `step` isn't positive.
+    pub fn sweep_collisions(
+        &self,
+        time: &DBig,
+        step: &DBig,
+    ) -> Result<Vec<CollisionEvent>, SimulationError> {
+        check_positive(step, "step")?;
+        let bodies: Vec<_> = self.awake_bodies().collect();
+        let mut pairs: Vec<Pair> = vec![];
+        for (i, body) in bodies.iter().enumerate() {
+            for other in &bodies[i + 1..] {
+                pairs.push(Pair {
+                    subject: TriggerSubject::Body(body.body.name.clone()),
+                    body: other.body.name.clone(),
+                    contact: lift(&(&body.body.radius + &other.body.radius)),
+                });
+            }
+            for craft in &self.spacecraft {
+                pairs.push(Pair {
+                    subject: TriggerSubject::Spacecraft(craft.name.clone()),
+                    body: body.body.name.clone(),
+                    contact: lift(&body.body.radius),
+                });
+            }
+        }
+        let apart = |sim: &Simulation, pair: &Pair| {
+            sim.subject_offset(&pair.subject, &pair.body)
+                .is_some_and(|(offset, _)| offset.length() >= pair.contact)
+        };
+        pairs.retain(|pair| apart(self, pair));
+
+        let mut probe = self.copy_bodies();
+        probe.spacecraft.clone_from(&self.spacecraft);
+        let mut events: Vec<CollisionEvent> = vec![];
+        let mut start = self.time.clone();
+        let step = lift(step);
+        while start < *time && !pairs.is_empty() {
+            let spacecraft = probe.spacecraft.clone();
+            let moving = Some((spacecraft.as_slice(), &step));
+            let next = (&start + &step).min(time.clone());
+            let before: Vec<_> = pairs
+                .iter()
+                .map(|pair| probe.subject_offset(&pair.subject, &pair.body))
+                .collect();
+            self.move_probe(&mut probe, &next, moving);
+
+            let mut touching: Vec<(usize, DBig)> = vec![];
+            for (i, pair) in pairs.iter().enumerate() {
+                let (Some((from, _)), Some((to, _))) =
+                    (&before[i], probe.subject_offset(&pair.subject, &pair.body))
+                else {
+                    continue;
+                };
+                if to.length() < pair.contact {
+                    touching.push((i, next.clone()));
+                    continue;
+                }
+                // the closest approach on the straight line between the samples
+                let travel = &to - from;
+                let length_squared = travel.length_squared();
+                if length_squared == DBig::ZERO {
+                    continue;
+                }
+                let fraction = (-from.dot(&travel) / length_squared).clamp(DBig::ZERO, DBig::ONE);
+                if (from + &travel * &fraction).length() < pair.contact {
+                    touching.push((i, &start + (&next - &start) * fraction));
+                }
+            }
+
+            let mut done: Vec<usize> = vec![];
+            for (i, end) in touching {
+                let pair = &pairs[i];
+                self.move_probe(&mut probe, &end, moving);
+                // the straight line is only a guess, the real paths can miss
+                if apart(&probe, pair) {
+                    continue;
+                }
+                let (mut low, mut high) = (start.clone(), end);
+                for _ in 0..REFINE_ITERATIONS {
+                    let middle = (&low + &high) / DBig::from(2);
+                    self.move_probe(&mut probe, &middle, moving);
+                    if apart(&probe, pair) {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                self.move_probe(&mut probe, &high, moving);
+                let Some((_, velocity)) = probe.subject_offset(&pair.subject, &pair.body) else {
+                    continue;
+                };
+                events.push(CollisionEvent {
+                    subject: pair.subject.clone(),
+                    body: pair.body.clone(),
+                    time: high,
+                    speed: velocity.length(),
+                });
+                done.push(i);
+            }
+            if !done.is_empty() {
+                for i in done.into_iter().rev() {
+                    pairs.remove(i);
+                }
+                self.move_probe(&mut probe, &next, moving);
+            }
+            start = next;
+        }
+        events.sort_by(|a, b| a.time.cmp(&b.time));
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::propagation::{CraftState, ThrustProfile};
+    use crate::spacecraft::Spacecraft;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use crate::triggers::TriggerSubject;
+    use dashu_float::DBig;
+
+    #[test]
+    fn sweep_collisions_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let earth = sim.get_body("earth").unwrap();
+        let (earth_position, earth_velocity) =
+            (sim.world_position(earth), sim.world_velocity(earth));
+        let craft = |name: &str, position: DecimalVector3d, velocity: DecimalVector3d| Spacecraft {
+            name: String::from(name),
+            state: CraftState {
+                time: DBig::ZERO,
+                position: &earth_position + position,
+                velocity: &earth_velocity + velocity,
+                propulsion: None,
+            },
+            thrust: ThrustProfile::Coast,
+            primary: None,
+        };
+        // fast enough to go through the earth within one step, about 320 s in
+        sim.add_spacecraft(craft(
+            "impactor",
+            DecimalVector3d::from_f64(2e7, 5e6, 0.0),
+            DecimalVector3d::from_f64(-5e4, 0.0, 0.0),
+        ))
+        .unwrap();
+        // already on the ground, left out
+        sim.add_spacecraft(craft(
+            "lander",
+            DecimalVector3d::from_f64(0.0, 6e6, 0.0),
+            DecimalVector3d::zero(),
+        ))
+        .unwrap();
+
+        let events = sim
+            .sweep_collisions(&DBig::from(3000), &DBig::from(1000))
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(
+            event.subject,
+            TriggerSubject::Spacecraft(String::from("impactor"))
+        );
+        assert_eq!(event.body, "earth");
+        let time = dbig_to_f64(&event.time);
+        assert!(time > 290.0 && time < 330.0);
+        assert!(dbig_to_f64(&event.speed) > 5e4);
+        // the sweep runs on a copy
+        assert_eq!(sim.time(), &DBig::ZERO);
+
+        // the bodies keep well apart
+        sim.remove_spacecraft("impactor").unwrap();
+        assert!(sim
+            .sweep_collisions(&DBig::from(6 * 3600), &DBig::from(3600))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            sim.sweep_collisions(&DBig::from(3600), &DBig::ZERO)
+                .unwrap_err(),
+            SimulationError::InvalidArgument(String::from("the step has to be positive"))
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use crate::body::Body;
+use crate::decimal_matrix_3d::DecimalMatrix3d;
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::simulation::{SimulatedBody, Simulation, G_CONSTANT};
+use crate::sin_cos::{atan2, cos, sin};
+use crate::surface::reference_meridian;
+use dashu_float::ops::SquareRoot;
+use dashu_float::DBig;
+
+// bodies are spheres, so the latitude is the angle above the equator seen from the center and
+// the altitude is over the body radius
+#[derive(Debug, Clone)]
+pub struct GeodeticCoordinates {
+    pub latitude: DBig,  // in radians, positive towards the rotation axis
+    pub longitude: DBig, // in radians within (-pi, pi], growing towards the east
+    pub altitude: DBig,
+}
+
+// the body-fixed axes in world space for an orientation of the body: +X through latitude and
+// longitude zero, +Y along the rotation axis and +Z completing a right-handed frame, so longitude
+// 90 degrees east lies on -Z like the world orbits turn +X towards -Z
+pub(crate) fn body_fixed_axes(body: &Body, orientation: &DecimalMatrix3d) -> [DecimalVector3d; 3] {
+    let axis = body.rotation_axis.normalized();
+    let meridian = reference_meridian(&axis);
+    let quarter = axis.cross(&meridian);
+    [
+        orientation.apply(&meridian),
+        orientation.apply(&axis),
+        -orientation.apply(&quarter),
+    ]
+}
+
+// the body-fixed axes at the current orientation, computed once for a whole batch
+struct BodyFrame {
+    center: DecimalVector3d,
+    x: DecimalVector3d,
+    y: DecimalVector3d,
+    z: DecimalVector3d,
+}
+
+impl BodyFrame {
+    fn new(sim: &Simulation, body: &SimulatedBody) -> Self {
+        let [x, y, z] = body_fixed_axes(&body.body, &body.orientation);
+        BodyFrame {
+            center: sim.world_position(body),
+            x,
+            y,
+            z,
+        }
+    }
+
+    fn to_fixed(&self, point: &DecimalVector3d) -> DecimalVector3d {
+        let offset = point - &self.center;
+        DecimalVector3d::new(
+            offset.dot(&self.x),
+            offset.dot(&self.y),
+            offset.dot(&self.z),
+        )
+    }
+
+    fn to_world(&self, point: &DecimalVector3d) -> DecimalVector3d {
+        &self.center + &self.x * &point.x + &self.y * &point.y + &self.z * &point.z
+    }
+}
+
+impl Simulation {
+    /// world positions into the body-fixed frame of the body at the current time, see `BodyFrame`
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn world_to_body_fixed(
+        &self,
+        body_name: &str,
+        points: &[DecimalVector3d],
+    ) -> Result<Vec<DecimalVector3d>, SimulationError> {
+        let frame = BodyFrame::new(self, self.get_body(body_name)?);
+        Ok(points.iter().map(|p| frame.to_fixed(p)).collect())
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn body_fixed_to_world(
+        &self,
+        body_name: &str,
+        points: &[DecimalVector3d],
+    ) -> Result<Vec<DecimalVector3d>, SimulationError> {
+        let frame = BodyFrame::new(self, self.get_body(body_name)?);
+        Ok(points.iter().map(|p| frame.to_world(p)).collect())
+    }
+
+    /// the body center itself has no direction and maps to latitude and longitude zero
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn body_fixed_to_geodetic(
+        &self,
+        body_name: &str,
+        points: &[DecimalVector3d],
+    ) -> Result<Vec<GeodeticCoordinates>, SimulationError> {
+        let radius = &self.get_body(body_name)?.body.radius;
+        Ok(points
+            .iter()
+            .map(|p| fixed_to_geodetic(p, radius))
+            .collect())
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn geodetic_to_body_fixed(
+        &self,
+        body_name: &str,
+        coordinates: &[GeodeticCoordinates],
+    ) -> Result<Vec<DecimalVector3d>, SimulationError> {
+        let radius = &self.get_body(body_name)?.body.radius;
+        Ok(coordinates
+            .iter()
+            .map(|c| geodetic_to_fixed(c, radius))
+            .collect())
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn world_to_geodetic(
+        &self,
+        body_name: &str,
+        points: &[DecimalVector3d],
+    ) -> Result<Vec<GeodeticCoordinates>, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let frame = BodyFrame::new(self, body);
+        Ok(points
+            .iter()
+            .map(|p| fixed_to_geodetic(&frame.to_fixed(p), &body.body.radius))
+            .collect())
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn geodetic_to_world(
+        &self,
+        body_name: &str,
+        coordinates: &[GeodeticCoordinates],
+    ) -> Result<Vec<DecimalVector3d>, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let frame = BodyFrame::new(self, body);
+        Ok(coordinates
+            .iter()
+            .map(|c| frame.to_world(&geodetic_to_fixed(c, &body.body.radius)))
+            .collect())
+    }
+
+    /// in m/s^2, of the body alone at its radius and current mass, without the rotation
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation, `InvalidDynamics` if it has no radius.
+    pub fn surface_gravity(&self, body_name: &str) -> Result<DBig, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let radius = body.body.radius.clone().with_precision(32).value();
+        if radius == DBig::ZERO {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{body_name}: has no radius"
+            )));
+        }
+        Ok(&*G_CONSTANT * body.body.mass_at(&self.time) / (&radius * &radius))
+    }
+
+    /// the world position on the surface at the latitude and longitude in radians
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn surface_point(
+        &self,
+        body_name: &str,
+        latitude: &DBig,
+        longitude: &DBig,
+    ) -> Result<DecimalVector3d, SimulationError> {
+        let coordinates = GeodeticCoordinates {
+            latitude: latitude.clone(),
+            longitude: longitude.clone(),
+            altitude: DBig::ZERO,
+        };
+        Ok(self.geodetic_to_world(body_name, &[coordinates])?.remove(0))
+    }
+
+    /// negative below the surface
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn altitude_above_surface(
+        &self,
+        body_name: &str,
+        point: &DecimalVector3d,
+    ) -> Result<DBig, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let distance = (point - self.world_position(body))
+            .length_squared()
+            .with_precision(32)
+            .value()
+            .sqrt();
+        Ok(distance - &body.body.radius)
+    }
+}
+
+fn fixed_to_geodetic(point: &DecimalVector3d, radius: &DBig) -> GeodeticCoordinates {
+    let distance = point.length_squared().with_precision(32).value().sqrt();
+    if distance == DBig::ZERO {
+        return GeodeticCoordinates {
+            latitude: DBig::ZERO,
+            longitude: DBig::ZERO,
+            altitude: -radius,
+        };
+    }
+    let horizontal = (&point.x * &point.x + &point.z * &point.z)
+        .with_precision(32)
+        .value()
+        .sqrt();
+    GeodeticCoordinates {
+        latitude: atan2(point.y.clone(), horizontal, 32),
+        longitude: atan2(-&point.z, point.x.clone(), 32),
+        altitude: distance - radius,
+    }
+}
+
+fn geodetic_to_fixed(coordinates: &GeodeticCoordinates, radius: &DBig) -> DecimalVector3d {
+    let distance = radius + &coordinates.altitude;
+    let (latitude, longitude) = (&coordinates.latitude, &coordinates.longitude);
+    let horizontal = &distance * cos(latitude.clone(), 32);
+    DecimalVector3d::new(
+        &horizontal * cos(longitude.clone(), 32),
+        &distance * sin(latitude.clone(), 32),
+        -(&horizontal * sin(longitude.clone(), 32)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coordinates::GeodeticCoordinates;
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn batch_coordinate_conversion_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::from(3600));
+        let earth = sim.get_body("earth").unwrap();
+        let center = sim.world_position(earth);
+
+        // surface points agree with the surface frame, east of the meridian lies on -Z
+        let (latitude, longitude) = (f64_to_dbig(0.4), f64_to_dbig(1.2));
+        let (surface, _) = sim
+            .surface_point_state("earth", &latitude, &longitude, &DBig::from(1000))
+            .unwrap();
+        let points = vec![
+            surface.clone(),
+            center.clone(),
+            &center + DecimalVector3d::from_f64(0.0, 7e6, 0.0),
+        ];
+        let geodetic = sim.world_to_geodetic("earth", &points).unwrap();
+        assert!(approx_eq(
+            &geodetic[0].latitude,
+            &f64_to_dbig(0.4),
+            &f64_to_dbig(1e-9)
+        ));
+        assert!(approx_eq(
+            &geodetic[0].longitude,
+            &f64_to_dbig(1.2),
+            &f64_to_dbig(1e-9)
+        ));
+        assert!(approx_eq(
+            &geodetic[0].altitude,
+            &f64_to_dbig(1000.0),
+            &f64_to_dbig(1e-3)
+        ));
+        assert_eq!(geodetic[1].altitude, DBig::from(-6_371_000));
+        let fixed = sim.world_to_body_fixed("earth", &points[..1]).unwrap();
+        assert!(fixed[0].z < DBig::ZERO);
+
+        let back = sim.geodetic_to_world("earth", &geodetic).unwrap();
+        let epsilon = f64_to_dbig(1e-3);
+        assert!(back[0].approx_eq(&points[0], &epsilon));
+        assert!(back[2].approx_eq(&points[2], &epsilon));
+        let world = sim.body_fixed_to_world("earth", &fixed).unwrap();
+        assert!(world[0].approx_eq(&surface, &epsilon));
+
+        let pole = GeodeticCoordinates {
+            latitude: f64_to_dbig(std::f64::consts::FRAC_PI_2),
+            longitude: DBig::ZERO,
+            altitude: DBig::ZERO,
+        };
+        let fixed = sim.geodetic_to_body_fixed("earth", &[pole]).unwrap();
+        assert!(approx_eq(
+            &fixed[0].y,
+            &f64_to_dbig(6_371_000.0),
+            &f64_to_dbig(1e-3)
+        ));
+        let geodetic = sim.body_fixed_to_geodetic("earth", &fixed).unwrap();
+        assert!(approx_eq(
+            &geodetic[0].latitude,
+            &f64_to_dbig(std::f64::consts::FRAC_PI_2),
+            &f64_to_dbig(1e-9)
+        ));
+        // the length rounded to the working precision can end up below the height over the equator
+        let pole = DecimalVector3d::from_str("0", "6371000.1234567890123", "0").unwrap();
+        let geodetic = sim.body_fixed_to_geodetic("earth", &[pole]).unwrap();
+        assert!(approx_eq(
+            &geodetic[0].latitude,
+            &f64_to_dbig(std::f64::consts::FRAC_PI_2),
+            &f64_to_dbig(1e-9)
+        ));
+    }
+
+    #[test]
+    fn surface_helpers_work() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::from(3600));
+
+        let gravity = dbig_to_f64(&sim.surface_gravity("earth").unwrap());
+        assert!((gravity - 6.67408e-11 * 5.97219e24 / 6_371_000.0_f64.powi(2)).abs() < 1e-9);
+        assert!((gravity - 9.82).abs() < 0.01);
+
+        let (latitude, longitude) = (f64_to_dbig(-0.3), f64_to_dbig(2.0));
+        let point = sim.surface_point("earth", &latitude, &longitude).unwrap();
+        let (state, _) = sim
+            .surface_point_state("earth", &latitude, &longitude, &DBig::ZERO)
+            .unwrap();
+        assert!(point.approx_eq(&state, &f64_to_dbig(1e-3)));
+        let altitude = sim.altitude_above_surface("earth", &point).unwrap();
+        assert!(approx_eq(&altitude, &DBig::ZERO, &f64_to_dbig(1e-3)));
+
+        let earth = sim.world_position(sim.get_body("earth").unwrap());
+        let above = &earth + DecimalVector3d::from_f64(0.0, 0.0, 6_500_000.0);
+        let altitude = sim.altitude_above_surface("earth", &above).unwrap();
+        assert!(approx_eq(
+            &altitude,
+            &f64_to_dbig(129_000.0),
+            &f64_to_dbig(1e-3)
+        ));
+        let altitude = sim.altitude_above_surface("earth", &earth).unwrap();
+        assert_eq!(altitude, DBig::from(-6_371_000));
+
+        assert!(matches!(
+            sim.surface_gravity("pluto"),
+            Err(SimulationError::UnknownBody(_))
+        ));
+    }
+}
@@ -0,0 +1,174 @@
+use crate::simulation::Simulation;
+use crate::sin_cos::dbig_to_f64;
+use dashu_float::DBig;
+use std::fmt::Write as _;
+use std::io::{Error, ErrorKind, Result, Write};
+
+pub(crate) fn json_string(v: &str) -> String {
+    let mut result = String::from("\"");
+    for c in v.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(result, "\\u{:04x}", u32::from(c));
+            }
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+pub(crate) fn json_numbers(values: &[f64]) -> String {
+    values
+        .iter()
+        .map(std::string::ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl Simulation {
+    /// samples every body from `start` to `end` (inclusive) on a copy of the simulation and writes
+    /// them as CZML, `epoch` is the ISO 8601 date of simulation time zero, positions are world
+    /// positions in meters in Cesium's inertial frame, with the export scale applied if set
+    ///
+    /// # Errors
+    ///
+    /// `InvalidInput` if `step` isn't positive, and any error of the writer.
+    pub fn write_czml<W: Write>(
+        &self,
+        writer: &mut W,
+        name: &str,
+        epoch: &str,
+        start: &DBig,
+        end: &DBig,
+        step: &DBig,
+    ) -> Result<()> {
+        // the samples would never reach the end
+        if *step <= DBig::ZERO {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the CZML step has to be positive",
+            ));
+        }
+        let mut sim = self.copy_bodies();
+        sim.export_scale = self.export_scale;
+
+        let mut positions: Vec<Vec<f64>> = vec![vec![]; sim.bodies.len()];
+        let mut orientations: Vec<Vec<f64>> = vec![vec![]; sim.bodies.len()];
+        let mut time = start.clone();
+        while &time <= end {
+            sim.update(&time);
+            let seconds = dbig_to_f64(&time);
+            for (i, body) in sim.bodies.iter().enumerate() {
+                let [x, y, z] = sim.scaled_world_position(body);
+                positions[i].extend([seconds, x, y, z]);
+                let [x, y, z, w] = body.orientation.as_quat();
+                orientations[i].extend([
+                    seconds,
+                    dbig_to_f64(&x),
+                    dbig_to_f64(&y),
+                    dbig_to_f64(&z),
+                    dbig_to_f64(&w),
+                ]);
+            }
+            time += step;
+        }
+
+        write!(
+            writer,
+            "[{{\"id\":\"document\",\"name\":{},\"version\":\"1.0\"}}",
+            json_string(name)
+        )?;
+        for (i, body) in sim.bodies.iter().enumerate() {
+            let mut radius = dbig_to_f64(&body.body.radius);
+            if let Some(scale) = &sim.export_scale {
+                radius = scale.scale_radius(radius);
+            }
+            // texture and emission have no CZML graphics, they go to custom properties
+            let mut material = String::new();
+            let mut properties = String::new();
+            if let Some(visual) = &body.body.visual {
+                if let Some([r, g, b]) = visual.color {
+                    material = format!(
+                        ",\"material\":{{\"solidColor\":{{\"color\":{{\"rgba\":[{r},{g},{b},255]}}}}}}"
+                    );
+                }
+                let mut values = vec![format!("\"emissive\":{}", visual.emissive)];
+                if let Some(texture) = &visual.texture {
+                    values.push(format!("\"texture\":{}", json_string(texture)));
+                }
+                properties = format!(",\"properties\":{{{}}}", values.join(","));
+            }
+            write!(
+                writer,
+                ",{{\"id\":{},\"name\":{},\
+                \"position\":{{\"epoch\":{},\"referenceFrame\":\"INERTIAL\",\"cartesian\":[{}]}},\
+                \"orientation\":{{\"epoch\":{},\"unitQuaternion\":[{}]}},\
+                \"ellipsoid\":{{\"radii\":{{\"cartesian\":[{},{},{}]}}{}}}{}}}",
+                json_string(&body.id().to_string()),
+                json_string(&body.body.name),
+                json_string(epoch),
+                json_numbers(&positions[i]),
+                json_string(epoch),
+                json_numbers(&orientations[i]),
+                radius,
+                radius,
+                radius,
+                material,
+                properties
+            )?;
+        }
+        write!(writer, "]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::prepare_sim;
+    use dashu_float::DBig;
+
+    #[test]
+    fn write_czml_works() {
+        let sim = prepare_sim();
+        let mut buf: Vec<u8> = vec![];
+        sim.write_czml(
+            &mut buf,
+            "solar system",
+            "2000-01-01T12:00:00Z",
+            &DBig::ZERO,
+            &DBig::from(24 * 3600),
+            &DBig::from(6 * 3600),
+        )
+        .unwrap();
+        let czml = String::from_utf8(buf).unwrap();
+
+        assert!(czml.starts_with("[{\"id\":\"document\",\"name\":\"solar system\""));
+        assert!(czml.ends_with("}]"));
+        assert_eq!(czml.matches("\"name\":\"moon\"").count(), 1);
+        assert_eq!(czml.matches("\"referenceFrame\":\"INERTIAL\"").count(), 3);
+        // the source simulation is left untouched
+        assert_eq!(sim.time(), &DBig::ZERO);
+    }
+
+    #[test]
+    fn write_czml_rejects_non_positive_steps() {
+        let sim = prepare_sim();
+        for step in [DBig::ZERO, DBig::from(-60)] {
+            let mut buf: Vec<u8> = vec![];
+            let error = sim
+                .write_czml(
+                    &mut buf,
+                    "solar system",
+                    "2000-01-01T12:00:00Z",
+                    &DBig::ZERO,
+                    &DBig::from(3600),
+                    &step,
+                )
+                .unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+            assert!(buf.is_empty());
+        }
+    }
+}
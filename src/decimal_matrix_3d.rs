@@ -1,5 +1,5 @@
 use crate::decimal_vector_3d::DecimalVector3d;
-use crate::sin_cos::{cos, f64_to_dbig, sin};
+use crate::sin_cos::{approx_eq, cos, f64_to_dbig, sin};
 use dashu_float::ops::SquareRoot;
 use dashu_float::DBig;
 use std::ops::Deref;
@@ -68,6 +68,11 @@ impl DecimalMatrix3d {
         }
     }
 
+    // every element within epsilon
+    pub fn approx_eq(&self, other: &DecimalMatrix3d, epsilon: &DBig) -> bool {
+        (0..3).all(|i| (0..3).all(|j| approx_eq(&self.data[i][j], &other.data[i][j], epsilon)))
+    }
+
     pub fn as_quat(&self) -> [DBig; 4] {
         let f_trace = &self.data[0][0] + &self.data[1][1] + &self.data[2][2];
         let half = DBIGHALF.deref();
@@ -103,3 +108,25 @@ impl DecimalMatrix3d {
         }
     }
 }
+
+// q and -q are the same rotation, so either sign matches
+pub fn quat_approx_eq(a: &[DBig; 4], b: &[DBig; 4], epsilon: &DBig) -> bool {
+    (0..4).all(|i| approx_eq(&a[i], &b[i], epsilon))
+        || (0..4).all(|i| approx_eq(&a[i], &-b[i].clone(), epsilon))
+}
+
+// applies the right side first, (a * b).apply(v) is a.apply(b.apply(v))
+impl std::ops::Mul<&DecimalMatrix3d> for &DecimalMatrix3d {
+    type Output = DecimalMatrix3d;
+
+    fn mul(self, rhs: &DecimalMatrix3d) -> DecimalMatrix3d {
+        let row = |i: usize| {
+            let [x, y, z] = rhs.data[i].clone();
+            let v = self.apply(&DecimalVector3d::new(x, y, z));
+            [v.x, v.y, v.z]
+        };
+        DecimalMatrix3d {
+            data: [row(0), row(1), row(2)],
+        }
+    }
+}
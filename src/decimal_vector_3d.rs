@@ -1,3 +1,5 @@
+use crate::error::SimulationError;
+use crate::sin_cos::approx_eq;
 use dashu_float::ops::SquareRoot;
 use dashu_float::DBig;
 use std::fmt;
@@ -29,12 +31,16 @@ impl DecimalVector3d {
         self.z = v.z.clone();
     }
 
-    pub fn from_str(x: &str, y: &str, z: &str) -> DecimalVector3d {
-        DecimalVector3d {
-            x: DBig::from_str(x).unwrap(),
-            y: DBig::from_str(y).unwrap(),
-            z: DBig::from_str(z).unwrap(),
-        }
+    /// # Errors
+    ///
+    /// `Parse` if a component isn't a number.
+    pub fn from_str(x: &str, y: &str, z: &str) -> Result<DecimalVector3d, SimulationError> {
+        let parse = |v: &str| DBig::from_str(v).map_err(|_| SimulationError::Parse(v.to_string()));
+        Ok(DecimalVector3d {
+            x: parse(x)?,
+            y: parse(y)?,
+            z: parse(z)?,
+        })
     }
 
     pub fn from_f64(x: f64, y: f64, z: f64) -> DecimalVector3d {
@@ -86,17 +92,18 @@ impl DecimalVector3d {
 
         DecimalVector3d { x, y, z }
     }
+
+    // every component within epsilon
+    pub fn approx_eq(&self, other: &DecimalVector3d, epsilon: &DBig) -> bool {
+        approx_eq(&self.x, &other.x, epsilon)
+            && approx_eq(&self.y, &other.y, epsilon)
+            && approx_eq(&self.z, &other.z, epsilon)
+    }
 }
 
 impl fmt::Display for DecimalVector3d {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{{ x: {}, y: {}, z: {} }}",
-            self.x.to_string(),
-            self.y.to_string(),
-            self.z.to_string()
-        )
+        write!(f, "{{ x: {}, y: {}, z: {} }}", self.x, self.y, self.z)
     }
 }
 
@@ -555,3 +562,29 @@ impl std::ops::DivAssign<DBig> for DecimalVector3d {
         self.z /= &rhs;
     }
 }
+
+// NEG
+
+impl std::ops::Neg for DecimalVector3d {
+    type Output = DecimalVector3d;
+
+    fn neg(self) -> DecimalVector3d {
+        DecimalVector3d {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+}
+
+impl std::ops::Neg for &DecimalVector3d {
+    type Output = DecimalVector3d;
+
+    fn neg(self) -> DecimalVector3d {
+        DecimalVector3d {
+            x: -self.x.clone(),
+            y: -self.y.clone(),
+            z: -self.z.clone(),
+        }
+    }
+}
@@ -0,0 +1,644 @@
+use crate::decimal_matrix_3d::DecimalMatrix3d;
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::simulation::{PositionStorage, Simulation};
+use crate::snapshot::{
+    invalid_data, read_dbig, read_i32, read_u32, read_u8, read_vector, write_dbig, write_i32,
+    write_len, write_u8, write_vector,
+};
+use dashu_float::DBig;
+use std::io::{Read, Result, Write};
+
+const PRECISION: usize = 40;
+
+const POSITION: u8 = 1;
+const VELOCITY: u8 = 2;
+const ORIENTATION: u8 = 4;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+// steps the values are rounded to; root positions count from the anchor, satellite positions
+// and all velocities from the parent, so the quanta stay small numbers near the action
+#[derive(Debug, Clone)]
+pub struct DeltaQuantization {
+    pub anchor: DecimalVector3d,
+    pub position: DBig,    // in meters
+    pub velocity: DBig,    // in m/s
+    pub orientation: DBig, // per element of the rotation matrix
+}
+
+// changes of one body in quanta, None where the field stayed within a quantum
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BodyDelta {
+    pub id: i32,
+    pub position: Option<[i64; 3]>,
+    pub velocity: Option<[i64; 3]>,
+    pub orientation: Option<[i64; 9]>,
+}
+
+// from the state at one time to the state at the next of the same bodies, differences of the
+// quantized values so applying and rolling back are exact inverses on the quantized state
+#[derive(Debug, Clone)]
+pub struct StateDelta {
+    pub from_time: DBig,
+    pub to_time: DBig,
+    pub quantization: DeltaQuantization,
+    pub bodies: Vec<BodyDelta>,
+}
+
+fn quantize(
+    value: &DBig,
+    offset: &DBig,
+    quantum: &DBig,
+) -> std::result::Result<i64, SimulationError> {
+    let steps = ((lift(value) - offset) / lift(quantum))
+        .round()
+        .to_int()
+        .value();
+    i64::try_from(steps)
+        .map_err(|_| SimulationError::InvalidState(format!("{value} is too many quanta")))
+}
+
+// a quantum of zero can't be divided by, a negative one would flip the signs
+fn check_quantization(
+    quantization: &DeltaQuantization,
+) -> std::result::Result<(), SimulationError> {
+    let quanta = [
+        &quantization.position,
+        &quantization.velocity,
+        &quantization.orientation,
+    ];
+    if quanta.iter().any(|quantum| **quantum <= DBig::ZERO) {
+        return Err(SimulationError::InvalidArgument(String::from(
+            "the delta quanta have to be positive",
+        )));
+    }
+    Ok(())
+}
+
+// the quantized value moved by the difference in the given direction
+fn shift<const N: usize>(
+    current: [i64; N],
+    difference: &[i64; N],
+    sign: i64,
+) -> std::result::Result<[i64; N], SimulationError> {
+    let mut shifted = [0; N];
+    for (i, value) in shifted.iter_mut().enumerate() {
+        *value = difference[i]
+            .checked_mul(sign)
+            .and_then(|step| current[i].checked_add(step))
+            .ok_or_else(|| {
+                SimulationError::InvalidState(String::from("the delta overflows the quanta"))
+            })?;
+    }
+    Ok(shifted)
+}
+
+fn dequantize(steps: i64, offset: &DBig, quantum: &DBig) -> DBig {
+    offset + lift(&DBig::from(steps)) * lift(quantum)
+}
+
+fn differences<const N: usize>(
+    from: [i64; N],
+    to: [i64; N],
+) -> std::result::Result<Option<[i64; N]>, SimulationError> {
+    let mut difference = [0; N];
+    for (i, value) in difference.iter_mut().enumerate() {
+        *value = to[i].checked_sub(from[i]).ok_or_else(|| {
+            SimulationError::InvalidArgument(String::from("the delta overflows the quanta"))
+        })?;
+    }
+    Ok(difference.iter().any(|v| *v != 0).then_some(difference))
+}
+
+// zigzag LEB128, small changes of either sign take a byte or two
+fn write_varint<W: Write>(w: &mut W, v: i64) -> Result<()> {
+    let mut bits = ((v << 1) ^ (v >> 63)).cast_unsigned();
+    loop {
+        let byte = (bits & 0x7f) as u8;
+        bits >>= 7;
+        if bits == 0 {
+            return write_u8(w, byte);
+        }
+        write_u8(w, byte | 0x80)?;
+    }
+}
+
+fn read_varint<R: Read>(r: &mut R) -> Result<i64> {
+    let mut bits = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = read_u8(r)?;
+        bits |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok((bits >> 1).cast_signed() ^ -(bits & 1).cast_signed());
+        }
+    }
+    Err(invalid_data("varint too long"))
+}
+
+fn write_values<W: Write>(w: &mut W, values: &[i64]) -> Result<()> {
+    for v in values {
+        write_varint(w, *v)?;
+    }
+    Ok(())
+}
+
+fn read_values<R: Read, const N: usize>(r: &mut R) -> Result<[i64; N]> {
+    let mut values = [0; N];
+    for v in &mut values {
+        *v = read_varint(r)?;
+    }
+    Ok(values)
+}
+
+impl StateDelta {
+    /// # Errors
+    ///
+    /// Any error of the writer, and `InvalidInput` if there are too many bodies for the format.
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        write_dbig(w, &self.from_time)?;
+        write_dbig(w, &self.to_time)?;
+        write_vector(w, &self.quantization.anchor)?;
+        write_dbig(w, &self.quantization.position)?;
+        write_dbig(w, &self.quantization.velocity)?;
+        write_dbig(w, &self.quantization.orientation)?;
+        write_len(w, self.bodies.len())?;
+        for body in &self.bodies {
+            write_i32(w, body.id)?;
+            let mut fields = 0;
+            if body.position.is_some() {
+                fields |= POSITION;
+            }
+            if body.velocity.is_some() {
+                fields |= VELOCITY;
+            }
+            if body.orientation.is_some() {
+                fields |= ORIENTATION;
+            }
+            write_u8(w, fields)?;
+            if let Some(position) = &body.position {
+                write_values(w, position)?;
+            }
+            if let Some(velocity) = &body.velocity {
+                write_values(w, velocity)?;
+            }
+            if let Some(orientation) = &body.orientation {
+                write_values(w, orientation)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// Any error of the reader, and `InvalidData` if the delta is malformed.
+    pub fn read<R: Read>(r: &mut R) -> Result<StateDelta> {
+        let from_time = read_dbig(r)?;
+        let to_time = read_dbig(r)?;
+        let quantization = DeltaQuantization {
+            anchor: read_vector(r)?,
+            position: read_dbig(r)?,
+            velocity: read_dbig(r)?,
+            orientation: read_dbig(r)?,
+        };
+        if check_quantization(&quantization).is_err() {
+            return Err(invalid_data("invalid delta quanta"));
+        }
+        let count = read_u32(r)?;
+        let mut bodies = vec![];
+        for _ in 0..count {
+            let id = read_i32(r)?;
+            let fields = read_u8(r)?;
+            if fields & !(POSITION | VELOCITY | ORIENTATION) != 0 {
+                return Err(invalid_data("invalid delta fields"));
+            }
+            bodies.push(BodyDelta {
+                id,
+                position: match fields & POSITION {
+                    0 => None,
+                    _ => Some(read_values(r)?),
+                },
+                velocity: match fields & VELOCITY {
+                    0 => None,
+                    _ => Some(read_values(r)?),
+                },
+                orientation: match fields & ORIENTATION {
+                    0 => None,
+                    _ => Some(read_values(r)?),
+                },
+            });
+        }
+        Ok(StateDelta {
+            from_time,
+            to_time,
+            quantization,
+            bodies,
+        })
+    }
+}
+
+impl Simulation {
+    fn quantized_position(
+        &self,
+        id: i32,
+        quantization: &DeltaQuantization,
+    ) -> std::result::Result<[i64; 3], SimulationError> {
+        let body = self
+            .get_body_by_id(id)
+            .ok_or(SimulationError::UnknownBodyId(id))?;
+        let anchor = match body.parent {
+            None => quantization.anchor.clone(),
+            Some(_) => DecimalVector3d::zero(),
+        };
+        let v = &body.relative_position;
+        Ok([
+            quantize(&v.x, &anchor.x, &quantization.position)?,
+            quantize(&v.y, &anchor.y, &quantization.position)?,
+            quantize(&v.z, &anchor.z, &quantization.position)?,
+        ])
+    }
+
+    fn quantized_velocity(
+        &self,
+        id: i32,
+        quantization: &DeltaQuantization,
+    ) -> std::result::Result<[i64; 3], SimulationError> {
+        let v = &self
+            .get_body_by_id(id)
+            .ok_or(SimulationError::UnknownBodyId(id))?
+            .velocity;
+        Ok([
+            quantize(&v.x, &DBig::ZERO, &quantization.velocity)?,
+            quantize(&v.y, &DBig::ZERO, &quantization.velocity)?,
+            quantize(&v.z, &DBig::ZERO, &quantization.velocity)?,
+        ])
+    }
+
+    fn quantized_orientation(
+        &self,
+        id: i32,
+        quantization: &DeltaQuantization,
+    ) -> std::result::Result<[i64; 9], SimulationError> {
+        let data = &self
+            .get_body_by_id(id)
+            .ok_or(SimulationError::UnknownBodyId(id))?
+            .orientation
+            .data;
+        let mut values = [0; 9];
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = quantize(&data[i / 3][i % 3], &DBig::ZERO, &quantization.orientation)?;
+        }
+        Ok(values)
+    }
+
+    /// the changes from this state to `next`, both holding the same bodies; bodies coming and
+    /// going need a full snapshot
+    ///
+    /// # Errors
+    ///
+    /// `InvalidArgument` if the quanta aren't positive, `InvalidState` if a body is only in one of
+    /// the states or a value doesn't fit the quanta.
+    pub fn delta_to(
+        &self,
+        next: &Simulation,
+        quantization: &DeltaQuantization,
+    ) -> std::result::Result<StateDelta, SimulationError> {
+        check_quantization(quantization)?;
+        for (a, b) in [(self, next), (next, self)] {
+            if let Some(body) = a
+                .bodies
+                .iter()
+                .find(|body| b.get_body_by_id(body.id).is_none())
+            {
+                return Err(SimulationError::InvalidState(format!(
+                    "{}: only on one side of the delta",
+                    body.body.name
+                )));
+            }
+        }
+        let mut bodies = vec![];
+        for body in &self.bodies {
+            let id = body.id;
+            let delta = BodyDelta {
+                id,
+                position: differences(
+                    self.quantized_position(id, quantization)?,
+                    next.quantized_position(id, quantization)?,
+                )?,
+                velocity: differences(
+                    self.quantized_velocity(id, quantization)?,
+                    next.quantized_velocity(id, quantization)?,
+                )?,
+                orientation: differences(
+                    self.quantized_orientation(id, quantization)?,
+                    next.quantized_orientation(id, quantization)?,
+                )?,
+            };
+            if delta.position.is_some() || delta.velocity.is_some() || delta.orientation.is_some() {
+                bodies.push(delta);
+            }
+        }
+        Ok(StateDelta {
+            from_time: self.time.clone(),
+            to_time: next.time.clone(),
+            quantization: quantization.clone(),
+            bodies,
+        })
+    }
+
+    /// brings a state at the start of the delta to its end, on the quantized values
+    ///
+    /// # Errors
+    ///
+    /// `InvalidState` if the simulation isn't at the start of the delta, a body is missing or a
+    /// value overflows the quanta, and `InvalidArgument` if the quanta aren't positive. Nothing is
+    /// changed then.
+    pub fn apply_delta(&mut self, delta: &StateDelta) -> std::result::Result<(), SimulationError> {
+        self.shift_by_delta(delta, &delta.from_time, &delta.to_time, 1)
+    }
+
+    /// undoes `apply_delta`, from the end of the delta back to its start
+    ///
+    /// # Errors
+    ///
+    /// The same as `apply_delta`, with the simulation at the end of the delta.
+    pub fn rollback_delta(
+        &mut self,
+        delta: &StateDelta,
+    ) -> std::result::Result<(), SimulationError> {
+        self.shift_by_delta(delta, &delta.to_time, &delta.from_time, -1)
+    }
+
+    // everything is worked out before the first body changes, so a failing delta leaves the
+    // state as it was
+    fn shift_by_delta(
+        &mut self,
+        delta: &StateDelta,
+        from: &DBig,
+        to: &DBig,
+        sign: i64,
+    ) -> std::result::Result<(), SimulationError> {
+        if self.time != *from {
+            return Err(SimulationError::InvalidState(format!(
+                "the delta goes from {} but the simulation is at {}",
+                from, self.time
+            )));
+        }
+        let quantization = &delta.quantization;
+        check_quantization(quantization)?;
+        let mut changes = vec![];
+        for body in &delta.bodies {
+            let id = body.id;
+            let parent = self
+                .get_body_by_id(id)
+                .ok_or(SimulationError::UnknownBodyId(id))?
+                .parent;
+            let position = match &body.position {
+                None => None,
+                Some(difference) => {
+                    let current = self.quantized_position(id, quantization)?;
+                    let anchor = match parent {
+                        None => quantization.anchor.clone(),
+                        Some(_) => DecimalVector3d::zero(),
+                    };
+                    let [x, y, z] = shift(current, difference, sign)?;
+                    Some(DecimalVector3d::new(
+                        dequantize(x, &anchor.x, &quantization.position),
+                        dequantize(y, &anchor.y, &quantization.position),
+                        dequantize(z, &anchor.z, &quantization.position),
+                    ))
+                }
+            };
+            let velocity = match &body.velocity {
+                None => None,
+                Some(difference) => {
+                    let current = self.quantized_velocity(id, quantization)?;
+                    let [x, y, z] = shift(current, difference, sign)?
+                        .map(|v| dequantize(v, &DBig::ZERO, &quantization.velocity));
+                    Some(DecimalVector3d::new(x, y, z))
+                }
+            };
+            let orientation = match &body.orientation {
+                None => None,
+                Some(difference) => {
+                    let current = self.quantized_orientation(id, quantization)?;
+                    let values = shift(current, difference, sign)?
+                        .map(|v| dequantize(v, &DBig::ZERO, &quantization.orientation));
+                    Some(DecimalMatrix3d {
+                        data: std::array::from_fn(|row| {
+                            std::array::from_fn(|column| values[row * 3 + column].clone())
+                        }),
+                    })
+                }
+            };
+            changes.push((id, position, velocity, orientation));
+        }
+        for (id, position, velocity, orientation) in changes {
+            let Some(target) = self.get_mut_body_by_id(id) else {
+                continue;
+            };
+            if let Some(position) = position {
+                target.relative_position = position;
+            }
+            if let Some(velocity) = velocity {
+                target.velocity = velocity;
+            }
+            if let Some(orientation) = orientation {
+                target.orientation = orientation;
+            }
+            target.last_update = Some(to.clone());
+        }
+        // world positions follow the changed parents, composed from the relative ones
+        if self.position_storage == PositionStorage::World {
+            let world: Vec<DecimalVector3d> = self
+                .bodies
+                .iter()
+                .map(|body| {
+                    let mut position = body.relative_position.clone();
+                    for parent in self.resolve_hierarchy_up(body) {
+                        position = position + &parent.relative_position;
+                    }
+                    position
+                })
+                .collect();
+            for (body, position) in self.bodies.iter_mut().zip(world) {
+                body.position = position;
+            }
+        }
+        self.time.clone_from(to);
+        self.rebuild_index();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::delta::{BodyDelta, DeltaQuantization, StateDelta};
+    use crate::error::SimulationError;
+    use crate::simulation::Simulation;
+    use crate::sin_cos::f64_to_dbig;
+    use crate::tests::prepare_sim;
+    use dashu_float::DBig;
+
+    fn states() -> (Simulation, Simulation, DeltaQuantization) {
+        let mut before = prepare_sim();
+        before.update(&DBig::ZERO);
+        let mut after = prepare_sim();
+        after.update(&DBig::from(60));
+        let sun = before.get_body("sun").unwrap();
+        let quantization = DeltaQuantization {
+            anchor: before.world_position(sun),
+            position: f64_to_dbig(0.001),
+            velocity: f64_to_dbig(0.000_001),
+            orientation: f64_to_dbig(0.000_000_001),
+        };
+        (before, after, quantization)
+    }
+
+    fn close(a: &Simulation, b: &Simulation) {
+        for name in ["moon", "earth", "sun"] {
+            let (x, y) = (a.get_body(name).unwrap(), b.get_body(name).unwrap());
+            assert!(a.world_position(x).distance_to(&b.world_position(y)) < f64_to_dbig(0.01));
+            assert!((&x.velocity - &y.velocity).length() < f64_to_dbig(0.00001));
+        }
+    }
+
+    #[test]
+    fn state_delta_works() {
+        let (before, after, quantization) = states();
+        let delta = before.delta_to(&after, &quantization).unwrap();
+        // the sun stays put, only its rotation goes along
+        let sun = before.get_body("sun").unwrap().id;
+        let sun = delta.bodies.iter().find(|body| body.id == sun).unwrap();
+        assert!(sun.position.is_none() && sun.velocity.is_none());
+
+        let mut buf: Vec<u8> = vec![];
+        delta.write(&mut buf).unwrap();
+        let mut snapshot: Vec<u8> = vec![];
+        after.write_snapshot(&mut snapshot).unwrap();
+        assert!(buf.len() * 4 < snapshot.len());
+        let delta = StateDelta::read(&mut buf.as_slice()).unwrap();
+
+        let mut client = prepare_sim();
+        client.update(&DBig::ZERO);
+        client.apply_delta(&delta).unwrap();
+        assert_eq!(client.time(), &DBig::from(60));
+        close(&client, &after);
+        client.rollback_delta(&delta).unwrap();
+        assert_eq!(client.time(), &DBig::ZERO);
+        close(&client, &before);
+    }
+
+    #[test]
+    fn mismatched_states_are_rejected() {
+        let (before, mut after, quantization) = states();
+        let delta = before.delta_to(&after, &quantization).unwrap();
+        // the delta starts at 0, not at 60
+        assert_eq!(
+            after.apply_delta(&delta),
+            Err(SimulationError::InvalidState(String::from(
+                "the delta goes from 0 but the simulation is at 60"
+            )))
+        );
+        // bodies have to match on both sides
+        after.bodies.retain(|body| body.body.name != "moon");
+        assert_eq!(
+            before.delta_to(&after, &quantization).unwrap_err(),
+            SimulationError::InvalidState(String::from("moon: only on one side of the delta"))
+        );
+    }
+
+    #[test]
+    fn quanta_have_to_be_positive() {
+        let (mut before, after, quantization) = states();
+        let invalid =
+            SimulationError::InvalidArgument(String::from("the delta quanta have to be positive"));
+        for zero in 0..3 {
+            let mut broken = quantization.clone();
+            match zero {
+                0 => broken.position = DBig::ZERO,
+                1 => broken.velocity = -DBig::ONE,
+                _ => broken.orientation = DBig::ZERO,
+            }
+            assert_eq!(before.delta_to(&after, &broken).unwrap_err(), invalid);
+            let delta = StateDelta {
+                from_time: DBig::ZERO,
+                to_time: DBig::from(60),
+                quantization: broken,
+                bodies: vec![],
+            };
+            assert_eq!(before.apply_delta(&delta), Err(invalid.clone()));
+            let mut buf: Vec<u8> = vec![];
+            delta.write(&mut buf).unwrap();
+            let error = StateDelta::read(&mut buf.as_slice()).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn overflowing_quanta_are_errors() {
+        let (mut before, after, mut quantization) = states();
+        // an AU in femtometers doesn't fit an i64
+        quantization.position = f64_to_dbig(1e-15);
+        assert!(matches!(
+            before.delta_to(&after, &quantization),
+            Err(SimulationError::InvalidState(_))
+        ));
+
+        quantization.position = f64_to_dbig(0.001);
+        let moon = before.get_body("moon").unwrap().id;
+        let earth = before.get_body("earth").unwrap().id;
+        let shifted = BodyDelta {
+            id: earth,
+            position: Some([1, 0, 0]),
+            velocity: None,
+            orientation: None,
+        };
+        let overflowing = BodyDelta {
+            id: moon,
+            position: Some([i64::MAX, 0, 0]),
+            velocity: None,
+            orientation: None,
+        };
+        let delta = StateDelta {
+            from_time: DBig::ZERO,
+            to_time: DBig::from(60),
+            quantization,
+            bodies: vec![shifted, overflowing],
+        };
+        let earth_before = before.get_body("earth").unwrap().relative_position.clone();
+        assert_eq!(
+            before.apply_delta(&delta),
+            Err(SimulationError::InvalidState(String::from(
+                "the delta overflows the quanta"
+            )))
+        );
+        // nothing moved, not even the bodies before the failing one
+        let earth_after = &before.get_body("earth").unwrap().relative_position;
+        assert_eq!(earth_after.distance_to(&earth_before), DBig::ZERO);
+        assert_eq!(before.time(), &DBig::ZERO);
+    }
+
+    #[test]
+    fn overflowing_differences_are_errors() {
+        let (mut before, mut after, mut quantization) = states();
+        // each side fits an i64 on its own, the jump between them doesn't
+        quantization.position = f64_to_dbig(5e-11);
+        for (sim, x) in [(&mut before, 3e8), (&mut after, -3e8)] {
+            for body in &mut sim.bodies {
+                body.relative_position = match body.body.name.as_str() {
+                    "moon" => DecimalVector3d::from_f64(x, 0.0, 0.0),
+                    "earth" => DecimalVector3d::zero(),
+                    _ => continue,
+                };
+            }
+        }
+        assert_eq!(
+            before.delta_to(&after, &quantization).unwrap_err(),
+            SimulationError::InvalidArgument(String::from("the delta overflows the quanta"))
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use crate::body::BodyDynamics;
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::simulation::{SimulatedBody, Simulation, G_CONSTANT};
+use crate::vis_viva::vis_viva_speed;
+use dashu_float::ops::{Abs, SquareRoot};
+use dashu_float::DBig;
+
+const PRECISION: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct CircularOrbit {
+    pub parent: String,
+    pub radius: DBig, // in meters, from the parent center
+    pub plane_normal: DecimalVector3d,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Burn {
+    Departure,   // onto the transfer orbit, around the shared parent
+    PlaneChange, // extra cost of turning the plane, combined with the burn at the larger radius
+    Arrival,     // circularization at the target, around the shared parent
+    Escape,      // from the starting orbit onto a hyperbola around the starting parent
+    Capture,     // from the arrival hyperbola into the target orbit
+}
+
+#[derive(Debug, Clone)]
+pub struct DeltaVBudget {
+    pub items: Vec<(Burn, DBig)>, // in m/s, in the order they are performed
+    pub total: DBig,
+}
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+fn mu(body: &SimulatedBody, time: &DBig) -> DBig {
+    &*G_CONSTANT * lift(&body.body.mass_at(time))
+}
+
+fn circular_speed(mu: &DBig, radius: &DBig) -> DBig {
+    vis_viva_speed(mu, radius, radius)
+}
+
+// circular orbit to a hyperbola with the given excess speed, or back, at the periapsis
+fn hyperbolic_burn(mu: &DBig, radius: &DBig, excess_speed: &DBig) -> DBig {
+    let radius = lift(radius);
+    (excess_speed * excess_speed + DBig::from(2) * mu / &radius).sqrt() - (mu / &radius).sqrt()
+}
+
+// (speed change at r1, speed change at r2) of a Hohmann transfer, signed by the direction
+fn hohmann(mu: &DBig, r1: &DBig, r2: &DBig) -> (DBig, DBig) {
+    let semi_major_axis = (lift(r1) + lift(r2)) / DBig::from(2);
+    (
+        vis_viva_speed(mu, r1, &semi_major_axis) - circular_speed(mu, r1),
+        circular_speed(mu, r2) - vis_viva_speed(mu, r2, &semi_major_axis),
+    )
+}
+
+// the orbits of the bodies themselves are taken as circles at their orbit radius
+fn orbit_radius(body: &SimulatedBody) -> Result<&DBig, SimulationError> {
+    match &body.body.dynamics {
+        BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics) => {
+            Ok(&dynamics.orbit_radius)
+        }
+        BodyDynamics::Static(_) | BodyDynamics::Formation(_) => {
+            Err(SimulationError::InvalidDynamics(format!(
+                "{}: the body is not orbiting",
+                body.body.name
+            )))
+        }
+    }
+}
+
+impl Simulation {
+    /// impulsive estimate between circular orbits, the orbits can be around the same body, a body
+    /// and one of its satellites or two satellites of the same body; None for other combinations.
+    /// Planes are only matched for orbits around the same body, a hyperbola can leave in any plane
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if a parent isn't in the simulation, `InvalidDynamics` if an orbit radius is
+    /// needed from a body that doesn't orbit.
+    pub fn delta_v_budget(
+        &self,
+        from: &CircularOrbit,
+        to: &CircularOrbit,
+    ) -> Result<Option<DeltaVBudget>, SimulationError> {
+        let from_parent = self.get_body(&from.parent)?;
+        let to_parent = self.get_body(&to.parent)?;
+
+        let mut items: Vec<(Burn, DBig)> = vec![];
+        if from_parent.id() == to_parent.id() {
+            let parent_mu = mu(from_parent, &self.time);
+            let (departure, arrival) = hohmann(&parent_mu, &from.radius, &to.radius);
+            let departure = departure.abs();
+            let arrival = arrival.abs();
+
+            // turning at the larger radius is cheaper, where the transfer orbit is slowest
+            let (outer_radius, outer_burn) = if from.radius > to.radius {
+                (&from.radius, &departure)
+            } else {
+                (&to.radius, &arrival)
+            };
+            let semi_major_axis = (lift(&from.radius) + lift(&to.radius)) / DBig::from(2);
+            let transfer_speed = vis_viva_speed(&parent_mu, outer_radius, &semi_major_axis);
+            let circular = circular_speed(&parent_mu, outer_radius);
+            let cos_angle = from
+                .plane_normal
+                .normalized()
+                .dot(&to.plane_normal.normalized());
+            let combined = (&transfer_speed * &transfer_speed + &circular * &circular
+                - DBig::from(2) * &transfer_speed * &circular * cos_angle)
+                .sqrt();
+            let plane_change = combined - outer_burn;
+
+            items.push((Burn::Departure, departure));
+            items.push((Burn::PlaneChange, plane_change));
+            items.push((Burn::Arrival, arrival));
+        } else if to_parent.parent() == Some(from_parent.id()) {
+            // down into a satellite's sphere of influence
+            let parent_mu = mu(from_parent, &self.time);
+            let (departure, arrival) = hohmann(&parent_mu, &from.radius, orbit_radius(to_parent)?);
+            items.push((Burn::Departure, departure.abs()));
+            items.push((
+                Burn::Capture,
+                hyperbolic_burn(&mu(to_parent, &self.time), &to.radius, &arrival.abs()),
+            ));
+        } else if from_parent.parent() == Some(to_parent.id()) {
+            // up out of a satellite's sphere of influence
+            let parent_mu = mu(to_parent, &self.time);
+            let (departure, arrival) = hohmann(&parent_mu, orbit_radius(from_parent)?, &to.radius);
+            items.push((
+                Burn::Escape,
+                hyperbolic_burn(&mu(from_parent, &self.time), &from.radius, &departure.abs()),
+            ));
+            items.push((Burn::Arrival, arrival.abs()));
+        } else if let Some(shared) = from_parent
+            .parent()
+            .filter(|shared| to_parent.parent() == Some(*shared))
+        {
+            let shared_parent = self
+                .get_body_by_id(shared)
+                .ok_or(SimulationError::UnknownBodyId(shared))?;
+            let (departure, arrival) = hohmann(
+                &mu(shared_parent, &self.time),
+                orbit_radius(from_parent)?,
+                orbit_radius(to_parent)?,
+            );
+            items.push((
+                Burn::Escape,
+                hyperbolic_burn(&mu(from_parent, &self.time), &from.radius, &departure.abs()),
+            ));
+            items.push((
+                Burn::Capture,
+                hyperbolic_burn(&mu(to_parent, &self.time), &to.radius, &arrival.abs()),
+            ));
+        } else {
+            return Ok(None);
+        }
+
+        let mut total = DBig::ZERO;
+        for (_, delta_v) in &items {
+            total += delta_v;
+        }
+        Ok(Some(DeltaVBudget { items, total }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::au::au_to_meters;
+    use crate::body::{BodyDynamics, StaticBodyDynamics};
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::delta_v::{Burn, CircularOrbit};
+    use crate::error::SimulationError;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::prepare_sim;
+    use dashu_float::DBig;
+
+    #[test]
+    fn delta_v_budget_works() {
+        let sim = prepare_sim();
+        let equatorial = DecimalVector3d::from_f64(0.0, 1.0, 0.0);
+        let leo = CircularOrbit {
+            parent: String::from("earth"),
+            radius: DBig::from(6_671_000),
+            plane_normal: equatorial.clone(),
+        };
+        let geo = CircularOrbit {
+            parent: String::from("earth"),
+            radius: DBig::from(42_164_000),
+            plane_normal: equatorial.clone(),
+        };
+
+        // textbook LEO to GEO Hohmann transfer is about 2.43 + 1.46 km/s
+        let budget = sim.delta_v_budget(&leo, &geo).unwrap().unwrap();
+        assert_eq!(budget.items[0].0, Burn::Departure);
+        assert!(approx_eq(
+            &budget.items[0].1,
+            &f64_to_dbig(2430.0),
+            &f64_to_dbig(20.0)
+        ));
+        assert!(approx_eq(
+            &budget.items[1].1,
+            &DBig::ZERO,
+            &f64_to_dbig(1e-6)
+        ));
+        assert!(approx_eq(
+            &budget.total,
+            &f64_to_dbig(3890.0),
+            &f64_to_dbig(30.0)
+        ));
+
+        // turning the plane by 28.5 degrees at GEO costs about 0.37 km/s over the coplanar transfer
+        let inclined = CircularOrbit {
+            plane_normal: DecimalVector3d::from_f64(
+                0.0,
+                28.5f64.to_radians().cos(),
+                28.5f64.to_radians().sin(),
+            ),
+            ..leo.clone()
+        };
+        let budget = sim.delta_v_budget(&inclined, &geo).unwrap().unwrap();
+        assert_eq!(budget.items[1].0, Burn::PlaneChange);
+        assert!(approx_eq(
+            &budget.items[1].1,
+            &f64_to_dbig(370.0),
+            &f64_to_dbig(30.0)
+        ));
+
+        // trans-lunar injection and lunar orbit insertion, about 3.1 + 0.8 km/s
+        let low_lunar = CircularOrbit {
+            parent: String::from("moon"),
+            radius: DBig::from(1_837_400),
+            plane_normal: equatorial,
+        };
+        let budget = sim.delta_v_budget(&leo, &low_lunar).unwrap().unwrap();
+        assert_eq!(budget.items[1].0, Burn::Capture);
+        assert!(approx_eq(
+            &budget.items[0].1,
+            &f64_to_dbig(3100.0),
+            &f64_to_dbig(50.0)
+        ));
+        assert!(approx_eq(
+            &budget.items[1].1,
+            &f64_to_dbig(800.0),
+            &f64_to_dbig(100.0)
+        ));
+        let back = sim.delta_v_budget(&low_lunar, &leo).unwrap().unwrap();
+        assert_eq!(back.items[0].0, Burn::Escape);
+        assert!(approx_eq(&back.total, &budget.total, &f64_to_dbig(1e-6)));
+
+        let sun_orbit = CircularOrbit {
+            parent: String::from("sun"),
+            radius: au_to_meters(f64_to_dbig(1.5)),
+            plane_normal: DecimalVector3d::from_f64(0.0, 1.0, 0.0),
+        };
+        assert!(sim
+            .delta_v_budget(&low_lunar, &sun_orbit)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn bodies_that_are_not_orbiting_are_errors() {
+        let mut sim = prepare_sim();
+        sim.get_body_mut("moon").unwrap().dynamics = BodyDynamics::Static(StaticBodyDynamics {
+            position: DecimalVector3d::from_f64(384_400_000.0, 0.0, 0.0),
+        });
+        let equatorial = DecimalVector3d::from_f64(0.0, 1.0, 0.0);
+        let leo = CircularOrbit {
+            parent: String::from("earth"),
+            radius: DBig::from(6_671_000),
+            plane_normal: equatorial.clone(),
+        };
+        let low_lunar = CircularOrbit {
+            parent: String::from("moon"),
+            radius: DBig::from(1_837_400),
+            plane_normal: equatorial,
+        };
+        let not_orbiting =
+            SimulationError::InvalidDynamics(String::from("moon: the body is not orbiting"));
+        assert_eq!(
+            sim.delta_v_budget(&leo, &low_lunar).unwrap_err(),
+            not_orbiting
+        );
+        assert_eq!(
+            sim.delta_v_budget(&low_lunar, &leo).unwrap_err(),
+            not_orbiting
+        );
+    }
+}
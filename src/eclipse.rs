@@ -0,0 +1,282 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::simulation::{SimulatedBody, Simulation};
+use dashu_float::DBig;
+
+const PRECISION: usize = 40;
+const REFINE_ITERATIONS: usize = 32;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+fn lift_vector(v: &DecimalVector3d) -> DecimalVector3d {
+    DecimalVector3d::new(lift(&v.x), lift(&v.y), lift(&v.z))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shadow {
+    Lit,
+    Penumbra, // part of the light is covered
+    Umbra,    // all of the light is covered
+    Antumbra, // past the tip of the umbra, the occluder is inside the light disc as in annular eclipses
+}
+
+// the shadow `body` enters at `time`, coming from `previous`
+#[derive(Debug, Clone)]
+pub struct EclipseEvent {
+    pub time: DBig,
+    pub previous: Shadow,
+    pub shadow: Shadow,
+}
+
+// cones touching the light and the occluder spheres, along the axis from the light through the
+// occluder center; the umbra narrows behind the occluder and the penumbra widens
+fn shadow_of(
+    point: &DecimalVector3d,
+    occluder: &DecimalVector3d,
+    occluder_radius: &DBig,
+    light: &DecimalVector3d,
+    light_radius: &DBig,
+) -> Shadow {
+    let axis = occluder - light;
+    let separation = axis.length();
+    let axis = axis / &separation;
+    let offset = point - occluder;
+    let behind = offset.dot(&axis);
+    if behind <= DBig::ZERO {
+        return Shadow::Lit;
+    }
+    let off_axis = (&offset - &axis * &behind).length();
+    let penumbra = occluder_radius + &behind * (light_radius + occluder_radius) / &separation;
+    if off_axis >= penumbra {
+        return Shadow::Lit;
+    }
+    let umbra = occluder_radius - &behind * (light_radius - occluder_radius) / &separation;
+    if umbra > DBig::ZERO && off_axis < umbra {
+        Shadow::Umbra
+    } else if umbra < DBig::ZERO && off_axis < -umbra {
+        Shadow::Antumbra
+    } else {
+        Shadow::Penumbra
+    }
+}
+
+impl Simulation {
+    /// of `occluder_name` at the world point, lit by `light_name`; both are taken as spheres of
+    /// their radius at the time of the last update
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the occluder or the light isn't in the simulation, `InvalidDynamics` if
+    /// they are the same body.
+    pub fn shadow_at(
+        &self,
+        point: &DecimalVector3d,
+        occluder_name: &str,
+        light_name: &str,
+    ) -> Result<Shadow, SimulationError> {
+        let occluder = self.get_body(occluder_name)?;
+        let light = self.get_body(light_name)?;
+        if occluder.id() == light.id() {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{occluder_name}: can't shadow its own light"
+            )));
+        }
+        Ok(self.shadow_between(&lift_vector(point), occluder, light))
+    }
+
+    /// the shadow at the center of `body_name`, see `shadow_at`
+    ///
+    /// # Errors
+    ///
+    /// The errors of `shadow_at`, and `InvalidDynamics` if the body is the occluder or the light.
+    pub fn body_shadow(
+        &self,
+        body_name: &str,
+        occluder_name: &str,
+        light_name: &str,
+    ) -> Result<Shadow, SimulationError> {
+        let body = self.get_body(body_name)?;
+        if [occluder_name, light_name].contains(&body_name) {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{body_name}: can't be in its own shadow or light"
+            )));
+        }
+        self.shadow_at(&self.world_position(body), occluder_name, light_name)
+    }
+
+    /// every change of the shadow at the center of `body_name` between `start` and `end`, found on
+    /// a copy of the simulation by sampling every `step` and bisecting; the step has to be well
+    /// below the shortest eclipse phase or it can be missed
+    ///
+    /// # Errors
+    ///
+    /// The errors of `body_shadow`, and `InvalidState` if one of the bodies is sleeping.
+    pub fn eclipse_events(
+        &self,
+        body_name: &str,
+        occluder_name: &str,
+        light_name: &str,
+        start: &DBig,
+        end: &DBig,
+        step: &DBig,
+    ) -> Result<Vec<EclipseEvent>, SimulationError> {
+        for name in [body_name, occluder_name, light_name] {
+            self.get_awake_body(name)?;
+        }
+        self.body_shadow(body_name, occluder_name, light_name)?;
+        let mut sim = self.copy_bodies();
+        let mut shadow_at = |time: &DBig| {
+            sim.update(time);
+            sim.body_shadow(body_name, occluder_name, light_name)
+        };
+
+        let mut result: Vec<EclipseEvent> = vec![];
+        let mut time = lift(start);
+        let end = lift(end);
+        let step = lift(step);
+        let mut current = shadow_at(&time)?;
+        while time < end {
+            let next_time = (&time + &step).min(end.clone());
+            let next = shadow_at(&next_time)?;
+            // a step can pass through more than one boundary, like penumbra into umbra
+            while current != next {
+                let (mut low, mut high) = (time.clone(), next_time.clone());
+                for _ in 0..REFINE_ITERATIONS {
+                    let middle = (&low + &high) / DBig::from(2);
+                    if shadow_at(&middle)? == current {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                let shadow = shadow_at(&high)?;
+                result.push(EclipseEvent {
+                    time: high.clone(),
+                    previous: current,
+                    shadow,
+                });
+                time = high;
+                current = shadow;
+            }
+            time = next_time;
+        }
+        Ok(result)
+    }
+
+    fn shadow_between(
+        &self,
+        point: &DecimalVector3d,
+        occluder: &SimulatedBody,
+        light: &SimulatedBody,
+    ) -> Shadow {
+        shadow_of(
+            point,
+            &self.world_position(occluder),
+            &lift(&occluder.body.radius),
+            &self.world_position(light),
+            &lift(&light.body.radius),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::BodyDynamics;
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::eclipse::Shadow;
+    use crate::error::SimulationError;
+    use crate::sin_cos::f64_to_dbig;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn shadow_at_point_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let earth = sim.world_position(sim.get_body("earth").unwrap());
+        let sun = sim.world_position(sim.get_body("sun").unwrap());
+        let axis = (&earth - &sun).normalized();
+        let side = axis
+            .cross(&DecimalVector3d::from_f64(0.0, 1.0, 0.0))
+            .normalized();
+        let at = |behind: f64, aside: f64| {
+            &earth + &axis * f64_to_dbig(behind) + &side * f64_to_dbig(aside)
+        };
+        let shadow = |point: &DecimalVector3d| sim.shadow_at(point, "earth", "sun").unwrap();
+        // the umbra of the earth is about 1.4 million km long, narrowing from the earth radius
+        assert_eq!(shadow(&at(-1e8, 0.0)), Shadow::Lit);
+        assert_eq!(shadow(&at(1e8, 0.0)), Shadow::Umbra);
+        assert_eq!(shadow(&at(1e8, 6.4e6)), Shadow::Penumbra);
+        assert_eq!(shadow(&at(1e8, 7e6)), Shadow::Lit);
+        assert_eq!(shadow(&at(2e9, 0.0)), Shadow::Antumbra);
+        assert_eq!(
+            sim.shadow_at(&earth, "sun", "sun").unwrap_err(),
+            SimulationError::InvalidDynamics(String::from("sun: can't shadow its own light"))
+        );
+        assert!(matches!(
+            sim.body_shadow("moon", "earth", "moon"),
+            Err(SimulationError::InvalidDynamics(_))
+        ));
+    }
+
+    #[test]
+    fn lunar_eclipse_events_work() {
+        let mut sim = prepare_sim();
+        // with the moon going around in the plane of the earth orbit there is a lunar eclipse
+        // every synodic month, it starts in one and the next one is a little over 29 days later
+        for name in ["earth", "moon"] {
+            if let BodyDynamics::Orbiting(dynamics) = &mut sim.get_body_mut(name).unwrap().dynamics
+            {
+                dynamics.orbit_plane_normal = DecimalVector3d::from_f64(0.0, 1.0, 0.0);
+            }
+        }
+        sim.update(&DBig::ZERO);
+        assert_eq!(
+            sim.body_shadow("moon", "earth", "sun").unwrap(),
+            Shadow::Umbra
+        );
+        let events = sim
+            .eclipse_events(
+                "moon",
+                "earth",
+                "sun",
+                &DBig::from(25 * 24 * 3600),
+                &DBig::from(30 * 24 * 3600),
+                &DBig::from(3000),
+            )
+            .unwrap();
+        let shadows: Vec<Shadow> = events.iter().map(|e| e.shadow).collect();
+        assert_eq!(
+            shadows,
+            vec![
+                Shadow::Penumbra,
+                Shadow::Umbra,
+                Shadow::Penumbra,
+                Shadow::Lit
+            ]
+        );
+        assert_eq!(events[0].previous, Shadow::Lit);
+        for pair in events.windows(2) {
+            assert!(pair[0].time < pair[1].time);
+            assert_eq!(pair[0].shadow, pair[1].previous);
+        }
+        // a few hours for the whole eclipse
+        let duration = dbig_to_f64(&(&events[3].time - &events[0].time));
+        assert!(duration > 3.0 * 3600.0 && duration < 6.0 * 3600.0);
+        // the search runs on a copy, the shadow holds on the boundaries
+        assert_eq!(sim.time(), &DBig::ZERO);
+        let mut probe = sim.copy_bodies();
+        probe.update(&(&events[1].time + DBig::from(60)));
+        assert_eq!(
+            probe.body_shadow("moon", "earth", "sun").unwrap(),
+            Shadow::Umbra
+        );
+        probe.update(&(&events[0].time - DBig::from(60)));
+        assert_eq!(
+            probe.body_shadow("moon", "earth", "sun").unwrap(),
+            Shadow::Lit
+        );
+    }
+}
@@ -0,0 +1,288 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::kepler::{eccentric_anomaly_from_true, mean_anomaly_from_eccentric};
+use crate::phase_angle::wrap_angle;
+use crate::simulation::{Simulation, G_CONSTANT};
+use crate::sin_cos::{acos, atan2, PI, PIMUL2};
+use dashu_float::DBig;
+
+const PRECISION: usize = 32;
+
+// relative to the ecliptic, the XZ plane with +Y north and longitudes counted from +X towards -Z,
+// angles in radians and lengths in meters
+#[derive(Debug, Clone)]
+pub struct OrbitalElements {
+    pub semi_major_axis: DBig,
+    pub eccentricity: DBig,
+    pub inclination: DBig,
+    pub ascending_node: DBig,
+    pub argument_of_periapsis: DBig, // 0 for circular orbits, the anomalies count from the node
+    pub mean_longitude: DBig,        // node + argument of periapsis + mean anomaly
+}
+
+#[derive(Debug, Clone)]
+pub struct MeanElements {
+    pub mean: OrbitalElements,
+    pub rates: OrbitalElements, // per second, from a least squares line through the samples
+    pub samples: usize,
+}
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+// into (-pi, pi]
+fn signed_angle(angle: DBig) -> DBig {
+    let angle = wrap_angle(angle);
+    if angle > *PI {
+        angle - &*PIMUL2
+    } else {
+        angle
+    }
+}
+
+fn elements_array(elements: &OrbitalElements) -> [DBig; 6] {
+    [
+        elements.semi_major_axis.clone(),
+        elements.eccentricity.clone(),
+        elements.inclination.clone(),
+        elements.ascending_node.clone(),
+        elements.argument_of_periapsis.clone(),
+        elements.mean_longitude.clone(),
+    ]
+}
+
+fn elements_from_array(values: [DBig; 6]) -> OrbitalElements {
+    let [semi_major_axis, eccentricity, inclination, ascending_node, argument_of_periapsis, mean_longitude] =
+        values;
+    OrbitalElements {
+        semi_major_axis,
+        eccentricity,
+        inclination,
+        ascending_node,
+        argument_of_periapsis,
+        mean_longitude,
+    }
+}
+
+impl Simulation {
+    /// elements of the conic through the current state around the parent, None for roots and
+    /// unbound states
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn osculating_elements(
+        &self,
+        body_name: &str,
+    ) -> Result<Option<OrbitalElements>, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let Some(parent) = body.parent() else {
+            return Ok(None);
+        };
+        let parent = self
+            .get_body_by_id(parent)
+            .ok_or(SimulationError::UnknownBodyId(parent))?;
+        let mu = &*G_CONSTANT * lift(&parent.body.mass_at(&self.time));
+        let position = self.world_position(body) - self.world_position(parent);
+        let velocity = &body.velocity;
+        let radius = lift(&position.length());
+        let speed_squared = lift(&velocity.length_squared());
+
+        let energy = &speed_squared / DBig::from(2) - &mu / &radius;
+        if energy >= DBig::ZERO {
+            return Ok(None);
+        }
+        let semi_major_axis = -&mu / (DBig::from(2) * energy);
+        let momentum = position.cross(velocity);
+        let normal = momentum.normalized();
+        let eccentricity_vector = velocity.cross(&momentum) / &mu - &position / &radius;
+        let eccentricity = lift(&eccentricity_vector.length());
+
+        let inclination = acos(normal.y.clone(), 32);
+        // north x normal, any direction in the plane works for orbits in the ecliptic
+        let mut node = DecimalVector3d::new(momentum.z.clone(), DBig::ZERO, -&momentum.x);
+        if node.length_squared() == DBig::ZERO {
+            node.x = lift(&DBig::ONE);
+        }
+        let node = node.normalized();
+        let ascending_node = wrap_angle(atan2(-&node.z, node.x.clone(), 32));
+
+        // angles in the plane along the motion, from the node
+        let in_plane = |v: &DecimalVector3d| atan2(normal.dot(&node.cross(v)), node.dot(v), 32);
+        let (argument_of_periapsis, true_anomaly) = if eccentricity == DBig::ZERO {
+            (DBig::ZERO, in_plane(&position))
+        } else {
+            let periapsis = in_plane(&eccentricity_vector);
+            (
+                wrap_angle(periapsis.clone()),
+                in_plane(&position) - periapsis,
+            )
+        };
+        let eccentric_anomaly = eccentric_anomaly_from_true(&true_anomaly, &eccentricity);
+        let mean_anomaly = mean_anomaly_from_eccentric(&eccentric_anomaly, &eccentricity);
+
+        Ok(Some(OrbitalElements {
+            semi_major_axis,
+            eccentricity,
+            inclination,
+            mean_longitude: wrap_angle(&ascending_node + &argument_of_periapsis + mean_anomaly),
+            ascending_node,
+            argument_of_periapsis,
+        }))
+    }
+
+    /// osculating elements sampled every `step` from `start` to `end` on a copy of the simulation,
+    /// averaged, with angles unwrapped so the rates show precession and the mean motion; None if
+    /// any sample has no elements
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn mean_elements(
+        &self,
+        body_name: &str,
+        start: &DBig,
+        end: &DBig,
+        step: &DBig,
+    ) -> Result<Option<MeanElements>, SimulationError> {
+        self.get_body(body_name)?;
+        let mut sim = self.copy_bodies();
+
+        let mut times: Vec<DBig> = vec![];
+        let mut series: Vec<[DBig; 6]> = vec![];
+        let mut time = lift(start);
+        let end = lift(end);
+        let step = lift(step);
+        while time <= end {
+            sim.update(&time);
+            let Some(elements) = sim.osculating_elements(body_name)? else {
+                return Ok(None);
+            };
+            let mut values = elements_array(&elements);
+            if let Some(previous) = series.last() {
+                for i in 3..6 {
+                    values[i] = &previous[i] + signed_angle(&values[i] - &previous[i]);
+                }
+            }
+            series.push(values);
+            times.push(time.clone());
+            time += &step;
+        }
+
+        let count = DBig::from(series.len());
+        let mean_time = times.iter().fold(DBig::ZERO, |sum, t| sum + t) / &count;
+        let mut mean: [DBig; 6] = Default::default();
+        let mut rates: [DBig; 6] = Default::default();
+        for i in 0..6 {
+            let mean_value = series.iter().fold(DBig::ZERO, |sum, v| sum + &v[i]) / &count;
+            let mut covariance = DBig::ZERO;
+            let mut variance = DBig::ZERO;
+            for (t, v) in times.iter().zip(&series) {
+                let dt = t - &mean_time;
+                covariance += &dt * (&v[i] - &mean_value);
+                variance += &dt * &dt;
+            }
+            if variance > DBig::ZERO {
+                rates[i] = covariance / variance;
+            }
+            mean[i] = if i >= 3 {
+                wrap_angle(mean_value)
+            } else {
+                mean_value
+            };
+        }
+        Ok(Some(MeanElements {
+            mean: elements_from_array(mean),
+            rates: elements_from_array(rates),
+            samples: series.len(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{tilted_axis, BodyDynamics, SecularDrift};
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn mean_elements_work() {
+        let mut sim = prepare_sim();
+        let mu = 6.67408e-11 * 5.97219e24;
+        let radius = 384_400_000.0_f64;
+        let period = 2.0 * std::f64::consts::PI * (radius.powi(3) / mu).sqrt();
+        let inclination = 5f64.to_radians();
+        let regression = 1e-9;
+        // tilted towards -Z, orbits stay circles around the parent only for normals across X
+        let normal = tilted_axis(
+            &DecimalVector3d::from_f64(0.0, 1.0, 0.0),
+            &f64_to_dbig(inclination),
+            &f64_to_dbig(std::f64::consts::FRAC_PI_2),
+        );
+        if let BodyDynamics::Orbiting(dynamics) = &mut sim.get_body_mut("moon").unwrap().dynamics {
+            dynamics.orbit_plane_normal = normal;
+            dynamics.orbit_period = f64_to_dbig(period);
+        }
+        sim.update(&DBig::from(1000));
+        let elements = sim.osculating_elements("moon").unwrap().unwrap();
+        assert!(approx_eq(
+            &elements.semi_major_axis,
+            &f64_to_dbig(radius),
+            &f64_to_dbig(radius * 1e-5)
+        ));
+        assert!(dbig_to_f64(&elements.eccentricity) < 1e-4);
+        assert!(approx_eq(
+            &elements.inclination,
+            &f64_to_dbig(inclination),
+            &f64_to_dbig(1e-6)
+        ));
+        // the plane leans towards -Z, so the node is on -X
+        let node = dbig_to_f64(&elements.ascending_node);
+        assert!((node - std::f64::consts::PI).abs() < 1e-6);
+        assert!(sim.osculating_elements("sun").unwrap().is_none());
+
+        // the regression adds a little speed and wobbles the osculating plane every orbit, averaging
+        // over a few orbits recovers it
+        if let BodyDynamics::Orbiting(dynamics) = &mut sim.get_body_mut("moon").unwrap().dynamics {
+            dynamics.drift = Some(SecularDrift {
+                nodal_regression: f64_to_dbig(regression),
+                apsidal_precession: DBig::ZERO,
+                radius_rate: DBig::ZERO,
+            });
+        }
+
+        let mean = sim
+            .mean_elements(
+                "moon",
+                &DBig::ZERO,
+                &DBig::from(108 * 24 * 3600),
+                &DBig::from(24 * 3600),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(mean.samples, 109);
+        assert!(approx_eq(
+            &mean.mean.semi_major_axis,
+            &f64_to_dbig(radius),
+            &f64_to_dbig(radius * 1e-3)
+        ));
+        assert!(approx_eq(
+            &mean.mean.inclination,
+            &f64_to_dbig(inclination),
+            &f64_to_dbig(1e-4)
+        ));
+        let node_rate = dbig_to_f64(&mean.rates.ascending_node);
+        assert!((node_rate - regression).abs() < 5e-11);
+        let longitude_rate = dbig_to_f64(&mean.rates.mean_longitude);
+        let expected = 2.0 * std::f64::consts::PI / period + regression;
+        assert!((longitude_rate - expected).abs() / expected < 1e-4);
+        assert!(approx_eq(
+            &mean.rates.semi_major_axis,
+            &DBig::ZERO,
+            &f64_to_dbig(1e-2)
+        ));
+    }
+}
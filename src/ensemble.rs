@@ -0,0 +1,237 @@
+use crate::body::{Body, BodyDynamics};
+use crate::error::SimulationError;
+use crate::simulation::Simulation;
+use crate::sin_cos::f64_to_dbig;
+use dashu_float::ops::SquareRoot;
+use dashu_float::DBig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PerturbedParameter {
+    Mass,
+    Radius,
+    RotationPeriod,
+    OrbitRadius,    // only for orbiting bodies
+    OrbitPeriod,    // only for orbiting bodies
+    StaticPosition, // only for static bodies, each axis is perturbed independently
+}
+
+#[derive(Debug, Clone)]
+pub struct Perturbation {
+    pub body_name: String,
+    pub parameter: PerturbedParameter,
+    pub sigma: DBig, // standard deviation, in the unit of the parameter
+}
+
+#[derive(Debug, Clone)]
+pub struct EnsembleConfig {
+    pub runs: usize,
+    pub seed: u64,
+    pub perturbations: Vec<Perturbation>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Dispersion {
+    pub mean: DBig,
+    pub standard_deviation: DBig,
+    pub min: DBig,
+    pub max: DBig,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnsembleReport {
+    pub samples: Vec<Vec<DBig>>, // per run, outputs in the order the run closure returns them
+    pub dispersion: Vec<Dispersion>, // per output
+}
+
+// splitmix64, enough for sampling and reproducible across platforms
+struct Random {
+    state: u64,
+}
+
+impl Random {
+    fn new(seed: u64) -> Random {
+        Random { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    #[allow(clippy::cast_precision_loss)] // 53 bits, exact in an f64
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Box-Muller
+    fn next_gaussian(&mut self) -> f64 {
+        let u1 = 1.0 - self.next_f64();
+        let u2 = self.next_f64();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+}
+
+// the scalars a parameter consists of, empty if it doesn't apply to the body's dynamics
+pub(crate) fn parameter_values_mut(
+    body: &mut Body,
+    parameter: PerturbedParameter,
+) -> Vec<&mut DBig> {
+    match (parameter, &mut body.dynamics) {
+        (PerturbedParameter::Mass, _) => vec![&mut body.mass],
+        (PerturbedParameter::Radius, _) => vec![&mut body.radius],
+        (PerturbedParameter::RotationPeriod, _) => vec![&mut body.rotation_period],
+        (
+            PerturbedParameter::OrbitRadius,
+            BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics),
+        ) => {
+            vec![&mut dynamics.orbit_radius]
+        }
+        (
+            PerturbedParameter::OrbitPeriod,
+            BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics),
+        ) => {
+            vec![&mut dynamics.orbit_period]
+        }
+        (PerturbedParameter::StaticPosition, BodyDynamics::Static(dynamics)) => vec![
+            &mut dynamics.position.x,
+            &mut dynamics.position.y,
+            &mut dynamics.position.z,
+        ],
+        _ => vec![],
+    }
+}
+
+fn perturb(
+    sim: &mut Simulation,
+    perturbation: &Perturbation,
+    random: &mut Random,
+) -> Result<(), SimulationError> {
+    let body = sim.get_body_mut(&perturbation.body_name)?;
+    for value in parameter_values_mut(body, perturbation.parameter) {
+        *value += &perturbation.sigma * f64_to_dbig(random.next_gaussian());
+    }
+    Ok(())
+}
+
+fn dispersion(values: &[DBig]) -> Dispersion {
+    let count = DBig::from(values.len());
+    let mut sum = DBig::ZERO;
+    let mut min = values[0].clone();
+    let mut max = values[0].clone();
+    for v in values {
+        sum += v;
+        min = min.min(v.clone());
+        max = max.max(v.clone());
+    }
+    let mean = sum / &count;
+    let mut variance = DBig::ZERO;
+    for v in values {
+        let difference = v - &mean;
+        variance += &difference * &difference;
+    }
+    variance /= &count;
+    Dispersion {
+        mean,
+        standard_deviation: variance.sqrt(),
+        min,
+        max,
+    }
+}
+
+impl Simulation {
+    /// every run gets a perturbed copy of this simulation, `run` advances it and returns the outputs
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if a perturbation names a body that isn't in the simulation.
+    ///
+    /// # Panics
+    ///
+    /// If `run` panics for any member.
+    pub fn run_ensemble<F>(
+        &self,
+        config: &EnsembleConfig,
+        run: F,
+    ) -> Result<EnsembleReport, SimulationError>
+    where
+        F: Fn(&mut Simulation) -> Vec<DBig> + Sync,
+    {
+        // members are prepared up front so results don't depend on the thread count
+        let mut random = Random::new(config.seed);
+        let mut members: Vec<Simulation> = vec![];
+        for _ in 0..config.runs {
+            let mut member = self.copy_bodies();
+            for perturbation in &config.perturbations {
+                perturb(&mut member, perturbation, &mut random)?;
+            }
+            members.push(member);
+        }
+
+        let threads = std::thread::available_parallelism().map_or(1, std::num::NonZero::get);
+        let chunk_size = config.runs.div_ceil(threads).max(1);
+        let run = &run;
+        let samples: Vec<Vec<DBig>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = members
+                .chunks_mut(chunk_size)
+                .map(|chunk| scope.spawn(move || chunk.iter_mut().map(run).collect::<Vec<_>>()))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+
+        let outputs = samples.first().map_or(0, std::vec::Vec::len);
+        let dispersion = (0..outputs)
+            .map(|i| {
+                let values: Vec<DBig> = samples.iter().map(|sample| sample[i].clone()).collect();
+                dispersion(&values)
+            })
+            .collect();
+        Ok(EnsembleReport {
+            samples,
+            dispersion,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ensemble::{EnsembleConfig, Perturbation, PerturbedParameter};
+    use crate::simulation::Simulation;
+    use crate::sin_cos::f64_to_dbig;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn ensemble_works() {
+        let sim = prepare_sim();
+        let config = EnsembleConfig {
+            runs: 8,
+            seed: 42,
+            perturbations: vec![Perturbation {
+                body_name: String::from("moon"),
+                parameter: PerturbedParameter::OrbitRadius,
+                sigma: DBig::from(1000),
+            }],
+        };
+        let run = |sim: &mut Simulation| {
+            sim.update(&f64_to_dbig(123_123.0));
+            vec![sim.relative_position("earth", "moon").unwrap().length()]
+        };
+
+        let report = sim.run_ensemble(&config, run).unwrap();
+        assert_eq!(report.samples.len(), 8);
+        let dispersion = &report.dispersion[0];
+        assert!(dbig_to_f64(&dispersion.standard_deviation) > 100.0);
+        assert!(dbig_to_f64(&dispersion.standard_deviation) < 5000.0);
+        assert!(dispersion.min <= dispersion.mean && dispersion.mean <= dispersion.max);
+
+        // same seed, same samples
+        let again = sim.run_ensemble(&config, run).unwrap();
+        assert_eq!(again.samples[3][0], report.samples[3][0]);
+    }
+}
@@ -0,0 +1,256 @@
+use crate::decimal_matrix_3d::DecimalMatrix3d;
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::simulation::{Simulation, G_CONSTANT};
+use crate::sin_cos::{acos, atan2, cos, sin, PIMUL2};
+use crate::surface::geographic_coordinates;
+use crate::vis_viva::vis_viva_speed;
+use dashu_float::ops::{Abs, SquareRoot};
+use dashu_float::DBig;
+
+const PRECISION: usize = 40;
+
+#[derive(Debug, Clone)]
+pub struct SurfacePass {
+    pub time: DBig,              // simulation time
+    pub latitude: DBig, // in radians, of the ground point below, with the rotation until then
+    pub longitude: DBig, // in radians
+    pub flight_path_angle: DBig, // in radians, negative below the local horizon
+    pub speed: DBig,    // in m/s, inertial, relative to the body center
+}
+
+#[derive(Debug, Clone)]
+pub struct EntryEstimate {
+    pub interface: SurfacePass,
+    // where the trajectory reaches the surface without drag, None if the periapsis is above it.
+    // There is no atmosphere model, drag brings the real landing point back uprange of this
+    pub impact: Option<SurfacePass>,
+}
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+// keplerian conic from a state relative to the attractor
+struct Conic {
+    mu: DBig,
+    eccentricity: DBig,
+    semi_latus_rectum: DBig,
+    semi_major_axis: DBig,      // negative for hyperbolas
+    periapsis: DecimalVector3d, // unit vectors of the orbit plane
+    quarter: DecimalVector3d,
+    true_anomaly: DBig, // of the initial state
+}
+
+impl Conic {
+    // mean anomaly, in the hyperbolic sense for open orbits
+    fn mean_anomaly(&self, true_anomaly: &DBig) -> DBig {
+        let e = &self.eccentricity;
+        let half = true_anomaly / DBig::from(2);
+        if *e < DBig::ONE {
+            let eccentric = DBig::from(2)
+                * atan2(
+                    (DBig::ONE - e).sqrt() * sin(half.clone(), 32),
+                    (DBig::ONE + e).sqrt() * cos(half, 32),
+                    32,
+                );
+            &eccentric - e * sin(eccentric.clone(), 32)
+        } else {
+            let x =
+                ((e - DBig::ONE) / (e + DBig::ONE)).sqrt() * sin(half.clone(), 32) / cos(half, 32);
+            let hyperbolic = ((DBig::ONE + &x) / (DBig::ONE - x)).ln();
+            let sinh = (hyperbolic.exp() - (-&hyperbolic).exp()) / DBig::from(2);
+            e * sinh - hyperbolic
+        }
+    }
+
+    // time from the initial state until the true anomaly is reached, the next pass for ellipses
+    // and None for hyperbolas that are already past it
+    fn time_until(&self, true_anomaly: &DBig) -> Option<DBig> {
+        let a = self.semi_major_axis.clone().abs();
+        let mean_motion = (&self.mu / (&a * &a * &a)).sqrt();
+        let mut time = (self.mean_anomaly(true_anomaly) - self.mean_anomaly(&self.true_anomaly))
+            / &mean_motion;
+        if time < DBig::ZERO {
+            if self.eccentricity >= DBig::ONE {
+                return None;
+            }
+            time += &*PIMUL2 / mean_motion;
+        }
+        Some(time)
+    }
+
+    // true anomaly of the inbound crossing of the radius, None if the orbit doesn't reach it
+    fn inbound_anomaly(&self, radius: &DBig) -> Option<DBig> {
+        let cos_anomaly = (&self.semi_latus_rectum / radius - DBig::ONE) / &self.eccentricity;
+        if cos_anomaly.clone().abs() > DBig::ONE {
+            return None;
+        }
+        Some(-acos(cos_anomaly, 32))
+    }
+}
+
+impl Simulation {
+    /// entry interface at `interface_altitude` above the radius of `body_name` for a free-falling
+    /// state in world coordinates, along the keplerian conic around that body; None if the state
+    /// is already inside the interface or never reaches it
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn entry_estimate(
+        &self,
+        body_name: &str,
+        position: &DecimalVector3d,
+        velocity: &DecimalVector3d,
+        interface_altitude: &DBig,
+    ) -> Result<Option<EntryEstimate>, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let mu = &*G_CONSTANT * lift(&body.body.mass_at(&self.time));
+        let relative_position = position - self.world_position(body);
+        let relative_velocity = velocity - self.world_velocity(body);
+        let radius = lift(&relative_position.length());
+        let surface_radius = lift(&body.body.radius);
+        let interface_radius = &surface_radius + lift(interface_altitude);
+        if radius <= interface_radius {
+            return Ok(None);
+        }
+
+        let momentum = relative_position.cross(&relative_velocity);
+        let eccentricity_vector =
+            relative_velocity.cross(&momentum) / &mu - &relative_position / &radius;
+        let eccentricity = lift(&eccentricity_vector.length());
+        if eccentricity == DBig::ZERO {
+            return Ok(None);
+        }
+        let periapsis = &eccentricity_vector / &eccentricity;
+        let quarter = momentum.normalized().cross(&periapsis);
+        let semi_latus_rectum = lift(&momentum.length_squared()) / &mu;
+        let conic = Conic {
+            semi_major_axis: &semi_latus_rectum / (DBig::ONE - &eccentricity * &eccentricity),
+            true_anomaly: atan2(
+                relative_position.dot(&quarter),
+                relative_position.dot(&periapsis),
+                32,
+            ),
+            mu,
+            eccentricity,
+            semi_latus_rectum,
+            periapsis,
+            quarter,
+        };
+
+        let axis = body.body.rotation_axis.normalized();
+        let spin = &*PIMUL2 / lift(&body.body.spin_period());
+        let pass = |radius: &DBig| -> Option<SurfacePass> {
+            let true_anomaly = conic.inbound_anomaly(radius)?;
+            let time = conic.time_until(&true_anomaly)?;
+            let (sin_anomaly, cos_anomaly) =
+                (sin(true_anomaly.clone(), 32), cos(true_anomaly.clone(), 32));
+            let direction = &conic.periapsis * &cos_anomaly + &conic.quarter * &sin_anomaly;
+            // the body keeps turning until then, undone to read the coordinates now
+            let direction = DecimalMatrix3d::axis_angle(&axis, -(&spin * &time)).apply(&direction);
+            let (latitude, longitude) = geographic_coordinates(body, &direction);
+            Some(SurfacePass {
+                time: &self.time + time,
+                latitude,
+                longitude,
+                flight_path_angle: atan2(
+                    &conic.eccentricity * sin_anomaly,
+                    DBig::ONE + &conic.eccentricity * cos_anomaly,
+                    32,
+                ),
+                speed: vis_viva_speed(&conic.mu, radius, &conic.semi_major_axis),
+            })
+        };
+
+        let Some(interface) = pass(&interface_radius) else {
+            return Ok(None);
+        };
+        Ok(Some(EntryEstimate {
+            interface,
+            impact: pass(&surface_radius),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::prepare_sim;
+    use dashu_float::DBig;
+
+    #[test]
+    fn entry_estimate_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let earth = sim.get_body("earth").unwrap();
+        let mu = 6.67408e-11 * 5.97219e24;
+        let start = 7_000_000.0_f64;
+        // apoapsis here, moving east with a periapsis well below the surface
+        let speed = 0.9 * (mu / start).sqrt();
+        let position = sim.world_position(earth) + DecimalVector3d::from_f64(start, 0.0, 0.0);
+        let velocity = sim.world_velocity(earth) + DecimalVector3d::from_f64(0.0, 0.0, -speed);
+        let estimate = sim
+            .entry_estimate("earth", &position, &velocity, &DBig::from(120_000))
+            .unwrap()
+            .unwrap();
+
+        // plain integration of the same fall in the equatorial plane
+        let interface = 6_371_000.0 + 120_000.0;
+        let (mut p, mut v, mut t) = ([start, 0.0], [0.0, -speed], 0.0);
+        let dt = 0.01;
+        while (p[0] * p[0] + p[1] * p[1]).sqrt() > interface {
+            let r3 = (p[0] * p[0] + p[1] * p[1]).powf(1.5);
+            v = [v[0] - mu * p[0] / r3 * dt, v[1] - mu * p[1] / r3 * dt];
+            p = [p[0] + v[0] * dt, p[1] + v[1] * dt];
+            t += dt;
+        }
+        let interface_pass = &estimate.interface;
+        assert!(approx_eq(&interface_pass.time, &f64_to_dbig(t), &DBig::ONE));
+        let expected_speed = (v[0] * v[0] + v[1] * v[1]).sqrt();
+        assert!(approx_eq(
+            &interface_pass.speed,
+            &f64_to_dbig(expected_speed),
+            &DBig::ONE
+        ));
+        let radial = (p[0] * v[0] + p[1] * v[1]) / (p[0] * p[0] + p[1] * p[1]).sqrt();
+        let expected_angle = (radial / expected_speed).asin();
+        assert!(approx_eq(
+            &interface_pass.flight_path_angle,
+            &f64_to_dbig(expected_angle),
+            &f64_to_dbig(1e-3)
+        ));
+
+        // longitudes grow towards -Z, the ground turned east in the meantime
+        assert!(approx_eq(
+            &interface_pass.latitude,
+            &DBig::ZERO,
+            &f64_to_dbig(1e-6)
+        ));
+        let spin = 2.0 * std::f64::consts::PI / (24.0 * 3600.0);
+        let expected_longitude = (-p[1]).atan2(p[0]) - spin * t;
+        assert!(approx_eq(
+            &interface_pass.longitude,
+            &f64_to_dbig(expected_longitude),
+            &f64_to_dbig(1e-3)
+        ));
+
+        let impact = estimate.impact.unwrap();
+        assert!(impact.time > interface_pass.time);
+        assert!(impact.flight_path_angle < interface_pass.flight_path_angle);
+
+        // the same state inside the interface, and an orbit that stays above it
+        assert!(sim
+            .entry_estimate("earth", &position, &velocity, &DBig::from(1_000_000))
+            .unwrap()
+            .is_none());
+        let orbit = sim.world_velocity(earth)
+            + DecimalVector3d::from_f64(0.0, 0.0, -(mu / start).sqrt() * 1.01);
+        assert!(sim
+            .entry_estimate("earth", &position, &orbit, &DBig::from(120_000))
+            .unwrap()
+            .is_none());
+    }
+}
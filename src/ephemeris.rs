@@ -0,0 +1,233 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::simulation::Simulation;
+use dashu_float::ops::SquareRoot;
+use dashu_float::DBig;
+use std::io::{self, Error, ErrorKind};
+use std::str::FromStr;
+
+const PRECISION: usize = 40;
+const DAY_SECONDS: u32 = 86400;
+const KILOMETER: u32 = 1000;
+
+// position of a body relative to the center of the ephemeris, in world coordinates and meters
+#[derive(Debug, Clone)]
+pub struct EphemerisSample {
+    pub time: DBig, // simulation time in seconds
+    pub position: DecimalVector3d,
+}
+
+#[derive(Debug, Clone)]
+pub struct EphemerisError {
+    pub time: DBig,
+    pub offset: DecimalVector3d, // model minus reference
+    pub distance: DBig,
+}
+
+#[derive(Debug, Clone)]
+pub struct EphemerisReport {
+    pub errors: Vec<EphemerisError>, // one per sample, in the sample order
+    pub rms: DBig,                   // in meters
+    pub max: DBig,                   // in meters
+    pub max_time: DBig,
+    pub drift: DBig, // in m/s, least-squares slope of the error distance over time
+}
+
+fn invalid_data(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+fn parse_number(value: &str) -> io::Result<DBig> {
+    let number = DBig::from_str(&value.trim().to_lowercase().replace("e+", "e"))
+        .map_err(|_| invalid_data(format!("not a number: {}", value.trim())))?;
+    Ok(lift(&number))
+}
+
+/// rows of a JPL Horizons vector table exported as CSV, between $$SOE and $$EOE with JDTDB first
+/// and X, Y, Z in km after the calendar date. Times become seconds from `epoch_jd` and the ecliptic
+/// axes are turned into world ones, +Y north with -Z as the second ecliptic axis
+///
+/// # Errors
+///
+/// `InvalidData` if the markers are missing or a row doesn't have the columns or numbers.
+pub fn parse_horizons_vectors(source: &str, epoch_jd: &DBig) -> io::Result<Vec<EphemerisSample>> {
+    let mut result: Vec<EphemerisSample> = vec![];
+    let mut in_table = false;
+    for line in source.lines() {
+        let line = line.trim();
+        match line {
+            "$$SOE" => in_table = true,
+            "$$EOE" => return Ok(result),
+            _ if in_table && !line.is_empty() => {
+                let fields: Vec<&str> = line.split(',').collect();
+                if fields.len() < 5 {
+                    return Err(invalid_data(format!("too few columns: {line}")));
+                }
+                let days = parse_number(fields[0])? - lift(epoch_jd);
+                let [x, y, z] = [2, 3, 4].map(|i| parse_number(fields[i]));
+                let kilometer = DBig::from(KILOMETER);
+                result.push(EphemerisSample {
+                    time: days * DBig::from(DAY_SECONDS),
+                    position: DecimalVector3d::new(
+                        x? * &kilometer,
+                        z? * &kilometer,
+                        -y? * kilometer,
+                    ),
+                });
+            }
+            _ => (),
+        }
+    }
+    if in_table {
+        Err(invalid_data(String::from("missing $$EOE")))
+    } else {
+        Err(invalid_data(String::from("missing $$SOE")))
+    }
+}
+
+impl Simulation {
+    /// position of `body_name` relative to `center_name` from the model at every sample time,
+    /// evaluated on a copy of the simulation, against the reference
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if either body isn't in the simulation, `InvalidArgument` if the reference is
+    /// empty.
+    pub fn compare_ephemeris(
+        &self,
+        body_name: &str,
+        center_name: &str,
+        reference: &[EphemerisSample],
+    ) -> Result<EphemerisReport, SimulationError> {
+        if reference.is_empty() {
+            return Err(SimulationError::InvalidArgument(String::from(
+                "the reference has no samples",
+            )));
+        }
+        self.get_body(body_name)?;
+        self.get_body(center_name)?;
+        let mut sim = self.copy_bodies();
+
+        let mut errors: Vec<EphemerisError> = vec![];
+        for sample in reference {
+            sim.update(&sample.time);
+            let offset = sim.relative_position(center_name, body_name)? - &sample.position;
+            errors.push(EphemerisError {
+                time: sample.time.clone(),
+                distance: lift(&offset.length()),
+                offset,
+            });
+        }
+
+        let count = DBig::from(errors.len());
+        let mut sum_squared = DBig::ZERO;
+        let mut worst = &errors[0];
+        for error in &errors {
+            sum_squared += &error.distance * &error.distance;
+            if error.distance > worst.distance {
+                worst = error;
+            }
+        }
+        let mean_time = errors.iter().fold(DBig::ZERO, |sum, e| sum + &e.time) / &count;
+        let mean_distance = errors.iter().fold(DBig::ZERO, |sum, e| sum + &e.distance) / &count;
+        let mut covariance = DBig::ZERO;
+        let mut variance = DBig::ZERO;
+        for error in &errors {
+            let dt = &error.time - &mean_time;
+            covariance += &dt * (&error.distance - &mean_distance);
+            variance += &dt * &dt;
+        }
+        let drift = if variance > DBig::ZERO {
+            covariance / variance
+        } else {
+            DBig::ZERO
+        };
+
+        Ok(EphemerisReport {
+            rms: (sum_squared / count).sqrt(),
+            max: worst.distance.clone(),
+            max_time: worst.time.clone(),
+            drift,
+            errors,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::ephemeris::{parse_horizons_vectors, EphemerisSample};
+    use crate::error::SimulationError;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::prepare_sim;
+    use dashu_float::DBig;
+
+    #[test]
+    fn ephemeris_comparison_works() {
+        let table = "
+*******************************************************************************
+$$SOE
+2451545.000000000, A.D. 2000-Jan-01 12:00:00.0000, -2.649903367743050E+07,  1.327574173547081E+08, -5.755671847054509E+03,
+2451546.000000000, A.D. 2000-Jan-02 12:00:00.0000, -2.947233728162655E+07,  1.321986864460094E+08, -5.687908587068319E+03,
+$$EOE
+*******************************************************************************
+";
+        let samples = parse_horizons_vectors(table, &DBig::from(2_451_545)).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[1].time, DBig::from(86400));
+        // ecliptic y is world -z, in meters
+        assert!(approx_eq(
+            &samples[0].position.x,
+            &f64_to_dbig(-2.649_903_367_743_05e10),
+            &f64_to_dbig(1e-3)
+        ));
+        assert!(approx_eq(
+            &samples[0].position.z,
+            &f64_to_dbig(-1.327_574_173_547_081e11),
+            &f64_to_dbig(1e-3)
+        ));
+        assert!(approx_eq(
+            &samples[0].position.y,
+            &f64_to_dbig(-5.755_671_847_054_509e6),
+            &f64_to_dbig(1e-3)
+        ));
+        assert!(parse_horizons_vectors("$$SOE\n2451545.0, date\n$$EOE", &DBig::ZERO).is_err());
+        assert!(parse_horizons_vectors("no table", &DBig::ZERO).is_err());
+
+        // a reference drifting away from the model at 1 m/s along x
+        let sim = prepare_sim();
+        let mut copy = prepare_sim();
+        let mut reference: Vec<EphemerisSample> = vec![];
+        for day in 0..4 {
+            let time = DBig::from(day * 86400);
+            copy.update(&time);
+            let position = copy.relative_position("sun", "earth").unwrap()
+                - DecimalVector3d::new(time.clone(), DBig::ZERO, DBig::ZERO);
+            reference.push(EphemerisSample { time, position });
+        }
+        let report = sim.compare_ephemeris("earth", "sun", &reference).unwrap();
+        assert_eq!(report.errors.len(), 4);
+        assert!(approx_eq(
+            &report.max,
+            &f64_to_dbig(3.0 * 86400.0),
+            &f64_to_dbig(1e-3)
+        ));
+        assert_eq!(report.max_time, DBig::from(3 * 86400));
+        assert!(approx_eq(&report.drift, &DBig::ONE, &f64_to_dbig(1e-9)));
+        let rms = 86400.0 * ((1.0 + 4.0 + 9.0) / 4.0f64).sqrt();
+        assert!(approx_eq(
+            &report.rms,
+            &f64_to_dbig(rms),
+            &f64_to_dbig(1e-3)
+        ));
+        assert!(sim.compare_ephemeris("pluto", "sun", &reference).is_err());
+        assert_eq!(
+            sim.compare_ephemeris("earth", "sun", &[]).unwrap_err(),
+            SimulationError::InvalidArgument(String::from("the reference has no samples"))
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use dashu_float::DBig;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimulationError {
+    UnknownBody(String),
+    UnknownBodyId(i32),
+    UnknownSpacecraft(String),
+    UnknownTrigger(String),
+    UnknownBookmark(String),
+    MissingParent(i32),      // id given as the parent of a new hierarchy
+    InvalidDynamics(String), // body name and what is wrong with its definition
+    InvalidState(String),    // what the simulation isn't ready for, like a rollback without history
+    InvalidArgument(String), // a parameter out of its range, like a step that isn't positive
+    Parse(String),           // the text that failed to parse
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimulationError::UnknownBody(name) => write!(f, "unknown body {name}"),
+            SimulationError::UnknownBodyId(id) => write!(f, "unknown body id {id}"),
+            SimulationError::UnknownSpacecraft(name) => write!(f, "unknown spacecraft {name}"),
+            SimulationError::UnknownTrigger(name) => write!(f, "unknown trigger {name}"),
+            SimulationError::UnknownBookmark(name) => write!(f, "unknown bookmark {name}"),
+            SimulationError::MissingParent(id) => write!(f, "parent body {id} doesn't exist"),
+            SimulationError::InvalidDynamics(reason)
+            | SimulationError::InvalidState(reason)
+            | SimulationError::InvalidArgument(reason) => write!(f, "{reason}"),
+            SimulationError::Parse(text) => write!(f, "can't parse {text:?} as a number"),
+        }
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+// for steps, tolerances and the like, `what` names the parameter in the error
+pub(crate) fn check_positive(value: &DBig, what: &str) -> Result<(), SimulationError> {
+    if *value <= DBig::ZERO {
+        return Err(SimulationError::InvalidArgument(format!(
+            "the {what} has to be positive"
+        )));
+    }
+    Ok(())
+}
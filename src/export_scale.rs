@@ -0,0 +1,130 @@
+use crate::simulation::{SimulatedBody, Simulation};
+use crate::sin_cos::dbig_to_f64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DistanceScale {
+    Linear(f64),      // multiplies every distance
+    Logarithmic(f64), // d -> k * ln(1 + d / k), distances well below k are kept as they are
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExportScale {
+    pub distance: DistanceScale,
+    pub radius_factor: f64,
+}
+
+impl ExportScale {
+    pub fn scale_distance(&self, distance: f64) -> f64 {
+        match self.distance {
+            DistanceScale::Linear(factor) => distance * factor,
+            DistanceScale::Logarithmic(knee) => knee * (distance / knee).ln_1p(),
+        }
+    }
+
+    pub fn scale_radius(&self, radius: f64) -> f64 {
+        radius * self.radius_factor
+    }
+
+    // keeps the direction, only the length changes
+    fn scale_offset(&self, offset: [f64; 3]) -> [f64; 3] {
+        let length = (offset[0] * offset[0] + offset[1] * offset[1] + offset[2] * offset[2]).sqrt();
+        if length == 0.0 {
+            return offset;
+        }
+        let factor = self.scale_distance(length) / length;
+        [offset[0] * factor, offset[1] * factor, offset[2] * factor]
+    }
+}
+
+impl Simulation {
+    // only used by exports, the simulation itself always runs at true scale
+    pub fn set_export_scale(&mut self, scale: Option<ExportScale>) {
+        self.export_scale = scale;
+    }
+
+    pub fn export_scale(&self) -> Option<ExportScale> {
+        self.export_scale
+    }
+
+    // world position with the export scale applied to every offset from the parent, so satellites
+    // stay around their parents and directions are preserved at every level of the hierarchy
+    pub fn scaled_world_position(&self, body: &SimulatedBody) -> [f64; 3] {
+        let world_position = self.world_position(body);
+        let Some(scale) = &self.export_scale else {
+            return [
+                dbig_to_f64(&world_position.x),
+                dbig_to_f64(&world_position.y),
+                dbig_to_f64(&world_position.z),
+            ];
+        };
+        let (offset, base) = match body.parent().and_then(|parent| self.get_body_by_id(parent)) {
+            None => (world_position, [0.0, 0.0, 0.0]),
+            Some(parent) => (
+                world_position - self.world_position(parent),
+                self.scaled_world_position(parent),
+            ),
+        };
+        let offset = scale.scale_offset([
+            dbig_to_f64(&offset.x),
+            dbig_to_f64(&offset.y),
+            dbig_to_f64(&offset.z),
+        ]);
+        [
+            base[0] + offset[0],
+            base[1] + offset[1],
+            base[2] + offset[2],
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export_scale::{DistanceScale, ExportScale};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn export_scale_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::from(3600));
+        let earth_offset = sim.world_position(sim.get_body("moon").unwrap())
+            - sim.world_position(sim.get_body("earth").unwrap());
+        let true_distance = dbig_to_f64(&earth_offset.length());
+
+        let knee = 1e8;
+        sim.set_export_scale(Some(ExportScale {
+            distance: DistanceScale::Logarithmic(knee),
+            radius_factor: 100.0,
+        }));
+        let moon = sim.scaled_world_position(sim.get_body("moon").unwrap());
+        let earth = sim.scaled_world_position(sim.get_body("earth").unwrap());
+        let offset = [moon[0] - earth[0], moon[1] - earth[1], moon[2] - earth[2]];
+        let distance =
+            (offset[0] * offset[0] + offset[1] * offset[1] + offset[2] * offset[2]).sqrt();
+        assert!((distance - knee * (true_distance / knee).ln_1p()).abs() < 1.0);
+        // same direction as the true offset
+        assert!((offset[0] / distance - dbig_to_f64(&earth_offset.x) / true_distance).abs() < 1e-9);
+        assert!((offset[2] / distance - dbig_to_f64(&earth_offset.z) / true_distance).abs() < 1e-9);
+
+        let mut buf: Vec<u8> = vec![];
+        sim.write_czml(
+            &mut buf,
+            "scaled",
+            "2000-01-01T12:00:00Z",
+            &DBig::ZERO,
+            &DBig::ZERO,
+            &DBig::ONE,
+        )
+        .unwrap();
+        let czml = String::from_utf8(buf).unwrap();
+        assert!(czml.contains("[173740000,173740000,173740000]"));
+
+        sim.set_export_scale(None);
+        let moon = sim.get_body("moon").unwrap();
+        let world = sim.world_position(moon);
+        assert_eq!(
+            sim.scaled_world_position(moon).map(f64::to_bits),
+            [world.x, world.y, world.z].map(|v| dbig_to_f64(&v).to_bits())
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use crate::au::AU_METERS;
+use crate::decimal_matrix_3d::DecimalMatrix3d;
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::simulation::{SimulatedBody, Simulation};
+use dashu_float::DBig;
+use dashu_int::IBig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthUnit {
+    Meters,
+    Kilometers,
+    Au,
+}
+
+impl LengthUnit {
+    fn suffix(self) -> &'static str {
+        match self {
+            LengthUnit::Meters => "m",
+            LengthUnit::Kilometers => "km",
+            LengthUnit::Au => "AU",
+        }
+    }
+
+    fn convert(self, meters: &DBig) -> DBig {
+        match self {
+            LengthUnit::Meters => meters.clone(),
+            LengthUnit::Kilometers => meters / DBig::from(1000),
+            LengthUnit::Au => meters / &*AU_METERS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayOptions {
+    pub significant_digits: usize,
+    pub scientific: bool,
+    pub unit: Option<LengthUnit>, // values are taken as meters and converted, None prints as is
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        DisplayOptions {
+            significant_digits: 6,
+            scientific: false,
+            unit: None,
+        }
+    }
+}
+
+// rounded to the significant digits, trailing zeros are dropped
+fn format_number(v: &DBig, options: &DisplayOptions) -> String {
+    let rounded = v
+        .clone()
+        .with_precision(options.significant_digits.max(1))
+        .value();
+    let repr = rounded.repr();
+    if *repr.significand() == IBig::ZERO {
+        return String::from("0");
+    }
+    let sign = if *repr.significand() < IBig::ZERO {
+        "-"
+    } else {
+        ""
+    };
+    let mut digits = repr
+        .significand()
+        .to_string()
+        .trim_start_matches('-')
+        .to_string();
+    let mut exponent = repr.exponent();
+    while digits.len() > 1 && digits.ends_with('0') {
+        digits.pop();
+        exponent += 1;
+    }
+
+    // position of the decimal point counted from the first digit, a length always fits an isize
+    let length = digits.len().cast_signed();
+    let point = length + exponent;
+    if options.scientific {
+        let (first, rest) = digits.split_at(1);
+        let fraction = if rest.is_empty() {
+            String::new()
+        } else {
+            format!(".{rest}")
+        };
+        format!("{}{}{}e{}", sign, first, fraction, point - 1)
+    } else if point <= 0 {
+        format!("{}0.{}{}", sign, "0".repeat(point.unsigned_abs()), digits)
+    } else if point >= length {
+        format!(
+            "{}{}{}",
+            sign,
+            digits,
+            "0".repeat((point - length).unsigned_abs())
+        )
+    } else {
+        let (whole, fraction) = digits.split_at(point.unsigned_abs());
+        format!("{sign}{whole}.{fraction}")
+    }
+}
+
+fn format_length(v: &DBig, options: &DisplayOptions) -> String {
+    match options.unit {
+        None => format_number(v, options),
+        Some(unit) => format_number(&unit.convert(v), options),
+    }
+}
+
+fn format_components(v: &DecimalVector3d, options: &DisplayOptions) -> String {
+    format!(
+        "{{ x: {}, y: {}, z: {} }}",
+        format_length(&v.x, options),
+        format_length(&v.y, options),
+        format_length(&v.z, options)
+    )
+}
+
+// DBig is foreign, so its formatting goes through a trait
+pub trait DBigFormat {
+    fn format(&self, options: &DisplayOptions) -> String;
+}
+
+impl DBigFormat for DBig {
+    fn format(&self, options: &DisplayOptions) -> String {
+        match options.unit {
+            None => format_number(self, options),
+            Some(unit) => format!("{} {}", format_length(self, options), unit.suffix()),
+        }
+    }
+}
+
+impl DecimalVector3d {
+    pub fn format(&self, options: &DisplayOptions) -> String {
+        match options.unit {
+            None => format_components(self, options),
+            Some(unit) => format!("{} {}", format_components(self, options), unit.suffix()),
+        }
+    }
+}
+
+impl DecimalMatrix3d {
+    // matrices have no unit, it is ignored
+    pub fn format(&self, options: &DisplayOptions) -> String {
+        let rows: Vec<String> = self
+            .data
+            .iter()
+            .map(|row| {
+                let values: Vec<String> = row.iter().map(|v| format_number(v, options)).collect();
+                format!("[{}]", values.join(", "))
+            })
+            .collect();
+        format!("[{}]", rows.join(", "))
+    }
+}
+
+impl Simulation {
+    // one line per body state, for logs
+    pub fn format_body_state(&self, body: &SimulatedBody, options: &DisplayOptions) -> String {
+        let velocity_unit = match options.unit {
+            None => String::new(),
+            Some(unit) => format!(" {}/s", unit.suffix()),
+        };
+        format!(
+            "{} position {} velocity {}{} mass {} kg",
+            body.body.name,
+            self.world_position(body).format(options),
+            format_components(&body.velocity, options),
+            velocity_unit,
+            format_number(&body.body.mass_at(&self.time), options)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::au::au_to_meters;
+    use crate::decimal_matrix_3d::DecimalMatrix3d;
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::format::{DBigFormat, DisplayOptions, LengthUnit};
+    use crate::sin_cos::f64_to_dbig;
+    use crate::tests::prepare_sim;
+    use dashu_float::DBig;
+    use std::str::FromStr;
+
+    #[test]
+    fn display_format_works() {
+        let options = DisplayOptions {
+            significant_digits: 4,
+            ..DisplayOptions::default()
+        };
+        assert_eq!(
+            DBig::from_str("123456.789").unwrap().format(&options),
+            "123500"
+        );
+        assert_eq!(
+            DBig::from_str("-0.00012345").unwrap().format(&options),
+            "-0.0001235"
+        );
+        assert_eq!(DBig::from_str("2.5").unwrap().format(&options), "2.5");
+        assert_eq!(DBig::ZERO.format(&options), "0");
+
+        let scientific = DisplayOptions {
+            scientific: true,
+            ..options
+        };
+        assert_eq!(
+            DBig::from_str("123456.789").unwrap().format(&scientific),
+            "1.235e5"
+        );
+        assert_eq!(DBig::from_str("0.5").unwrap().format(&scientific), "5e-1");
+
+        let kilometers = DisplayOptions {
+            unit: Some(LengthUnit::Kilometers),
+            ..options
+        };
+        let v = DecimalVector3d::from_f64(384_400_000.0, -1500.0, 0.0);
+        assert_eq!(v.format(&kilometers), "{ x: 384400, y: -1.5, z: 0 } km");
+        let au = DisplayOptions {
+            unit: Some(LengthUnit::Au),
+            ..options
+        };
+        assert_eq!(au_to_meters(f64_to_dbig(1.5)).format(&au), "1.5 AU");
+
+        let mut sim = prepare_sim();
+        sim.update(&DBig::from(3600));
+        let state = sim.format_body_state(sim.get_body("moon").unwrap(), &kilometers);
+        assert!(state.starts_with("moon position { x: "));
+        assert!(state.contains(" km velocity { x: "));
+        assert!(state.ends_with(" km/s mass 73000000000000000000000 kg"));
+
+        let identity = DecimalMatrix3d::identity().format(&options);
+        assert_eq!(identity, "[[1, 0, 0], [0, 1, 0], [0, 0, 1]]");
+    }
+}
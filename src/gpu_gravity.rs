@@ -0,0 +1,402 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::particles::field_attractors;
+use crate::simulation::Simulation;
+use wgpu::util::DeviceExt;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+// Every value is a double-single, an f32 pair whose sum carries about 48 bits of mantissa, so
+// the flux stays close to the f64 path on adapters without f64 support. The products are split
+// Dekker style instead of relying on fma being fused, and every step of the error terms is kept
+// apart, or compilers allowed to reassociate simplify them to zero.
+const SHADER: &str = r"
+// hi in xyz and the gravitational parameter in w, the lo parts the same way
+struct Value {
+    hi: vec4<f32>,
+    lo: vec4<f32>,
+}
+
+@group(0) @binding(0) var<storage, read> attractors: array<Value>;
+@group(0) @binding(1) var<storage, read> points: array<Value>;
+@group(0) @binding(2) var<storage, read_write> flux: array<Value>;
+@group(0) @binding(3) var<uniform> zero: vec4<u32>;
+
+// x with its bits xored with zero from a uniform, which the compiler can't see through, so it
+// neither reorders nor cancels the operations around it
+fn keep(x: f32) -> f32 {
+    return bitcast<f32>(bitcast<u32>(x) ^ zero.x);
+}
+
+fn two_sum(a: f32, b: f32) -> vec2<f32> {
+    let s = keep(a + b);
+    let b_part = keep(s - a);
+    let a_part = keep(s - b_part);
+    return vec2<f32>(s, keep(keep(a - a_part) + keep(b - b_part)));
+}
+
+fn quick_two_sum(a: f32, b: f32) -> vec2<f32> {
+    let s = keep(a + b);
+    return vec2<f32>(s, keep(b - keep(s - a)));
+}
+
+fn split(a: f32) -> vec2<f32> {
+    let t = keep(4097.0 * a);
+    let hi = keep(t - keep(t - a));
+    return vec2<f32>(hi, keep(a - hi));
+}
+
+fn two_prod(a: f32, b: f32) -> vec2<f32> {
+    let p = keep(a * b);
+    let x = split(a);
+    let y = split(b);
+    let error = keep(keep(keep(x.x * y.x - p) + keep(x.x * y.y)) + keep(x.y * y.x));
+    return vec2<f32>(p, keep(error + keep(x.y * y.y)));
+}
+
+fn ds_add(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    let s = two_sum(a.x, b.x);
+    let t = two_sum(a.y, b.y);
+    let r = quick_two_sum(s.x, s.y + t.x);
+    return quick_two_sum(r.x, r.y + t.y);
+}
+
+fn ds_sub(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return ds_add(a, -b);
+}
+
+fn ds_mul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    let p = two_prod(a.x, b.x);
+    return quick_two_sum(p.x, p.y + (a.x * b.y + a.y * b.x));
+}
+
+fn ds_div(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    let q = a.x / b.x;
+    let r = ds_sub(a, ds_mul(b, vec2<f32>(q, 0.0)));
+    return quick_two_sum(q, r.x / b.x);
+}
+
+fn ds_sqrt(a: vec2<f32>) -> vec2<f32> {
+    if (a.x <= 0.0) {
+        return vec2<f32>(0.0, 0.0);
+    }
+    let x = sqrt(a.x);
+    let r = ds_sub(a, two_prod(x, x));
+    return quick_two_sum(x, r.x / (2.0 * x));
+}
+
+fn x(value: Value) -> vec2<f32> {
+    return vec2<f32>(value.hi.x, value.lo.x);
+}
+
+fn y(value: Value) -> vec2<f32> {
+    return vec2<f32>(value.hi.y, value.lo.y);
+}
+
+fn z(value: Value) -> vec2<f32> {
+    return vec2<f32>(value.hi.z, value.lo.z);
+}
+
+fn w(value: Value) -> vec2<f32> {
+    return vec2<f32>(value.hi.w, value.lo.w);
+}
+
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) groups: vec3<u32>,
+) {
+    // the dispatch is spread over y when there are more groups than one dimension takes
+    let i = id.x + id.y * groups.x * 64u;
+    if (i >= arrayLength(&points)) {
+        return;
+    }
+    let point = points[i];
+    var flux_x = vec2<f32>(0.0, 0.0);
+    var flux_y = vec2<f32>(0.0, 0.0);
+    var flux_z = vec2<f32>(0.0, 0.0);
+    for (var a = 0u; a < arrayLength(&attractors); a++) {
+        let attractor = attractors[a];
+        let relative_x = ds_sub(x(attractor), x(point));
+        let relative_y = ds_sub(y(attractor), y(point));
+        let relative_z = ds_sub(z(attractor), z(point));
+        let length_squared = ds_add(
+            ds_add(ds_mul(relative_x, relative_x), ds_mul(relative_y, relative_y)),
+            ds_mul(relative_z, relative_z),
+        );
+        // divided in two steps, cubed distances overflow f32 at interplanetary scales
+        let factor = ds_div(ds_div(w(attractor), length_squared), ds_sqrt(length_squared));
+        flux_x = ds_add(flux_x, ds_mul(relative_x, factor));
+        flux_y = ds_add(flux_y, ds_mul(relative_y, factor));
+        flux_z = ds_add(flux_z, ds_mul(relative_z, factor));
+    }
+    flux[i] = Value(
+        vec4<f32>(flux_x.x, flux_y.x, flux_z.x, 0.0),
+        vec4<f32>(flux_x.y, flux_y.y, flux_z.y, 0.0),
+    );
+}
+";
+
+// hi and lo halves as laid out in the shader's Value
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct Value {
+    hi: [f32; 4],
+    lo: [f32; 4],
+}
+
+impl Value {
+    fn new(values: [f64; 4]) -> Value {
+        let mut value = Value::default();
+        for (i, v) in values.into_iter().enumerate() {
+            (value.hi[i], value.lo[i]) = split(v);
+        }
+        value
+    }
+
+    fn get(&self, i: usize) -> f64 {
+        f64::from(self.hi[i]) + f64::from(self.lo[i])
+    }
+}
+
+// the nearest f32 and what it misses of the value
+#[allow(clippy::cast_possible_truncation)]
+fn split(value: f64) -> (f32, f32) {
+    let hi = value as f32;
+    (hi, (value - f64::from(hi)) as f32)
+}
+
+pub struct GpuGravity {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    max_workgroups: u32, // per dimension of a dispatch
+    max_points: usize,   // per pass, the point and flux buffers have to fit a binding
+}
+
+impl GpuGravity {
+    // None if no usable adapter is available
+    pub fn new() -> Option<GpuGravity> {
+        pollster::block_on(Self::new_async())
+    }
+
+    async fn new_async() -> Option<GpuGravity> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .await
+            .ok()?;
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor::default())
+            .await
+            .ok()?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("bulk gravity"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("bulk gravity"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        let limits = device.limits();
+        let binding_size = usize::try_from(limits.max_storage_buffer_binding_size).ok()?;
+        Some(GpuGravity {
+            device,
+            queue,
+            pipeline,
+            max_workgroups: limits.max_compute_workgroups_per_dimension,
+            max_points: binding_size / std::mem::size_of::<Value>(),
+        })
+    }
+
+    // attractors are (position, mu) pairs in the same frame as the points; large populations
+    // are evaluated in several passes
+    pub fn evaluate(&self, attractors: &[([f64; 3], f64)], points: &[[f64; 3]]) -> Vec<[f64; 3]> {
+        if points.is_empty() {
+            return vec![];
+        }
+        if attractors.is_empty() {
+            return vec![[0.0, 0.0, 0.0]; points.len()];
+        }
+        let attractor_data: Vec<Value> = attractors
+            .iter()
+            .map(|(p, mu)| Value::new([p[0], p[1], p[2], *mu]))
+            .collect();
+        let attractor_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("attractors"),
+                contents: bytemuck::cast_slice(&attractor_data),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+        let zero_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("zero"),
+                contents: bytemuck::cast_slice(&[0u32; 4]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        points
+            .chunks(self.max_points.max(1))
+            .flat_map(|chunk| self.evaluate_pass(&attractor_buffer, &zero_buffer, chunk))
+            .collect()
+    }
+
+    fn evaluate_pass(
+        &self,
+        attractor_buffer: &wgpu::Buffer,
+        zero_buffer: &wgpu::Buffer,
+        points: &[[f64; 3]],
+    ) -> Vec<[f64; 3]> {
+        let point_data: Vec<Value> = points
+            .iter()
+            .map(|p| Value::new([p[0], p[1], p[2], 0.0]))
+            .collect();
+        let flux_size = std::mem::size_of_val(point_data.as_slice()) as u64;
+
+        let point_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("points"),
+                contents: bytemuck::cast_slice(&point_data),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+        let flux_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("flux"),
+            size: flux_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("flux readback"),
+            size: flux_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bulk gravity"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: attractor_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: point_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: flux_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: zero_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        // a pass holds no more points than a binding, far fewer than u32 can count
+        let groups = u32::try_from(point_data.len())
+            .unwrap()
+            .div_ceil(WORKGROUP_SIZE);
+        let (x, y) = dispatch_size(groups, self.max_workgroups);
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(x, y, 1);
+        }
+        encoder.copy_buffer_to_buffer(&flux_buffer, 0, &readback_buffer, 0, flux_size);
+        self.queue.submit(Some(encoder.finish()));
+
+        readback_buffer.map_async(wgpu::MapMode::Read, .., |_| ());
+        self.device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .unwrap();
+        let data = readback_buffer.get_mapped_range(..).unwrap();
+        let flux: &[Value] = bytemuck::cast_slice(&data);
+        flux.iter()
+            .map(|f| [f.get(0), f.get(1), f.get(2)])
+            .collect()
+    }
+}
+
+// workgroups along x and y covering `groups`, with neither above `max` per dimension
+fn dispatch_size(groups: u32, max: u32) -> (u32, u32) {
+    let x = groups.min(max).max(1);
+    (x, groups.div_ceil(x))
+}
+
+impl Simulation {
+    /// `bulk_gravity_flux` evaluated on the GPU in double-single floats, points are relative to
+    /// `origin`
+    ///
+    /// # Errors
+    ///
+    /// `InvalidDynamics` if the dominant body at `origin` or a parent along the way has no mass.
+    pub fn bulk_gravity_flux_gpu(
+        &self,
+        gpu: &GpuGravity,
+        origin: &DecimalVector3d,
+        points: &[[f64; 3]],
+    ) -> Result<Vec<[f64; 3]>, SimulationError> {
+        Ok(gpu.evaluate(&field_attractors(self, origin)?, points))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gpu_gravity::{dispatch_size, split, GpuGravity};
+    use crate::sin_cos::f64_to_dbig;
+    use crate::tests::prepare_sim;
+
+    #[test]
+    fn dispatch_is_split_over_y() {
+        assert_eq!(dispatch_size(1, 65535), (1, 1));
+        assert_eq!(dispatch_size(65535, 65535), (65535, 1));
+        assert_eq!(dispatch_size(65536, 65535), (65535, 2));
+        assert_eq!(dispatch_size(0, 65535), (1, 0));
+        let (hi, lo) = split(1.0 / 3.0);
+        assert!(lo.abs() > 0.0);
+        assert!((f64::from(hi) + f64::from(lo) - 1.0 / 3.0).abs() < 1e-14);
+    }
+
+    #[test]
+    fn gpu_gravity_matches_the_cpu() {
+        // nothing to compare on machines without an adapter
+        let Some(mut gpu) = GpuGravity::new() else {
+            return;
+        };
+        let mut sim = prepare_sim();
+        sim.update(&f64_to_dbig(123_123.0));
+        let origin = sim.get_body("earth").unwrap().position.clone();
+        let points: Vec<[f64; 3]> = (0..1000)
+            .map(|i| {
+                let angle = f64::from(i) * 0.01;
+                let radius = 7e6 + f64::from(i) * 1000.0;
+                [radius * angle.cos(), radius * angle.sin(), f64::from(i)]
+            })
+            .collect();
+        let cpu = sim.bulk_gravity_flux(&origin, &points).unwrap();
+        // several passes and a dispatch spread over y, as for populations beyond the limits
+        gpu.max_points = 300;
+        gpu.max_workgroups = 2;
+        let gpu = sim.bulk_gravity_flux_gpu(&gpu, &origin, &points).unwrap();
+        assert_eq!(gpu.len(), points.len());
+        for (cpu, gpu) in cpu.iter().zip(&gpu) {
+            for c in 0..3 {
+                let scale = cpu.iter().map(|v| v.abs()).fold(0.0, f64::max);
+                assert!((cpu[c] - gpu[c]).abs() < scale * 1e-9, "{cpu:?} {gpu:?}");
+            }
+        }
+    }
+}
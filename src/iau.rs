@@ -0,0 +1,258 @@
+use crate::body::Body;
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::phase_angle::wrap_angle;
+use crate::sin_cos::{atan2, cos, sin, PI};
+use crate::surface::reference_meridian;
+use dashu_float::DBig;
+use std::io::{Error, ErrorKind, Result};
+use std::str::FromStr;
+
+const PRECISION: usize = 32;
+const J2000_JD: i64 = 2_451_545;
+const CENTURY_DAYS: u32 = 36525;
+const DAY_SECONDS: u32 = 86400;
+// obliquity of the ecliptic at J2000, in degrees
+const OBLIQUITY: &str = "23.4392911";
+
+// leading terms of a WGCCRE rotation model, the periodic and higher order terms are left out
+#[derive(Debug, Clone)]
+pub struct IauRotation {
+    pub name: String,
+    pub right_ascension: [DBig; 2], // alpha0 of the pole, in degrees and degrees per century
+    pub declination: [DBig; 2],     // delta0 of the pole, in degrees and degrees per century
+    pub prime_meridian: [DBig; 2],  // W, in degrees and degrees per day
+}
+
+fn invalid_data(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn lift(v: DBig) -> DBig {
+    v.with_precision(PRECISION).value()
+}
+
+fn radians(degrees: &DBig) -> DBig {
+    lift(degrees.clone()) * &*PI / DBig::from(180)
+}
+
+// "329.5988 + 6.1385108 d" into the constant and the rate of `variable`, terms with other
+// variables, powers or sin/cos are skipped
+fn parse_expression(expression: &str, variable: char) -> Result<[DBig; 2]> {
+    let expression = expression.replace(['−', '–'], "-").replace('°', "");
+    let mut terms: Vec<String> = vec![];
+    for c in expression.chars() {
+        // a sign starts a new term unless it belongs to an exponent like 1.4E-12
+        let splits = terms.last().is_some_and(|term| {
+            let term = term.trim();
+            let exponent = term.ends_with(['e', 'E'])
+                && term[..term.len() - 1].ends_with(|c: char| c.is_ascii_digit());
+            !term.is_empty() && !exponent
+        });
+        if (c == '+' || c == '-') && splits {
+            terms.push(String::new());
+        }
+        match terms.last_mut() {
+            Some(term) => term.push(c),
+            None => terms.push(c.to_string()),
+        }
+    }
+
+    let mut result = [DBig::ZERO, DBig::ZERO];
+    for term in terms {
+        let term: String = term.chars().filter(|c| !c.is_whitespace()).collect();
+        if term.contains("sin") || term.contains("cos") {
+            continue;
+        }
+        let mut split = term
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '+' || c == '-'))
+            .unwrap_or(term.len());
+        if term[split..].starts_with(['e', 'E'])
+            && term[split + 1..].starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+')
+        {
+            split += 1 + term[split + 1..]
+                .find(|c: char| !(c.is_ascii_digit() || c == '-' || c == '+'))
+                .unwrap_or(term.len() - split - 1);
+        }
+        let (number, unit) = term.split_at(split);
+        let value = DBig::from_str(number.trim_start_matches('+'))
+            .map_err(|_| invalid_data(format!("not a number: {term}")))?;
+        if unit.is_empty() {
+            result[0] += value;
+        } else if unit.len() == 1 && unit.starts_with(variable) {
+            result[1] += value;
+        }
+    }
+    Ok(result)
+}
+
+/// blocks of a body name followed by `alpha0 = ...`, `delta0 = ...` and `W = ...` lines, the way
+/// the WGCCRE report tabulates them; T is in Julian centuries and d in days from J2000
+///
+/// # Errors
+///
+/// `InvalidData` for an element before a body name, an unknown or missing element, or an expression
+/// that doesn't parse.
+pub fn parse_iau_rotation(source: &str) -> Result<Vec<IauRotation>> {
+    let mut result: Vec<IauRotation> = vec![];
+    let mut current: Option<(String, [Option<[DBig; 2]>; 3])> = None;
+    let finish = |current: Option<(String, [Option<[DBig; 2]>; 3])>| -> Result<Option<_>> {
+        let Some((name, [right_ascension, declination, prime_meridian])) = current else {
+            return Ok(None);
+        };
+        let missing = |what: &str| invalid_data(format!("{name}: missing {what}"));
+        Ok(Some(IauRotation {
+            right_ascension: right_ascension.ok_or_else(|| missing("alpha0"))?,
+            declination: declination.ok_or_else(|| missing("delta0"))?,
+            prime_meridian: prime_meridian.ok_or_else(|| missing("W"))?,
+            name,
+        }))
+    };
+
+    for line in source.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, expression)) = line.split_once('=') else {
+            result.extend(finish(current.take())?);
+            current = Some((String::from(line), [None, None, None]));
+            continue;
+        };
+        let Some((name, values)) = current.as_mut() else {
+            return Err(invalid_data(format!("{line} before a body name")));
+        };
+        let (index, variable) = match key.trim() {
+            "alpha0" | "α0" => (0, 'T'),
+            "delta0" | "δ0" => (1, 'T'),
+            "W" => (2, 'd'),
+            other => return Err(invalid_data(format!("{name}: unknown element {other}"))),
+        };
+        values[index] = Some(parse_expression(expression, variable)?);
+    }
+    result.extend(finish(current)?);
+    Ok(result)
+}
+
+// ICRF equatorial to world, the ecliptic is the XZ plane with +Y north and +X at the equinox
+fn equatorial_to_world(v: [DBig; 3]) -> DecimalVector3d {
+    let obliquity = radians(&DBig::from_str(OBLIQUITY).unwrap());
+    let (sin_obliquity, cos_obliquity) = (sin(obliquity.clone(), 32), cos(obliquity, 32));
+    let [x, y, z] = v;
+    let ecliptic_y = &y * &cos_obliquity + &z * &sin_obliquity;
+    let ecliptic_z = &z * &cos_obliquity - &y * &sin_obliquity;
+    DecimalVector3d::new(x, ecliptic_z, -ecliptic_y)
+}
+
+impl IauRotation {
+    // sets the axis, period and phase of a body for a simulation whose time zero is `epoch_jd`;
+    // the pole is taken at the epoch and stays there, there is no precession model
+    pub fn apply(&self, body: &mut Body, epoch_jd: &DBig) {
+        let days = lift(epoch_jd.clone()) - DBig::from(J2000_JD);
+        let centuries = &days / DBig::from(CENTURY_DAYS);
+        let right_ascension =
+            radians(&(&self.right_ascension[0] + &self.right_ascension[1] * &centuries));
+        let declination = radians(&(&self.declination[0] + &self.declination[1] * &centuries));
+        let prime_meridian = radians(&(&self.prime_meridian[0] + &self.prime_meridian[1] * &days));
+
+        let (sin_ra, cos_ra) = (sin(right_ascension.clone(), 32), cos(right_ascension, 32));
+        let (sin_dec, cos_dec) = (sin(declination.clone(), 32), cos(declination, 32));
+        let pole = equatorial_to_world([&cos_dec * &cos_ra, &cos_dec * &sin_ra, sin_dec]);
+        // W is counted from the ascending node of the equator on the ICRF equator
+        let node = equatorial_to_world([-sin_ra, cos_ra, DBig::ZERO]);
+        let meridian = reference_meridian(&pole);
+        let node_angle = atan2(pole.dot(&meridian.cross(&node)), meridian.dot(&node), 32);
+
+        body.rotation_period =
+            DBig::from(360) / lift(self.prime_meridian[1].clone()) * DBig::from(DAY_SECONDS);
+        body.rotation_phase = wrap_angle(node_angle + prime_meridian);
+        body.rotation_axis = pole;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::iau::parse_iau_rotation;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::prepare_sim;
+    use dashu_float::DBig;
+    use std::str::FromStr;
+
+    #[test]
+    fn iau_rotation_works() {
+        let table = "
+        # WGCCRE 2015
+        Earth
+        α0 = 0.00 − 0.641 T
+        δ0 = 90.00 − 0.557 T
+        W = 190.147 + 360.9856235 d
+
+        Venus
+        alpha0 = 272.76
+        delta0 = 67.16
+        W = 160.20 - 1.4813688 d
+
+        Moon
+        α0 = 269.9949 + 0.0031 T − 3.8787 sin E1 − 0.1204 sin E2
+        δ0 = 66.5392 + 0.0130 T + 1.5419 cos E1
+        W = 38.3213 + 13.17635815 d − 1.4E−12 d² + 3.5610 sin E1
+
+        Test
+        alpha0 = 1.5E1 + 2e-1 T
+        delta0 = 0
+        W = 0 + 1 d
+    ";
+        let rotations = parse_iau_rotation(table).unwrap();
+        assert_eq!(rotations.len(), 4);
+        assert_eq!(rotations[3].right_ascension[0], DBig::from(15));
+        assert_eq!(
+            rotations[3].right_ascension[1],
+            DBig::from_str("0.2").unwrap()
+        );
+        assert_eq!(rotations[2].name, "Moon");
+        assert_eq!(
+            rotations[2].right_ascension[0],
+            DBig::from_str("269.9949").unwrap()
+        );
+        assert_eq!(
+            rotations[2].prime_meridian[1],
+            DBig::from_str("13.17635815").unwrap()
+        );
+        assert!(parse_iau_rotation("Mars\nalpha0 = 317.68\n").is_err());
+
+        let mut sim = prepare_sim();
+        let epoch = DBig::from_str("2451545.0").unwrap();
+        rotations[0].apply(sim.get_body_mut("earth").unwrap(), &epoch);
+        rotations[1].apply(sim.get_body_mut("sun").unwrap(), &epoch);
+        let earth = &sim.get_body("earth").unwrap().body;
+        assert!(approx_eq(
+            &earth.rotation_period,
+            &f64_to_dbig(86164.09),
+            &f64_to_dbig(0.01)
+        ));
+        assert!(sim.get_body("sun").unwrap().body.rotation_period < DBig::ZERO);
+
+        // the pole is the ecliptic pole tilted by the obliquity, away from the summer solstice
+        let obliquity = 23.439_291_1_f64.to_radians();
+        let expected = DecimalVector3d::from_f64(0.0, obliquity.cos(), -obliquity.sin());
+        assert!(earth.rotation_axis.approx_eq(&expected, &f64_to_dbig(1e-9)));
+
+        // the prime meridian is W from the node, the vernal equinox for the Earth
+        for time in [0, 86164] {
+            sim.update(&DBig::from(time));
+            let w = (190.147f64 + 360.985_623_5 * f64::from(time) / 86400.0).to_radians();
+            let (x, y, z) = (
+                -w.sin(),
+                w.cos() * obliquity.cos(),
+                -w.cos() * obliquity.sin(),
+            );
+            let expected = DecimalVector3d::from_f64(x, z, -y);
+            let [up, _, _] = crate::surface::surface_frame(
+                sim.get_body("earth").unwrap(),
+                &DBig::ZERO,
+                &DBig::ZERO,
+            );
+            assert!(up.approx_eq(&expected, &f64_to_dbig(1e-6)));
+        }
+    }
+}
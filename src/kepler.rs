@@ -0,0 +1,196 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::sin_cos::{atan2, cos, sin};
+use dashu_float::ops::{Abs, SquareRoot};
+use dashu_float::DBig;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+const PRECISION: usize = 40;
+const KEPLER_ITERATIONS: usize = 64;
+const SERIES_TERMS: usize = 40;
+
+// in radians, what the simulation itself solves to
+pub static DEFAULT_KEPLER_TOLERANCE: LazyLock<DBig> =
+    LazyLock::new(|| DBig::from_str("1e-30").unwrap());
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+// solves Kepler's equation E - e sin E = M by Newton iteration, for eccentricities within [0, 1)
+// and angles in radians. Stops once a step is below `tolerance`, or after 64 steps when the
+// tolerance is finer than the 40 digits the solver works with
+pub fn eccentric_anomaly(mean_anomaly: &DBig, eccentricity: &DBig, tolerance: &DBig) -> DBig {
+    let mean_anomaly = lift(mean_anomaly);
+    let eccentricity = lift(eccentricity);
+    let mut anomaly = &mean_anomaly + &eccentricity * sin(mean_anomaly.clone(), 40);
+    for _ in 0..KEPLER_ITERATIONS {
+        let residual = &anomaly - &eccentricity * sin(anomaly.clone(), 40) - &mean_anomaly;
+        let slope = DBig::ONE - &eccentricity * cos(anomaly.clone(), 40);
+        let step = residual / slope;
+        anomaly -= &step;
+        if step.abs() < *tolerance {
+            break;
+        }
+    }
+    anomaly
+}
+
+// Kepler's equation itself, on the same turn as the eccentric anomaly
+pub fn mean_anomaly_from_eccentric(eccentric_anomaly: &DBig, eccentricity: &DBig) -> DBig {
+    let anomaly = lift(eccentric_anomaly);
+    &anomaly - lift(eccentricity) * sin(anomaly.clone(), 40)
+}
+
+// within (-pi, pi]
+pub fn true_anomaly_from_eccentric(eccentric_anomaly: &DBig, eccentricity: &DBig) -> DBig {
+    let anomaly = lift(eccentric_anomaly);
+    let eccentricity = lift(eccentricity);
+    let semi_minor_ratio = (DBig::ONE - &eccentricity * &eccentricity).sqrt();
+    atan2(
+        semi_minor_ratio * sin(anomaly.clone(), 40),
+        cos(anomaly, 40) - eccentricity,
+        40,
+    )
+}
+
+// within (-pi, pi]
+pub fn eccentric_anomaly_from_true(true_anomaly: &DBig, eccentricity: &DBig) -> DBig {
+    let anomaly = lift(true_anomaly);
+    let eccentricity = lift(eccentricity);
+    let semi_minor_ratio = (DBig::ONE - &eccentricity * &eccentricity).sqrt();
+    atan2(
+        semi_minor_ratio * sin(anomaly.clone(), 40),
+        eccentricity + cos(anomaly, 40),
+        40,
+    )
+}
+
+// mean to true anomaly in one go, within (-pi, pi]
+pub fn true_anomaly_from_mean(mean_anomaly: &DBig, eccentricity: &DBig, tolerance: &DBig) -> DBig {
+    let anomaly = eccentric_anomaly(mean_anomaly, eccentricity, tolerance);
+    true_anomaly_from_eccentric(&anomaly, eccentricity)
+}
+
+// Stumpff functions S(z) and C(z), by their series near zero where the closed forms cancel out
+pub(crate) fn stumpff(z: &DBig) -> (DBig, DBig) {
+    if z.clone().abs() <= DBig::ONE {
+        let (mut s, mut c) = (DBig::ZERO, DBig::ZERO);
+        let mut term = lift(&DBig::ONE); // (-z)^k
+        let mut factorial = lift(&DBig::from(2)); // (2k + 2)!
+        for k in 0..SERIES_TERMS {
+            c += &term / &factorial;
+            factorial *= DBig::from(2 * k + 3);
+            s += &term / &factorial;
+            factorial *= DBig::from(2 * k + 4);
+            term *= -z;
+        }
+        return (s, c);
+    }
+    if *z > DBig::ZERO {
+        let root = z.sqrt();
+        let s = (&root - sin(root.clone(), 40)) / (z * &root);
+        let c = (DBig::ONE - cos(root, 40)) / z;
+        (s, c)
+    } else {
+        let root = (-z).sqrt();
+        let (grow, decay) = (root.exp(), (-&root).exp());
+        let two = DBig::from(2);
+        let sinh = (&grow - &decay) / &two;
+        let cosh = (grow + decay) / two;
+        let s = (sinh - &root) / (-z * &root);
+        let c = (cosh - DBig::ONE) / -z;
+        (s, c)
+    }
+}
+
+// position and velocity after `time` seconds on the conic through the given state, relative to
+// an attractor with gravitational parameter `mu`; universal variables, so any eccentricity and
+// negative times work. Newton iteration on the universal anomaly like the solver above
+#[allow(clippy::many_single_char_names)] // named as in the formulas
+pub fn propagate_kepler(
+    mu: &DBig,
+    position: &DecimalVector3d,
+    velocity: &DecimalVector3d,
+    time: &DBig,
+) -> (DecimalVector3d, DecimalVector3d) {
+    let mu = lift(mu);
+    let time = lift(time);
+    let r0 = DecimalVector3d::new(lift(&position.x), lift(&position.y), lift(&position.z));
+    let v0 = DecimalVector3d::new(lift(&velocity.x), lift(&velocity.y), lift(&velocity.z));
+    let r0_length = r0.length();
+    let root_mu = mu.sqrt();
+    let radial_speed = r0.dot(&v0) / &r0_length; // times r0 over sqrt(mu) below
+    let alpha = DBig::from(2) / &r0_length - v0.length_squared() / &mu; // 1 / a
+
+    // the first guess is exact for circles
+    let mut chi = &root_mu * &time / &r0_length;
+    for _ in 0..KEPLER_ITERATIONS {
+        let z = &alpha * &chi * &chi;
+        let (s, c) = stumpff(&z);
+        let radius_term = &r0_length * &radial_speed / &root_mu;
+        let chi_squared = &chi * &chi;
+        let flight = &radius_term * &chi_squared * &c
+            + (DBig::ONE - &alpha * &r0_length) * &chi_squared * &chi * &s
+            + &r0_length * &chi;
+        // the derivative of the flight time in chi is the radius there
+        let radius = &radius_term * &chi * (DBig::ONE - &z * &s)
+            + (DBig::ONE - &alpha * &r0_length) * &chi_squared * &c
+            + &r0_length;
+        let step = (flight - &root_mu * &time) / radius;
+        chi -= &step;
+        if step.abs() < *DEFAULT_KEPLER_TOLERANCE {
+            break;
+        }
+    }
+
+    let z = &alpha * &chi * &chi;
+    let (s, c) = stumpff(&z);
+    let chi_squared = &chi * &chi;
+    let f = DBig::ONE - &chi_squared / &r0_length * &c;
+    let g = &time - &chi_squared * &chi / &root_mu * &s;
+    let r = &r0 * &f + &v0 * &g;
+    let radius = r.length();
+    let f_dot = &root_mu / (&radius * &r0_length) * (&z * &chi * &s - &chi);
+    let g_dot = DBig::ONE - &chi_squared / &radius * &c;
+    (r, &r0 * &f_dot + &v0 * &g_dot)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kepler::{
+        eccentric_anomaly, eccentric_anomaly_from_true, mean_anomaly_from_eccentric,
+        true_anomaly_from_eccentric, true_anomaly_from_mean, DEFAULT_KEPLER_TOLERANCE,
+    };
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use dashu_float::DBig;
+
+    #[test]
+    fn kepler_solver_works() {
+        let eccentricity = f64_to_dbig(0.9);
+        let mean = f64_to_dbig(0.3);
+        let anomaly = eccentric_anomaly(&mean, &eccentricity, &DEFAULT_KEPLER_TOLERANCE);
+        let residual = mean_anomaly_from_eccentric(&anomaly, &eccentricity) - &mean;
+        assert!(approx_eq(&residual, &DBig::ZERO, &f64_to_dbig(1e-28)));
+
+        // a coarse tolerance stops early but still lands close
+        let coarse = eccentric_anomaly(&mean, &eccentricity, &f64_to_dbig(1e-3));
+        assert!(approx_eq(&coarse, &anomaly, &f64_to_dbig(1e-3)));
+
+        let true_anomaly = true_anomaly_from_eccentric(&anomaly, &eccentricity);
+        assert!(approx_eq(
+            &true_anomaly,
+            &true_anomaly_from_mean(&mean, &eccentricity, &DEFAULT_KEPLER_TOLERANCE),
+            &f64_to_dbig(1e-20)
+        ));
+        assert!(approx_eq(
+            &eccentric_anomaly_from_true(&true_anomaly, &eccentricity),
+            &anomaly,
+            &f64_to_dbig(1e-20)
+        ));
+        // the true anomaly runs ahead of the mean one after the periapsis, and the circle is exact
+        assert!(true_anomaly > anomaly && anomaly > mean);
+        let circular = true_anomaly_from_mean(&mean, &DBig::ZERO, &DEFAULT_KEPLER_TOLERANCE);
+        assert!(approx_eq(&circular, &mean, &f64_to_dbig(1e-30)));
+    }
+}
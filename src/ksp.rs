@@ -0,0 +1,384 @@
+use crate::body::{Body, BodyDynamics, OrbitingBodyDynamics, StaticBodyDynamics};
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::simulation::G_CONSTANT;
+use crate::sin_cos::{PI, PIMUL2};
+use dashu_float::ops::SquareRoot;
+use dashu_float::DBig;
+use std::io::{Error, ErrorKind, Result};
+use std::str::FromStr;
+
+const PRECISION: usize = 32;
+const STANDARD_GRAVITY: &str = "9.80665";
+
+#[derive(Debug, Clone, Default)]
+struct ConfigNode {
+    name: String,
+    values: Vec<(String, String)>,
+    nodes: Vec<ConfigNode>,
+}
+
+impl ConfigNode {
+    fn value(&self, key: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn node(&self, name: &str) -> Option<&ConfigNode> {
+        self.nodes.iter().find(|node| node.name == name)
+    }
+
+    // every node with this name, at any depth
+    fn collect<'a>(&'a self, name: &str, result: &mut Vec<&'a ConfigNode>) {
+        for node in &self.nodes {
+            if node.name == name {
+                result.push(node);
+            } else {
+                node.collect(name, result);
+            }
+        }
+    }
+}
+
+fn invalid_data(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+// nodes are `name { ... }` with the brace on the same or the next line, values are `key = value`
+fn parse_config(source: &str) -> Result<ConfigNode> {
+    let mut stack: Vec<ConfigNode> = vec![ConfigNode::default()];
+    let mut pending_name = String::new();
+    for line in source.lines() {
+        let line = line.split("//").next().unwrap();
+        let mut rest = line;
+        while !rest.is_empty() {
+            let brace = rest.find(['{', '}']).unwrap_or(rest.len());
+            let piece = rest[..brace].trim();
+            if !piece.is_empty() {
+                match piece.split_once('=') {
+                    Some((key, value)) => {
+                        let key = key.trim().trim_start_matches(['@', '%']).to_string();
+                        let value = value.trim().to_string();
+                        stack.last_mut().unwrap().values.push((key, value));
+                    }
+                    None => pending_name = piece.to_string(),
+                }
+            }
+            if brace == rest.len() {
+                break;
+            }
+            if rest[brace..].starts_with('{') {
+                // patch operators and passes are irrelevant here, "@Body[Kerbin]:FINAL" is "Body"
+                let name = pending_name.trim_start_matches(['@', '%', '+']);
+                let name = name.split([':', '[']).next().unwrap().trim().to_string();
+                stack.push(ConfigNode {
+                    name,
+                    ..ConfigNode::default()
+                });
+                pending_name.clear();
+            } else {
+                if stack.len() == 1 {
+                    return Err(invalid_data(String::from("unbalanced '}'")));
+                }
+                let node = stack.pop().unwrap();
+                stack.last_mut().unwrap().nodes.push(node);
+            }
+            rest = &rest[brace + 1..];
+        }
+    }
+    if stack.len() != 1 {
+        return Err(invalid_data(String::from("unclosed '{'")));
+    }
+    Ok(stack.pop().unwrap())
+}
+
+fn parse_number(body: &str, key: &str, value: &str) -> Result<DBig> {
+    let number = DBig::from_str(&value.to_lowercase().replace("e+", "e"))
+        .map_err(|_| invalid_data(format!("{body}: {key} is not a number: {value}")))?;
+    Ok(number.with_precision(PRECISION).value())
+}
+
+fn number(body: &str, node: &ConfigNode, key: &str) -> Result<Option<DBig>> {
+    node.value(key)
+        .map(|value| parse_number(body, key, value))
+        .transpose()
+}
+
+fn degrees_to_radians(degrees: DBig) -> DBig {
+    degrees * &*PI / DBig::from(180)
+}
+
+struct ImportedBody {
+    body: Body,
+    reference_body: Option<String>,
+    tidally_locked: bool,
+}
+
+fn import_body(node: &ConfigNode) -> Result<ImportedBody> {
+    let name = node
+        .value("name")
+        .ok_or_else(|| invalid_data(String::from("body without a name")))?
+        .to_string();
+    let missing = |what: &str| invalid_data(format!("{name}: missing {what}"));
+    let properties = node
+        .node("Properties")
+        .ok_or_else(|| missing("Properties"))?;
+
+    let radius = number(&name, properties, "radius")?.ok_or_else(|| missing("radius"))?;
+    // KSP defines gravity in one of three ways, only the mass is kept
+    let mass = match (
+        number(&name, properties, "mass")?,
+        number(&name, properties, "gravParameter")?,
+        number(&name, properties, "geeASL")?,
+    ) {
+        (Some(mass), _, _) => mass,
+        (None, Some(mu), _) => mu / &*G_CONSTANT,
+        (None, None, Some(gee)) => {
+            gee * DBig::from_str(STANDARD_GRAVITY).unwrap() * &radius * &radius / &*G_CONSTANT
+        }
+        _ => return Err(missing("mass, gravParameter or geeASL")),
+    };
+    let tidally_locked = properties.value("tidallyLocked") == Some("true");
+    let rotation_period = number(&name, properties, "rotationPeriod")?;
+
+    // the orbit epoch is taken as time zero, meanAnomalyAtEpoch is in radians and the Kopernicus
+    // meanAnomalyAtEpochD in degrees
+    let (dynamics, reference_body) = match node.node("Orbit") {
+        None => (
+            BodyDynamics::Static(StaticBodyDynamics {
+                position: DecimalVector3d::zero(),
+            }),
+            None,
+        ),
+        Some(orbit) => {
+            let reference_body = orbit
+                .value("referenceBody")
+                .ok_or_else(|| missing("Orbit.referenceBody"))?;
+            let semi_major_axis =
+                number(&name, orbit, "semiMajorAxis")?.ok_or_else(|| missing("semiMajorAxis"))?;
+            let inclination =
+                degrees_to_radians(number(&name, orbit, "inclination")?.unwrap_or(DBig::ZERO));
+            let node_longitude =
+                degrees_to_radians(number(&name, orbit, "LAN")?.unwrap_or(DBig::ZERO));
+            let eccentricity = number(&name, orbit, "eccentricity")?.unwrap_or(DBig::ZERO);
+            let argument_of_periapsis = degrees_to_radians(
+                number(&name, orbit, "argumentOfPeriapsis")?.unwrap_or(DBig::ZERO),
+            );
+            let mut dynamics = OrbitingBodyDynamics::from_elements(
+                semi_major_axis,
+                eccentricity,
+                &inclination,
+                &node_longitude,
+                argument_of_periapsis,
+                DBig::ZERO, // needs the parent mass, resolved later
+            );
+            dynamics.mean_anomaly_at_epoch = match number(&name, orbit, "meanAnomalyAtEpoch")? {
+                Some(radians) => radians,
+                None => degrees_to_radians(
+                    number(&name, orbit, "meanAnomalyAtEpochD")?.unwrap_or(DBig::ZERO),
+                ),
+            };
+            (
+                BodyDynamics::Orbiting(dynamics),
+                Some(reference_body.to_string()),
+            )
+        }
+    };
+
+    // roots have nothing to be locked to
+    let tidally_locked = tidally_locked && reference_body.is_some();
+    let rotation_period = match (rotation_period, tidally_locked) {
+        (_, true) => DBig::ZERO, // follows the orbit period, resolved later
+        (Some(period), false) => period,
+        (None, false) => return Err(missing("rotationPeriod")),
+    };
+    Ok(ImportedBody {
+        body: Body {
+            name,
+            rotation_axis: DecimalVector3d::new(DBig::ZERO, DBig::ONE, DBig::ZERO),
+            rotation_period,
+            rotation_phase: DBig::ZERO,
+            resonance: None,
+            visual: None,
+            kind: None,
+            mass,
+            mass_variation: None,
+            nutation: None,
+            libration: None,
+            radius,
+            dynamics,
+            update_interval: None,
+            satellites: vec![],
+        },
+        reference_body,
+        tidally_locked,
+    })
+}
+
+fn attach_satellites(body: &mut Body, imported: &mut Vec<ImportedBody>) {
+    let mut i = 0;
+    while i < imported.len() {
+        if imported[i].reference_body.as_deref() == Some(body.name.as_str()) {
+            let ImportedBody {
+                body: mut satellite,
+                tidally_locked,
+                ..
+            } = imported.remove(i);
+            if let BodyDynamics::Orbiting(dynamics) = &mut satellite.dynamics {
+                let radius = &dynamics.orbit_radius;
+                dynamics.orbit_period =
+                    &*PIMUL2 * (radius * radius * radius / (&*G_CONSTANT * &body.mass)).sqrt();
+                if tidally_locked {
+                    satellite.rotation_period.clone_from(&dynamics.orbit_period);
+                    satellite.rotation_axis = dynamics.orbit_plane_normal.clone();
+                }
+            }
+            body.satellites.push(satellite);
+        } else {
+            i += 1;
+        }
+    }
+    for satellite in &mut body.satellites {
+        attach_satellites(satellite, imported);
+    }
+}
+
+/// reads every Body node of a Kopernicus planet pack into hierarchies ready for `add_hierarchy`,
+/// bodies without an Orbit node are the roots, periods follow from the parent mass
+///
+/// # Errors
+///
+/// `InvalidData` for unbalanced braces, a body without a name or a required value, a value that
+/// isn't a number, or a referenceBody that isn't in the pack.
+pub fn import_ksp_config(source: &str) -> Result<Vec<Body>> {
+    let root = parse_config(source)?;
+    let mut nodes: Vec<&ConfigNode> = vec![];
+    root.collect("Body", &mut nodes);
+    let mut imported = nodes
+        .into_iter()
+        .map(import_body)
+        .collect::<Result<Vec<ImportedBody>>>()?;
+
+    let mut roots: Vec<Body> = vec![];
+    let mut i = 0;
+    while i < imported.len() {
+        if imported[i].reference_body.is_none() {
+            roots.push(imported.remove(i).body);
+        } else {
+            i += 1;
+        }
+    }
+    for root in &mut roots {
+        attach_satellites(root, &mut imported);
+    }
+    if let Some(orphan) = imported.first() {
+        return Err(invalid_data(format!(
+            "{}: unknown referenceBody {}",
+            orphan.body.name,
+            orphan.reference_body.as_deref().unwrap_or_default()
+        )));
+    }
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::BodyDynamics;
+    use crate::ksp::import_ksp_config;
+    use crate::simulation::Simulation;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::dbig_to_f64;
+    use dashu_float::DBig;
+
+    #[test]
+    fn import_ksp_config_works() {
+        let config = r"
+@Kopernicus:FOR[Example]
+{
+    Body
+    {
+        name = Kerbol
+        Properties
+        {
+            radius = 261600000
+            gravParameter = 1.1723328E+18
+            rotationPeriod = 432000
+        }
+    }
+    Body
+    {
+        name = Minmus // orbits Kerbin, defined before it on purpose
+        Properties { radius = 60000
+            geeASL = 0.05
+            tidallyLocked = true
+        }
+        Orbit
+        {
+            referenceBody = Kerbin
+            semiMajorAxis = 47000000
+            inclination = 6
+            eccentricity = 0.22
+            LAN = 78
+            argumentOfPeriapsis = 38
+            meanAnomalyAtEpoch = 1.7
+        }
+    }
+    Body
+    {
+        name = Kerbin
+        Properties
+        {
+            radius = 600000
+            mass = 5.2915158e22
+            rotationPeriod = 21549.425
+        }
+        Orbit
+        {
+            referenceBody = Kerbol
+            semiMajorAxis = 13599840256
+        }
+    }
+}
+";
+        let roots = import_ksp_config(config).unwrap();
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].name, "Kerbol");
+        let kerbin = &roots[0].satellites[0];
+        let minmus = &kerbin.satellites[0];
+        assert_eq!(minmus.name, "Minmus");
+
+        // stock KSP lists Kerbin's orbital period as 9203545 s
+        let BodyDynamics::Orbiting(kerbin_orbit) = &kerbin.dynamics else {
+            panic!("kerbin is orbiting")
+        };
+        assert!(approx_eq(
+            &kerbin_orbit.orbit_period,
+            &f64_to_dbig(9_203_545.0),
+            &f64_to_dbig(100.0)
+        ));
+        let BodyDynamics::Orbiting(minmus_orbit) = &minmus.dynamics else {
+            panic!("minmus is orbiting")
+        };
+        assert_eq!(minmus.rotation_period, minmus_orbit.orbit_period);
+        let inclination = dbig_to_f64(&minmus_orbit.orbit_plane_normal.y).acos();
+        assert!((inclination.to_degrees() - 6.0).abs() < 1e-9);
+        let ellipse = minmus_orbit.ellipse.as_ref().unwrap();
+        assert!(approx_eq(
+            &ellipse.eccentricity,
+            &f64_to_dbig(0.22),
+            &f64_to_dbig(1e-12)
+        ));
+        assert!((dbig_to_f64(&ellipse.argument_of_periapsis).to_degrees() - 38.0).abs() < 1e-9);
+        assert_eq!(minmus_orbit.mean_anomaly_at_epoch, f64_to_dbig(1.7));
+
+        let mut sim = Simulation::new();
+        sim.add_hierarchy(roots.into_iter().next().unwrap(), None)
+            .unwrap();
+        sim.update(&DBig::from(3600));
+        assert_eq!(sim.bodies.len(), 3);
+
+        assert!(import_ksp_config("Body { name = Lost\n Properties { radius = 1\n mass = 1\n rotationPeriod = 1 }\n Orbit { referenceBody = Nowhere\n semiMajorAxis = 1 } }").is_err());
+        assert!(import_ksp_config("Body {").is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::simulation::Simulation;
+use dashu_float::ops::SquareRoot;
+use dashu_float::DBig;
+
+const PRECISION: usize = 40;
+const BISECTION_ITERATIONS: usize = 140;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+// world positions of the five equilibrium points of a pair, L1 between the bodies, L2 beyond the
+// secondary, L3 opposite of it, L4 leading it by 60 degrees and L5 trailing it
+#[derive(Debug, Clone)]
+pub struct LagrangePoints {
+    pub l1: DecimalVector3d,
+    pub l2: DecimalVector3d,
+    pub l3: DecimalVector3d,
+    pub l4: DecimalVector3d,
+    pub l5: DecimalVector3d,
+}
+
+// net acceleration along the line in the rotating frame, in units of the separation with the
+// primary at -q and the secondary at 1 - q; it grows with x between and beyond the bodies
+fn collinear_balance(q: &DBig, x: &DBig) -> DBig {
+    let to_primary = x + q;
+    let to_secondary = x - DBig::ONE + q;
+    let pull = |mass: DBig, offset: &DBig| {
+        let cube = offset * offset * offset;
+        mass * offset / cube.clone().max(-cube)
+    };
+    x - pull(DBig::ONE - q, &to_primary) - pull(q.clone(), &to_secondary)
+}
+
+fn collinear_point(q: &DBig, mut low: DBig, mut high: DBig) -> DBig {
+    for _ in 0..BISECTION_ITERATIONS {
+        let middle = (&low + &high) / DBig::from(2);
+        if collinear_balance(q, &middle) < DBig::ZERO {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    high
+}
+
+impl Simulation {
+    /// for `secondary_name` going around `primary_name` at `time`, in the circular restricted
+    /// three-body approximation: the pair is taken as it is at that moment, so eccentric orbits
+    /// give the points of the current separation. Positions come from a copy of the simulation
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if either body isn't in the simulation, `InvalidDynamics` if the secondary
+    /// doesn't move around the primary.
+    pub fn lagrange_points(
+        &self,
+        primary_name: &str,
+        secondary_name: &str,
+        time: &DBig,
+    ) -> Result<LagrangePoints, SimulationError> {
+        self.get_body(primary_name)?;
+        self.get_body(secondary_name)?;
+        let mut sim = self.copy_bodies();
+        sim.update(time);
+
+        let primary = sim.get_body(primary_name)?;
+        let secondary = sim.get_body(secondary_name)?;
+        let origin = sim.world_position(primary);
+        let separation = sim.world_position(secondary) - &origin;
+        let normal =
+            separation.cross(&(sim.world_velocity(secondary) - sim.world_velocity(primary)));
+        if normal.length_squared() == DBig::ZERO {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{secondary_name}: doesn't move around {primary_name}"
+            )));
+        }
+        let (m1, m2) = (
+            lift(&primary.body.mass_at(time)),
+            lift(&secondary.body.mass_at(time)),
+        );
+        let q = &m2 / (&m1 + &m2);
+
+        let two = DBig::from(2);
+        let one_minus_q = DBig::ONE - &q;
+        let barycenter = &origin + &separation * &q;
+        let on_line = |x: DBig| &barycenter + &separation * &x;
+        // in the sense of the motion, perpendicular to the separation
+        let ahead = normal.normalized().cross(&separation);
+        let half = DBig::ONE / &two;
+        let sin_60 = lift(&DBig::from(3)).sqrt() / &two;
+        let apex = &origin + &separation * &half;
+        Ok(LagrangePoints {
+            l1: on_line(collinear_point(&q, -&q, one_minus_q.clone())),
+            l2: on_line(collinear_point(&q, one_minus_q, two.clone())),
+            l3: on_line(collinear_point(&q, -two, -&q)),
+            l4: &apex + &ahead * &sin_60,
+            l5: &apex - &ahead * &sin_60,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn lagrange_points_works() {
+        let sim = prepare_sim();
+        let time = DBig::from(100_000).with_precision(40).value();
+        let points = sim.lagrange_points("earth", "moon", &time).unwrap();
+
+        let mut check = prepare_sim();
+        check.update(&time);
+        let earth = check.get_body("earth").unwrap();
+        let moon = check.get_body("moon").unwrap();
+        let earth_position = check.world_position(earth);
+        let separation = check.world_position(moon) - &earth_position;
+        let distance = dbig_to_f64(&separation.length());
+        let along = |point: &DecimalVector3d| {
+            dbig_to_f64(&(point - &earth_position).dot(&separation)) / (distance * distance)
+        };
+        // the mass ratio of the fixture is close to the real 0.0123
+        assert!((along(&points.l1) - 0.8491).abs() < 1e-3);
+        assert!((along(&points.l2) - 1.1678).abs() < 1e-3);
+        assert!((along(&points.l3) + 0.9929).abs() < 1e-3);
+
+        let motion = check.world_velocity(moon) - check.world_velocity(earth);
+        for (point, leading) in [(&points.l4, true), (&points.l5, false)] {
+            let to_earth = dbig_to_f64(&point.distance_to(&earth_position));
+            let to_moon = dbig_to_f64(&point.distance_to(&check.world_position(moon)));
+            assert!((to_earth / distance - 1.0).abs() < 1e-9);
+            assert!((to_moon / distance - 1.0).abs() < 1e-9);
+            assert_eq!((point - &earth_position).dot(&motion) > DBig::ZERO, leading);
+        }
+
+        assert!(matches!(
+            sim.lagrange_points("earth", "pluto", &time),
+            Err(SimulationError::UnknownBody(_))
+        ));
+    }
+}
@@ -0,0 +1,227 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::kepler::stumpff;
+use crate::simulation::{Simulation, G_CONSTANT};
+use crate::sin_cos::{acos, sin, PIMUL2};
+use dashu_float::ops::{Abs, SquareRoot};
+use dashu_float::DBig;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+const PRECISION: usize = 40;
+const BISECTION_ITERATIONS: usize = 160;
+
+static COLLINEAR_TOLERANCE: LazyLock<DBig> = LazyLock::new(|| DBig::from_str("1e-20").unwrap());
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+fn lift_vector(v: &DecimalVector3d) -> DecimalVector3d {
+    DecimalVector3d::new(lift(&v.x), lift(&v.y), lift(&v.z))
+}
+
+// velocities at both ends of a keplerian transfer, relative to the attractor
+#[derive(Debug, Clone)]
+pub struct LambertSolution {
+    pub departure_velocity: DecimalVector3d,
+    pub arrival_velocity: DecimalVector3d,
+}
+
+// velocities that take a body from `departure` to `arrival` in `time_of_flight` seconds around an
+// attractor with gravitational parameter `mu`, both positions relative to it. The transfer turns
+// counterclockwise about `normal` without whole revolutions, so it goes the long way when the
+// arrival is more than half a turn ahead in that sense. Universal variables with bisection on z;
+// None for collinear positions, where the transfer plane is undefined, or a time that isn't
+// positive
+#[allow(clippy::many_single_char_names)] // named as in the formulas
+pub fn lambert(
+    mu: &DBig,
+    departure: &DecimalVector3d,
+    arrival: &DecimalVector3d,
+    time_of_flight: &DBig,
+    normal: &DecimalVector3d,
+) -> Option<LambertSolution> {
+    let mu = lift(mu);
+    let time_of_flight = lift(time_of_flight);
+    let (r1, r2) = (lift_vector(departure), lift_vector(arrival));
+    let (r1_length, r2_length) = (r1.length(), r2.length());
+    if time_of_flight <= DBig::ZERO || r1_length == DBig::ZERO || r2_length == DBig::ZERO {
+        return None;
+    }
+
+    let cos_angle = (r1.dot(&r2) / (&r1_length * &r2_length)).clamp(-DBig::ONE, DBig::ONE);
+    let mut angle = acos(cos_angle.clone(), 40);
+    if r1.cross(&r2).dot(normal) < DBig::ZERO {
+        angle = &*PIMUL2 - angle;
+    }
+    let one_minus_cos = DBig::ONE - &cos_angle;
+    if one_minus_cos == DBig::ZERO {
+        return None;
+    }
+    let a = sin(angle, 40) * (&r1_length * &r2_length / one_minus_cos).sqrt();
+    // positions half a turn apart leave sin(angle) at rounding noise
+    if a.clone().abs() < &*COLLINEAR_TOLERANCE * &r1_length {
+        return None;
+    }
+
+    // y(z) grows with z, the time of flight with it; z is bounded by a full turn above
+    let y = |z: &DBig| {
+        let (s, c) = stumpff(z);
+        &r1_length + &r2_length + &a * (z * s - DBig::ONE) / c.sqrt()
+    };
+    let time = |z: &DBig, y: &DBig| {
+        let (s, c) = stumpff(z);
+        let x = (y / &c).sqrt();
+        (&x * &x * &x * s + &a * y.sqrt()) / mu.sqrt()
+    };
+    let too_short = |z: &DBig| {
+        let y = y(z);
+        y < DBig::ZERO || time(z, &y) < time_of_flight
+    };
+
+    let full_turn = &*PIMUL2 * &*PIMUL2;
+    let mut high = lift(&full_turn);
+    let mut low = -lift(&full_turn);
+    while !too_short(&low) {
+        low *= DBig::from(2);
+    }
+    for _ in 0..BISECTION_ITERATIONS {
+        let middle = (&low + &high) / DBig::from(2);
+        if too_short(&middle) {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    let y = y(&high);
+    let f = DBig::ONE - &y / &r1_length;
+    let g = &a * (&y / &mu).sqrt();
+    let g_dot = DBig::ONE - &y / &r2_length;
+    Some(LambertSolution {
+        departure_velocity: (&r2 - &r1 * &f) / &g,
+        arrival_velocity: (&r2 * &g_dot - &r1) / &g,
+    })
+}
+
+impl Simulation {
+    /// transfer around `central_name` from where `from_name` is at `departure` to where `to_name`
+    /// is `time_of_flight` seconds later, in the sense `from_name` moves around it; positions are
+    /// taken from a copy of the simulation and the velocities are relative to the central body
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if any of the bodies isn't in the simulation, `InvalidDynamics` if the
+    /// departure body doesn't move around the central one or no transfer takes that time.
+    pub fn lambert_transfer(
+        &self,
+        central_name: &str,
+        from_name: &str,
+        to_name: &str,
+        departure: &DBig,
+        time_of_flight: &DBig,
+    ) -> Result<LambertSolution, SimulationError> {
+        self.get_body(central_name)?;
+        self.get_body(from_name)?;
+        self.get_body(to_name)?;
+        let mut sim = self.copy_bodies();
+
+        sim.update(departure);
+        let central = sim.get_body(central_name)?;
+        let from = sim.get_body(from_name)?;
+        let start = sim.world_position(from) - sim.world_position(central);
+        let normal = start.cross(&(sim.world_velocity(from) - sim.world_velocity(central)));
+        let mu = &*G_CONSTANT * lift(&central.body.mass_at(departure));
+
+        sim.update(&(departure + time_of_flight));
+        let central = sim.get_body(central_name)?;
+        let end = sim.world_position(sim.get_body(to_name)?) - sim.world_position(central);
+
+        if normal.length_squared() == DBig::ZERO {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{from_name}: doesn't move around {central_name}"
+            )));
+        }
+        lambert(&mu, &start, &end, time_of_flight, &normal).ok_or_else(|| {
+            SimulationError::InvalidDynamics(format!(
+                "{from_name}: no transfer to {to_name} around {central_name} for this time"
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::lambert::lambert;
+    use crate::sin_cos::f64_to_dbig;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn lambert_solver_works() {
+        // a quarter of a circular orbit, counterclockwise about +Y
+        let mu = f64_to_dbig(3.986e14);
+        let radius = 7e6f64;
+        let speed = (3.986e14 / radius).sqrt();
+        let period = 2.0 * std::f64::consts::PI * radius / speed;
+        let departure = DecimalVector3d::from_f64(radius, 0.0, 0.0);
+        let arrival = DecimalVector3d::from_f64(0.0, 0.0, -radius);
+        let up = DecimalVector3d::from_f64(0.0, 1.0, 0.0);
+        let epsilon = f64_to_dbig(1e-3);
+
+        let quarter = lambert(&mu, &departure, &arrival, &f64_to_dbig(period / 4.0), &up).unwrap();
+        assert!(quarter
+            .departure_velocity
+            .approx_eq(&DecimalVector3d::from_f64(0.0, 0.0, -speed), &epsilon));
+        assert!(quarter
+            .arrival_velocity
+            .approx_eq(&DecimalVector3d::from_f64(-speed, 0.0, 0.0), &epsilon));
+
+        // the other way around is three quarters
+        let down = DecimalVector3d::from_f64(0.0, -1.0, 0.0);
+        let long = lambert(
+            &mu,
+            &departure,
+            &arrival,
+            &f64_to_dbig(period * 0.75),
+            &down,
+        )
+        .unwrap();
+        assert!(long
+            .departure_velocity
+            .approx_eq(&DecimalVector3d::from_f64(0.0, 0.0, speed), &epsilon));
+
+        // faster than the circle is a hyperbola, energy and momentum match at both ends
+        let fast = lambert(&mu, &departure, &arrival, &f64_to_dbig(period / 20.0), &up).unwrap();
+        let energy = |position: &DecimalVector3d, velocity: &DecimalVector3d| {
+            dbig_to_f64(&velocity.length_squared()) / 2.0
+                - 3.986e14 / dbig_to_f64(&position.length())
+        };
+        let start_energy = energy(&departure, &fast.departure_velocity);
+        assert!(start_energy > 0.0);
+        assert!((start_energy - energy(&arrival, &fast.arrival_velocity)).abs() < 1e-3);
+        let momentum = departure.cross(&fast.departure_velocity);
+        assert!(momentum.approx_eq(&arrival.cross(&fast.arrival_velocity), &DBig::ONE));
+
+        let opposite = DecimalVector3d::from_f64(-radius, 0.0, 0.0);
+        assert!(lambert(&mu, &departure, &opposite, &f64_to_dbig(period / 2.0), &up).is_none());
+        assert!(lambert(&mu, &departure, &arrival, &DBig::ZERO, &up).is_none());
+
+        // earth to where the moon is a week later, around the sun
+        let sim = prepare_sim();
+        let week = DBig::from(7 * 24 * 3600);
+        let transfer = sim
+            .lambert_transfer("sun", "earth", "moon", &DBig::ZERO, &week)
+            .unwrap();
+        let speed = dbig_to_f64(&transfer.departure_velocity.length());
+        assert!(speed > 25000.0 && speed < 35000.0, "{}", speed);
+        assert_eq!(
+            sim.lambert_transfer("sun", "earth", "pluto", &DBig::ZERO, &week)
+                .unwrap_err(),
+            SimulationError::UnknownBody(String::from("pluto"))
+        );
+    }
+}
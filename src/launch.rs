@@ -0,0 +1,213 @@
+use crate::error::SimulationError;
+use crate::simulation::{Simulation, G_CONSTANT};
+use crate::sin_cos::{asin, atan2, cos, PI, PIMUL2};
+use crate::surface::surface_frame;
+use dashu_float::ops::{Abs, SquareRoot};
+use dashu_float::DBig;
+
+const PRECISION: usize = 40;
+const REFINE_ITERATIONS: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct LaunchSite {
+    pub body: String,
+    pub latitude: DBig,  // in radians
+    pub longitude: DBig, // in radians
+}
+
+#[derive(Debug, Clone)]
+pub struct LaunchSolution {
+    pub azimuth: DBig, // in radians from north towards east, to fly relative to the ground
+    pub inertial_azimuth: DBig, // in radians, direction of the orbital velocity at insertion
+    pub delta_v: DBig, // in m/s, ideal, climb and orbital speed without gravity or drag losses
+}
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+fn wrap(angle: DBig) -> DBig {
+    let turns = (&angle / &*PIMUL2).floor();
+    angle - turns * &*PIMUL2
+}
+
+impl Simulation {
+    /// direct ascent into a circular orbit at `altitude` with the given inclination to the body's
+    /// equator, northbound or southbound; None if the latitude is higher than the inclination
+    /// allows, such orbits need a plane change after insertion
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the site body isn't in the simulation.
+    pub fn launch_solution(
+        &self,
+        site: &LaunchSite,
+        inclination: &DBig,
+        altitude: &DBig,
+        northbound: bool,
+    ) -> Result<Option<LaunchSolution>, SimulationError> {
+        let body = &self.get_body(&site.body)?.body;
+        let cos_latitude = cos(site.latitude.clone(), 32);
+        let sin_azimuth = cos(inclination.clone(), 32) / &cos_latitude;
+        if sin_azimuth.clone().abs() > DBig::ONE {
+            return Ok(None);
+        }
+        let mut inertial_azimuth = asin(sin_azimuth.clone(), 32);
+        if !northbound {
+            inertial_azimuth = &*PI - inertial_azimuth;
+        }
+        let inertial_azimuth = wrap(inertial_azimuth);
+
+        let mu = &*G_CONSTANT * lift(&body.mass_at(&self.time));
+        let surface_radius = lift(&body.radius);
+        let orbit_radius = &surface_radius + lift(altitude);
+        let orbit_speed = (&mu / &orbit_radius).sqrt();
+        let rotation_speed = &*PIMUL2 / lift(&body.spin_period()) * &surface_radius * &cos_latitude;
+
+        let east = &orbit_speed * &sin_azimuth - rotation_speed;
+        let cos_azimuth = (DBig::ONE - &sin_azimuth * &sin_azimuth).sqrt();
+        let mut north = &orbit_speed * cos_azimuth;
+        if !northbound {
+            north = -north;
+        }
+        let climb = DBig::from(2) * &mu * (DBig::ONE / &surface_radius - DBig::ONE / &orbit_radius);
+        let delta_v = (&east * &east + &north * &north + climb).sqrt();
+        Ok(Some(LaunchSolution {
+            azimuth: wrap(atan2(east, north, 32)),
+            inertial_azimuth,
+            delta_v,
+        }))
+    }
+
+    /// times between `start` and `end` when the rotation carries the site through the orbit plane
+    /// of `target_name`, a satellite of the launch body, found on a copy of the simulation by
+    /// sampling every `step` and bisecting; the step has to be well below half a rotation
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the site body or the target isn't in the simulation.
+    pub fn launch_windows(
+        &self,
+        site: &LaunchSite,
+        target_name: &str,
+        start: &DBig,
+        end: &DBig,
+        step: &DBig,
+    ) -> Result<Vec<DBig>, SimulationError> {
+        self.get_body(&site.body)?;
+        self.get_body(target_name)?;
+        let mut sim = self.copy_bodies();
+        // signed distance of the site direction from the target plane
+        let mut plane_offset = |time: &DBig| -> Result<DBig, SimulationError> {
+            sim.update(time);
+            let body = sim.get_body(&site.body)?;
+            let target = sim.get_body(target_name)?;
+            let offset = sim.world_position(target) - sim.world_position(body);
+            let velocity = sim.world_velocity(target) - sim.world_velocity(body);
+            let normal = offset.cross(&velocity);
+            let [up, _, _] = surface_frame(body, &site.latitude, &site.longitude);
+            Ok(up.dot(&normal))
+        };
+
+        let mut result: Vec<DBig> = vec![];
+        let mut time = lift(start);
+        let end = lift(end);
+        let step = lift(step);
+        let mut offset = plane_offset(&time)?;
+        while time < end {
+            let next_time = (&time + &step).min(end.clone());
+            let next_offset = plane_offset(&next_time)?;
+            if (offset < DBig::ZERO) != (next_offset < DBig::ZERO) {
+                let (mut low, mut high) = (time.clone(), next_time.clone());
+                for _ in 0..REFINE_ITERATIONS {
+                    let middle = (&low + &high) / DBig::from(2);
+                    if (plane_offset(&middle)? < DBig::ZERO) == (offset < DBig::ZERO) {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                result.push(high);
+            }
+            time = next_time;
+            offset = next_offset;
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::launch::LaunchSite;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn launch_works() {
+        let sim = prepare_sim();
+        let degrees = |v: f64| f64_to_dbig(v.to_radians());
+        let site = |latitude: f64| LaunchSite {
+            body: String::from("earth"),
+            latitude: degrees(latitude),
+            longitude: DBig::ZERO,
+        };
+        let altitude = DBig::from(200_000);
+
+        let equatorial = sim
+            .launch_solution(&site(0.0), &DBig::ZERO, &altitude, true)
+            .unwrap()
+            .unwrap();
+        assert!(approx_eq(
+            &equatorial.azimuth,
+            &f64_to_dbig(90f64.to_radians()),
+            &f64_to_dbig(1e-6)
+        ));
+        let mu = 6.67408e-11 * 5.97219e24;
+        let (surface, orbit) = (6_371_000.0_f64, 6_571_000.0_f64);
+        let expected =
+            ((mu / orbit).sqrt() - 463.31f64).powi(2) + 2.0 * mu * (1.0 / surface - 1.0 / orbit);
+        assert!(approx_eq(
+            &equatorial.delta_v,
+            &f64_to_dbig(expected.sqrt()),
+            &f64_to_dbig(0.5)
+        ));
+
+        let inclined = sim
+            .launch_solution(&site(45.6), &degrees(51.6), &altitude, true)
+            .unwrap()
+            .unwrap();
+        let inertial = dbig_to_f64(&inclined.inertial_azimuth).to_degrees();
+        assert!((inertial - 62.6).abs() < 0.1);
+        // the ground already moves east, so the rocket aims further north than the orbit
+        assert!(dbig_to_f64(&inclined.azimuth).to_degrees() < inertial);
+        let southbound = sim
+            .launch_solution(&site(45.6), &degrees(51.6), &altitude, false)
+            .unwrap()
+            .unwrap();
+        assert!(
+            (dbig_to_f64(&southbound.inertial_azimuth).to_degrees() - (180.0 - inertial)).abs()
+                < 1e-6
+        );
+        assert!(sim
+            .launch_solution(&site(60.0), &degrees(30.0), &altitude, true)
+            .unwrap()
+            .is_none());
+
+        // the moon's plane is tilted a few degrees from the equator, the equator crosses it twice a day
+        let windows = sim
+            .launch_windows(
+                &site(0.0),
+                "moon",
+                &DBig::ZERO,
+                &DBig::from(36 * 3600),
+                &DBig::from(2 * 3600),
+            )
+            .unwrap();
+        assert!(windows.len() >= 3);
+        for pair in windows.windows(2) {
+            let gap = dbig_to_f64(&(&pair[1] - &pair[0])) / 3600.0;
+            assert!(gap > 11.0 && gap < 13.0);
+        }
+    }
+}
@@ -1,7 +1,76 @@
+pub mod alignment;
+pub mod anomaly;
+pub mod atmosphere;
 pub mod au;
 pub mod body;
+pub mod bookmarks;
+pub mod camera;
+pub mod capture;
+pub mod celestia;
+pub mod collision;
+pub mod coordinates;
+pub mod czml;
 pub mod decimal_matrix_3d;
 pub mod decimal_vector_3d;
+pub mod delta;
+pub mod delta_v;
+pub mod eclipse;
+pub mod elements;
+pub mod ensemble;
+pub mod entry;
+pub mod ephemeris;
+pub mod error;
+pub mod export_scale;
+pub mod format;
+#[cfg(feature = "gpu")]
+pub mod gpu_gravity;
+pub mod iau;
+pub mod kepler;
+pub mod ksp;
+pub mod lagrange;
+pub mod lambert;
+pub mod launch;
+pub mod lockstep;
+pub mod lunar_phase;
+pub mod nbody;
+pub mod observer;
+pub mod octree;
+pub mod orbit_classification;
+pub mod orbit_design;
+pub mod orbit_fit;
+pub mod orbit_path;
+pub mod orbit_vectors;
+pub mod particles;
+pub mod patched_conics;
+pub mod phase_angle;
+pub mod propagation;
+pub mod realtime;
+pub mod rendezvous;
+pub mod retrograde;
+pub mod rings;
+pub mod rollback;
+pub mod rotation_tracks;
+pub mod scenario;
+pub mod sensitivity;
 pub mod simulation;
 pub mod sin_cos;
+pub mod sleep;
+pub mod snapshot;
+pub mod soi;
+pub mod spacecraft;
+pub mod stats;
+pub mod surface;
+#[cfg(test)]
 mod tests;
+pub mod tidal_limits;
+pub mod triggers;
+pub mod uncertainty;
+pub mod vis_viva;
+pub mod visibility;
+
+pub use au::{au_to_meters, meters_to_au, AU_METERS};
+pub use body::{Body, BodyDynamics, BodyKind, OrbitingBodyDynamics, StaticBodyDynamics};
+pub use decimal_matrix_3d::DecimalMatrix3d;
+pub use decimal_vector_3d::DecimalVector3d;
+pub use error::SimulationError;
+pub use simulation::Simulation;
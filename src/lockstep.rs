@@ -0,0 +1,199 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::{check_positive, SimulationError};
+use crate::simulation::Simulation;
+use dashu_float::DBig;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+// fixed steps for simulations run side by side, like multiplayer clients: the time of every tick
+// comes from the tick count instead of adding up steps, every body is updated on every tick
+// regardless of its update interval, and the state is rounded to `precision` digits afterwards.
+// The arithmetic is all decimal in software, so the same ticks give the same digits everywhere
+#[derive(Debug, Clone)]
+pub struct Lockstep {
+    pub step: DBig,       // in seconds
+    pub precision: usize, // in decimal digits, enough for the time and the world positions
+    pub start: DBig,
+    pub tick: u64,
+}
+
+fn fix(v: &DBig, precision: usize) -> DBig {
+    v.clone().with_precision(precision).value()
+}
+
+fn fix_vector(v: &DecimalVector3d, precision: usize) -> DecimalVector3d {
+    DecimalVector3d::new(
+        fix(&v.x, precision),
+        fix(&v.y, precision),
+        fix(&v.z, precision),
+    )
+}
+
+impl Simulation {
+    /// tick 0 is the current time, the state is rounded right away so every client starts from
+    /// the same digits
+    ///
+    /// # Errors
+    ///
+    /// `InvalidArgument` if `step` isn't positive.
+    pub fn enable_lockstep(
+        &mut self,
+        step: &DBig,
+        precision: usize,
+    ) -> Result<(), SimulationError> {
+        check_positive(step, "step")?;
+        self.lockstep = Some(Lockstep {
+            step: fix(step, precision),
+            precision,
+            start: fix(&self.time, precision),
+            tick: 0,
+        });
+        self.fix_state(precision);
+        Ok(())
+    }
+
+    pub fn disable_lockstep(&mut self) {
+        self.lockstep = None;
+    }
+
+    pub fn lockstep(&self) -> Option<&Lockstep> {
+        self.lockstep.as_ref()
+    }
+
+    /// advances by one tick and gives its number; a plain update in between leaves the tick grid
+    /// and breaks the agreement with the other clients
+    ///
+    /// # Errors
+    ///
+    /// `InvalidState` if lockstep isn't enabled.
+    pub fn step_lockstep(&mut self) -> Result<u64, SimulationError> {
+        let Some(lockstep) = self.lockstep.as_mut() else {
+            return Err(SimulationError::InvalidState(String::from(
+                "lockstep: isn't enabled",
+            )));
+        };
+        lockstep.tick += 1;
+        let (tick, precision) = (lockstep.tick, lockstep.precision);
+        let time = fix(
+            &(&lockstep.start + &lockstep.step * DBig::from(tick)),
+            precision,
+        );
+        for body in &mut self.bodies {
+            body.last_update = None;
+        }
+        self.update(&time);
+        self.fix_state(precision);
+        // the rounded state replaces the one recorded by the update
+        self.record_history();
+        Ok(tick)
+    }
+
+    // FNV-1a over the time and the state of every body, equal on clients in agreement and cheap
+    // to send around; the digits count, not the precision they are kept at
+    pub fn state_checksum(&self) -> u64 {
+        let mut bytes: Vec<u8> = vec![];
+        let mut push = |v: &DBig| {
+            // as text, the marker and the terminator keep neighbouring values apart
+            let repr = v.repr();
+            bytes.extend(format!("{}e{};", repr.significand(), repr.exponent()).as_bytes());
+        };
+        push(&self.time);
+        for body in &self.bodies {
+            for vector in [&body.position, &body.relative_position, &body.velocity] {
+                for v in [&vector.x, &vector.y, &vector.z] {
+                    push(v);
+                }
+            }
+            for v in body.orientation.data.iter().flatten() {
+                push(v);
+            }
+        }
+        bytes.iter().fold(FNV_OFFSET, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+        })
+    }
+
+    fn fix_state(&mut self, precision: usize) {
+        self.time = fix(&self.time, precision);
+        for body in &mut self.bodies {
+            body.position = fix_vector(&body.position, precision);
+            body.relative_position = fix_vector(&body.relative_position, precision);
+            body.velocity = fix_vector(&body.velocity, precision);
+            for row in &mut body.orientation.data {
+                for value in row.iter_mut() {
+                    *value = fix(value, precision);
+                }
+            }
+        }
+        self.rebuild_index();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::SimulationError;
+    use crate::simulation::Simulation;
+    use crate::tests::prepare_sim;
+    use dashu_float::DBig;
+    use std::str::FromStr;
+
+    #[test]
+    fn lockstep_works() {
+        let mut host = prepare_sim();
+        host.get_body_mut("moon").unwrap().update_interval = Some(DBig::from(10 * 24 * 3600));
+        host.update(&DBig::ZERO);
+        host.enable_lockstep(&DBig::from_str("0.1").unwrap(), 48)
+            .unwrap();
+        for _ in 0..3 {
+            host.step_lockstep().unwrap();
+        }
+
+        // a client joining from a snapshot goes on in agreement
+        let mut buf: Vec<u8> = vec![];
+        host.write_snapshot(&mut buf).unwrap();
+        let mut client = Simulation::read_snapshot(&mut buf.as_slice()).unwrap();
+        assert_eq!(client.lockstep().unwrap().tick, 3);
+        assert_eq!(client.state_checksum(), host.state_checksum());
+        for _ in 0..7 {
+            host.step_lockstep().unwrap();
+            client.step_lockstep().unwrap();
+            assert_eq!(client.state_checksum(), host.state_checksum());
+        }
+        // the time comes from the tick count, the tenths don't add up any error
+        assert_eq!(host.step_lockstep(), Ok(11));
+        client.step_lockstep().unwrap();
+        assert_eq!(host.time(), &DBig::from_str("1.1").unwrap());
+        // the moon moves on every tick despite its update interval
+        let moon = host.get_body("moon").unwrap();
+        assert_eq!(moon.last_update, Some(host.time().clone()));
+
+        // leaving the grid shows in the checksum
+        client.update(&DBig::from_str("1.15").unwrap());
+        assert_ne!(client.state_checksum(), host.state_checksum());
+        host.disable_lockstep();
+        assert!(host.lockstep().is_none());
+    }
+
+    #[test]
+    fn lockstep_errors() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        assert_eq!(
+            sim.step_lockstep(),
+            Err(SimulationError::InvalidState(String::from(
+                "lockstep: isn't enabled"
+            )))
+        );
+        assert_eq!(
+            sim.enable_lockstep(&DBig::ZERO, 48),
+            Err(SimulationError::InvalidArgument(String::from(
+                "the step has to be positive"
+            )))
+        );
+        assert!(sim.lockstep().is_none());
+        sim.enable_lockstep(&DBig::ONE, 48).unwrap();
+        sim.disable_lockstep();
+        assert!(sim.step_lockstep().is_err());
+    }
+}
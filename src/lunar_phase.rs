@@ -0,0 +1,133 @@
+use crate::error::SimulationError;
+use crate::phase_angle::wrap_angle;
+use crate::simulation::Simulation;
+use crate::sin_cos::{atan2, PIDIV2};
+use dashu_float::DBig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LunarPhase {
+    New,
+    FirstQuarter,
+    Full,
+    LastQuarter,
+}
+
+#[derive(Debug, Clone)]
+pub struct PhaseEvent {
+    pub phase: LunarPhase,
+    pub time: DBig,
+}
+
+const PHASES: [LunarPhase; 4] = [
+    LunarPhase::New,
+    LunarPhase::FirstQuarter,
+    LunarPhase::Full,
+    LunarPhase::LastQuarter,
+];
+
+impl Simulation {
+    /// angle from the star to the moon as seen from the observer, in radians within [0, 2pi) and
+    /// growing along the moon's motion around the observer; 0 is new, pi/2 first quarter, pi full
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if any of the bodies isn't in the simulation.
+    pub fn lunar_elongation(
+        &self,
+        moon_name: &str,
+        star_name: &str,
+        observer_name: &str,
+    ) -> Result<DBig, SimulationError> {
+        let moon = self.get_body(moon_name)?;
+        let observer = self.get_body(observer_name)?;
+        let observer_position = self.world_position(observer);
+        let moon_offset = self.world_position(moon) - &observer_position;
+        let star_offset = self.world_position(self.get_body(star_name)?) - &observer_position;
+        let moon_velocity = self.world_velocity(moon) - self.world_velocity(observer);
+        let normal = moon_offset.cross(&moon_velocity).normalized();
+
+        let star_in_plane = &star_offset - &normal * normal.dot(&star_offset);
+        let angle = atan2(
+            normal.dot(&star_in_plane.cross(&moon_offset)),
+            star_in_plane.dot(&moon_offset),
+            32,
+        );
+        Ok(wrap_angle(angle))
+    }
+
+    /// new, quarter and full moons between `start` and `end`, sampled every `step` and refined by
+    /// bisection. The step has to be well below a quarter of the synodic period
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if any of the bodies isn't in the simulation, `InvalidState` if one of them is
+    /// sleeping.
+    pub fn lunar_phase_calendar(
+        &self,
+        moon_name: &str,
+        star_name: &str,
+        observer_name: &str,
+        start: &DBig,
+        end: &DBig,
+        step: &DBig,
+    ) -> Result<Vec<PhaseEvent>, SimulationError> {
+        for name in [moon_name, star_name, observer_name] {
+            self.get_awake_body(name)?;
+        }
+        let targets: Vec<DBig> = (0..PHASES.len())
+            .map(|i| &*PIDIV2 * DBig::from(i))
+            .collect();
+        Ok(self
+            .angle_crossings(start, end, step, &targets, |sim| {
+                sim.lunar_elongation(moon_name, star_name, observer_name)
+            })?
+            .into_iter()
+            .map(|(i, time)| PhaseEvent {
+                phase: PHASES[i],
+                time,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lunar_phase::LunarPhase;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn lunar_phase_calendar_works() {
+        let sim = prepare_sim();
+        let day = 24.0 * 3600.0;
+        let events = sim
+            .lunar_phase_calendar(
+                "moon",
+                "sun",
+                "earth",
+                &DBig::ZERO,
+                &DBig::from(40 * 24 * 3600),
+                &DBig::from(24 * 3600),
+            )
+            .unwrap();
+        assert!(events.len() >= 5);
+        let synodic = 1.0 / (1.0 / 27.0 - 1.0 / 365.0);
+        for pair in events.windows(2) {
+            let interval = (dbig_to_f64(&pair[1].time) - dbig_to_f64(&pair[0].time)) / day;
+            assert!((interval - synodic / 4.0).abs() < 0.5);
+        }
+        for quad in events.windows(5) {
+            assert_eq!(quad[0].phase, quad[4].phase);
+            let interval = (dbig_to_f64(&quad[4].time) - dbig_to_f64(&quad[0].time)) / day;
+            assert!((interval - synodic).abs() < 0.1);
+        }
+        let full = events
+            .iter()
+            .find(|event| event.phase == LunarPhase::Full)
+            .unwrap();
+        let mut sim = sim;
+        sim.update(&full.time);
+        let elongation = dbig_to_f64(&sim.lunar_elongation("moon", "sun", "earth").unwrap());
+        assert!((elongation - std::f64::consts::PI).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,520 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::{check_positive, SimulationError};
+use crate::simulation::{Simulation, G_CONSTANT};
+use dashu_float::ops::SquareRoot;
+use dashu_float::DBig;
+
+const PRECISION: usize = 40;
+// substeps are this fraction of the time the closest pair takes to meet, and at least this
+// fraction of the step
+const ENCOUNTER_FRACTION: i64 = 32;
+const MAX_SUBSTEPS: i64 = 4096;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+fn lift_vector(v: &DecimalVector3d) -> DecimalVector3d {
+    DecimalVector3d::new(lift(&v.x), lift(&v.y), lift(&v.z))
+}
+
+// world state of a point mass, the hierarchy is gone once the bodies are integrated
+#[derive(Debug, Clone)]
+pub struct NBodyState {
+    pub name: String,
+    pub mass: DBig,
+    pub position: DecimalVector3d,
+    pub velocity: DecimalVector3d,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Integrator {
+    RungeKutta4, // accurate per step, the energy slowly drifts over long runs
+    Leapfrog,    // kick-drift-kick, symplectic, the energy error stays bounded
+}
+
+// numerical alternative to the analytic hierarchy, every body pulls on every other one so the
+// orbits drift and perturb each other instead of repeating. Masses stay as they were seeded,
+// rotation isn't integrated
+#[derive(Debug, Clone)]
+pub struct NBodySimulation {
+    pub bodies: Vec<NBodyState>,
+    time: DBig,
+    integrator: Integrator,
+    recentering: Option<DecimalVector3d>, // barycenter held in place after every step
+}
+
+impl NBodySimulation {
+    pub fn new(bodies: Vec<NBodyState>, time: &DBig) -> Self {
+        NBodySimulation {
+            bodies: bodies
+                .into_iter()
+                .map(|body| NBodyState {
+                    mass: lift(&body.mass),
+                    position: lift_vector(&body.position),
+                    velocity: lift_vector(&body.velocity),
+                    name: body.name,
+                })
+                .collect(),
+            time: lift(time),
+            integrator: Integrator::RungeKutta4,
+            recentering: None,
+        }
+    }
+
+    // every body at the state of the last update of the simulation
+    pub fn from_simulation(sim: &Simulation) -> Self {
+        let bodies = sim
+            .bodies
+            .iter()
+            .map(|body| NBodyState {
+                name: body.body.name.clone(),
+                mass: body.body.mass_at(&sim.time),
+                position: sim.world_position(body),
+                velocity: sim.world_velocity(body),
+            })
+            .collect();
+        Self::new(bodies, &sim.time)
+    }
+
+    pub fn time(&self) -> &DBig {
+        &self.time
+    }
+
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        self.integrator = integrator;
+    }
+
+    pub fn integrator(&self) -> Integrator {
+        self.integrator
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if there is no body of that name.
+    pub fn get_body(&self, body_name: &str) -> Result<&NBodyState, SimulationError> {
+        self.bodies
+            .iter()
+            .find(|body| body.name == body_name)
+            .ok_or_else(|| SimulationError::UnknownBody(body_name.to_string()))
+    }
+
+    /// kinetic plus potential energy of the whole system, constant up to the integration error
+    ///
+    /// # Errors
+    ///
+    /// `InvalidState` if two bodies are at the same position.
+    pub fn total_energy(&self) -> Result<DBig, SimulationError> {
+        let mut energy = DBig::ZERO;
+        for (i, body) in self.bodies.iter().enumerate() {
+            energy += &body.mass * body.velocity.length_squared() / DBig::from(2);
+            for other in &self.bodies[i + 1..] {
+                let distance = body.position.distance_to(&other.position);
+                if distance == DBig::ZERO {
+                    return Err(coincident(body, other));
+                }
+                energy -= &*G_CONSTANT * &body.mass * &other.mass / distance;
+            }
+        }
+        Ok(energy)
+    }
+
+    pub fn total_momentum(&self) -> DecimalVector3d {
+        let mut momentum = DecimalVector3d::zero();
+        for body in &self.bodies {
+            momentum = momentum + &body.velocity * &body.mass;
+        }
+        momentum
+    }
+
+    fn total_mass(&self) -> DBig {
+        self.bodies.iter().map(|body| &body.mass).sum()
+    }
+
+    // the origin for a massless system
+    pub fn barycenter(&self) -> DecimalVector3d {
+        let total_mass = self.total_mass();
+        if total_mass == DBig::ZERO {
+            return DecimalVector3d::zero();
+        }
+        let mut weighted = DecimalVector3d::zero();
+        for body in &self.bodies {
+            weighted = weighted + &body.position * &body.mass;
+        }
+        weighted / total_mass
+    }
+
+    // speed of the whole system through space, zero for a massless system
+    pub fn barycenter_velocity(&self) -> DecimalVector3d {
+        let total_mass = self.total_mass();
+        if total_mass == DBig::ZERO {
+            return DecimalVector3d::zero();
+        }
+        self.total_momentum() / total_mass
+    }
+
+    // a system seeded from a hierarchy with a static root usually carries momentum, the root
+    // stands still while everything goes around it, and the whole system drifts away with it
+    pub fn is_momentum_balanced(&self, max_speed: &DBig) -> bool {
+        self.barycenter_velocity().length() <= *max_speed
+    }
+
+    // takes the momentum out of the system and moves it so the barycenter is at `position`,
+    // where it then stays; recentering holds it there from now on
+    pub fn recenter(&mut self, position: &DecimalVector3d) {
+        let position = lift_vector(position);
+        let velocity = self.barycenter_velocity();
+        let shift = &position - self.barycenter();
+        for body in &mut self.bodies {
+            body.velocity = &body.velocity - &velocity;
+            body.position = &body.position + &shift;
+        }
+        if self.recentering.is_some() {
+            self.recentering = Some(position);
+        }
+    }
+
+    // with recentering the momentum is taken out now and the barycenter is put back where it is
+    // after every step, so rounding can't make the system wander over long integrations
+    pub fn set_recentering(&mut self, enabled: bool) {
+        self.recentering = None;
+        if enabled {
+            let barycenter = self.barycenter();
+            self.recenter(&barycenter);
+            self.recentering = Some(barycenter);
+        }
+    }
+
+    pub fn recentering(&self) -> bool {
+        self.recentering.is_some()
+    }
+
+    /// advances by `duration` seconds in fixed `step`s, the last one shortened to land on the end.
+    /// Close encounters split a step into substeps, a fraction of the time the closest pair takes
+    /// to fall into each other or to cover their distance, picked again after every substep so
+    /// slingshots don't throw the bodies apart
+    ///
+    /// # Errors
+    ///
+    /// `InvalidArgument` if `step` isn't positive, `InvalidState` if two bodies meet at the same
+    /// position.
+    pub fn advance(&mut self, duration: &DBig, step: &DBig) -> Result<(), SimulationError> {
+        check_positive(step, "step")?;
+        let end = &self.time + lift(duration);
+        while self.time < end {
+            let step = lift(step).min(&end - &self.time);
+            let target = &self.time + &step;
+            let shortest = &step / DBig::from(MAX_SUBSTEPS);
+            while self.time < target {
+                let substep = match self.encounter_time()? {
+                    Some(time) => (time / DBig::from(ENCOUNTER_FRACTION)).max(shortest.clone()),
+                    None => step.clone(),
+                };
+                self.step(&substep.min(&target - &self.time))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// one step over all the bodies at once with the selected integrator, without substeps
+    ///
+    /// # Errors
+    ///
+    /// `InvalidState` if two bodies are at the same position.
+    pub fn step(&mut self, step: &DBig) -> Result<(), SimulationError> {
+        match self.integrator {
+            Integrator::RungeKutta4 => self.runge_kutta_step(step)?,
+            Integrator::Leapfrog => self.leapfrog_step(step)?,
+        }
+        if let Some(barycenter) = self.recentering.clone() {
+            self.recenter(&barycenter);
+        }
+        self.time = &self.time + step;
+        Ok(())
+    }
+
+    // the shortest time any pair takes to fall into each other or to cover their distance, None
+    // when nothing moves or pulls
+    fn encounter_time(&self) -> Result<Option<DBig>, SimulationError> {
+        let mut shortest: Option<DBig> = None;
+        for (i, body) in self.bodies.iter().enumerate() {
+            for other in &self.bodies[i + 1..] {
+                let distance = body.position.distance_to(&other.position);
+                if distance == DBig::ZERO {
+                    return Err(coincident(body, other));
+                }
+                let mut times = vec![];
+                let pull = &*G_CONSTANT * (&body.mass + &other.mass);
+                if pull > DBig::ZERO {
+                    times.push((&distance * &distance * &distance / pull).sqrt());
+                }
+                let speed = body.velocity.distance_to(&other.velocity);
+                if speed > DBig::ZERO {
+                    times.push(&distance / speed);
+                }
+                for time in times {
+                    if shortest.as_ref().is_none_or(|shortest| time < *shortest) {
+                        shortest = Some(time);
+                    }
+                }
+            }
+        }
+        Ok(shortest)
+    }
+
+    fn leapfrog_step(&mut self, step: &DBig) -> Result<(), SimulationError> {
+        let half = step / DBig::from(2);
+        let positions: Vec<DecimalVector3d> =
+            self.bodies.iter().map(|b| b.position.clone()).collect();
+        let kick = self.accelerations(&positions)?;
+        for (body, acceleration) in self.bodies.iter_mut().zip(kick) {
+            body.velocity = &body.velocity + acceleration * &half;
+            body.position = &body.position + &body.velocity * step;
+        }
+        let positions: Vec<DecimalVector3d> =
+            self.bodies.iter().map(|b| b.position.clone()).collect();
+        let kick = self.accelerations(&positions)?;
+        for (body, acceleration) in self.bodies.iter_mut().zip(kick) {
+            body.velocity = &body.velocity + acceleration * &half;
+        }
+        Ok(())
+    }
+
+    fn runge_kutta_step(&mut self, step: &DBig) -> Result<(), SimulationError> {
+        let half = step / DBig::from(2);
+        let positions: Vec<DecimalVector3d> =
+            self.bodies.iter().map(|b| b.position.clone()).collect();
+        let velocities: Vec<DecimalVector3d> =
+            self.bodies.iter().map(|b| b.velocity.clone()).collect();
+        let offset = |base: &[DecimalVector3d], rate: &[DecimalVector3d], time: &DBig| {
+            base.iter()
+                .zip(rate)
+                .map(|(value, rate)| value + rate * time)
+                .collect::<Vec<_>>()
+        };
+
+        let a1 = self.accelerations(&positions)?;
+        let v1 = velocities.clone();
+        let v2 = offset(&velocities, &a1, &half);
+        let a2 = self.accelerations(&offset(&positions, &v1, &half))?;
+        let v3 = offset(&velocities, &a2, &half);
+        let a3 = self.accelerations(&offset(&positions, &v2, &half))?;
+        let v4 = offset(&velocities, &a3, step);
+        let a4 = self.accelerations(&offset(&positions, &v3, step))?;
+
+        let sixth = step / DBig::from(6);
+        let two = DBig::from(2);
+        for (i, body) in self.bodies.iter_mut().enumerate() {
+            let velocity_sum = &v1[i] + (&v2[i] + &v3[i]) * &two + &v4[i];
+            let acceleration_sum = &a1[i] + (&a2[i] + &a3[i]) * &two + &a4[i];
+            body.position = &body.position + velocity_sum * &sixth;
+            body.velocity = &body.velocity + acceleration_sum * &sixth;
+        }
+        Ok(())
+    }
+
+    // pairwise gravity, each pair is evaluated once and applied to both sides
+    fn accelerations(
+        &self,
+        positions: &[DecimalVector3d],
+    ) -> Result<Vec<DecimalVector3d>, SimulationError> {
+        let mut accelerations = vec![DecimalVector3d::zero(); positions.len()];
+        for i in 0..positions.len() {
+            for j in i + 1..positions.len() {
+                let relative = &positions[j] - &positions[i];
+                let length_squared = relative.length_squared();
+                if length_squared == DBig::ZERO {
+                    return Err(coincident(&self.bodies[i], &self.bodies[j]));
+                }
+                let pull = relative / (&length_squared * length_squared.sqrt());
+                accelerations[i] =
+                    &accelerations[i] + &pull * (&*G_CONSTANT * &self.bodies[j].mass);
+                accelerations[j] =
+                    &accelerations[j] - &pull * (&*G_CONSTANT * &self.bodies[i].mass);
+            }
+        }
+        Ok(accelerations)
+    }
+}
+
+fn coincident(body: &NBodyState, other: &NBodyState) -> SimulationError {
+    SimulationError::InvalidState(format!(
+        "{} and {} are at the same position",
+        body.name, other.name
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::nbody::{Integrator, NBodySimulation, NBodyState};
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn nbody_simulation_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let mut nbody = NBodySimulation::from_simulation(&sim);
+        assert_eq!(nbody.bodies.len(), 3);
+        let momentum = |nbody: &NBodySimulation| {
+            nbody
+                .bodies
+                .iter()
+                .fold(DecimalVector3d::zero(), |sum, body| {
+                    sum + &body.velocity * &body.mass
+                })
+        };
+        let (energy, start_momentum) = (nbody.total_energy().unwrap(), momentum(&nbody));
+
+        let day = DBig::from(24 * 3600);
+        nbody.advance(&day, &DBig::from(3600)).unwrap();
+        assert_eq!(nbody.time(), &DBig::from(86400));
+        let drift = (nbody.total_energy().unwrap() - &energy) / &energy;
+        assert!(approx_eq(&drift, &DBig::ZERO, &f64_to_dbig(1e-12)));
+        let change = (momentum(&nbody) - &start_momentum).length() / start_momentum.length();
+        assert!(dbig_to_f64(&change) < 1e-12);
+
+        // the moon tugs the earth aside, noticeably but not by much in a day
+        let seeded = NBodySimulation::from_simulation(&sim);
+        let without_moon = seeded
+            .bodies
+            .into_iter()
+            .filter(|body| body.name != "moon")
+            .collect();
+        let mut two_body = NBodySimulation::new(without_moon, &DBig::ZERO);
+        two_body.advance(&day, &DBig::from(3600)).unwrap();
+        let perturbed = &nbody.get_body("earth").unwrap().position;
+        let offset = perturbed.distance_to(&two_body.get_body("earth").unwrap().position);
+        let offset = dbig_to_f64(&offset);
+        assert!(offset > 10000.0 && offset < 1e6, "{}", offset);
+        assert!(matches!(
+            nbody.get_body("pluto"),
+            Err(SimulationError::UnknownBody(_))
+        ));
+        assert_eq!(
+            nbody.advance(&day, &DBig::ZERO),
+            Err(SimulationError::InvalidArgument(String::from(
+                "the step has to be positive"
+            )))
+        );
+        assert_eq!(nbody.time(), &DBig::from(86400));
+    }
+
+    #[test]
+    fn leapfrog_integrator_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let mut leapfrog = NBodySimulation::from_simulation(&sim);
+        assert_eq!(leapfrog.integrator(), Integrator::RungeKutta4);
+        leapfrog.set_integrator(Integrator::Leapfrog);
+        let energy = leapfrog.total_energy().unwrap();
+
+        // two lunar months in six hour steps, the moon stays bound and the energy doesn't wander
+        let step = DBig::from(6 * 3600);
+        let mut worst = 0.0f64;
+        for _ in 0..216 {
+            leapfrog.step(&step).unwrap();
+            let drift = (leapfrog.total_energy().unwrap() - &energy) / &energy;
+            worst = worst.max(dbig_to_f64(&drift).abs());
+        }
+        assert_eq!(leapfrog.time(), &DBig::from(216 * 6 * 3600));
+        assert!(worst < 1e-7, "{}", worst);
+        let earth = &leapfrog.get_body("earth").unwrap().position;
+        let moon = &leapfrog.get_body("moon").unwrap().position;
+        let distance = dbig_to_f64(&earth.distance_to(moon));
+        assert!(distance > 3e8 && distance < 4.5e8, "{}", distance);
+
+        // both integrators agree over a short run
+        let mut runge_kutta = NBodySimulation::from_simulation(&sim);
+        let mut leapfrog = NBodySimulation::from_simulation(&sim);
+        leapfrog.set_integrator(Integrator::Leapfrog);
+        let day = DBig::from(24 * 3600);
+        runge_kutta.advance(&day, &DBig::from(600)).unwrap();
+        leapfrog.advance(&day, &DBig::from(600)).unwrap();
+        let offset = runge_kutta
+            .get_body("moon")
+            .unwrap()
+            .position
+            .distance_to(&leapfrog.get_body("moon").unwrap().position);
+        assert!(dbig_to_f64(&offset) < 1000.0, "{}", offset);
+    }
+
+    #[test]
+    fn nbody_recentering_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let mut drifting = NBodySimulation::from_simulation(&sim);
+        // the static sun stands still while the earth carries the momentum of the system
+        let speed = drifting.barycenter_velocity().length();
+        assert!(approx_eq(
+            &speed,
+            &f64_to_dbig(5.97219e24 * 29800.0 / 1.98847e30),
+            &f64_to_dbig(0.01)
+        ));
+        assert!(!drifting.is_momentum_balanced(&f64_to_dbig(1e-3)));
+        let mut held = drifting.clone();
+        held.set_recentering(true);
+        assert!(held.recentering());
+        assert!(held.is_momentum_balanced(&f64_to_dbig(1e-20)));
+
+        let start = drifting.barycenter();
+        let (duration, step) = (DBig::from(5 * 24 * 3600), DBig::from(3600));
+        drifting.advance(&duration, &step).unwrap();
+        held.advance(&duration, &step).unwrap();
+        let drift = drifting.barycenter().distance_to(&start);
+        assert!(approx_eq(&drift, &(&speed * &duration), &DBig::ONE));
+        assert!(dbig_to_f64(&held.barycenter().distance_to(&start)) < 1e-9);
+        assert!(held.is_momentum_balanced(&f64_to_dbig(1e-20)));
+
+        held.recenter(&DecimalVector3d::zero());
+        held.advance(&step, &step).unwrap();
+        assert!(dbig_to_f64(&held.barycenter().length()) < 1e-9);
+    }
+
+    #[test]
+    fn close_encounters_are_substepped() {
+        let body = |name: &str, mass: f64, position: [f64; 3], velocity: [f64; 3]| NBodyState {
+            name: String::from(name),
+            mass: f64_to_dbig(mass),
+            position: DecimalVector3d::from_f64(position[0], position[1], position[2]),
+            velocity: DecimalVector3d::from_f64(velocity[0], velocity[1], velocity[2]),
+        };
+        // a probe swinging past a planet at about 12000 km, in steps as long as the whole pass
+        let bodies = vec![
+            body("planet", 6e24, [0.0, 0.0, 0.0], [0.0, 0.0, 0.0]),
+            body("probe", 1000.0, [-1e8, 2e7, 0.0], [5000.0, 0.0, 0.0]),
+        ];
+        let mut swing = NBodySimulation::new(bodies.clone(), &DBig::ZERO);
+        let energy = swing.total_energy().unwrap();
+        let step = DBig::from(10000);
+        swing.advance(&DBig::from(40000), &step).unwrap();
+        let drift = (swing.total_energy().unwrap() - &energy) / &energy;
+        assert!(
+            approx_eq(&drift, &DBig::ZERO, &f64_to_dbig(1e-6)),
+            "{}",
+            drift
+        );
+        let probe = &swing.get_body("probe").unwrap().position;
+        assert!(dbig_to_f64(&probe.length()) > 5e7);
+        // the same steps without the substeps lose the energy
+        let mut coarse = NBodySimulation::new(bodies.clone(), &DBig::ZERO);
+        for _ in 0..4 {
+            coarse.step(&step).unwrap();
+        }
+        let drift = (coarse.total_energy().unwrap() - &energy) / &energy;
+        assert!(dbig_to_f64(&drift).abs() > 1e-3, "{}", drift);
+
+        let mut crashed = bodies;
+        crashed[1].position = DecimalVector3d::zero();
+        let mut crashed = NBodySimulation::new(crashed, &DBig::ZERO);
+        let error = SimulationError::InvalidState(String::from(
+            "planet and probe are at the same position",
+        ));
+        assert_eq!(crashed.total_energy(), Err(error.clone()));
+        assert_eq!(crashed.advance(&step, &step), Err(error.clone()));
+        assert_eq!(crashed.step(&step), Err(error));
+    }
+}
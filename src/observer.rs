@@ -0,0 +1,532 @@
+use crate::decimal_matrix_3d::DecimalMatrix3d;
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::{check_positive, SimulationError};
+use crate::simulation::Simulation;
+use crate::sin_cos::{asin, atan2, PIMUL2};
+use crate::surface::surface_frame;
+use crate::visibility::VisibleBody;
+use dashu_float::ops::SquareRoot;
+use dashu_float::DBig;
+use std::ops::Deref;
+
+const PRECISION: usize = 32;
+const REFINE_ITERATIONS: usize = 32;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+#[derive(Debug, Clone)]
+pub enum ObserverPlacement {
+    // angles in radians, altitude in meters above the radius
+    Surface {
+        body: String,
+        latitude: DBig,
+        longitude: DBig,
+        altitude: DBig,
+    },
+    Free {
+        position: DecimalVector3d,
+        orientation: Box<DecimalMatrix3d>,
+    },
+}
+
+// looks along its local -Z with +Y up, like the raw visibility queries; on a surface that is
+// towards the north horizon with +Y at the zenith and +X to the east
+#[derive(Debug, Clone)]
+pub struct Observer {
+    pub placement: ObserverPlacement,
+    position: DecimalVector3d,
+    orientation: DecimalMatrix3d,
+    time: Option<DBig>, // simulation time of the cached state, None for free observers
+}
+
+#[derive(Debug, Clone)]
+pub struct HorizontalCoordinates {
+    pub azimuth: DBig,   // in radians from the local north (-Z) towards the east (+X)
+    pub elevation: DBig, // in radians above the local horizon (XZ plane)
+    pub distance: DBig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RiseSetKind {
+    Rise, // the center of the body comes up over the horizon
+    Set,  // and goes down below it
+}
+
+#[derive(Debug, Clone)]
+pub struct RiseSet {
+    pub kind: RiseSetKind,
+    pub time: DBig,
+}
+
+impl Observer {
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body of a surface observer isn't in the simulation.
+    pub fn new(placement: ObserverPlacement, sim: &Simulation) -> Result<Self, SimulationError> {
+        let mut observer = Observer {
+            placement,
+            position: DecimalVector3d::zero(),
+            orientation: DecimalMatrix3d::identity(),
+            time: None,
+        };
+        observer.update(sim)?;
+        Ok(observer)
+    }
+
+    /// recomputes the cached state, needed after every simulation update for surface observers
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body of a surface observer isn't in the simulation.
+    pub fn update(&mut self, sim: &Simulation) -> Result<(), SimulationError> {
+        match &self.placement {
+            ObserverPlacement::Surface {
+                body,
+                latitude,
+                longitude,
+                altitude,
+            } => {
+                if self.time.as_ref() == Some(sim.time()) {
+                    return Ok(());
+                }
+                let body = sim.get_body(body)?;
+                let [up, east, north] = surface_frame(body, latitude, longitude);
+                self.position = sim.world_position(body) + &up * (&body.body.radius + altitude);
+                self.orientation = DecimalMatrix3d {
+                    data: [
+                        [east.x, east.y, east.z],
+                        [up.x, up.y, up.z],
+                        [-north.x, -north.y, -north.z],
+                    ],
+                };
+                self.time = Some(sim.time().clone());
+            }
+            ObserverPlacement::Free {
+                position,
+                orientation,
+            } => {
+                self.position = position.clone();
+                self.orientation = orientation.deref().clone();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn position(&self) -> &DecimalVector3d {
+        &self.position
+    }
+
+    pub fn orientation(&self) -> &DecimalMatrix3d {
+        &self.orientation
+    }
+}
+
+impl Simulation {
+    // surface observers cache their state, it has to be from the current time
+    fn check_observer_current(&self, observer: &Observer) -> Result<(), SimulationError> {
+        match &observer.time {
+            Some(time) if time != self.time() => Err(SimulationError::InvalidState(String::from(
+                "the observer is out of date, update it after the simulation",
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// # Errors
+    ///
+    /// `InvalidState` if the observer wasn't updated to the current time.
+    pub fn visible_bodies_from(
+        &self,
+        observer: &Observer,
+        fov: &DBig,
+        aspect_ratio: &DBig,
+    ) -> Result<Vec<VisibleBody<'_>>, SimulationError> {
+        self.check_observer_current(observer)?;
+        Ok(self.visible_bodies(
+            observer.position(),
+            observer.orientation(),
+            fov,
+            aspect_ratio,
+        ))
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation, `InvalidState` if the observer wasn't
+    /// updated to the current time.
+    pub fn horizontal_coordinates(
+        &self,
+        observer: &Observer,
+        body_name: &str,
+    ) -> Result<HorizontalCoordinates, SimulationError> {
+        self.check_observer_current(observer)?;
+        let relative = self.world_position(self.get_body(body_name)?) - observer.position();
+        let distance = relative.length();
+        let direction = &relative / &distance;
+        let orientation = observer.orientation();
+        let axis = |row: usize| {
+            let [x, y, z] = orientation.data[row].clone();
+            DecimalVector3d::new(x, y, z)
+        };
+        let east = direction.dot(&axis(0));
+        let up = direction.dot(&axis(1));
+        let north = -direction.dot(&axis(2));
+
+        let mut azimuth = atan2(east, north, 32);
+        if azimuth < DBig::ZERO {
+            azimuth += &*PIMUL2;
+        }
+        Ok(HorizontalCoordinates {
+            azimuth,
+            elevation: asin(up, 32),
+            distance,
+        })
+    }
+
+    /// straight line distance from a point at the altitude above the body to where its line of
+    /// sight grazes the surface, zero on or below the surface
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn horizon_distance(
+        &self,
+        body_name: &str,
+        altitude: &DBig,
+    ) -> Result<DBig, SimulationError> {
+        let radius = lift(&self.get_body(body_name)?.body.radius);
+        if *altitude <= DBig::ZERO {
+            return Ok(DBig::ZERO);
+        }
+        let altitude = lift(altitude);
+        Ok((&altitude * (radius * DBig::from(2) + &altitude)).sqrt())
+    }
+
+    /// whether the line of sight from a surface observer to the target clears the body the
+    /// observer stands on, other bodies in the way are not considered
+    ///
+    /// # Errors
+    ///
+    /// `InvalidArgument` if the observer isn't on a surface, `InvalidState` if it wasn't updated to
+    /// the current time and `UnknownBody` if its body isn't in the simulation.
+    pub fn is_visible_over_horizon(
+        &self,
+        observer: &Observer,
+        target: &DecimalVector3d,
+    ) -> Result<bool, SimulationError> {
+        self.check_observer_current(observer)?;
+        let ObserverPlacement::Surface { body, .. } = &observer.placement else {
+            return Err(SimulationError::InvalidArgument(String::from(
+                "the observer is not on a surface",
+            )));
+        };
+        let body = self.get_body(body)?;
+        let radius_squared = lift(&body.body.radius).sqr();
+        let center = self.world_position(body);
+        let from = observer.position() - &center;
+        let to = target - &center;
+        let segment = &to - &from;
+
+        // a line of sight heading away from the center never dips below the observer
+        let along = from.dot(&segment);
+        if along >= DBig::ZERO {
+            return Ok(true);
+        }
+        // the target is the closest point when it lies before the tangent point
+        let length_squared = segment.length_squared();
+        if -&along >= length_squared {
+            return Ok(to.length_squared() >= radius_squared);
+        }
+        Ok(from.length_squared() - &along * &along / length_squared >= radius_squared)
+    }
+
+    /// times between `start` and `end` when the center of the body crosses the horizon of the
+    /// observer, found on a copy of the simulation by sampling every `step` and bisecting; the step
+    /// has to be below the shortest time the body stays up or down or a pass can be missed
+    ///
+    /// # Errors
+    ///
+    /// `InvalidArgument` if `step` isn't positive, `UnknownBody` if the body or the observer's body
+    /// isn't in the simulation and `InvalidState` if one of them is sleeping.
+    pub fn rise_set_times(
+        &self,
+        observer: &Observer,
+        body_name: &str,
+        start: &DBig,
+        end: &DBig,
+        step: &DBig,
+    ) -> Result<Vec<RiseSet>, SimulationError> {
+        check_positive(step, "step")?;
+        self.get_awake_body(body_name)?;
+        if let ObserverPlacement::Surface { body, .. } = &observer.placement {
+            self.get_awake_body(body)?;
+        }
+        let mut sim = self.copy_bodies();
+        let mut observer = observer.clone();
+        let mut is_up = |time: &DBig| -> Result<bool, SimulationError> {
+            sim.update(time);
+            observer.update(&sim)?;
+            let elevation = sim.horizontal_coordinates(&observer, body_name)?.elevation;
+            Ok(elevation > DBig::ZERO)
+        };
+
+        let mut result: Vec<RiseSet> = vec![];
+        let mut time = lift(start);
+        let end = lift(end);
+        let step = lift(step);
+        let mut up = is_up(&time)?;
+        while time < end {
+            let next_time = (&time + &step).min(end.clone());
+            if is_up(&next_time)? != up {
+                let (mut low, mut high) = (time.clone(), next_time.clone());
+                for _ in 0..REFINE_ITERATIONS {
+                    let middle = (&low + &high) / DBig::from(2);
+                    if is_up(&middle)? == up {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                up = !up;
+                result.push(RiseSet {
+                    kind: if up {
+                        RiseSetKind::Rise
+                    } else {
+                        RiseSetKind::Set
+                    },
+                    time: high,
+                });
+            }
+            time = next_time;
+        }
+        Ok(result)
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation, and the errors of
+    /// `is_visible_over_horizon`.
+    pub fn is_body_over_horizon(
+        &self,
+        observer: &Observer,
+        body_name: &str,
+    ) -> Result<bool, SimulationError> {
+        let target = self.world_position(self.get_body(body_name)?);
+        self.is_visible_over_horizon(observer, &target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_matrix_3d::DecimalMatrix3d;
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::observer::{Observer, ObserverPlacement, RiseSetKind};
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn observer_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let placement = |longitude: f64| ObserverPlacement::Surface {
+            body: String::from("earth"),
+            latitude: DBig::ZERO,
+            longitude: f64_to_dbig(longitude),
+            altitude: DBig::from(100),
+        };
+        let half_pi = std::f64::consts::FRAC_PI_2;
+
+        // noon on the far side, midnight at longitude zero
+        let noon = Observer::new(placement(std::f64::consts::PI), &sim).unwrap();
+        let sun = sim.horizontal_coordinates(&noon, "sun").unwrap();
+        assert!(approx_eq(
+            &sun.elevation,
+            &f64_to_dbig(half_pi),
+            &f64_to_dbig(1e-6)
+        ));
+        let mut midnight = Observer::new(placement(0.0), &sim).unwrap();
+        let sun = sim.horizontal_coordinates(&midnight, "sun").unwrap();
+        assert!(approx_eq(
+            &sun.elevation,
+            &f64_to_dbig(-half_pi),
+            &f64_to_dbig(1e-6)
+        ));
+        let earth_distance = dbig_to_f64(&sim.get_body("earth").unwrap().body.radius) + 100.0;
+        let offset = midnight.position() - sim.world_position(sim.get_body("earth").unwrap());
+        assert!(approx_eq(
+            &offset.length(),
+            &f64_to_dbig(earth_distance),
+            &f64_to_dbig(1e-3)
+        ));
+
+        // a quarter of a day later the sun rises in the east
+        sim.update(&DBig::from(6 * 3600));
+        midnight.update(&sim).unwrap();
+        let sun = sim.horizontal_coordinates(&midnight, "sun").unwrap();
+        assert!(approx_eq(&sun.elevation, &DBig::ZERO, &f64_to_dbig(0.01)));
+        assert!(approx_eq(
+            &sun.azimuth,
+            &f64_to_dbig(half_pi),
+            &f64_to_dbig(0.01)
+        ));
+
+        let free = Observer::new(
+            ObserverPlacement::Free {
+                position: sim.world_position(sim.get_body("sun").unwrap())
+                    + DecimalVector3d::from_f64(0.0, 0.0, 1e12),
+                orientation: Box::new(DecimalMatrix3d::identity()),
+            },
+            &sim,
+        )
+        .unwrap();
+        let sun = sim.horizontal_coordinates(&free, "sun").unwrap();
+        assert!(approx_eq(&sun.elevation, &DBig::ZERO, &f64_to_dbig(1e-6)));
+        assert!(approx_eq(&sun.azimuth, &DBig::ZERO, &f64_to_dbig(1e-6)));
+        let visible = sim
+            .visible_bodies_from(&free, &f64_to_dbig(0.1), &DBig::ONE)
+            .unwrap();
+        assert_eq!(visible[0].body.body.name, "sun");
+
+        // surface observers have to follow the simulation, free ones don't move
+        sim.update(&DBig::from(7 * 3600));
+        let out_of_date = SimulationError::InvalidState(String::from(
+            "the observer is out of date, update it after the simulation",
+        ));
+        assert_eq!(
+            sim.horizontal_coordinates(&midnight, "sun").unwrap_err(),
+            out_of_date
+        );
+        assert_eq!(
+            sim.visible_bodies_from(&midnight, &f64_to_dbig(0.1), &DBig::ONE)
+                .unwrap_err(),
+            out_of_date
+        );
+        assert!(sim.horizontal_coordinates(&free, "sun").is_ok());
+    }
+
+    #[test]
+    fn horizon_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let horizon = dbig_to_f64(&sim.horizon_distance("earth", &DBig::from(100)).unwrap());
+        assert!((horizon - (100.0f64 * (2.0 * 6_371_000.0 + 100.0)).sqrt()).abs() < 1e-3);
+        assert_eq!(
+            sim.horizon_distance("earth", &DBig::ZERO).unwrap(),
+            DBig::ZERO
+        );
+
+        let placement = |longitude: f64| ObserverPlacement::Surface {
+            body: String::from("earth"),
+            latitude: DBig::ZERO,
+            longitude: f64_to_dbig(longitude),
+            altitude: DBig::from(100),
+        };
+        let noon = Observer::new(placement(std::f64::consts::PI), &sim).unwrap();
+        let midnight = Observer::new(placement(0.0), &sim).unwrap();
+        assert!(sim.is_body_over_horizon(&noon, "sun").unwrap());
+        assert!(!sim.is_body_over_horizon(&midnight, "sun").unwrap());
+
+        // both horizons together reach about 0.0233 rad around the earth for a target at 1 km
+        let target = |longitude: f64| {
+            sim.surface_point_state(
+                "earth",
+                &DBig::ZERO,
+                &f64_to_dbig(longitude),
+                &DBig::from(1000),
+            )
+            .unwrap()
+            .0
+        };
+        assert!(sim
+            .is_visible_over_horizon(&midnight, &target(0.022))
+            .unwrap());
+        assert!(sim
+            .is_visible_over_horizon(&midnight, &target(-0.022))
+            .unwrap());
+        assert!(!sim
+            .is_visible_over_horizon(&midnight, &target(0.025))
+            .unwrap());
+        assert!(!sim
+            .is_visible_over_horizon(&midnight, &target(std::f64::consts::PI))
+            .unwrap());
+
+        let free = Observer::new(
+            ObserverPlacement::Free {
+                position: target(0.0),
+                orientation: Box::new(DecimalMatrix3d::identity()),
+            },
+            &sim,
+        )
+        .unwrap();
+        assert_eq!(
+            sim.is_body_over_horizon(&free, "sun").unwrap_err(),
+            SimulationError::InvalidArgument(String::from("the observer is not on a surface"))
+        );
+    }
+
+    #[test]
+    fn rise_set_times_work() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let observer = Observer::new(
+            ObserverPlacement::Surface {
+                body: String::from("earth"),
+                latitude: DBig::ZERO,
+                longitude: DBig::ZERO,
+                altitude: DBig::ZERO,
+            },
+            &sim,
+        )
+        .unwrap();
+        // midnight at the start, the sun rises a quarter of a day later and sets half a day after
+        let day = 24.0 * 3600.0;
+        let events = sim
+            .rise_set_times(
+                &observer,
+                "sun",
+                &DBig::ZERO,
+                &f64_to_dbig(2.0 * day),
+                &DBig::from(1800),
+            )
+            .unwrap();
+        let kinds: Vec<RiseSetKind> = events.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                RiseSetKind::Rise,
+                RiseSetKind::Set,
+                RiseSetKind::Rise,
+                RiseSetKind::Set
+            ]
+        );
+        assert!(approx_eq(
+            &events[0].time,
+            &f64_to_dbig(day / 4.0),
+            &f64_to_dbig(600.0)
+        ));
+        assert!(approx_eq(
+            &events[1].time,
+            &f64_to_dbig(3.0 * day / 4.0),
+            &f64_to_dbig(600.0)
+        ));
+        // a solar day, a little longer than the rotation
+        let solar_day = dbig_to_f64(&(&events[2].time - &events[0].time));
+        assert!((solar_day - day * (1.0 + 1.0 / 365.0)).abs() < 60.0);
+        // the search runs on a copy
+        assert_eq!(sim.time(), &DBig::ZERO);
+
+        assert_eq!(
+            sim.rise_set_times(&observer, "sun", &DBig::ZERO, &DBig::ONE, &DBig::ZERO)
+                .unwrap_err(),
+            SimulationError::InvalidArgument(String::from("the step has to be positive"))
+        );
+        assert!(matches!(
+            sim.rise_set_times(&observer, "pluto", &DBig::ZERO, &DBig::ONE, &DBig::ONE),
+            Err(SimulationError::UnknownBody(_))
+        ));
+    }
+}
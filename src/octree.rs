@@ -0,0 +1,266 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use dashu_float::ops::{Abs, SquareRoot};
+use dashu_float::DBig;
+
+const NODE_CAPACITY: usize = 8;
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone)]
+struct OctreeNode {
+    center: DecimalVector3d,
+    half_size: DBig,
+    items: Vec<(i32, DecimalVector3d)>,
+    children: Vec<OctreeNode>, // either empty or exactly 8
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Octree {
+    root: Option<OctreeNode>,
+}
+
+impl OctreeNode {
+    fn new(center: DecimalVector3d, half_size: DBig) -> OctreeNode {
+        OctreeNode {
+            center,
+            half_size,
+            items: vec![],
+            children: vec![],
+        }
+    }
+
+    fn child_index(&self, point: &DecimalVector3d) -> usize {
+        let mut index = 0;
+        if point.x >= self.center.x {
+            index |= 1;
+        }
+        if point.y >= self.center.y {
+            index |= 2;
+        }
+        if point.z >= self.center.z {
+            index |= 4;
+        }
+        index
+    }
+
+    fn split(&mut self) {
+        let quarter = &self.half_size / DBig::from(2);
+        for i in 0..8 {
+            let offset = |bit: usize| {
+                if i & bit == 0 {
+                    -quarter.clone()
+                } else {
+                    quarter.clone()
+                }
+            };
+            let center = &self.center + DecimalVector3d::new(offset(1), offset(2), offset(4));
+            self.children.push(OctreeNode::new(center, quarter.clone()));
+        }
+        for (id, position) in std::mem::take(&mut self.items) {
+            let index = self.child_index(&position);
+            self.children[index].items.push((id, position));
+        }
+    }
+
+    fn insert(&mut self, id: i32, position: DecimalVector3d, depth: usize) {
+        if !self.children.is_empty() {
+            let index = self.child_index(&position);
+            self.children[index].insert(id, position, depth + 1);
+            return;
+        }
+        self.items.push((id, position));
+        if self.items.len() > NODE_CAPACITY && depth < MAX_DEPTH {
+            self.split();
+        }
+    }
+
+    fn distance_squared_to_box(&self, point: &DecimalVector3d) -> DBig {
+        let axis = |p: &DBig, c: &DBig| {
+            let d = (p - c).abs() - &self.half_size;
+            if d > DBig::ZERO {
+                &d * &d
+            } else {
+                DBig::ZERO
+            }
+        };
+        axis(&point.x, &self.center.x)
+            + axis(&point.y, &self.center.y)
+            + axis(&point.z, &self.center.z)
+    }
+
+    fn k_nearest(
+        &self,
+        point: &DecimalVector3d,
+        k: usize,
+        filter: &dyn Fn(i32) -> bool,
+        best: &mut Vec<(i32, DBig)>,
+    ) {
+        for (id, position) in &self.items {
+            if !filter(*id) {
+                continue;
+            }
+            let distance_squared = (position - point).length_squared();
+            if best.len() < k || distance_squared < best[best.len() - 1].1 {
+                let at = best.partition_point(|(_, d)| *d <= distance_squared);
+                best.insert(at, (*id, distance_squared));
+                best.truncate(k);
+            }
+        }
+
+        let mut children: Vec<(&OctreeNode, DBig)> = self
+            .children
+            .iter()
+            .map(|child| (child, child.distance_squared_to_box(point)))
+            .collect();
+        children.sort_by(|a, b| a.1.cmp(&b.1));
+        for (child, box_distance_squared) in children {
+            if best.len() == k && box_distance_squared > best[best.len() - 1].1 {
+                break;
+            }
+            child.k_nearest(point, k, filter, best);
+        }
+    }
+
+    fn within(
+        &self,
+        point: &DecimalVector3d,
+        radius_squared: &DBig,
+        result: &mut Vec<(i32, DBig)>,
+    ) {
+        if self.distance_squared_to_box(point) > *radius_squared {
+            return;
+        }
+        for (id, position) in &self.items {
+            let distance_squared = (position - point).length_squared();
+            if distance_squared <= *radius_squared {
+                result.push((*id, distance_squared));
+            }
+        }
+        for child in &self.children {
+            child.within(point, radius_squared, result);
+        }
+    }
+}
+
+impl Octree {
+    pub fn new() -> Octree {
+        Octree { root: None }
+    }
+
+    pub fn build(items: &[(i32, DecimalVector3d)]) -> Octree {
+        if items.is_empty() {
+            return Octree::new();
+        }
+        let mut min = items[0].1.clone();
+        let mut max = items[0].1.clone();
+        for (_, position) in items {
+            min.x = min.x.clone().min(position.x.clone());
+            min.y = min.y.clone().min(position.y.clone());
+            min.z = min.z.clone().min(position.z.clone());
+            max.x = max.x.clone().max(position.x.clone());
+            max.y = max.y.clone().max(position.y.clone());
+            max.z = max.z.clone().max(position.z.clone());
+        }
+        let extent = &max - &min;
+        let half_size = extent.x.max(extent.y).max(extent.z) / DBig::from(2);
+        let center = (&min + &max) / DBig::from(2);
+
+        let mut root = OctreeNode::new(center, half_size);
+        for (id, position) in items {
+            root.insert(*id, position.clone(), 0);
+        }
+        Octree { root: Some(root) }
+    }
+
+    // returns (id, distance) pairs sorted from the closest
+    pub fn k_nearest(
+        &self,
+        point: &DecimalVector3d,
+        k: usize,
+        filter: &dyn Fn(i32) -> bool,
+    ) -> Vec<(i32, DBig)> {
+        let mut best: Vec<(i32, DBig)> = vec![];
+        if let Some(root) = &self.root {
+            if k > 0 {
+                root.k_nearest(point, k, filter, &mut best);
+            }
+        }
+        best.into_iter().map(|(id, d)| (id, d.sqrt())).collect()
+    }
+
+    pub fn nearest(
+        &self,
+        point: &DecimalVector3d,
+        filter: &dyn Fn(i32) -> bool,
+    ) -> Option<(i32, DBig)> {
+        self.k_nearest(point, 1, filter).into_iter().next()
+    }
+
+    // returns (id, distance) pairs sorted from the closest
+    pub fn within_radius(&self, point: &DecimalVector3d, radius: &DBig) -> Vec<(i32, DBig)> {
+        let mut result: Vec<(i32, DBig)> = vec![];
+        if let Some(root) = &self.root {
+            root.within(point, &(radius * radius), &mut result);
+        }
+        result.sort_by(|a, b| a.1.cmp(&b.1));
+        result.into_iter().map(|(id, d)| (id, d.sqrt())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> Vec<(i32, DecimalVector3d)> {
+        let mut items = vec![];
+        let mut id = 0;
+        for x in 0..5 {
+            for y in 0..5 {
+                for z in 0..5 {
+                    items.push((
+                        id,
+                        DecimalVector3d::from_f64(
+                            f64::from(x) * 1.0e20,
+                            f64::from(y) * 1.0e20,
+                            f64::from(z),
+                        ),
+                    ));
+                    id += 1;
+                }
+            }
+        }
+        items
+    }
+
+    #[test]
+    fn k_nearest_matches_linear_scan() {
+        let items = grid();
+        let octree = Octree::build(&items);
+        let point = DecimalVector3d::from_f64(1.2e20, 3.1e20, 2.2);
+
+        let mut expected: Vec<(i32, DBig)> = items
+            .iter()
+            .map(|(id, p)| (*id, p.distance_to(&point)))
+            .collect();
+        expected.sort_by(|a, b| a.1.cmp(&b.1));
+
+        let found = octree.k_nearest(&point, 4, &|_| true);
+        assert_eq!(found.len(), 4);
+        for i in 0..4 {
+            assert_eq!(found[i].1, expected[i].1);
+        }
+
+        let filtered = octree.nearest(&point, &|id| id % 2 == 0).unwrap();
+        let expected_filtered = expected.iter().find(|(id, _)| id % 2 == 0).unwrap();
+        assert_eq!(filtered.1, expected_filtered.1);
+    }
+
+    #[test]
+    fn within_radius_works() {
+        let octree = Octree::build(&grid());
+        let point = DecimalVector3d::from_f64(0.0, 0.0, 0.0);
+        let found = octree.within_radius(&point, &DBig::from(120_000_000_000_000_000_000u128));
+        // the x=0 y=0 column plus the x=1e20 y=0 and x=0 y=1e20 columns
+        assert_eq!(found.len(), 15);
+        assert_eq!(found[0].1, DBig::ZERO);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::simulation::{SimulatedBody, Simulation, G_CONSTANT};
+use dashu_float::ops::Abs;
+use dashu_float::DBig;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+const PRECISION: usize = 40;
+
+// eccentricities this close to 1 count as parabolic, a coast can't tell them apart anyway
+static PARABOLIC_TOLERANCE: LazyLock<DBig> = LazyLock::new(|| DBig::from_str("1e-6").unwrap());
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+fn lift_vector(v: &DecimalVector3d) -> DecimalVector3d {
+    DecimalVector3d::new(lift(&v.x), lift(&v.y), lift(&v.z))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrbitKind {
+    Elliptic,
+    Parabolic,
+    Hyperbolic,
+}
+
+// the two-body orbit of a state around its dominant primary
+#[derive(Debug, Clone)]
+pub struct OrbitClassification {
+    pub primary: String,
+    pub kind: OrbitKind,
+    pub specific_energy: DBig, // in J/kg, negative for elliptic orbits
+    pub eccentricity: DBig,
+    pub apoapsis: Option<DBig>, // in meters from the primary center, None unless elliptic
+    // elliptic with the apoapsis inside the sphere of influence of the primary, roots hold
+    // every elliptic orbit
+    pub bound: bool,
+}
+
+impl Simulation {
+    /// of a body from its current state, against the body whose sphere of influence it is in
+    /// apart from its own satellites, usually the parent; None for roots
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation, `InvalidDynamics` if the body it orbits
+    /// or a parent along the way has no mass.
+    pub fn classify_orbit(
+        &self,
+        body_name: &str,
+    ) -> Result<Option<OrbitClassification>, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let position = self.world_position(body);
+        let own_system = self.resolve_hierarchy_down(body);
+        let mut primary = self.find_dominant_body(&position)?;
+        // a body is always in its own sphere of influence, its primary is found above it
+        while let Some(candidate) = primary {
+            if candidate.id() != body.id() && own_system.iter().all(|b| b.id() != candidate.id()) {
+                break;
+            }
+            primary = candidate.parent().and_then(|id| self.get_body_by_id(id));
+        }
+        let Some(primary) = primary else {
+            return Ok(None);
+        };
+        self.classify_around(primary, &position, &self.world_velocity(body))
+            .map(Some)
+    }
+
+    /// of a spacecraft, see `classify_orbit`
+    ///
+    /// # Errors
+    ///
+    /// `UnknownSpacecraft` if there is no spacecraft of that name, `InvalidDynamics` if the body it
+    /// orbits or a parent along the way has no mass.
+    pub fn classify_spacecraft_orbit(
+        &self,
+        name: &str,
+    ) -> Result<Option<OrbitClassification>, SimulationError> {
+        let state = &self.get_spacecraft(name)?.state;
+        let Some(primary) = self.find_dominant_body(&state.position)? else {
+            return Ok(None);
+        };
+        self.classify_around(primary, &state.position, &state.velocity)
+            .map(Some)
+    }
+
+    // world position and velocity against the primary
+    fn classify_around(
+        &self,
+        primary: &SimulatedBody,
+        position: &DecimalVector3d,
+        velocity: &DecimalVector3d,
+    ) -> Result<OrbitClassification, SimulationError> {
+        let mu = &*G_CONSTANT * lift(&primary.body.mass_at(&self.time));
+        if mu <= DBig::ZERO {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{}: there is no mass to orbit",
+                primary.body.name
+            )));
+        }
+        let position = lift_vector(position) - self.world_position(primary);
+        let velocity = lift_vector(velocity) - self.world_velocity(primary);
+        let radius = position.length();
+
+        let specific_energy = velocity.length_squared() / DBig::from(2) - &mu / &radius;
+        let momentum = position.cross(&velocity);
+        let eccentricity = (velocity.cross(&momentum) / &mu - &position / &radius).length();
+        let kind = if (&eccentricity - DBig::ONE).abs() <= *PARABOLIC_TOLERANCE {
+            OrbitKind::Parabolic
+        } else if eccentricity < DBig::ONE {
+            OrbitKind::Elliptic
+        } else {
+            OrbitKind::Hyperbolic
+        };
+        let apoapsis = match kind {
+            OrbitKind::Elliptic => {
+                let semi_major_axis = -&mu / (DBig::from(2) * &specific_energy);
+                Some(semi_major_axis * (DBig::ONE + &eccentricity))
+            }
+            OrbitKind::Parabolic | OrbitKind::Hyperbolic => None,
+        };
+        let limit = self.sphere_of_influence(&primary.body.name)?;
+        let bound = match (&apoapsis, &limit) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(apoapsis), Some(limit)) => apoapsis < limit,
+        };
+        Ok(OrbitClassification {
+            primary: primary.body.name.clone(),
+            kind,
+            specific_energy,
+            eccentricity,
+            apoapsis,
+            bound,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::orbit_classification::OrbitKind;
+    use crate::propagation::{CraftState, ThrustProfile};
+    use crate::spacecraft::Spacecraft;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn classify_orbit_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let moon = sim.classify_orbit("moon").unwrap().unwrap();
+        assert_eq!(moon.primary, "earth");
+        assert_eq!(moon.kind, OrbitKind::Elliptic);
+        assert!(moon.bound);
+        assert!(moon.specific_energy < DBig::ZERO);
+        let earth = sim.classify_orbit("earth").unwrap().unwrap();
+        assert_eq!(earth.primary, "sun");
+        assert!(earth.bound);
+        assert!(sim.classify_orbit("sun").unwrap().is_none());
+        assert!(matches!(
+            sim.classify_orbit("pluto"),
+            Err(SimulationError::UnknownBody(_))
+        ));
+
+        let earth = sim.get_body("earth").unwrap();
+        let (earth_position, earth_velocity) =
+            (sim.world_position(earth), sim.world_velocity(earth));
+        let circular = (6.67408e-11 * 5.97219e24 / 7e6f64).sqrt();
+        let launch = |name: &str, speed: f64| Spacecraft {
+            name: String::from(name),
+            state: CraftState {
+                time: DBig::ZERO,
+                position: &earth_position + DecimalVector3d::from_f64(7e6, 0.0, 0.0),
+                velocity: &earth_velocity + DecimalVector3d::from_f64(0.0, 0.0, -speed),
+                propulsion: None,
+            },
+            thrust: ThrustProfile::Coast,
+            primary: None,
+        };
+        for (name, speed) in [
+            ("circular", circular),
+            ("distant", circular * 1.41),
+            ("escape", circular * 2f64.sqrt()),
+            ("fast", circular * 1.5),
+        ] {
+            sim.add_spacecraft(launch(name, speed)).unwrap();
+        }
+        let circular = sim.classify_spacecraft_orbit("circular").unwrap().unwrap();
+        assert_eq!(circular.primary, "earth");
+        assert_eq!(circular.kind, OrbitKind::Elliptic);
+        assert!(dbig_to_f64(&circular.eccentricity) < 1e-3);
+        assert!(circular.bound);
+        // elliptic, but the apoapsis is out of the sphere of influence of the earth
+        let distant = sim.classify_spacecraft_orbit("distant").unwrap().unwrap();
+        assert_eq!(distant.kind, OrbitKind::Elliptic);
+        assert!(dbig_to_f64(distant.apoapsis.as_ref().unwrap()) > 1e9);
+        assert!(!distant.bound);
+        let escape = sim.classify_spacecraft_orbit("escape").unwrap().unwrap();
+        assert_eq!(escape.kind, OrbitKind::Parabolic);
+        assert!(!escape.bound);
+        let fast = sim.classify_spacecraft_orbit("fast").unwrap().unwrap();
+        assert_eq!(fast.kind, OrbitKind::Hyperbolic);
+        assert!(fast.specific_energy > DBig::ZERO);
+        assert!(fast.apoapsis.is_none());
+        assert!(!fast.bound);
+    }
+
+    #[test]
+    fn classify_orbit_around_massless_parent_fails() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        sim.get_body_mut("sun").unwrap().mass = DBig::ZERO;
+        assert_eq!(
+            sim.classify_orbit("earth").unwrap_err(),
+            SimulationError::InvalidDynamics(String::from("earth: the parent has no mass"))
+        );
+    }
+}
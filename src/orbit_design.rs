@@ -0,0 +1,156 @@
+use crate::body::{BodyDynamics, OrbitingBodyDynamics};
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::simulation::{Simulation, G_CONSTANT};
+use crate::sin_cos::PIMUL2;
+use dashu_float::ops::{Abs, SquareRoot};
+use dashu_float::DBig;
+
+const PRECISION: usize = 32;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+impl Simulation {
+    // circular orbit at `altitude` above the body, inclined from its equator with the given cosine
+    fn inclined_orbit(
+        &self,
+        body_name: &str,
+        altitude: &DBig,
+        cos_inclination: DBig,
+    ) -> Result<OrbitingBodyDynamics, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let radius = lift(&body.body.radius) + lift(altitude);
+        let mu = &*G_CONSTANT * lift(&body.body.mass_at(&self.time));
+        let period = &*PIMUL2 * (&radius * &radius * &radius / mu).sqrt();
+
+        // tilted towards whichever world axis is furthest from the rotation axis
+        let axis = body.body.rotation_axis.normalized();
+        let reference = if axis.x.clone().abs() < axis.z.clone().abs() {
+            DecimalVector3d::new(DBig::ONE, DBig::ZERO, DBig::ZERO)
+        } else {
+            DecimalVector3d::new(DBig::ZERO, DBig::ZERO, DBig::ONE)
+        };
+        let tilt = (&reference - &axis * axis.dot(&reference)).normalized();
+        let sin_inclination = (DBig::ONE - &cos_inclination * &cos_inclination).sqrt();
+
+        Ok(OrbitingBodyDynamics {
+            orbit_radius: radius,
+            orbit_plane_normal: &axis * cos_inclination + &tilt * sin_inclination,
+            orbit_period: period,
+            mean_anomaly_at_epoch: DBig::ZERO,
+            ellipse: None,
+            drift: None,
+        })
+    }
+
+    /// the J2 nodal precession, -3/2 n J2 (R/a)^2 cos i, has to match the body's mean motion around
+    /// its parent; None if the body doesn't orbit anything or no inclination is fast enough
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn sun_synchronous_orbit(
+        &self,
+        body_name: &str,
+        altitude: &DBig,
+        j2: &DBig,
+    ) -> Result<Option<OrbitingBodyDynamics>, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let (BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics)) =
+            &body.body.dynamics
+        else {
+            return Ok(None);
+        };
+        let equatorial_radius = lift(&body.body.radius);
+        let radius = &equatorial_radius + lift(altitude);
+        let mu = &*G_CONSTANT * lift(&body.body.mass_at(&self.time));
+        let mean_motion = (mu / (&radius * &radius * &radius)).sqrt();
+        let required_precession = &*PIMUL2 / lift(&dynamics.orbit_period);
+        let ratio = &equatorial_radius / &radius;
+
+        let cos_inclination = -DBig::from(2) * required_precession
+            / (DBig::from(3) * mean_motion * lift(j2) * &ratio * &ratio);
+        if cos_inclination.clone().abs() > DBig::ONE {
+            return Ok(None);
+        }
+        Ok(Some(self.inclined_orbit(
+            body_name,
+            altitude,
+            cos_inclination,
+        )?))
+    }
+
+    /// at the critical inclination, cos^2 i = 1/5, J2 doesn't rotate the line of apsides, prograde
+    /// at 63.4 degrees or retrograde at 116.6 degrees
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn frozen_orbit(
+        &self,
+        body_name: &str,
+        altitude: &DBig,
+        retrograde: bool,
+    ) -> Result<OrbitingBodyDynamics, SimulationError> {
+        let mut cos_inclination = (DBig::ONE / lift(&DBig::from(5))).sqrt();
+        if retrograde {
+            cos_inclination = -cos_inclination;
+        }
+        self.inclined_orbit(body_name, altitude, cos_inclination)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+    use std::str::FromStr;
+
+    #[test]
+    fn orbit_design_works() {
+        let sim = prepare_sim();
+        let j2 = DBig::from_str("0.00108263").unwrap();
+
+        // sun-synchronous orbits at 700 km are inclined about 98.2 degrees
+        let orbit = sim
+            .sun_synchronous_orbit("earth", &DBig::from(700_000), &j2)
+            .unwrap()
+            .unwrap();
+        let inclination = dbig_to_f64(&orbit.orbit_plane_normal.y).acos().to_degrees();
+        assert!((inclination - 98.2).abs() < 0.1);
+        assert!(approx_eq(
+            &orbit.orbit_plane_normal.length(),
+            &DBig::ONE,
+            &f64_to_dbig(1e-20)
+        ));
+        assert!(approx_eq(
+            &orbit.orbit_period,
+            &f64_to_dbig(5926.0),
+            &f64_to_dbig(10.0)
+        ));
+
+        // too high for J2 to keep up, and the sun doesn't orbit anything
+        assert!(sim
+            .sun_synchronous_orbit("earth", &DBig::from(20_000_000), &j2)
+            .unwrap()
+            .is_none());
+        assert!(sim
+            .sun_synchronous_orbit("sun", &DBig::from(700_000), &j2)
+            .unwrap()
+            .is_none());
+
+        let orbit = sim
+            .frozen_orbit("earth", &DBig::from(700_000), false)
+            .unwrap();
+        let inclination = dbig_to_f64(&orbit.orbit_plane_normal.y).acos().to_degrees();
+        assert!((inclination - 63.435).abs() < 0.001);
+        let orbit = sim
+            .frozen_orbit("earth", &DBig::from(700_000), true)
+            .unwrap();
+        let inclination = dbig_to_f64(&orbit.orbit_plane_normal.y).acos().to_degrees();
+        assert!((inclination - 116.565).abs() < 0.001);
+    }
+}
@@ -0,0 +1,200 @@
+use crate::body::{Body, BodyDynamics, OrbitingBodyDynamics};
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::phase_angle::wrap_angle;
+use crate::sin_cos::{atan2, cos, sin, PI, PIMUL2};
+use dashu_float::ops::SquareRoot;
+use dashu_float::DBig;
+
+#[derive(Debug, Clone)]
+pub struct OrbitFit {
+    pub dynamics: OrbitingBodyDynamics,
+    pub rms_residual: DBig, // in meters, against the samples
+}
+
+impl OrbitFit {
+    // a tidally locked body following the fitted orbit
+    pub fn into_body(self, name: &str, mass: DBig, radius: DBig) -> Body {
+        Body {
+            name: String::from(name),
+            rotation_axis: self.dynamics.orbit_plane_normal.clone(),
+            rotation_period: self.dynamics.orbit_period.clone(),
+            rotation_phase: DBig::ZERO,
+            resonance: None,
+            visual: None,
+            kind: None,
+            mass,
+            mass_variation: None,
+            nutation: None,
+            libration: None,
+            radius,
+            update_interval: None,
+            dynamics: BodyDynamics::Orbiting(self.dynamics),
+            satellites: vec![],
+        }
+    }
+}
+
+/// Least-squares circular orbit through (time, position relative to the parent) samples, which must
+/// be ordered by time and less than half an orbit apart. The orbit model turns the parent's +X axis
+/// about the normal, the phase of the samples becomes the mean anomaly at epoch.
+///
+/// # Errors
+///
+/// `InvalidArgument` for fewer than 3 samples.
+pub fn fit_circular_orbit(
+    samples: &[(DBig, DecimalVector3d)],
+) -> Result<OrbitFit, SimulationError> {
+    if samples.len() < 3 {
+        return Err(SimulationError::InvalidArgument(String::from(
+            "at least 3 samples are needed",
+        )));
+    }
+    let count = DBig::from(samples.len());
+
+    // the body rotates about the normal, so it moves on a circle that is offset along the normal
+    // unless the normal is perpendicular to +X; consecutive chords span the plane of that circle
+    let mut normal = DecimalVector3d::zero();
+    for triple in samples.windows(3) {
+        let first_chord = &triple[1].1 - &triple[0].1;
+        let second_chord = &triple[2].1 - &triple[1].1;
+        normal = normal + first_chord.cross(&second_chord);
+    }
+    let normal = normal.normalized();
+
+    let mut radius = DBig::ZERO;
+    let mut offset = DBig::ZERO;
+    for (_, position) in samples {
+        radius += position.length();
+        offset += normal.dot(position);
+    }
+    let radius = radius / &count;
+    let offset = offset / &count;
+    let center = &normal * &offset;
+    let circle_radius = (&radius * &radius - &offset * &offset).sqrt();
+
+    // in-plane angles, unwrapped, then a linear fit of angle against time
+    let first = &samples[0].1 - &center;
+    let u = (&first - &normal * normal.dot(&first)).normalized();
+    let v = normal.cross(&u);
+    let mut angles: Vec<DBig> = vec![];
+    let mut previous = DBig::ZERO;
+    let mut turns = DBig::ZERO;
+    for (_, position) in samples {
+        let angle = atan2(position.dot(&v), position.dot(&u), 32);
+        if angle < &previous - &*PI {
+            turns += &*PIMUL2;
+        }
+        previous.clone_from(&angle);
+        angles.push(angle + &turns);
+    }
+
+    let mut mean_time = DBig::ZERO;
+    let mut mean_angle = DBig::ZERO;
+    for i in 0..samples.len() {
+        mean_time += &samples[i].0;
+        mean_angle += &angles[i];
+    }
+    mean_time /= &count;
+    mean_angle /= &count;
+    let mut covariance = DBig::ZERO;
+    let mut variance = DBig::ZERO;
+    for i in 0..samples.len() {
+        let dt = &samples[i].0 - &mean_time;
+        covariance += &dt * (&angles[i] - &mean_angle);
+        variance += &dt * &dt;
+    }
+    let angular_velocity = covariance / variance;
+    let phase = &mean_angle - &angular_velocity * &mean_time;
+
+    let mut residual = DBig::ZERO;
+    for (time, position) in samples {
+        let angle = &angular_velocity * time + &phase;
+        let model = &center + (&u * cos(angle.clone(), 32) + &v * sin(angle, 32)) * &circle_radius;
+        residual += (model - position).length_squared();
+    }
+
+    // the model angle is measured from +X, projected into the plane when it is not in it
+    let x_axis = DecimalVector3d::new(DBig::ONE, DBig::ZERO, DBig::ZERO);
+    let projected = &x_axis - &normal * normal.dot(&x_axis);
+    let mut mean_anomaly_at_epoch = if projected.length_squared() == DBig::ZERO {
+        DBig::ZERO
+    } else {
+        phase - atan2(projected.dot(&v), projected.dot(&u), 32)
+    };
+    if angular_velocity < DBig::ZERO {
+        mean_anomaly_at_epoch = -mean_anomaly_at_epoch;
+    }
+
+    let dynamics = OrbitingBodyDynamics {
+        orbit_radius: radius,
+        orbit_plane_normal: normal,
+        orbit_period: &*PIMUL2 / angular_velocity,
+        mean_anomaly_at_epoch: wrap_angle(mean_anomaly_at_epoch),
+        ellipse: None,
+        drift: None,
+    };
+
+    Ok(OrbitFit {
+        dynamics,
+        rms_residual: (residual / &count).sqrt(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::BodyDynamics;
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::orbit_fit::fit_circular_orbit;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+    use std::str::FromStr;
+
+    #[test]
+    fn fit_circular_orbit_works() {
+        let mut sim = prepare_sim();
+        // the fixture normal is not exactly unit length, which makes the orbit slightly elliptic
+        let exact_normal = DecimalVector3d::new(
+            DBig::from_str("0.6").unwrap(),
+            DBig::ZERO,
+            DBig::from_str("0.8").unwrap(),
+        );
+        if let BodyDynamics::Orbiting(dynamics) = &mut sim.get_body_mut("moon").unwrap().dynamics {
+            dynamics.orbit_plane_normal = exact_normal.clone();
+            dynamics.mean_anomaly_at_epoch = f64_to_dbig(1.0);
+        }
+        let mut samples = vec![];
+        for i in 0..10 {
+            // lifted so the orbit progression isn't rounded to the precision of the period
+            let time = DBig::from(i * 24 * 3600).with_precision(40).value();
+            sim.update(&time);
+            samples.push((
+                time,
+                sim.get_body("moon").unwrap().relative_position.clone(),
+            ));
+        }
+
+        let fit = fit_circular_orbit(&samples).unwrap();
+        let period = dbig_to_f64(&fit.dynamics.orbit_period);
+        assert!((period - 27.0 * 24.0 * 3600.0).abs() < 1.0);
+        let radius = dbig_to_f64(&fit.dynamics.orbit_radius);
+        assert!((radius - 384_400_000.0).abs() < 1.0);
+        let normal_error = (&fit.dynamics.orbit_plane_normal - &exact_normal).length();
+        assert!(dbig_to_f64(&normal_error) < 1e-9);
+        assert!(approx_eq(
+            &fit.dynamics.mean_anomaly_at_epoch,
+            &DBig::ONE,
+            &f64_to_dbig(1e-6)
+        ));
+        assert!(dbig_to_f64(&fit.rms_residual) < 1.0);
+
+        let body = fit.into_body("fitted moon", DBig::ZERO, DBig::ZERO);
+        assert_eq!(body.name, "fitted moon");
+        assert_eq!(
+            fit_circular_orbit(&samples[..2]).unwrap_err(),
+            SimulationError::InvalidArgument(String::from("at least 3 samples are needed"))
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use crate::body::BodyDynamics;
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::simulation::Simulation;
+use dashu_float::ops::Abs;
+use dashu_float::DBig;
+
+const PRECISION: usize = 40;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+// offsets from the parent, drawn around wherever the parent is when the trail is rendered
+#[derive(Debug, Clone)]
+pub struct OrbitSample {
+    pub time: DBig,
+    pub position: DecimalVector3d,
+    pub velocity: DecimalVector3d, // along the motion, for arrows on the trail
+}
+
+impl Simulation {
+    /// `count` samples evenly spread in time from `start` to `end` inclusive, in time order, so the
+    /// arc can be shorter than a revolution or wrap around several times; the orbit is evaluated
+    /// at each time directly, without advancing the simulation
+    ///
+    /// # Errors
+    ///
+    /// `InvalidArgument` if `count` is below 2, `UnknownBody` if the body isn't in the simulation
+    /// and `InvalidDynamics` if it doesn't orbit.
+    pub fn orbit_path(
+        &self,
+        body_name: &str,
+        start: &DBig,
+        end: &DBig,
+        count: usize,
+    ) -> Result<Vec<OrbitSample>, SimulationError> {
+        if count < 2 {
+            return Err(SimulationError::InvalidArgument(String::from(
+                "a path needs at least two samples",
+            )));
+        }
+        let body = self.get_body(body_name)?;
+        if !matches!(
+            body.body.dynamics,
+            BodyDynamics::Orbiting(_) | BodyDynamics::Barycentric(_)
+        ) {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{body_name}: static bodies have no orbit path"
+            )));
+        }
+        let start = lift(start);
+        let step = (lift(end) - &start) / DBig::from(count - 1);
+        Ok((0..count)
+            .map(|i| {
+                let time = &start + &step * DBig::from(i);
+                let (position, velocity) = self.get_body_relative_state(&time, body);
+                OrbitSample {
+                    time,
+                    position,
+                    velocity,
+                }
+            })
+            .collect())
+    }
+
+    /// the last full revolution up to the current time, oldest sample first
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation, `InvalidDynamics` if it doesn't orbit.
+    pub fn orbit_trail(
+        &self,
+        body_name: &str,
+        count: usize,
+    ) -> Result<Vec<OrbitSample>, SimulationError> {
+        let (BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics)) =
+            &self.get_body(body_name)?.body.dynamics
+        else {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{body_name}: static bodies have no orbit path"
+            )));
+        };
+        let start = &self.time - dynamics.orbit_period.clone().abs();
+        self.orbit_path(body_name, &start, &self.time, count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::SimulationError;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn orbit_path_works() {
+        let mut sim = prepare_sim();
+        let period = 27.0 * 24.0 * 3600.0;
+        sim.update(&f64_to_dbig(period / 3.0));
+
+        // a quarter revolution from the current time starts at the stored state, which was computed
+        // at the precision of the update time
+        let arc = sim
+            .orbit_path(
+                "moon",
+                sim.time(),
+                &(sim.time() + f64_to_dbig(period / 4.0)),
+                10,
+            )
+            .unwrap();
+        assert_eq!(arc.len(), 10);
+        let moon = sim.get_body("moon").unwrap();
+        assert!(arc[0]
+            .position
+            .approx_eq(&moon.relative_position, &f64_to_dbig(100.0)));
+        assert!(approx_eq(
+            &arc[9].time,
+            &f64_to_dbig(period * (1.0 / 3.0 + 0.25)),
+            &f64_to_dbig(1e-6)
+        ));
+        for pair in arc.windows(2) {
+            let spacing = dbig_to_f64(&(&pair[1].time - &pair[0].time));
+            assert!((spacing - period / 36.0).abs() < 1e-6);
+        }
+        // the velocity points towards the next sample
+        for pair in arc.windows(2) {
+            let ahead = &pair[1].position - &pair[0].position;
+            assert!(pair[0].velocity.dot(&ahead) > DBig::ZERO);
+        }
+
+        // the trail closes on itself after a full revolution and ends now
+        let trail = sim.orbit_trail("moon", 5).unwrap();
+        assert_eq!(trail[4].time, *sim.time());
+        assert!(trail[0].position.approx_eq(&trail[4].position, &DBig::ONE));
+        assert!(matches!(
+            sim.orbit_trail("sun", 5),
+            Err(SimulationError::InvalidDynamics(_))
+        ));
+        assert_eq!(
+            sim.orbit_trail("moon", 1).unwrap_err(),
+            SimulationError::InvalidArgument(String::from("a path needs at least two samples"))
+        );
+    }
+}
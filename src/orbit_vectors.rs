@@ -0,0 +1,93 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::simulation::{SimulatedBody, Simulation, G_CONSTANT};
+use dashu_float::DBig;
+
+const PRECISION: usize = 32;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+impl Simulation {
+    // position and velocity relative to the parent, None for roots
+    fn state_around_parent(
+        &self,
+        body_name: &str,
+    ) -> Result<Option<(&SimulatedBody, DecimalVector3d, DecimalVector3d)>, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let Some(parent) = body.parent() else {
+            return Ok(None);
+        };
+        let parent = self.get_body_by_id(parent).unwrap();
+        let position = self.world_position(body) - self.world_position(parent);
+        Ok(Some((parent, position, body.velocity.clone())))
+    }
+
+    /// r x v around the parent in m^2/s, None for roots
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn specific_angular_momentum(
+        &self,
+        body_name: &str,
+    ) -> Result<Option<DecimalVector3d>, SimulationError> {
+        let Some((_, position, velocity)) = self.state_around_parent(body_name)? else {
+            return Ok(None);
+        };
+        Ok(Some(position.cross(&velocity)))
+    }
+
+    /// (v x h) / mu - r / |r|, points at the periapsis with the eccentricity as length. Taken from
+    /// the state, so it works for any propagated trajectory; None for roots
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn eccentricity_vector(
+        &self,
+        body_name: &str,
+    ) -> Result<Option<DecimalVector3d>, SimulationError> {
+        let Some((parent, position, velocity)) = self.state_around_parent(body_name)? else {
+            return Ok(None);
+        };
+        let mu = &*G_CONSTANT * lift(&parent.body.mass_at(&self.time));
+        let momentum = position.cross(&velocity);
+        let radius = lift(&position.length());
+        Ok(Some(velocity.cross(&momentum) / mu - position / radius))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::BodyDynamics;
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn orbit_vectors_work() {
+        let mut sim = prepare_sim();
+        let mu = 6.67408e-11 * 5.97219e24;
+        let radius = 384_400_000.0_f64;
+        if let BodyDynamics::Orbiting(dynamics) = &mut sim.get_body_mut("moon").unwrap().dynamics {
+            dynamics.orbit_plane_normal = DecimalVector3d::new(DBig::ZERO, DBig::ONE, DBig::ZERO);
+            dynamics.orbit_period =
+                f64_to_dbig(2.0 * std::f64::consts::PI * (radius.powi(3) / mu).sqrt());
+        }
+        sim.update(&DBig::from(100_000));
+
+        let momentum = sim.specific_angular_momentum("moon").unwrap().unwrap();
+        let expected = radius * (mu / radius).sqrt();
+        let tolerance = f64_to_dbig(expected * 1e-5);
+        assert!(approx_eq(&momentum.y, &f64_to_dbig(expected), &tolerance));
+        assert!(approx_eq(&momentum.x, &DBig::ZERO, &tolerance));
+        assert!(approx_eq(&momentum.z, &DBig::ZERO, &tolerance));
+
+        let eccentricity = sim.eccentricity_vector("moon").unwrap().unwrap();
+        assert!(dbig_to_f64(&eccentricity.length()) < 1e-4);
+        assert!(sim.eccentricity_vector("sun").unwrap().is_none());
+    }
+}
@@ -0,0 +1,103 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::simulation::{Simulation, G_CONSTANT};
+use crate::sin_cos::dbig_to_f64;
+
+// attractor positions relative to the field origin and their gravitational parameters
+pub(crate) fn field_attractors(
+    sim: &Simulation,
+    origin: &DecimalVector3d,
+) -> Result<Vec<([f64; 3], f64)>, SimulationError> {
+    Ok(sim
+        .gravity_sources(origin)?
+        .into_iter()
+        .map(|body| {
+            let relative = sim.world_position(body) - origin;
+            let mu = &*G_CONSTANT * body.body.mass_at(&sim.time);
+            (
+                [
+                    dbig_to_f64(&relative.x),
+                    dbig_to_f64(&relative.y),
+                    dbig_to_f64(&relative.z),
+                ],
+                dbig_to_f64(&mu),
+            )
+        })
+        .collect())
+}
+
+impl Simulation {
+    /// approximate gravity for large particle populations (debris, ring particles),
+    /// points are relative to `origin` which should be close to them
+    ///
+    /// # Errors
+    ///
+    /// `InvalidDynamics` if the dominant body at `origin` or a parent along the way has no mass.
+    pub fn bulk_gravity_flux(
+        &self,
+        origin: &DecimalVector3d,
+        points: &[[f64; 3]],
+    ) -> Result<Vec<[f64; 3]>, SimulationError> {
+        let attractors = field_attractors(self, origin)?;
+        Ok(points
+            .iter()
+            .map(|point| {
+                let mut flux = [0.0, 0.0, 0.0];
+                for (position, mu) in &attractors {
+                    let relative = [
+                        position[0] - point[0],
+                        position[1] - point[1],
+                        position[2] - point[2],
+                    ];
+                    let length_squared = relative[0] * relative[0]
+                        + relative[1] * relative[1]
+                        + relative[2] * relative[2];
+                    let factor = mu / (length_squared * length_squared.sqrt());
+                    flux[0] += relative[0] * factor;
+                    flux[1] += relative[1] * factor;
+                    flux[2] += relative[2] * factor;
+                }
+                flux
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::prepare_sim;
+
+    #[test]
+    fn bulk_gravity_flux_works() {
+        let mut sim = prepare_sim();
+        sim.update(&f64_to_dbig(123_123.0));
+        let origin = sim.get_body("earth").unwrap().position.clone();
+        let points = [[6_371_000.0, 0.0, 0.0], [0.0, 0.0, 7_000_000.0]];
+
+        let bulk = sim.bulk_gravity_flux(&origin, &points).unwrap();
+        for (point, flux) in points.iter().zip(bulk) {
+            let exact = sim
+                .calculate_gravity_flux(
+                    &(&origin + DecimalVector3d::from_f64(point[0], point[1], point[2])),
+                )
+                .unwrap();
+            assert!(approx_eq(
+                &f64_to_dbig(flux[0]),
+                &exact.x,
+                &f64_to_dbig(0.000_001)
+            ));
+            assert!(approx_eq(
+                &f64_to_dbig(flux[1]),
+                &exact.y,
+                &f64_to_dbig(0.000_001)
+            ));
+            assert!(approx_eq(
+                &f64_to_dbig(flux[2]),
+                &exact.z,
+                &f64_to_dbig(0.000_001)
+            ));
+        }
+    }
+}
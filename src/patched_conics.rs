@@ -0,0 +1,253 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::{check_positive, SimulationError};
+use crate::kepler::propagate_kepler;
+use crate::propagation::CraftState;
+use crate::simulation::{Simulation, G_CONSTANT};
+use dashu_float::DBig;
+
+const PRECISION: usize = 40;
+const REFINE_ITERATIONS: usize = 32;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+fn lift_vector(v: &DecimalVector3d) -> DecimalVector3d {
+    DecimalVector3d::new(lift(&v.x), lift(&v.y), lift(&v.z))
+}
+
+// a stretch of the trajectory on one keplerian conic around the body whose sphere of influence
+// it is in, the state is relative to that body at the segment start
+#[derive(Debug, Clone)]
+pub struct ConicSegment {
+    pub body: String,
+    pub mu: DBig, // gravitational parameter of the body
+    pub start_time: DBig,
+    pub end_time: DBig,
+    pub position: DecimalVector3d,
+    pub velocity: DecimalVector3d,
+}
+
+impl ConicSegment {
+    // relative to the segment body, also outside of the segment times
+    pub fn state_at(&self, time: &DBig) -> (DecimalVector3d, DecimalVector3d) {
+        propagate_kepler(
+            &self.mu,
+            &self.position,
+            &self.velocity,
+            &(time - &self.start_time),
+        )
+    }
+}
+
+impl Simulation {
+    /// coasts a craft on patched conics for `duration` seconds: only the body whose sphere of
+    /// influence the craft is in pulls on it, and the conic is switched to the next body where it
+    /// crosses a boundary. The boundaries are checked every `step` and the crossing times refined,
+    /// so the step only has to be short next to the time spent in a sphere; thrust and propulsion
+    /// aren't modelled
+    ///
+    /// # Errors
+    ///
+    /// `InvalidArgument` if `step` isn't positive, `InvalidDynamics` if there are no bodies to fall
+    /// towards.
+    pub fn propagate_patched_conics(
+        &self,
+        state: &CraftState,
+        duration: &DBig,
+        step: &DBig,
+    ) -> Result<Vec<ConicSegment>, SimulationError> {
+        check_positive(step, "step")?;
+        let mut sim = self.copy_bodies();
+        let start = lift(&state.time);
+        let end = &start + lift(duration);
+        sim.update(&start);
+        let mut segment = sim.conic_segment(
+            &start,
+            &lift_vector(&state.position),
+            &lift_vector(&state.velocity),
+        )?;
+
+        let mut segments = vec![];
+        let mut time = start;
+        while time < end {
+            let next = (&time + lift(step)).min(end.clone());
+            if sim.conic_region(&segment, &next)? == segment.body {
+                time = next;
+                continue;
+            }
+            let (mut low, mut high) = (time.clone(), next);
+            for _ in 0..REFINE_ITERATIONS {
+                let middle = (&low + &high) / DBig::from(2);
+                if sim.conic_region(&segment, &middle)? == segment.body {
+                    low = middle;
+                } else {
+                    high = middle;
+                }
+            }
+            let (position, velocity) = sim.conic_world_state(&segment, &high)?;
+            segment.end_time.clone_from(&high);
+            segments.push(segment);
+            segment = sim.conic_segment(&high, &position, &velocity)?;
+            time = high;
+        }
+        segment.end_time = end;
+        segments.push(segment);
+        Ok(segments)
+    }
+
+    // the conic around the body whose sphere of influence holds the world state, with the
+    // simulation updated to `time`
+    fn conic_segment(
+        &mut self,
+        time: &DBig,
+        position: &DecimalVector3d,
+        velocity: &DecimalVector3d,
+    ) -> Result<ConicSegment, SimulationError> {
+        self.update(time);
+        let Some(body) = self.find_dominant_body(position)? else {
+            return Err(SimulationError::InvalidDynamics(String::from(
+                "there are no bodies to fall towards",
+            )));
+        };
+        Ok(ConicSegment {
+            body: body.body.name.clone(),
+            mu: &*G_CONSTANT * lift(&body.body.mass_at(time)),
+            start_time: time.clone(),
+            end_time: time.clone(),
+            position: position - self.world_position(body),
+            velocity: velocity - self.world_velocity(body),
+        })
+    }
+
+    fn conic_world_state(
+        &mut self,
+        segment: &ConicSegment,
+        time: &DBig,
+    ) -> Result<(DecimalVector3d, DecimalVector3d), SimulationError> {
+        self.update(time);
+        let (position, velocity) = segment.state_at(time);
+        let body = self.get_body(&segment.body)?;
+        Ok((
+            position + self.world_position(body),
+            velocity + self.world_velocity(body),
+        ))
+    }
+
+    // name of the body whose sphere of influence holds the craft at `time` on the conic
+    fn conic_region(
+        &mut self,
+        segment: &ConicSegment,
+        time: &DBig,
+    ) -> Result<String, SimulationError> {
+        let (position, _) = self.conic_world_state(segment, time)?;
+        match self.find_dominant_body(&position)? {
+            Some(body) => Ok(body.body.name.clone()),
+            None => Err(SimulationError::InvalidDynamics(String::from(
+                "there are no bodies to fall towards",
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::kepler::propagate_kepler;
+    use crate::patched_conics::ConicSegment;
+    use crate::propagation::{CraftState, ThrustProfile};
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn kepler_propagation_follows_the_circle() {
+        // a full circle comes back to where it started, an eighth of a turn matches the rotation
+        let mu = f64_to_dbig(3.986e14);
+        let radius = 7e6f64;
+        let speed = (3.986e14 / radius).sqrt();
+        let period = 2.0 * std::f64::consts::PI * radius / speed;
+        let position = DecimalVector3d::from_f64(radius, 0.0, 0.0);
+        let velocity = DecimalVector3d::from_f64(0.0, 0.0, -speed);
+        let (after, _) = propagate_kepler(&mu, &position, &velocity, &f64_to_dbig(period));
+        assert!(after.approx_eq(&position, &f64_to_dbig(1e-3)));
+        let (after, moved) =
+            propagate_kepler(&mu, &position, &velocity, &f64_to_dbig(period / 8.0));
+        let diagonal = radius / 2f64.sqrt();
+        assert!(after.approx_eq(
+            &DecimalVector3d::from_f64(diagonal, 0.0, -diagonal),
+            &f64_to_dbig(1e-3)
+        ));
+        assert!(approx_eq(
+            &moved.length(),
+            &f64_to_dbig(speed),
+            &f64_to_dbig(1e-6)
+        ));
+    }
+
+    #[test]
+    fn patched_conics_work() {
+        // leaving the earth north of the ecliptic like in the sphere of influence test
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let limit = dbig_to_f64(&sim.sphere_of_influence("earth").unwrap().unwrap());
+        let earth = sim.get_body("earth").unwrap();
+        let start = CraftState {
+            time: DBig::ZERO,
+            position: sim.world_position(earth)
+                + DecimalVector3d::from_f64(0.0, limit - 2.5e7, 0.0),
+            velocity: sim.world_velocity(earth) + DecimalVector3d::from_f64(0.0, 3000.0, 0.0),
+            propulsion: None,
+        };
+        let segments: Vec<ConicSegment> = sim
+            .propagate_patched_conics(&start, &DBig::from(20000), &DBig::from(2000))
+            .unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].body, "earth");
+        assert_eq!(segments[1].body, "sun");
+        let crossing = dbig_to_f64(&segments[0].end_time);
+        assert!(crossing > 7000.0 && crossing < 9000.0, "{}", crossing);
+        assert_eq!(segments[0].end_time, segments[1].start_time);
+        assert_eq!(segments[1].end_time, DBig::from(20000));
+
+        // the segments join up, and the crossing is at the edge of the sphere
+        let mut check = prepare_sim();
+        check.update(&segments[0].end_time);
+        let earth = check.get_body("earth").unwrap();
+        let sun = check.get_body("sun").unwrap();
+        let (inside, _) = segments[0].state_at(&segments[0].end_time);
+        let outside =
+            &segments[1].position + check.world_position(sun) - check.world_position(earth);
+        assert!(inside.approx_eq(&outside, &f64_to_dbig(1e-3)));
+        assert!(approx_eq(
+            &inside.length(),
+            &f64_to_dbig(limit),
+            &f64_to_dbig(1000.0)
+        ));
+
+        // close to integrating the gravity of every body; near the edge of the sphere the tide of the
+        // sun is about 7e-4 m/s^2, which adds up to some 100 km by the end
+        let path = sim
+            .propagate(
+                &start,
+                &ThrustProfile::Coast,
+                &DBig::from(20000),
+                &DBig::from(500),
+            )
+            .unwrap();
+        let mut check = prepare_sim();
+        check.update(&DBig::from(20000));
+        let sun = check.get_body("sun").unwrap();
+        let (position, _) = segments[1].state_at(&DBig::from(20000));
+        let miss =
+            (position + check.world_position(sun)).distance_to(&path.last().unwrap().position);
+        assert!(dbig_to_f64(&miss) < 2e5, "{}", dbig_to_f64(&miss));
+
+        assert_eq!(
+            sim.propagate_patched_conics(&start, &DBig::from(20000), &DBig::ZERO)
+                .unwrap_err(),
+            SimulationError::InvalidArgument(String::from("the step has to be positive"))
+        );
+    }
+}
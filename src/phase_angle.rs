@@ -0,0 +1,221 @@
+use crate::error::SimulationError;
+use crate::simulation::Simulation;
+use crate::sin_cos::{atan2, PI, PIMUL2};
+use dashu_float::DBig;
+
+const PRECISION: usize = 40;
+const REFINE_ITERATIONS: usize = 32;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+// into [0, 2pi)
+pub(crate) fn wrap_angle(angle: DBig) -> DBig {
+    let turns = (&angle / &*PIMUL2).floor();
+    angle - turns * &*PIMUL2
+}
+
+impl Simulation {
+    // angle from `from` to `to` around their shared parent, in radians within [0, 2pi) and
+    // growing along the motion of `from`; for Earth and Mars this is how far Mars is ahead
+    fn shared_parent(&self, from_name: &str, to_name: &str) -> Result<i32, SimulationError> {
+        let from = self.get_body(from_name)?.parent();
+        match from {
+            Some(parent) if from == self.get_body(to_name)?.parent() => Ok(parent),
+            _ => Err(SimulationError::InvalidDynamics(format!(
+                "{from_name} and {to_name} don't orbit the same body"
+            ))),
+        }
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if either body isn't in the simulation, `InvalidDynamics` if they don't orbit
+    /// the same body.
+    pub fn phase_angle(&self, from_name: &str, to_name: &str) -> Result<DBig, SimulationError> {
+        let parent = self.shared_parent(from_name, to_name)?;
+        let from = self.get_body(from_name)?;
+        let to = self.get_body(to_name)?;
+        let center = self.world_position(
+            self.get_body_by_id(parent)
+                .ok_or(SimulationError::UnknownBodyId(parent))?,
+        );
+        let from_offset = self.world_position(from) - &center;
+        let to_offset = self.world_position(to) - &center;
+        let normal = from_offset.cross(&from.velocity).normalized();
+        let to_in_plane = &to_offset - &normal * normal.dot(&to_offset);
+        Ok(wrap_angle(atan2(
+            normal.dot(&from_offset.cross(&to_in_plane)),
+            from_offset.dot(&to_in_plane),
+            32,
+        )))
+    }
+
+    /// times between `start` and `end` when the phase angle equals `target`, in radians
+    ///
+    /// # Errors
+    ///
+    /// The errors of `phase_angle`, and `InvalidState` if either body is sleeping.
+    pub fn phase_angle_times(
+        &self,
+        from_name: &str,
+        to_name: &str,
+        target: &DBig,
+        start: &DBig,
+        end: &DBig,
+        step: &DBig,
+    ) -> Result<Vec<DBig>, SimulationError> {
+        self.get_awake_body(from_name)?;
+        self.get_awake_body(to_name)?;
+        self.shared_parent(from_name, to_name)?;
+        Ok(self
+            .angle_crossings(start, end, step, std::slice::from_ref(target), |sim| {
+                sim.phase_angle(from_name, to_name)
+            })?
+            .into_iter()
+            .map(|(_, time)| time)
+            .collect())
+    }
+
+    // (target index, time) of every time `angle` passes one of the targets, in either direction,
+    // found on a copy of the simulation by sampling every `step` and bisecting. The angle has to
+    // change by well below half a turn per step
+    pub(crate) fn angle_crossings<F>(
+        &self,
+        start: &DBig,
+        end: &DBig,
+        step: &DBig,
+        targets: &[DBig],
+        angle: F,
+    ) -> Result<Vec<(usize, DBig)>, SimulationError>
+    where
+        F: Fn(&Simulation) -> Result<DBig, SimulationError>,
+    {
+        let mut sim = self.copy_bodies();
+        let mut angle_at = |time: &DBig| {
+            sim.update(time);
+            angle(&sim)
+        };
+
+        let mut result: Vec<(usize, DBig)> = vec![];
+        let mut time = lift(start);
+        let end = lift(end);
+        let step = lift(step);
+        let mut current = angle_at(&time)?;
+        while time < end {
+            let next_time = (&time + &step).min(end.clone());
+            let next = angle_at(&next_time)?;
+            let mut change = wrap_angle(&next - &current);
+            let forward = change <= *PI;
+            if !forward {
+                change = &*PIMUL2 - change;
+            }
+            let progress = |angle: &DBig| {
+                if forward {
+                    wrap_angle(angle - &current)
+                } else {
+                    wrap_angle(&current - angle)
+                }
+            };
+
+            let mut crossings: Vec<(DBig, usize)> = vec![];
+            for (i, target) in targets.iter().enumerate() {
+                let offset = progress(target);
+                if offset > DBig::ZERO && offset <= change {
+                    crossings.push((offset, i));
+                }
+            }
+            crossings.sort_by(|a, b| a.0.cmp(&b.0));
+
+            for (offset, i) in crossings {
+                let (mut low, mut high) = (time.clone(), next_time.clone());
+                for _ in 0..REFINE_ITERATIONS {
+                    let middle = (&low + &high) / DBig::from(2);
+                    if progress(&angle_at(&middle)?) >= offset {
+                        high = middle;
+                    } else {
+                        low = middle;
+                    }
+                }
+                result.push((i, high));
+            }
+            time = next_time;
+            current = next;
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::au::au_to_meters;
+    use crate::body::{Body, BodyDynamics, OrbitingBodyDynamics};
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+    use std::str::FromStr;
+
+    #[test]
+    fn phase_angle_works() {
+        let mut sim = prepare_sim();
+        let sun = sim.get_body("sun").unwrap().id();
+        let mars = Body {
+            name: String::from("mars"),
+            dynamics: BodyDynamics::Orbiting(OrbitingBodyDynamics {
+                orbit_radius: au_to_meters(f64_to_dbig(1.524)),
+                orbit_period: DBig::from(687 * 24 * 3600),
+                orbit_plane_normal: DecimalVector3d::new(DBig::ZERO, DBig::ONE, DBig::ZERO),
+                mean_anomaly_at_epoch: DBig::ZERO,
+                ellipse: None,
+                drift: None,
+            }),
+            update_interval: None,
+            mass_variation: None,
+            nutation: None,
+            libration: None,
+            mass: f64_to_dbig(0.64171) * DBig::from_str("1000000000000000000000000").unwrap(),
+            radius: DBig::from(3_389_500),
+            satellites: vec![],
+            rotation_axis: DecimalVector3d::new(DBig::ZERO, DBig::ONE, DBig::ZERO),
+            rotation_period: DBig::from(88642),
+            rotation_phase: DBig::ZERO,
+            resonance: None,
+            visual: None,
+            kind: None,
+        };
+        sim.add_hierarchy(mars, Some(sun)).unwrap();
+        sim.update(&DBig::from(100 * 24 * 3600));
+        // earth gains on mars at the difference of their mean motions
+        let day = 24.0 * 3600.0;
+        let rate = 2.0 * std::f64::consts::PI * (1.0 / 687.0 - 1.0 / 365.0);
+        let expected = (rate * 100.0).rem_euclid(2.0 * std::f64::consts::PI);
+        assert!(approx_eq(
+            &sim.phase_angle("earth", "mars").unwrap(),
+            &f64_to_dbig(expected),
+            &f64_to_dbig(0.02)
+        ));
+
+        let target = f64_to_dbig(44f64.to_radians());
+        let times = sim
+            .phase_angle_times(
+                "earth",
+                "mars",
+                &target,
+                &DBig::ZERO,
+                &DBig::from(800 * 24 * 3600),
+                &DBig::from(10 * 24 * 3600),
+            )
+            .unwrap();
+        assert_eq!(times.len(), 1);
+        let expected = (44f64.to_radians() - 2.0 * std::f64::consts::PI) / rate;
+        assert!((dbig_to_f64(&times[0]) / day - expected).abs() < 3.0);
+        sim.update(&times[0]);
+        assert!(approx_eq(
+            &sim.phase_angle("earth", "mars").unwrap(),
+            &f64_to_dbig(44f64.to_radians()),
+            &f64_to_dbig(1e-6)
+        ));
+    }
+}
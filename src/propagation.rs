@@ -0,0 +1,682 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::{check_positive, SimulationError};
+use crate::simulation::Simulation;
+use dashu_float::ops::SquareRoot;
+use dashu_float::DBig;
+use dashu_int::IBig;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+
+const PRECISION: usize = 40;
+// digits of the PRECISION that adaptive steps can tell apart, the last ones are rounding noise
+const RESOLVED_DIGITS: isize = 36;
+
+// in m/s^2, converts a specific impulse in seconds to an exhaust velocity
+pub static STANDARD_GRAVITY: LazyLock<DBig> = LazyLock::new(|| DBig::from_str("9.80665").unwrap());
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+fn lift_vector(v: &DecimalVector3d) -> DecimalVector3d {
+    DecimalVector3d::new(lift(&v.x), lift(&v.y), lift(&v.z))
+}
+
+// engine and tank of a craft, masses in kg
+#[derive(Debug, Clone)]
+pub struct Propulsion {
+    pub dry_mass: DBig,
+    pub propellant_mass: DBig,  // what is left, burns take it down
+    pub thrust: DBig,           // in newtons at full throttle
+    pub specific_impulse: DBig, // in seconds
+}
+
+impl Propulsion {
+    pub fn mass(&self) -> DBig {
+        &self.dry_mass + &self.propellant_mass
+    }
+
+    // in m/s
+    pub fn exhaust_velocity(&self) -> DBig {
+        lift(&self.specific_impulse) * &*STANDARD_GRAVITY
+    }
+
+    // in m/s, from the rocket equation with the propellant that is left
+    pub fn remaining_delta_v(&self) -> DBig {
+        let ratio = lift(&self.mass()) / lift(&self.dry_mass);
+        self.exhaust_velocity() * ratio.ln()
+    }
+
+    // burns the propellant for an instant speed change in m/s, as much of it as the tank allows;
+    // returns the speed change actually delivered
+    pub fn burn(&mut self, delta_v: &DBig) -> DBig {
+        let delta_v = lift(delta_v).min(self.remaining_delta_v());
+        let final_mass = lift(&self.mass()) / (&delta_v / self.exhaust_velocity()).exp();
+        self.propellant_mass = (final_mass - &self.dry_mass).max(DBig::ZERO);
+        delta_v
+    }
+
+    // the commanded acceleration limited to what the engine can push at the current mass, and
+    // the propellant flow in kg/s that it takes
+    fn deliver(&self, commanded: DecimalVector3d) -> (DecimalVector3d, DBig) {
+        let magnitude = commanded.length_squared().sqrt();
+        if self.propellant_mass <= DBig::ZERO || magnitude == DBig::ZERO {
+            return (DecimalVector3d::zero(), DBig::ZERO);
+        }
+        let mass = lift(&self.mass());
+        let limit = &self.thrust / &mass;
+        let (acceleration, magnitude) = if magnitude > limit {
+            (commanded * (&limit / &magnitude), limit)
+        } else {
+            (commanded, magnitude)
+        };
+        (acceleration, -(mass * magnitude / self.exhaust_velocity()))
+    }
+}
+
+// a massless craft in world coordinates, it feels the bodies but doesn't pull on them. Without
+// propulsion any thrust profile is followed for free
+#[derive(Debug, Clone)]
+pub struct CraftState {
+    pub time: DBig,
+    pub position: DecimalVector3d,
+    pub velocity: DecimalVector3d,
+    pub propulsion: Option<Propulsion>,
+}
+
+impl CraftState {
+    // instant velocity change, shortened to what the propellant allows when there is propulsion;
+    // returns the speed change actually delivered in m/s
+    pub fn apply_impulse(&mut self, delta_v: &DecimalVector3d) -> DBig {
+        let requested = lift(&delta_v.length_squared()).sqrt();
+        if requested == DBig::ZERO {
+            return requested;
+        }
+        let delivered = match &mut self.propulsion {
+            Some(propulsion) => propulsion.burn(&requested),
+            None => requested.clone(),
+        };
+        self.velocity = &self.velocity + delta_v * (&delivered / requested);
+        delivered
+    }
+}
+
+// world acceleration for a state, the simulation is updated to the state time
+pub type ThrustFunction = dyn Fn(&Simulation, &CraftState) -> DecimalVector3d + Send + Sync;
+
+// continuous acceleration on top of gravity, in m/s^2. With propulsion it is capped at the
+// engine thrust over the current mass, a prograde acceleration above that burns at full throttle,
+// and stops once the tank is empty
+#[derive(Clone)]
+pub enum ThrustProfile {
+    Coast,
+    // along the velocity relative to the reference body, negative brakes and spirals inwards
+    Prograde {
+        reference: String,
+        acceleration: DBig,
+    },
+    Function(Arc<ThrustFunction>),
+}
+
+impl fmt::Debug for ThrustProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ThrustProfile::Coast => write!(f, "Coast"),
+            ThrustProfile::Prograde {
+                reference,
+                acceleration,
+            } => write!(f, "Prograde({reference}, {acceleration})"),
+            ThrustProfile::Function(_) => write!(f, "Function"),
+        }
+    }
+}
+
+impl ThrustProfile {
+    fn acceleration(
+        &self,
+        sim: &Simulation,
+        state: &CraftState,
+    ) -> Result<DecimalVector3d, SimulationError> {
+        match self {
+            ThrustProfile::Coast => Ok(DecimalVector3d::zero()),
+            ThrustProfile::Prograde {
+                reference,
+                acceleration,
+            } => {
+                let reference = sim.get_body(reference)?;
+                let velocity = &state.velocity - sim.world_velocity(reference);
+                if velocity.length_squared() == DBig::ZERO {
+                    return Ok(DecimalVector3d::zero());
+                }
+                Ok(velocity.normalized() * acceleration)
+            }
+            ThrustProfile::Function(function) => Ok(function(sim, state)),
+        }
+    }
+}
+
+impl Simulation {
+    /// integrates the craft from its state for `duration` seconds in fixed `step`s with the classic
+    /// Runge-Kutta method, the bodies stay on their orbits. Returns the state after every step,
+    /// starting with the initial one; a step that doesn't divide the duration is shortened at the
+    /// end
+    ///
+    /// # Errors
+    ///
+    /// `InvalidArgument` if `step` isn't positive, `UnknownBody` if a prograde thrust refers to a
+    /// body that isn't in the simulation.
+    pub fn propagate(
+        &self,
+        state: &CraftState,
+        thrust: &ThrustProfile,
+        duration: &DBig,
+        step: &DBig,
+    ) -> Result<Vec<CraftState>, SimulationError> {
+        check_positive(step, "step")?;
+        let (mut sim, mut state) = self.propagation_start(state);
+        let end = &state.time + lift(duration);
+        let mut result = vec![state.clone()];
+        while state.time < end {
+            let step = lift(step).min(&end - &state.time);
+            state = rk4_step(&mut sim, &state, &step, thrust)?;
+            result.push(state.clone());
+        }
+        Ok(result)
+    }
+
+    /// like propagate, but with the Runge-Kutta-Fehlberg 4(5) pair picking the steps: a step is
+    /// redone shorter when the two orders differ by more than `tolerance` meters in position (or
+    /// in velocity times the step), and the next one grows where they agree, so close passes get
+    /// short steps and long coasts long ones. Steps don't shrink below `min_step`, a tolerance the
+    /// steps can't reach there, or finer than the precision of the positions, is an error. Returns
+    /// the accepted states, starting with the initial one
+    ///
+    /// # Errors
+    ///
+    /// `InvalidArgument` if a step or the tolerance isn't positive, or the tolerance can't be met,
+    /// `UnknownBody` if a prograde thrust refers to a body that isn't in the simulation.
+    pub fn propagate_adaptive(
+        &self,
+        state: &CraftState,
+        thrust: &ThrustProfile,
+        duration: &DBig,
+        initial_step: &DBig,
+        min_step: &DBig,
+        tolerance: &DBig,
+    ) -> Result<Vec<CraftState>, SimulationError> {
+        check_positive(initial_step, "step")?;
+        check_positive(min_step, "minimum step")?;
+        check_positive(tolerance, "tolerance")?;
+        let (mut sim, mut state) = self.propagation_start(state);
+        let end = &state.time + lift(duration);
+        let tolerance = lift(tolerance);
+        let resolution = state.position.length() * DBig::from_parts(IBig::ONE, -RESOLVED_DIGITS);
+        if tolerance < resolution {
+            return Err(SimulationError::InvalidArgument(format!(
+                "the tolerance is below the resolution of {resolution}"
+            )));
+        }
+        let min_step = lift(min_step);
+        let mut step = lift(initial_step).max(min_step.clone());
+        let mut result = vec![state.clone()];
+        while state.time < end {
+            let attempt = step.clone().min(&end - &state.time);
+            let (next, error) = rkf45_step(&mut sim, &state, &attempt, thrust)?;
+            if error > tolerance && attempt <= min_step {
+                return Err(SimulationError::InvalidArgument(format!(
+                    "the tolerance can't be met at the minimum step, the error is {error}"
+                )));
+            }
+            // the usual safety factor and fifth root, within a fifth and five times the step
+            let factor = if error == DBig::ZERO {
+                DBig::from(5)
+            } else {
+                let scale = ((&tolerance / &error).ln() / DBig::from(5)).exp();
+                (scale * fraction(9, 10)).clamp(fraction(1, 5), DBig::from(5))
+            };
+            if error <= tolerance {
+                state = next;
+                result.push(state.clone());
+            }
+            step = (attempt * factor).max(min_step.clone());
+        }
+        Ok(result)
+    }
+
+    // a copy of the simulation to move the bodies in, and the lifted initial state
+    fn propagation_start(&self, state: &CraftState) -> (Simulation, CraftState) {
+        let sim = self.copy_bodies();
+        let state = CraftState {
+            time: lift(&state.time),
+            position: lift_vector(&state.position),
+            velocity: lift_vector(&state.velocity),
+            propulsion: state.propulsion.clone(),
+        };
+        (sim, state)
+    }
+}
+
+fn fraction(numerator: i64, denominator: i64) -> DBig {
+    lift(&DBig::from(numerator)) / DBig::from(denominator)
+}
+
+// time derivative of a craft state
+struct Rate {
+    velocity: DecimalVector3d,
+    acceleration: DecimalVector3d,
+    flow: DBig, // of propellant in kg/s, zero or negative
+}
+
+// gravity and thrust at a state and the propellant flow, updating the copy only when the time
+// moves
+fn rate(
+    sim: &mut Simulation,
+    state: &CraftState,
+    thrust: &ThrustProfile,
+) -> Result<Rate, SimulationError> {
+    if sim.time != state.time {
+        sim.update(&state.time);
+    }
+    let commanded = thrust.acceleration(sim, state)?;
+    let (thrust, flow) = match &state.propulsion {
+        Some(propulsion) => propulsion.deliver(commanded),
+        None => (commanded, DBig::ZERO),
+    };
+    Ok(Rate {
+        velocity: state.velocity.clone(),
+        acceleration: sim.calculate_gravity_flux(&state.position)? + thrust,
+        flow,
+    })
+}
+
+// the state `offset` seconds on, moved by `step` times the weighted rates; the tank can run dry
+// within a step, the overshoot is cut off at zero
+fn advance(state: &CraftState, offset: &DBig, step: &DBig, terms: &[(DBig, &Rate)]) -> CraftState {
+    let mut velocity = DecimalVector3d::zero();
+    let mut acceleration = DecimalVector3d::zero();
+    let mut flow = DBig::ZERO;
+    for (weight, rate) in terms {
+        velocity = velocity + &rate.velocity * weight;
+        acceleration = acceleration + &rate.acceleration * weight;
+        flow += &rate.flow * weight;
+    }
+    CraftState {
+        time: &state.time + offset,
+        position: &state.position + velocity * step,
+        velocity: &state.velocity + acceleration * step,
+        propulsion: state.propulsion.clone().map(|mut propulsion| {
+            propulsion.propellant_mass =
+                (&propulsion.propellant_mass + flow * step).max(DBig::ZERO);
+            propulsion
+        }),
+    }
+}
+
+fn rk4_step(
+    sim: &mut Simulation,
+    state: &CraftState,
+    step: &DBig,
+    thrust: &ThrustProfile,
+) -> Result<CraftState, SimulationError> {
+    let half = step / DBig::from(2);
+    let k1 = rate(sim, state, thrust)?;
+    let k2 = rate(
+        sim,
+        &advance(state, &half, &half, &[(DBig::ONE, &k1)]),
+        thrust,
+    )?;
+    let k3 = rate(
+        sim,
+        &advance(state, &half, &half, &[(DBig::ONE, &k2)]),
+        thrust,
+    )?;
+    let k4 = rate(
+        sim,
+        &advance(state, step, step, &[(DBig::ONE, &k3)]),
+        thrust,
+    )?;
+    let (sixth, third) = (fraction(1, 6), fraction(1, 3));
+    Ok(advance(
+        state,
+        step,
+        step,
+        &[
+            (sixth.clone(), &k1),
+            (third.clone(), &k2),
+            (third, &k3),
+            (sixth, &k4),
+        ],
+    ))
+}
+
+// the fifth order solution of the Fehlberg pair and its distance from the fourth order one
+fn rkf45_step(
+    sim: &mut Simulation,
+    state: &CraftState,
+    step: &DBig,
+    thrust: &ThrustProfile,
+) -> Result<(CraftState, DBig), SimulationError> {
+    let k1 = rate(sim, state, thrust)?;
+    let mut evaluate = |time: (i64, i64), weights: &[(DBig, &Rate)]| {
+        let offset = step * fraction(time.0, time.1);
+        rate(sim, &advance(state, &offset, step, weights), thrust)
+    };
+    let k2 = evaluate((1, 4), &[(fraction(1, 4), &k1)])?;
+    let k3 = evaluate((3, 8), &[(fraction(3, 32), &k1), (fraction(9, 32), &k2)])?;
+    let k4 = evaluate(
+        (12, 13),
+        &[
+            (fraction(1932, 2197), &k1),
+            (fraction(-7200, 2197), &k2),
+            (fraction(7296, 2197), &k3),
+        ],
+    )?;
+    let k5 = evaluate(
+        (1, 1),
+        &[
+            (fraction(439, 216), &k1),
+            (fraction(-8, 1), &k2),
+            (fraction(3680, 513), &k3),
+            (fraction(-845, 4104), &k4),
+        ],
+    )?;
+    let k6 = evaluate(
+        (1, 2),
+        &[
+            (fraction(-8, 27), &k1),
+            (fraction(2, 1), &k2),
+            (fraction(-3544, 2565), &k3),
+            (fraction(1859, 4104), &k4),
+            (fraction(-11, 40), &k5),
+        ],
+    )?;
+
+    let next = advance(
+        state,
+        step,
+        step,
+        &[
+            (fraction(16, 135), &k1),
+            (fraction(6656, 12825), &k3),
+            (fraction(28561, 56430), &k4),
+            (fraction(-9, 50), &k5),
+            (fraction(2, 55), &k6),
+        ],
+    );
+    // fifth minus fourth order weights
+    let difference = advance(
+        state,
+        step,
+        step,
+        &[
+            (fraction(1, 360), &k1),
+            (fraction(-128, 4275), &k3),
+            (fraction(-2197, 75240), &k4),
+            (fraction(1, 50), &k5),
+            (fraction(2, 55), &k6),
+        ],
+    );
+    let position_error = difference.position.distance_to(&state.position);
+    let velocity_error = difference.velocity.distance_to(&state.velocity) * step;
+    Ok((next, position_error.max(velocity_error)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::propagation::{CraftState, Propulsion, ThrustProfile};
+    use crate::simulation::Simulation;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+    use std::sync::Arc;
+
+    #[test]
+    fn low_thrust_propagation_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let earth = sim.get_body("earth").unwrap();
+        let (earth_position, earth_velocity) =
+            (sim.world_position(earth), sim.world_velocity(earth));
+        let mu = 6.674e-11 * 5.97219e24;
+        let radius = 7e6f64;
+        let speed = (mu / radius).sqrt();
+        let start = CraftState {
+            time: DBig::ZERO,
+            position: &earth_position + DecimalVector3d::from_f64(radius, 0.0, 0.0),
+            velocity: &earth_velocity + DecimalVector3d::from_f64(0.0, 0.0, -speed),
+            propulsion: None,
+        };
+        let energy = |sim: &mut Simulation, state: &CraftState| {
+            sim.update(&state.time);
+            let earth = sim.get_body("earth").unwrap();
+            let position = &state.position - sim.world_position(earth);
+            let velocity = &state.velocity - sim.world_velocity(earth);
+            let speed = dbig_to_f64(&velocity.length());
+            speed * speed / 2.0 - mu / dbig_to_f64(&position.length())
+        };
+        let duration = DBig::from(600);
+        let step = DBig::from(60);
+
+        let coast = sim
+            .propagate(&start, &ThrustProfile::Coast, &duration, &step)
+            .unwrap();
+        assert_eq!(coast.len(), 11);
+        assert_eq!(coast[10].time, DBig::from(600));
+        let mut check = prepare_sim();
+        let drift = energy(&mut check, &coast[10]) - energy(&mut check, &coast[0]);
+        assert!(drift.abs() < 100.0, "{}", drift);
+
+        // thrust along the velocity adds energy at the rate of acceleration times speed
+        let prograde = ThrustProfile::Prograde {
+            reference: String::from("earth"),
+            acceleration: f64_to_dbig(0.01),
+        };
+        let spiral = sim.propagate(&start, &prograde, &duration, &step).unwrap();
+        let gain = energy(&mut check, &spiral[10]) - energy(&mut check, &spiral[0]);
+        assert!(
+            (gain / (0.01 * speed * 600.0) - 1.0).abs() < 0.05,
+            "{}",
+            gain
+        );
+
+        let upwards = ThrustProfile::Function(Arc::new(|_: &Simulation, _: &CraftState| {
+            DecimalVector3d::from_f64(0.0, 0.01, 0.0)
+        }));
+        let long_step = DBig::from(250);
+        let lifted = sim
+            .propagate(&start, &upwards, &duration, &long_step)
+            .unwrap();
+        let coast = sim
+            .propagate(&start, &ThrustProfile::Coast, &duration, &long_step)
+            .unwrap();
+        assert_eq!(lifted.len(), 4);
+        let climb = &lifted[3].velocity.y - &coast[3].velocity.y;
+        // a little less than the thrust alone, gravity pulls back towards the orbit plane
+        let climb = dbig_to_f64(&climb);
+        assert!(climb > 5.0 && climb < 6.0, "{}", climb);
+
+        let unknown = ThrustProfile::Prograde {
+            reference: String::from("pluto"),
+            acceleration: DBig::ONE,
+        };
+        assert_eq!(
+            sim.propagate(&start, &unknown, &duration, &step)
+                .unwrap_err(),
+            SimulationError::UnknownBody(String::from("pluto"))
+        );
+        assert_eq!(
+            sim.propagate(&start, &ThrustProfile::Coast, &duration, &DBig::ZERO)
+                .unwrap_err(),
+            SimulationError::InvalidArgument(String::from("the step has to be positive"))
+        );
+    }
+
+    #[test]
+    fn propellant_tracking_works() {
+        let mut propulsion = Propulsion {
+            dry_mass: DBig::from(1000),
+            propellant_mass: DBig::from(1000),
+            thrust: DBig::from(100),
+            specific_impulse: DBig::from(300),
+        };
+        let exhaust_velocity = 300.0 * 9.80665;
+        let full = exhaust_velocity * 2f64.ln();
+        assert!(approx_eq(
+            &propulsion.remaining_delta_v(),
+            &f64_to_dbig(full),
+            &f64_to_dbig(1e-9)
+        ));
+        assert_eq!(propulsion.burn(&DBig::from(1000)), DBig::from(1000));
+        assert!(approx_eq(
+            &propulsion.remaining_delta_v(),
+            &f64_to_dbig(full - 1000.0),
+            &f64_to_dbig(1e-9)
+        ));
+
+        // an impulse larger than the tank is cut short and leaves the dry mass
+        let mut craft = CraftState {
+            time: DBig::ZERO,
+            position: DecimalVector3d::zero(),
+            velocity: DecimalVector3d::zero(),
+            propulsion: Some(propulsion.clone()),
+        };
+        let delivered = craft.apply_impulse(&DecimalVector3d::from_f64(0.0, 5000.0, 0.0));
+        assert!(approx_eq(
+            &delivered,
+            &f64_to_dbig(full - 1000.0),
+            &f64_to_dbig(1e-9)
+        ));
+        assert!(approx_eq(
+            &craft.velocity.y,
+            &f64_to_dbig(full - 1000.0),
+            &f64_to_dbig(1e-9)
+        ));
+        let left = craft.propulsion.as_ref().unwrap();
+        assert!(approx_eq(
+            &left.propellant_mass,
+            &DBig::ZERO,
+            &f64_to_dbig(1e-9)
+        ));
+
+        // full throttle for ten minutes, then a small tank that runs dry halfway
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let earth = sim.get_body("earth").unwrap();
+        let mut start = CraftState {
+            time: DBig::ZERO,
+            position: sim.world_position(earth) + DecimalVector3d::from_f64(7e6, 0.0, 0.0),
+            velocity: sim.world_velocity(earth) + DecimalVector3d::from_f64(0.0, 0.0, -7546.0),
+            propulsion: Some(Propulsion {
+                propellant_mass: DBig::from(1000),
+                ..propulsion.clone()
+            }),
+        };
+        let burn = ThrustProfile::Prograde {
+            reference: String::from("earth"),
+            acceleration: DBig::ONE,
+        };
+        let (duration, step) = (DBig::from(600), DBig::from(60));
+        let path = sim.propagate(&start, &burn, &duration, &step).unwrap();
+        let used = 1000.0 - dbig_to_f64(&path[10].propulsion.as_ref().unwrap().propellant_mass);
+        assert!(
+            (used - 100.0 / exhaust_velocity * 600.0).abs() < 1e-6,
+            "{}",
+            used
+        );
+
+        start.propulsion.as_mut().unwrap().propellant_mass = DBig::from(10);
+        let path = sim.propagate(&start, &burn, &duration, &step).unwrap();
+        let left = path[10].propulsion.as_ref().unwrap();
+        assert_eq!(left.propellant_mass, DBig::ZERO);
+        assert_eq!(left.remaining_delta_v(), DBig::ZERO);
+    }
+
+    #[test]
+    fn adaptive_propagation_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let earth = sim.get_body("earth").unwrap();
+        let earth_position = sim.world_position(earth);
+
+        // from the apoapsis of an orbit dipping to 6600 km, through the periapsis half an orbit later
+        let mu = 6.674e-11 * 5.97219e24;
+        let (apoapsis, periapsis) = (4e7f64, 6.6e6f64);
+        let semi_major_axis = f64::midpoint(apoapsis, periapsis);
+        let speed = (mu * (2.0 / apoapsis - 1.0 / semi_major_axis)).sqrt();
+        let start = CraftState {
+            time: DBig::ZERO,
+            position: &earth_position + DecimalVector3d::from_f64(apoapsis, 0.0, 0.0),
+            velocity: sim.world_velocity(earth) + DecimalVector3d::from_f64(0.0, 0.0, -speed),
+            propulsion: None,
+        };
+        let half_period = std::f64::consts::PI * (semi_major_axis.powi(3) / mu).sqrt();
+        let path = sim
+            .propagate_adaptive(
+                &start,
+                &ThrustProfile::Coast,
+                &f64_to_dbig(half_period + 1200.0),
+                &DBig::from(600),
+                &DBig::ONE,
+                &DBig::ONE,
+            )
+            .unwrap();
+        assert_eq!(path.last().unwrap().time, f64_to_dbig(half_period + 1200.0));
+
+        let steps: Vec<f64> = path
+            .windows(2)
+            .map(|pair| dbig_to_f64(&(&pair[1].time - &pair[0].time)))
+            .collect();
+        let longest = steps.iter().copied().fold(0.0, f64::max);
+        let shortest = steps[..steps.len() - 1]
+            .iter()
+            .copied()
+            .fold(f64::MAX, f64::min);
+        assert!(longest > 10.0 * shortest, "{longest} {shortest}");
+
+        // the closest approach lands on the periapsis, where the steps are shortest
+        let closest = path
+            .iter()
+            .map(|state| {
+                sim.update(&state.time);
+                let earth = sim.get_body("earth").unwrap();
+                dbig_to_f64(&state.position.distance_to(&sim.world_position(earth)))
+            })
+            .fold(f64::MAX, f64::min);
+        assert!((closest - periapsis).abs() < 10000.0, "{}", closest);
+
+        let coast = |initial_step: &DBig, min_step: &DBig, tolerance: &DBig| {
+            sim.propagate_adaptive(
+                &start,
+                &ThrustProfile::Coast,
+                &DBig::from(600),
+                initial_step,
+                min_step,
+                tolerance,
+            )
+            .unwrap_err()
+        };
+        assert_eq!(
+            coast(&DBig::from(-60), &DBig::ONE, &DBig::ONE),
+            SimulationError::InvalidArgument(String::from("the step has to be positive"))
+        );
+        assert_eq!(
+            coast(&DBig::from(60), &DBig::ZERO, &DBig::ONE),
+            SimulationError::InvalidArgument(String::from("the minimum step has to be positive"))
+        );
+        assert_eq!(
+            coast(&DBig::from(60), &DBig::ONE, &DBig::ZERO),
+            SimulationError::InvalidArgument(String::from("the tolerance has to be positive"))
+        );
+        // finer than the digits of the position, the sun sits 7e19 m out, and finer than the
+        // minimum step can get
+        let unresolved = coast(&DBig::from(60), &DBig::ONE, &f64_to_dbig(1e-45));
+        assert!(matches!(unresolved, SimulationError::InvalidArgument(_)));
+        assert!(unresolved.to_string().contains("resolution"));
+        let unreachable = coast(&DBig::from(60), &DBig::from(60), &f64_to_dbig(1e-12));
+        assert!(matches!(unreachable, SimulationError::InvalidArgument(_)));
+        assert!(unreachable.to_string().contains("minimum step"));
+    }
+}
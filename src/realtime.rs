@@ -0,0 +1,206 @@
+use crate::simulation::Simulation;
+use dashu_float::ops::Abs;
+use dashu_float::DBig;
+use std::time::{Duration, Instant};
+
+const FULL_PRECISION: usize = 40;
+const MIN_PRECISION: usize = 16;
+const PRECISION_STEP: usize = 8;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(FULL_PRECISION).value()
+}
+
+fn seconds(duration: Duration) -> DBig {
+    lift(&DBig::from(duration.as_nanos())) / DBig::from(1_000_000_000)
+}
+
+#[derive(Debug, Clone)]
+pub struct RealTimeTick {
+    pub updated: bool,         // false while the update interval holds updates back
+    pub update_cost: Duration, // wall time the update took, zero without one
+    pub precision: usize,      // digits of the time the update got
+    pub update_interval: DBig, // simulation seconds between updates after this tick
+    pub achieved_warp: Option<DBig>, // see RealTimeDriver::achieved_warp
+}
+
+// drives a simulation from the wall clock, `time_warp` simulation seconds pass per wall-clock
+// second. An update that takes longer than the frame budget first lowers the precision of the
+// times handed to update, then spaces the updates out up to `max_update_interval` simulation
+// seconds; cheap updates win both back in the reverse order
+#[derive(Debug, Clone)]
+pub struct RealTimeDriver {
+    time_warp: DBig,
+    frame_budget: Duration,
+    max_update_interval: DBig,
+    precision: usize,
+    update_interval: DBig,          // zero updates on every tick
+    clock: Option<(Instant, DBig)>, // wall time of the last tick and the simulation time it maps to
+    last_update: Option<DBig>,
+    started: Option<(Instant, DBig)>, // for the achieved warp
+    achieved_warp: Option<DBig>,
+}
+
+impl RealTimeDriver {
+    pub fn new(time_warp: &DBig, frame_budget: Duration, max_update_interval: &DBig) -> Self {
+        RealTimeDriver {
+            time_warp: lift(time_warp),
+            frame_budget,
+            max_update_interval: lift(max_update_interval),
+            precision: FULL_PRECISION,
+            update_interval: DBig::ZERO,
+            clock: None,
+            last_update: None,
+            started: None,
+            achieved_warp: None,
+        }
+    }
+
+    // takes effect from the next tick, zero pauses and negative runs backwards
+    pub fn set_time_warp(&mut self, time_warp: &DBig) {
+        self.time_warp = lift(time_warp);
+        self.started = None;
+    }
+
+    pub fn time_warp(&self) -> &DBig {
+        &self.time_warp
+    }
+
+    pub fn precision(&self) -> usize {
+        self.precision
+    }
+
+    pub fn update_interval(&self) -> &DBig {
+        &self.update_interval
+    }
+
+    // simulation seconds the simulation actually moved per wall-clock second since the first
+    // tick or the last warp change, None before two ticks
+    pub fn achieved_warp(&self) -> Option<&DBig> {
+        self.achieved_warp.as_ref()
+    }
+
+    // the first tick only starts the clock at the simulation time
+    pub fn tick(&mut self, sim: &mut Simulation, now: Instant) -> RealTimeTick {
+        let (last_tick, last_time) = match &self.clock {
+            Some((instant, time)) => (*instant, time.clone()),
+            None => (now, lift(sim.time())),
+        };
+        let elapsed = now.saturating_duration_since(last_tick);
+        let target = &last_time + seconds(elapsed) * &self.time_warp;
+        self.clock = Some((now, target.clone()));
+        let started = self.started.get_or_insert((now, lift(sim.time()))).clone();
+
+        let due = match &self.last_update {
+            None => true,
+            Some(last) => (&target - last).abs() >= self.update_interval,
+        };
+        let updated = due && elapsed > Duration::ZERO;
+        let mut update_cost = Duration::ZERO;
+        if updated {
+            let time = target.with_precision(self.precision).value();
+            let update_start = Instant::now();
+            sim.update(&time);
+            update_cost = update_start.elapsed();
+            self.last_update = Some(lift(&time));
+            self.govern(update_cost, &(seconds(elapsed) * &self.time_warp));
+        }
+
+        let wall = now.saturating_duration_since(started.0);
+        if wall > Duration::ZERO {
+            self.achieved_warp = Some((lift(sim.time()) - &started.1) / seconds(wall));
+        }
+        RealTimeTick {
+            updated,
+            update_cost,
+            precision: self.precision,
+            update_interval: self.update_interval.clone(),
+            achieved_warp: self.achieved_warp.clone(),
+        }
+    }
+
+    // `advance` is how far the simulation clock moved on this tick
+    fn govern(&mut self, update_cost: Duration, advance: &DBig) {
+        if update_cost > self.frame_budget {
+            if self.precision > MIN_PRECISION {
+                self.precision = (self.precision - PRECISION_STEP).max(MIN_PRECISION);
+            } else if self.update_interval == DBig::ZERO {
+                self.update_interval = advance.clone().abs().min(self.max_update_interval.clone());
+            } else {
+                let doubled = &self.update_interval * DBig::from(2);
+                self.update_interval = doubled.min(self.max_update_interval.clone());
+            }
+        } else if update_cost * 4 < self.frame_budget {
+            if self.update_interval > DBig::ZERO {
+                let halved = &self.update_interval / DBig::from(2);
+                // below one tick of movement every tick updates anyway
+                self.update_interval = if halved < advance.clone().abs() {
+                    DBig::ZERO
+                } else {
+                    halved
+                };
+            } else if self.precision < FULL_PRECISION {
+                self.precision = (self.precision + PRECISION_STEP).min(FULL_PRECISION);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::realtime::RealTimeDriver;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn realtime_driver_works() {
+        let base = Instant::now();
+        let second = |n: u64| base + Duration::from_secs(n);
+
+        let mut sim = prepare_sim();
+        let hour = DBig::from(3600);
+        let mut driver = RealTimeDriver::new(&hour, Duration::from_mins(1), &hour);
+        assert!(!driver.tick(&mut sim, second(0)).updated);
+        for n in 1..=3 {
+            let tick = driver.tick(&mut sim, second(n));
+            assert!(tick.updated);
+            assert_eq!(tick.precision, 40);
+        }
+        assert!(approx_eq(
+            sim.time(),
+            &f64_to_dbig(3.0 * 3600.0),
+            &f64_to_dbig(1e-6)
+        ));
+        assert!(approx_eq(
+            driver.achieved_warp().unwrap(),
+            &f64_to_dbig(3600.0),
+            &f64_to_dbig(1e-6)
+        ));
+
+        // no update fits a zero budget, precision goes first and then the updates thin out
+        let mut sim = prepare_sim();
+        let mut driver = RealTimeDriver::new(&hour, Duration::ZERO, &(&hour * DBig::from(4)));
+        driver.tick(&mut sim, second(0));
+        let precisions: Vec<usize> = (1..=4)
+            .map(|n| driver.tick(&mut sim, second(n)).precision)
+            .collect();
+        assert_eq!(precisions, vec![32, 24, 16, 16]);
+        assert_eq!(driver.update_interval(), &hour);
+        let updates = (5..=20)
+            .filter(|n| driver.tick(&mut sim, second(*n)).updated)
+            .count();
+        assert!(updates < 16);
+        assert_eq!(driver.update_interval(), &(&hour * DBig::from(4)));
+        assert!(dbig_to_f64(driver.achieved_warp().unwrap()) < 3600.0);
+        assert!(dbig_to_f64(driver.achieved_warp().unwrap()) > 2000.0);
+
+        // pausing holds the simulation
+        driver.set_time_warp(&DBig::ZERO);
+        let time = sim.time().clone();
+        driver.tick(&mut sim, second(30));
+        driver.tick(&mut sim, second(31));
+        assert_eq!(sim.time(), &time);
+    }
+}
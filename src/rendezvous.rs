@@ -0,0 +1,251 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::propagation::CraftState;
+use crate::simulation::{Simulation, G_CONSTANT};
+use crate::sin_cos::{cos, sin};
+use dashu_float::ops::SquareRoot;
+use dashu_float::DBig;
+
+const PRECISION: usize = 40;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+fn lift_vector(v: &DecimalVector3d) -> DecimalVector3d {
+    DecimalVector3d::new(lift(&v.x), lift(&v.y), lift(&v.z))
+}
+
+// chaser offset from the target in the target's LVLH frame: x radial (away from the attractor),
+// y along the motion and z along the orbit normal; the velocity is seen from the rotating frame
+#[derive(Debug, Clone)]
+pub struct RelativeState {
+    pub position: DecimalVector3d,
+    pub velocity: DecimalVector3d,
+}
+
+// local vertical, local horizontal frame of a target on a near circular orbit, frozen at the
+// moment it was built
+#[derive(Debug, Clone)]
+pub struct LvlhFrame {
+    pub origin: DecimalVector3d,   // world position of the target
+    pub velocity: DecimalVector3d, // world velocity of the target
+    pub radial: DecimalVector3d,
+    pub along_track: DecimalVector3d,
+    pub normal: DecimalVector3d,
+    pub angular_velocity: DBig, // in rad/s, how fast the frame turns about the normal
+    pub mean_motion: DBig,      // in rad/s, of a circular orbit at the target radius
+}
+
+impl LvlhFrame {
+    pub fn to_relative(&self, chaser: &CraftState) -> RelativeState {
+        let offset = lift_vector(&chaser.position) - &self.origin;
+        let rotation = &self.normal * &self.angular_velocity;
+        let velocity = lift_vector(&chaser.velocity) - &self.velocity - rotation.cross(&offset);
+        RelativeState {
+            position: self.project(&offset),
+            velocity: self.project(&velocity),
+        }
+    }
+
+    // world position and velocity of a relative state
+    pub fn to_world(&self, relative: &RelativeState) -> (DecimalVector3d, DecimalVector3d) {
+        let offset = self.unproject(&relative.position);
+        let rotation = &self.normal * &self.angular_velocity;
+        let velocity =
+            &self.velocity + self.unproject(&relative.velocity) + rotation.cross(&offset);
+        (&self.origin + offset, velocity)
+    }
+
+    fn project(&self, v: &DecimalVector3d) -> DecimalVector3d {
+        DecimalVector3d::new(
+            v.dot(&self.radial),
+            v.dot(&self.along_track),
+            v.dot(&self.normal),
+        )
+    }
+
+    fn unproject(&self, v: &DecimalVector3d) -> DecimalVector3d {
+        &self.radial * &v.x + &self.along_track * &v.y + &self.normal * &v.z
+    }
+}
+
+// the Clohessy-Wiltshire solution, the relative state after `time` seconds for a target on a
+// circular orbit, valid while the offset is small next to the orbit radius
+#[allow(clippy::many_single_char_names)] // named as in the formulas
+pub fn clohessy_wiltshire(state: &RelativeState, mean_motion: &DBig, time: &DBig) -> RelativeState {
+    let n = lift(mean_motion);
+    let angle = &n * lift(time);
+    let (s, c) = (sin(angle.clone(), 40), cos(angle.clone(), 40));
+    let one_minus_c = DBig::ONE - &c;
+    let (two, three, four, six) = (DBig::from(2), DBig::from(3), DBig::from(4), DBig::from(6));
+    let position = lift_vector(&state.position);
+    let velocity = lift_vector(&state.velocity);
+    let (x, y, z) = (&position.x, &position.y, &position.z);
+    let (vx, vy, vz) = (&velocity.x, &velocity.y, &velocity.z);
+
+    RelativeState {
+        position: DecimalVector3d::new(
+            (&four - &three * &c) * x + &s / &n * vx + &two * &one_minus_c / &n * vy,
+            &six * (&s - &angle) * x + y - &two * &one_minus_c / &n * vx
+                + (&four * &s - &three * &angle) / &n * vy,
+            &c * z + &s / &n * vz,
+        ),
+        velocity: DecimalVector3d::new(
+            &three * &n * &s * x + &c * vx + &two * &s * vy,
+            -(&six * &n * &one_minus_c * x) - &two * &s * vx + (&four * &c - &three) * vy,
+            -(&n * &s * z) + &c * vz,
+        ),
+    }
+}
+
+// relative velocity that brings the chaser from `position` to the target in `time` seconds along
+// the Clohessy-Wiltshire motion, the first burn of a two impulse rendezvous; None at whole
+// multiples of half a period where the transfer is degenerate
+#[allow(clippy::many_single_char_names)] // named as in the formulas
+pub fn clohessy_wiltshire_intercept(
+    position: &DecimalVector3d,
+    mean_motion: &DBig,
+    time: &DBig,
+) -> Option<DecimalVector3d> {
+    let n = lift(mean_motion);
+    let angle = &n * lift(time);
+    let (s, c) = (sin(angle.clone(), 40), cos(angle.clone(), 40));
+    let one_minus_c = DBig::ONE - &c;
+    let (two, three, four, six) = (DBig::from(2), DBig::from(3), DBig::from(4), DBig::from(6));
+    let position = lift_vector(position);
+
+    // in-plane, solves [a b; -b d] v = -(position part) for the x and y velocities
+    let a = &s / &n;
+    let b = &two * &one_minus_c / &n;
+    let d = (&four * &s - &three * &angle) / &n;
+    let determinant = &a * &d + &b * &b;
+    if determinant == DBig::ZERO || s == DBig::ZERO {
+        return None;
+    }
+    let px = -((&four - &three * &c) * &position.x);
+    let py = -(&six * (&s - &angle) * &position.x + &position.y);
+    Some(DecimalVector3d::new(
+        (&d * &px - &b * &py) / &determinant,
+        (&a * &py + &b * &px) / &determinant,
+        -(&c * &n * &position.z / &s),
+    ))
+}
+
+impl Simulation {
+    /// LVLH frame of a target craft orbiting `reference_name`, at the current state of the
+    /// simulation, which should be updated to the target time
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the reference isn't in the simulation, `InvalidDynamics` if the target
+    /// isn't moving around it.
+    pub fn lvlh_frame(
+        &self,
+        reference_name: &str,
+        target: &CraftState,
+    ) -> Result<LvlhFrame, SimulationError> {
+        let reference = self.get_body(reference_name)?;
+        let origin = lift_vector(&target.position);
+        let velocity = lift_vector(&target.velocity);
+        let radius = &origin - self.world_position(reference);
+        let relative_velocity = &velocity - self.world_velocity(reference);
+        let momentum = radius.cross(&relative_velocity);
+        let distance_squared = radius.length_squared();
+        if momentum.length_squared() == DBig::ZERO {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{reference_name}: the target isn't orbiting it"
+            )));
+        }
+
+        let radial = radius.normalized();
+        let normal = momentum.normalized();
+        let mu = &*G_CONSTANT * lift(&reference.body.mass_at(&self.time));
+        Ok(LvlhFrame {
+            along_track: normal.cross(&radial),
+            angular_velocity: momentum.length() / &distance_squared,
+            mean_motion: (mu / (&distance_squared * distance_squared.sqrt())).sqrt(),
+            origin,
+            velocity,
+            radial,
+            normal,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::propagation::{CraftState, ThrustProfile};
+    use crate::rendezvous::{clohessy_wiltshire, clohessy_wiltshire_intercept, RelativeState};
+    use crate::sin_cos::{f64_to_dbig, PIDIV2};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn clohessy_wiltshire_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let earth = sim.get_body("earth").unwrap();
+        let target = CraftState {
+            time: DBig::ZERO,
+            position: sim.world_position(earth) + DecimalVector3d::from_f64(7e6, 0.0, 0.0),
+            velocity: sim.world_velocity(earth)
+                + DecimalVector3d::from_f64(0.0, 0.0, -(6.674e-11 * 5.97219e24 / 7e6f64).sqrt()),
+            propulsion: None,
+        };
+        let frame = sim.lvlh_frame("earth", &target).unwrap();
+        // the target moves towards -Z from +X, so that is along the track
+        assert!(frame.along_track.z < f64_to_dbig(-0.999));
+
+        // a chaser a kilometer behind and a little below closes in within a quarter of an orbit
+        let start = RelativeState {
+            position: DecimalVector3d::from_f64(-100.0, -1000.0, 50.0),
+            velocity: DecimalVector3d::zero(),
+        };
+        let quarter = &(&*PIDIV2 / &frame.mean_motion);
+        let burn = RelativeState {
+            velocity: clohessy_wiltshire_intercept(&start.position, &frame.mean_motion, quarter)
+                .unwrap(),
+            ..start.clone()
+        };
+        let predicted = clohessy_wiltshire(&burn, &frame.mean_motion, quarter);
+        assert!(predicted.position.length() < f64_to_dbig(1e-6));
+
+        let (position, velocity) = frame.to_world(&burn);
+        let chaser = CraftState {
+            time: DBig::ZERO,
+            position,
+            velocity,
+            propulsion: None,
+        };
+        let back = frame.to_relative(&chaser);
+        assert!(back.position.approx_eq(&burn.position, &f64_to_dbig(1e-9)));
+        assert!(back.velocity.approx_eq(&burn.velocity, &f64_to_dbig(1e-9)));
+
+        // the linearization holds up against a full propagation of both crafts
+        let step = DBig::from(60);
+        let coast = ThrustProfile::Coast;
+        let target_path = sim.propagate(&target, &coast, quarter, &step).unwrap();
+        let chaser_path = sim.propagate(&chaser, &coast, quarter, &step).unwrap();
+        let (target_end, chaser_end) = (target_path.last().unwrap(), chaser_path.last().unwrap());
+        let miss = dbig_to_f64(&target_end.position.distance_to(&chaser_end.position));
+        assert!(miss < 10.0, "{}", miss);
+
+        // without the burn the chaser drifts the way the equations say
+        let (position, velocity) = frame.to_world(&start);
+        let drifter = CraftState {
+            time: DBig::ZERO,
+            position,
+            velocity,
+            propulsion: None,
+        };
+        let drifter_path = sim.propagate(&drifter, &coast, quarter, &step).unwrap();
+        sim.update(&target_end.time);
+        let end_frame = sim.lvlh_frame("earth", target_end).unwrap();
+        let actual = end_frame.to_relative(drifter_path.last().unwrap());
+        let predicted = clohessy_wiltshire(&start, &frame.mean_motion, quarter);
+        let error = dbig_to_f64(&actual.position.distance_to(&predicted.position));
+        assert!(error < 10.0, "{}", error);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::body::BodyDynamics;
+use crate::error::SimulationError;
+use crate::simulation::Simulation;
+use dashu_float::DBig;
+
+impl Simulation {
+    /// the orbit runs against the spin of the parent, false for roots and static bodies
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn is_retrograde_orbit(&self, body_name: &str) -> Result<bool, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let (BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics), Some(parent)) =
+            (&body.body.dynamics, body.parent())
+        else {
+            return Ok(false);
+        };
+        let parent = self
+            .get_body_by_id(parent)
+            .ok_or(SimulationError::UnknownBodyId(parent))?;
+        Ok(dynamics
+            .angular_momentum_direction()
+            .dot(&parent.body.angular_velocity())
+            < DBig::ZERO)
+    }
+
+    /// the spin runs against the body's own orbit, like Venus, false for static bodies
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn is_retrograde_rotation(&self, body_name: &str) -> Result<bool, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let (BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics)) =
+            &body.body.dynamics
+        else {
+            return Ok(false);
+        };
+        Ok(dynamics
+            .angular_momentum_direction()
+            .dot(&body.body.angular_velocity())
+            < DBig::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::BodyDynamics;
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn retrograde_works() {
+        let mut prograde = prepare_sim();
+        assert!(!prograde.is_retrograde_orbit("moon").unwrap());
+        assert!(!prograde.is_retrograde_rotation("moon").unwrap());
+        assert!(!prograde.is_retrograde_orbit("sun").unwrap());
+
+        let mut retrograde = prepare_sim();
+        if let BodyDynamics::Orbiting(dynamics) =
+            &mut retrograde.get_body_mut("moon").unwrap().dynamics
+        {
+            dynamics.orbit_period = -dynamics.orbit_period.clone();
+        }
+        assert!(retrograde.is_retrograde_orbit("moon").unwrap());
+        // the spin didn't change, so it now runs against the orbit
+        assert!(retrograde.is_retrograde_rotation("moon").unwrap());
+
+        // a quarter orbit one way ends up opposite to a quarter orbit the other way
+        let quarter = DBig::from(27 * 24 * 3600 / 4);
+        prograde.update(&quarter);
+        retrograde.update(&quarter);
+        let forward = &prograde.get_body("moon").unwrap().relative_position;
+        let backward = &retrograde.get_body("moon").unwrap().relative_position;
+        let sum = (forward + backward).length();
+        assert!(dbig_to_f64(&sum) < dbig_to_f64(&forward.length()) * 1e-9);
+
+        // Venus-like spin, the surface moves the other way
+        let surface = DecimalVector3d::from_f64(6_371_000.0, 0.0, 0.0);
+        let eastward = prograde.get_surface_velocity("earth", &surface).unwrap();
+        prograde.get_body_mut("earth").unwrap().rotation_period = DBig::from(-24 * 3600);
+        assert!(prograde.is_retrograde_rotation("earth").unwrap());
+        let westward = prograde.get_surface_velocity("earth", &surface).unwrap();
+        assert_eq!(westward.z, -eastward.z);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::simulation::Simulation;
+use dashu_float::DBig;
+
+// flat annulus in the equatorial plane of the body it belongs to, the tree has no ring component
+// on bodies yet so it is passed to the queries
+#[derive(Debug, Clone)]
+pub struct RingAnnulus {
+    pub inner_radius: DBig, // in meters from the body center
+    pub outer_radius: DBig,
+}
+
+impl Simulation {
+    /// whether the line from `point` to the star center crosses the rings of `ring_body`, the star
+    /// is taken as a point so penumbras are not modeled
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the ring body or the star isn't in the simulation.
+    pub fn ring_shadows_point(
+        &self,
+        ring_body_name: &str,
+        ring: &RingAnnulus,
+        point: &DecimalVector3d,
+        star_name: &str,
+    ) -> Result<bool, SimulationError> {
+        let ring_body = self.get_body(ring_body_name)?;
+        let center = self.world_position(ring_body);
+        let normal = ring_body
+            .orientation
+            .apply(&ring_body.body.rotation_axis)
+            .normalized();
+        let to_star = self.world_position(self.get_body(star_name)?) - point;
+        let along = normal.dot(&to_star);
+        if along == DBig::ZERO {
+            return Ok(false);
+        }
+        // fraction of the way to the star where the line meets the ring plane
+        let fraction = normal.dot(&(&center - point)) / along;
+        if fraction <= DBig::ZERO || fraction >= DBig::ONE {
+            return Ok(false);
+        }
+        let distance = (point + &to_star * fraction - &center).length();
+        Ok(distance >= ring.inner_radius && distance <= ring.outer_radius)
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if any of the bodies isn't in the simulation.
+    pub fn ring_shadows_body(
+        &self,
+        ring_body_name: &str,
+        ring: &RingAnnulus,
+        body_name: &str,
+        star_name: &str,
+    ) -> Result<bool, SimulationError> {
+        let point = self.world_position(self.get_body(body_name)?);
+        self.ring_shadows_point(ring_body_name, ring, &point, star_name)
+    }
+
+    /// a point on the surface of the ring body itself or of any other body, in radians
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if any of the bodies isn't in the simulation.
+    pub fn ring_shadows_surface(
+        &self,
+        ring_body_name: &str,
+        ring: &RingAnnulus,
+        body_name: &str,
+        latitude: &DBig,
+        longitude: &DBig,
+        star_name: &str,
+    ) -> Result<bool, SimulationError> {
+        let (point, _) = self.surface_point_state(body_name, latitude, longitude, &DBig::ZERO)?;
+        self.ring_shadows_point(ring_body_name, ring, &point, star_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::tilted_axis;
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::rings::RingAnnulus;
+    use crate::sin_cos::f64_to_dbig;
+    use crate::tests::prepare_sim;
+    use dashu_float::DBig;
+
+    #[test]
+    fn ring_shadow_works() {
+        let mut sim = prepare_sim();
+        let obliquity = 23.44f64.to_radians();
+        let pole = DecimalVector3d::from_f64(0.0, 1.0, 0.0);
+        sim.get_body_mut("earth").unwrap().rotation_axis =
+            tilted_axis(&pole, &f64_to_dbig(obliquity), &DBig::ZERO);
+        sim.update(&DBig::ZERO);
+
+        // the axis leans towards +X and the sun is on -X, the rings shade the north
+        let radius = 6_371_000.0;
+        let ring = RingAnnulus {
+            inner_radius: f64_to_dbig(1.5 * radius),
+            outer_radius: f64_to_dbig(3.0 * radius),
+        };
+        let center = sim.world_position(sim.get_body("earth").unwrap());
+        let above = |height: f64| &center + DecimalVector3d::from_f64(0.0, height, 0.0);
+        let shaded = above(2.0 * radius * obliquity.sin());
+        assert!(sim
+            .ring_shadows_point("earth", &ring, &shaded, "sun")
+            .unwrap());
+        let outside = above(4.0 * radius * obliquity.sin());
+        assert!(!sim
+            .ring_shadows_point("earth", &ring, &outside, "sun")
+            .unwrap());
+        let south = above(-2.0 * radius * obliquity.sin());
+        assert!(!sim
+            .ring_shadows_point("earth", &ring, &south, "sun")
+            .unwrap());
+
+        // the ray from the north pole meets the plane at R cot(obliquity)
+        let north = f64_to_dbig(std::f64::consts::FRAC_PI_2);
+        assert!(sim
+            .ring_shadows_surface("earth", &ring, "earth", &north, &DBig::ZERO, "sun")
+            .unwrap());
+        let narrow = RingAnnulus {
+            inner_radius: f64_to_dbig(2.5 * radius),
+            outer_radius: f64_to_dbig(3.0 * radius),
+        };
+        assert!(!sim
+            .ring_shadows_surface("earth", &narrow, "earth", &north, &DBig::ZERO, "sun")
+            .unwrap());
+        assert!(!sim
+            .ring_shadows_body("earth", &ring, "moon", "sun")
+            .unwrap());
+    }
+}
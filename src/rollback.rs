@@ -0,0 +1,270 @@
+use crate::error::SimulationError;
+use crate::lockstep::Lockstep;
+use crate::simulation::{SimulatedBody, Simulation};
+use crate::spacecraft::Spacecraft;
+use dashu_float::DBig;
+use std::collections::VecDeque;
+
+// the state after an update, kept in memory so thrust functions survive, unlike in snapshots
+#[derive(Debug, Clone)]
+struct HistoryState {
+    time: DBig,
+    bodies: Vec<SimulatedBody>,
+    spacecraft: Vec<Spacecraft>,
+    lockstep: Option<Lockstep>,
+}
+
+// the last `capacity` states, the oldest is dropped when a new one doesn't fit
+#[derive(Debug)]
+pub struct History {
+    pub capacity: usize,
+    states: VecDeque<HistoryState>,
+}
+
+impl Simulation {
+    /// from now on every update records its state, see `rollback_to`
+    ///
+    /// # Errors
+    ///
+    /// `InvalidArgument` if `capacity` is zero.
+    pub fn enable_history(&mut self, capacity: usize) -> Result<(), SimulationError> {
+        if capacity == 0 {
+            return Err(SimulationError::InvalidArgument(String::from(
+                "the history has to hold at least one state",
+            )));
+        }
+        self.history = Some(History {
+            capacity,
+            states: VecDeque::new(),
+        });
+        self.record_history();
+        Ok(())
+    }
+
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    // times of the recorded states, oldest first
+    pub fn history_times(&self) -> Vec<&DBig> {
+        self.history
+            .iter()
+            .flat_map(|history| history.states.iter().map(|state| &state.time))
+            .collect()
+    }
+
+    pub(crate) fn record_history(&mut self) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        let state = HistoryState {
+            time: self.time.clone(),
+            bodies: self.bodies.clone(),
+            spacecraft: self.spacecraft.clone(),
+            lockstep: self.lockstep.clone(),
+        };
+        let capacity = history.capacity;
+        let states = &mut self.history.as_mut().unwrap().states;
+        // updating to the same time again replaces the state, and going back forgets the future
+        while states.back().is_some_and(|last| last.time >= state.time) {
+            states.pop_back();
+        }
+        states.push_back(state);
+        while states.len() > capacity {
+            states.pop_front();
+        }
+    }
+
+    /// restores the latest recorded state at or before `time` and forgets the ones after it, gives
+    /// the time it was recorded at. Bodies, spacecraft and the lockstep tick go back, the rest of
+    /// the setup like triggers and bookmarks stays as it is
+    ///
+    /// # Errors
+    ///
+    /// `InvalidState` if the history isn't enabled or holds nothing at or before `time`.
+    pub fn rollback_to(&mut self, time: &DBig) -> Result<DBig, SimulationError> {
+        let states = match &mut self.history {
+            Some(history) => &mut history.states,
+            None => {
+                return Err(SimulationError::InvalidState(String::from(
+                    "history: isn't enabled",
+                )))
+            }
+        };
+        let kept = states.partition_point(|state| state.time <= *time);
+        if kept == 0 {
+            return Err(SimulationError::InvalidState(format!(
+                "history: nothing recorded at or before {time}"
+            )));
+        }
+        states.truncate(kept);
+        let state = states[kept - 1].clone();
+        self.time = state.time.clone();
+        self.bodies = state.bodies;
+        self.spacecraft = state.spacecraft;
+        self.lockstep = state.lockstep;
+        self.rebuild_index();
+        Ok(state.time)
+    }
+
+    /// the pattern for inputs that arrive late over the network: goes back to `time`, applies the
+    /// input there and simulates to the current time again. The bodies are on rails, so only the
+    /// spacecraft are propagated, every `max_step`. In lockstep the input lands on the first tick
+    /// at or after `time` and the ticks are replayed instead
+    ///
+    /// # Errors
+    ///
+    /// `InvalidState` if `time` is ahead of the current time, the errors of `rollback_to` and of
+    /// stepping the spacecraft, and whatever `input` returns.
+    pub fn replay_with<F>(
+        &mut self,
+        time: &DBig,
+        max_step: &DBig,
+        input: F,
+    ) -> Result<(), SimulationError>
+    where
+        F: FnOnce(&mut Simulation) -> Result<(), SimulationError>,
+    {
+        let now = self.time.clone();
+        if *time > now {
+            return Err(SimulationError::InvalidState(format!(
+                "history: {time} is still ahead of {now}"
+            )));
+        }
+        self.rollback_to(time)?;
+        self.replay_until(time, max_step)?;
+        input(self)?;
+        self.replay_until(&now, max_step)
+    }
+
+    fn replay_until(&mut self, time: &DBig, max_step: &DBig) -> Result<(), SimulationError> {
+        if self.lockstep.is_none() {
+            return self.step_spacecraft(time, max_step);
+        }
+        while self.time < *time {
+            self.step_lockstep()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::propagation::{CraftState, ThrustProfile};
+    use crate::simulation::Simulation;
+    use crate::sin_cos::f64_to_dbig;
+    use crate::spacecraft::Spacecraft;
+    use crate::tests::prepare_sim;
+    use dashu_float::DBig;
+
+    fn prepare() -> Simulation {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let earth = sim.get_body("earth").unwrap();
+        let (earth_position, earth_velocity) =
+            (sim.world_position(earth), sim.world_velocity(earth));
+        let speed = (6.67408e-11 * 5.97219e24 / 7e6f64).sqrt();
+        sim.add_spacecraft(Spacecraft {
+            name: String::from("probe"),
+            state: CraftState {
+                time: DBig::ZERO,
+                position: &earth_position + DecimalVector3d::from_f64(7e6, 0.0, 0.0),
+                velocity: &earth_velocity + DecimalVector3d::from_f64(0.0, 0.0, -speed),
+                propulsion: None,
+            },
+            thrust: ThrustProfile::Coast,
+            primary: None,
+        })
+        .unwrap();
+        sim
+    }
+
+    fn burn(sim: &mut Simulation) -> Result<(), SimulationError> {
+        let craft = sim.get_spacecraft_mut("probe")?;
+        craft.state.velocity = &craft.state.velocity + DecimalVector3d::from_f64(0.0, 50.0, 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn history_keeps_the_latest_states() {
+        let mut sim = prepare();
+        sim.enable_history(8).unwrap();
+        sim.step_spacecraft(&DBig::from(600), &DBig::from(60))
+            .unwrap();
+        let times = sim.history_times();
+        assert_eq!(times.len(), 8);
+        assert_eq!(times[0], &DBig::from(180));
+        assert_eq!(times[7], &DBig::from(600));
+    }
+
+    #[test]
+    fn late_input_is_replayed() {
+        let step = DBig::from(60);
+        // the burn as it should have happened at 300
+        let mut expected = prepare();
+        expected.step_spacecraft(&DBig::from(300), &step).unwrap();
+        burn(&mut expected).unwrap();
+        expected.step_spacecraft(&DBig::from(600), &step).unwrap();
+
+        let mut sim = prepare();
+        sim.enable_history(8).unwrap();
+        sim.step_spacecraft(&DBig::from(600), &step).unwrap();
+        let missed = sim.get_spacecraft("probe").unwrap().state.clone();
+
+        // the burn arrives late and is replayed from its time
+        sim.replay_with(&DBig::from(300), &step, burn).unwrap();
+        assert_eq!(sim.time(), &DBig::from(600));
+        let state = &sim.get_spacecraft("probe").unwrap().state;
+        let target = &expected.get_spacecraft("probe").unwrap().state;
+        assert!(state
+            .position
+            .approx_eq(&target.position, &f64_to_dbig(1e-6)));
+        assert!(state
+            .velocity
+            .approx_eq(&target.velocity, &f64_to_dbig(1e-9)));
+        assert!(state.position.distance_to(&missed.position) > f64_to_dbig(1000.0));
+        assert_eq!(sim.history_times().len(), 8);
+        assert_eq!(
+            sim.replay_with(&DBig::from(900), &step, |_| Ok(())),
+            Err(SimulationError::InvalidState(String::from(
+                "history: 900 is still ahead of 600"
+            )))
+        );
+    }
+
+    #[test]
+    fn rollback_lands_on_the_state_before() {
+        let mut sim = prepare();
+        assert_eq!(
+            sim.rollback_to(&DBig::ZERO),
+            Err(SimulationError::InvalidState(String::from(
+                "history: isn't enabled"
+            )))
+        );
+        assert_eq!(
+            sim.enable_history(0),
+            Err(SimulationError::InvalidArgument(String::from(
+                "the history has to hold at least one state"
+            )))
+        );
+        sim.enable_history(8).unwrap();
+        sim.step_spacecraft(&DBig::from(600), &DBig::from(60))
+            .unwrap();
+        // rolling back to between the states lands on the one before
+        let restored = sim.rollback_to(&DBig::from(500)).unwrap();
+        assert_eq!(restored, DBig::from(480));
+        assert_eq!(sim.time(), &DBig::from(480));
+        assert_eq!(
+            sim.get_body("earth").unwrap().last_update,
+            Some(DBig::from(480))
+        );
+        assert_eq!(sim.history_times().last().unwrap(), &&DBig::from(480));
+        assert!(matches!(
+            sim.rollback_to(&DBig::from(100)),
+            Err(SimulationError::InvalidState(_))
+        ));
+        assert_eq!(sim.history_times().len(), 6);
+    }
+}
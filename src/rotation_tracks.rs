@@ -0,0 +1,228 @@
+use crate::czml::{json_numbers, json_string};
+use crate::error::{check_positive, SimulationError};
+use crate::simulation::Simulation;
+use crate::sin_cos::dbig_to_f64;
+use dashu_float::DBig;
+use std::io::{self, Error, ErrorKind, Write};
+
+const MAGIC: &[u8; 4] = b"QTRK";
+const VERSION: u32 = 1;
+
+// counts and name lengths are stored as u32
+fn write_len<W: Write>(writer: &mut W, len: usize) -> io::Result<()> {
+    let len = u32::try_from(len)
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "too long for a track file"))?;
+    writer.write_all(&len.to_le_bytes())
+}
+
+// orientation of one body at every sample time, x, y, z, w turning local directions into world
+// ones like a Three JS quaternion
+#[derive(Debug, Clone)]
+pub struct QuaternionTrack {
+    pub name: String,
+    pub rotations: Vec<[f64; 4]>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QuaternionTracks {
+    pub times: Vec<f64>,
+    pub tracks: Vec<QuaternionTrack>,
+}
+
+impl Simulation {
+    /// samples every body from `start` to `end` (inclusive) on a copy of the simulation, every
+    /// rotation is on the same side as the one before it so a tool can interpolate between them
+    /// without spinning the long way around
+    ///
+    /// # Errors
+    ///
+    /// `InvalidArgument` if `step` isn't positive.
+    pub fn quaternion_tracks(
+        &self,
+        start: &DBig,
+        end: &DBig,
+        step: &DBig,
+    ) -> Result<QuaternionTracks, SimulationError> {
+        check_positive(step, "step")?;
+        let mut sim = self.copy_bodies();
+        let mut tracks: Vec<QuaternionTrack> = sim
+            .bodies
+            .iter()
+            .map(|body| QuaternionTrack {
+                name: body.body.name.clone(),
+                rotations: vec![],
+            })
+            .collect();
+        let mut times = vec![];
+        let mut time = start.clone();
+        while &time <= end {
+            sim.update(&time);
+            times.push(dbig_to_f64(&time));
+            for (track, body) in tracks.iter_mut().zip(&sim.bodies) {
+                // orientations drift a little off orthonormal, tools expect unit quaternions
+                let rotation = body.orientation.as_quat().map(|v| dbig_to_f64(&v));
+                let length = rotation.iter().map(|v| v * v).sum::<f64>().sqrt();
+                let mut rotation = rotation.map(|v| v / length);
+                if let Some(previous) = track.rotations.last() {
+                    let dot: f64 = previous.iter().zip(&rotation).map(|(a, b)| a * b).sum();
+                    if dot < 0.0 {
+                        rotation = rotation.map(|v| -v);
+                    }
+                }
+                track.rotations.push(rotation);
+            }
+            time += step;
+        }
+        Ok(QuaternionTracks { times, tracks })
+    }
+
+    /// {"times":[...],"tracks":[{"name":...,"rotations":[x,y,z,w,x,y,z,w,...]},...]}
+    ///
+    /// # Errors
+    ///
+    /// `InvalidInput` if `step` isn't positive, and any error of the writer.
+    pub fn write_quaternion_tracks_json<W: Write>(
+        &self,
+        writer: &mut W,
+        start: &DBig,
+        end: &DBig,
+        step: &DBig,
+    ) -> io::Result<()> {
+        let tracks = self
+            .quaternion_tracks(start, end, step)
+            .map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+        write!(
+            writer,
+            "{{\"times\":[{}],\"tracks\":[",
+            json_numbers(&tracks.times)
+        )?;
+        for (i, track) in tracks.tracks.iter().enumerate() {
+            if i > 0 {
+                write!(writer, ",")?;
+            }
+            write!(
+                writer,
+                "{{\"name\":{},\"rotations\":[{}]}}",
+                json_string(&track.name),
+                json_numbers(&track.rotations.concat())
+            )?;
+        }
+        write!(writer, "]}}")
+    }
+
+    /// little endian: "QTRK", u32 version, u32 track count, u32 sample count, the f64 times, then
+    /// every track as a u32 name length, the UTF-8 name and x, y, z, w f64 per sample
+    ///
+    /// # Errors
+    ///
+    /// `InvalidInput` if `step` isn't positive or a name or count is too long for the format, and
+    /// any error of the writer.
+    pub fn write_quaternion_tracks_binary<W: Write>(
+        &self,
+        writer: &mut W,
+        start: &DBig,
+        end: &DBig,
+        step: &DBig,
+    ) -> io::Result<()> {
+        let tracks = self
+            .quaternion_tracks(start, end, step)
+            .map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        write_len(writer, tracks.tracks.len())?;
+        write_len(writer, tracks.times.len())?;
+        for time in &tracks.times {
+            writer.write_all(&time.to_le_bytes())?;
+        }
+        for track in &tracks.tracks {
+            write_len(writer, track.name.len())?;
+            writer.write_all(track.name.as_bytes())?;
+            for value in track.rotations.concat() {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::SimulationError;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn quaternion_tracks_work() {
+        let sim = prepare_sim();
+        let (start, end, step) = (DBig::ZERO, DBig::from(24 * 3600), DBig::from(6 * 3600));
+        let tracks = sim.quaternion_tracks(&start, &end, &step).unwrap();
+        assert_eq!(tracks.times, vec![0.0, 21600.0, 43200.0, 64800.0, 86400.0]);
+        assert_eq!(tracks.tracks.len(), 3);
+        for track in &tracks.tracks {
+            assert_eq!(track.rotations.len(), 5);
+            for (i, rotation) in track.rotations.iter().enumerate() {
+                let length: f64 = rotation.iter().map(|v| v * v).sum();
+                assert!((length - 1.0).abs() < 1e-9);
+                if i > 0 {
+                    let previous = track.rotations[i - 1];
+                    let dot: f64 = previous.iter().zip(rotation).map(|(a, b)| a * b).sum();
+                    assert!(dot >= 0.0);
+                }
+            }
+        }
+        // the same rotation as the body at that time, up to the sign
+        let mut later = sim.copy_bodies();
+        later.update(&DBig::from(12 * 3600));
+        let earth = later.get_body("earth").unwrap();
+        let expected = earth.orientation.as_quat().map(|v| dbig_to_f64(&v));
+        let track = tracks.tracks.iter().find(|t| t.name == "earth").unwrap();
+        let dot: f64 = track.rotations[2]
+            .iter()
+            .zip(expected)
+            .map(|(a, b)| a * b)
+            .sum();
+        assert!((dot.abs() - 1.0).abs() < 1e-9);
+        // the source simulation is left untouched
+        assert_eq!(sim.time(), &DBig::ZERO);
+
+        let mut buf: Vec<u8> = vec![];
+        sim.write_quaternion_tracks_json(&mut buf, &start, &end, &step)
+            .unwrap();
+        let json = String::from_utf8(buf).unwrap();
+        assert!(json.starts_with("{\"times\":[0,21600,43200,64800,86400],\"tracks\":[{\"name\":"));
+        assert!(json.ends_with("]}]}"));
+        assert_eq!(json.matches("\"rotations\":[").count(), 3);
+
+        let mut buf: Vec<u8> = vec![];
+        sim.write_quaternion_tracks_binary(&mut buf, &start, &end, &step)
+            .unwrap();
+        assert_eq!(&buf[0..4], b"QTRK");
+        assert_eq!(u32::from_le_bytes(buf[8..12].try_into().unwrap()), 3);
+        assert_eq!(u32::from_le_bytes(buf[12..16].try_into().unwrap()), 5);
+        assert_eq!(buf[24..32], 21600.0f64.to_le_bytes());
+        let names: usize = tracks.tracks.iter().map(|t| 4 + t.name.len()).sum();
+        assert_eq!(buf.len(), 16 + 5 * 8 + names + 3 * 5 * 4 * 8);
+    }
+
+    #[test]
+    fn quaternion_tracks_reject_non_positive_steps() {
+        let sim = prepare_sim();
+        let (start, end) = (DBig::ZERO, DBig::from(3600));
+        for step in [DBig::ZERO, DBig::from(-60)] {
+            assert_eq!(
+                sim.quaternion_tracks(&start, &end, &step).unwrap_err(),
+                SimulationError::InvalidArgument(String::from("the step has to be positive"))
+            );
+            let mut buf: Vec<u8> = vec![];
+            let error = sim
+                .write_quaternion_tracks_json(&mut buf, &start, &end, &step)
+                .unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+            let error = sim
+                .write_quaternion_tracks_binary(&mut buf, &start, &end, &step)
+                .unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+            assert!(buf.is_empty());
+        }
+    }
+}
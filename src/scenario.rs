@@ -0,0 +1,453 @@
+use crate::body::{Body, BodyDynamics, BodyKind, OrbitingBodyDynamics, StaticBodyDynamics};
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::propagation::{CraftState, ThrustProfile};
+use crate::simulation::Simulation;
+use crate::spacecraft::Spacecraft;
+use dashu_float::DBig;
+use std::io::{Error, ErrorKind, Result};
+use std::str::FromStr;
+
+const PRECISION: usize = 32;
+
+#[derive(Debug, Clone)]
+pub enum ScenarioAction {
+    // added with add_hierarchy, under the named parent or as a root
+    SpawnBody {
+        body: Box<Body>,
+        parent: Option<String>,
+    },
+    // the state is in the world frame, or relative to the primary when there is one, and is
+    // taken as the state at the action time
+    SpawnSpacecraft(Box<Spacecraft>),
+    // instant velocity change in the world frame, see CraftState::apply_impulse
+    Maneuver {
+        spacecraft: String,
+        delta_v: DecimalVector3d,
+    },
+    // the body keeps this mass from then on, any mass variation is dropped
+    ChangeMass {
+        body: String,
+        mass: DBig,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct ScheduledAction {
+    pub time: DBig,
+    pub action: ScenarioAction,
+}
+
+// timed actions on top of a system built with add_hierarchy, run by the simulation as its clock
+// passes them; actions at the same time run in the order they were scheduled
+#[derive(Debug, Clone, Default)]
+pub struct Scenario {
+    pub actions: Vec<ScheduledAction>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ScenarioRun {
+    pending: Vec<ScheduledAction>, // sorted by time
+    last_error: Option<SimulationError>,
+}
+
+fn invalid_data(line: usize, message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("line {line}: {message}"))
+}
+
+fn parse_number(line: usize, value: &str) -> Result<DBig> {
+    let number = DBig::from_str(&value.to_lowercase().replace("e+", "e"))
+        .map_err(|_| invalid_data(line, &format!("{value} is not a number")))?;
+    Ok(number.with_precision(PRECISION).value())
+}
+
+fn parse_vector(line: usize, value: &str) -> Result<DecimalVector3d> {
+    let parts = value
+        .split(',')
+        .map(|part| parse_number(line, part.trim()))
+        .collect::<Result<Vec<DBig>>>()?;
+    match <[DBig; 3]>::try_from(parts) {
+        Ok([x, y, z]) => Ok(DecimalVector3d::new(x, y, z)),
+        Err(_) => Err(invalid_data(line, &format!("{value} is not a vector"))),
+    }
+}
+
+fn parse_kind(line: usize, value: &str) -> Result<BodyKind> {
+    match value {
+        "star" => Ok(BodyKind::Star),
+        "planet" => Ok(BodyKind::Planet),
+        "dwarf_planet" => Ok(BodyKind::DwarfPlanet),
+        "moon" => Ok(BodyKind::Moon),
+        "asteroid" => Ok(BodyKind::Asteroid),
+        "comet" => Ok(BodyKind::Comet),
+        "artificial_satellite" => Ok(BodyKind::ArtificialSatellite),
+        _ => Err(invalid_data(line, &format!("{value} is not a body kind"))),
+    }
+}
+
+// `key=value` pairs after the action name
+struct Arguments<'a> {
+    line: usize,
+    values: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Arguments<'a> {
+    fn text(&self, key: &str) -> Option<&'a str> {
+        self.values.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+    }
+
+    fn required(&self, key: &str) -> Result<&'a str> {
+        self.text(key)
+            .ok_or_else(|| invalid_data(self.line, &format!("{key} is missing")))
+    }
+
+    fn number(&self, key: &str) -> Result<Option<DBig>> {
+        self.text(key)
+            .map(|value| parse_number(self.line, value))
+            .transpose()
+    }
+
+    fn required_number(&self, key: &str) -> Result<DBig> {
+        parse_number(self.line, self.required(key)?)
+    }
+
+    fn vector(&self, key: &str) -> Result<Option<DecimalVector3d>> {
+        self.text(key)
+            .map(|value| parse_vector(self.line, value))
+            .transpose()
+    }
+
+    fn required_vector(&self, key: &str) -> Result<DecimalVector3d> {
+        parse_vector(self.line, self.required(key)?)
+    }
+}
+
+// circular orbits with orbit_radius and orbit_period around the parent, or static with
+// position; orbiting bodies are tidally locked unless rotation_period says otherwise
+fn parse_body(arguments: &Arguments) -> Result<Body> {
+    let up = DecimalVector3d::new(DBig::ZERO, DBig::ONE, DBig::ZERO);
+    let (dynamics, rotation_period) = if let Some(position) = arguments.vector("position")? {
+        (
+            BodyDynamics::Static(StaticBodyDynamics { position }),
+            arguments.required_number("rotation_period")?,
+        )
+    } else {
+        let orbit_period = arguments.required_number("orbit_period")?;
+        let dynamics = OrbitingBodyDynamics {
+            orbit_radius: arguments.required_number("orbit_radius")?,
+            orbit_plane_normal: arguments.vector("orbit_normal")?.unwrap_or(up.clone()),
+            orbit_period: orbit_period.clone(),
+            mean_anomaly_at_epoch: arguments.number("mean_anomaly")?.unwrap_or(DBig::ZERO),
+            ellipse: None,
+            drift: None,
+        };
+        let rotation_period = arguments.number("rotation_period")?;
+        (
+            BodyDynamics::Orbiting(dynamics),
+            rotation_period.unwrap_or(orbit_period),
+        )
+    };
+    Ok(Body {
+        name: arguments.required("name")?.to_string(),
+        rotation_axis: up,
+        rotation_period,
+        rotation_phase: DBig::ZERO,
+        resonance: None,
+        visual: None,
+        kind: arguments
+            .text("kind")
+            .map(|value| parse_kind(arguments.line, value))
+            .transpose()?,
+        nutation: None,
+        libration: None,
+        mass: arguments.required_number("mass")?,
+        mass_variation: None,
+        radius: arguments.required_number("radius")?,
+        dynamics,
+        update_interval: None,
+        satellites: vec![],
+    })
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn schedule(&mut self, time: DBig, action: ScenarioAction) {
+        self.actions.push(ScheduledAction { time, action });
+    }
+
+    /// one action per line, `at <time> <action> key=value ...`, with `//` comments. Vectors are
+    /// `x,y,z` without spaces, every value is in SI units:
+    ///
+    /// ```text
+    /// at 0 spawn_body name=rock parent=earth mass=1e12 radius=500 orbit_radius=1e7 orbit_period=9900
+    /// at 0 spawn_body name=beacon mass=1 radius=1 position=1e12,0,0 rotation_period=60
+    /// at 60 spawn_spacecraft name=probe primary=earth position=7e6,0,0 velocity=0,0,-7546
+    /// at 600 maneuver spacecraft=probe delta_v=0,0,-100
+    /// at 3600 change_mass body=rock mass=2e12
+    /// ```
+    ///
+    /// `spawn_body` also takes `orbit_normal`, `mean_anomaly` and `rotation_period` for orbiting
+    /// bodies
+    ///
+    /// # Errors
+    ///
+    /// `InvalidData` with the line number for a line that isn't a timed action, or an action with
+    /// missing or malformed arguments.
+    pub fn parse(source: &str) -> Result<Scenario> {
+        let mut scenario = Scenario::new();
+        for (index, line) in source.lines().enumerate() {
+            let number = index + 1;
+            let line = line.split_once("//").map_or(line, |(code, _)| code).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            if words.next() != Some("at") {
+                return Err(invalid_data(number, "expected `at <time>`"));
+            }
+            let time = match words.next() {
+                Some(time) => parse_number(number, time)?,
+                None => return Err(invalid_data(number, "the time is missing")),
+            };
+            let Some(kind) = words.next() else {
+                return Err(invalid_data(number, "the action is missing"));
+            };
+            let values = words
+                .map(|word| {
+                    word.split_once('=').ok_or_else(|| {
+                        invalid_data(number, &format!("{word} is not a key=value pair"))
+                    })
+                })
+                .collect::<Result<Vec<(&str, &str)>>>()?;
+            let arguments = Arguments {
+                line: number,
+                values,
+            };
+
+            let action = match kind {
+                "spawn_body" => ScenarioAction::SpawnBody {
+                    body: Box::new(parse_body(&arguments)?),
+                    parent: arguments.text("parent").map(String::from),
+                },
+                "spawn_spacecraft" => ScenarioAction::SpawnSpacecraft(Box::new(Spacecraft {
+                    name: arguments.required("name")?.to_string(),
+                    state: CraftState {
+                        time: time.clone(),
+                        position: arguments.required_vector("position")?,
+                        velocity: arguments.required_vector("velocity")?,
+                        propulsion: None,
+                    },
+                    thrust: ThrustProfile::Coast,
+                    primary: arguments.text("primary").map(String::from),
+                })),
+                "maneuver" => ScenarioAction::Maneuver {
+                    spacecraft: arguments.required("spacecraft")?.to_string(),
+                    delta_v: arguments.required_vector("delta_v")?,
+                },
+                "change_mass" => ScenarioAction::ChangeMass {
+                    body: arguments.required("body")?.to_string(),
+                    mass: arguments.required_number("mass")?,
+                },
+                _ => return Err(invalid_data(number, &format!("unknown action {kind}"))),
+            };
+            scenario.schedule(time, action);
+        }
+        Ok(scenario)
+    }
+}
+
+impl Simulation {
+    // replaces the actions still pending, the ones already due run on the next update
+    pub fn load_scenario(&mut self, scenario: Scenario) {
+        let mut pending = scenario.actions;
+        pending.sort_by(|a, b| a.time.cmp(&b.time));
+        self.scenario.pending = pending;
+    }
+
+    pub fn pending_actions(&self) -> &[ScheduledAction] {
+        &self.scenario.pending
+    }
+
+    // errors don't interrupt the simulation, the action is skipped and the last error kept here
+    pub fn scenario_error(&self) -> Option<&SimulationError> {
+        self.scenario.last_error.as_ref()
+    }
+
+    pub(crate) fn next_action_time(&self) -> Option<&DBig> {
+        self.scenario.pending.first().map(|action| &action.time)
+    }
+
+    // moves the bodies to `time`, stopping at every action due on the way to run it; actions
+    // loaded after their time passed run at the current time
+    pub(crate) fn advance_bodies(&mut self, time: &DBig) {
+        while let Some(action_time) = self.next_action_time().filter(|t| *t <= time).cloned() {
+            let at = action_time.max(self.time.clone());
+            self.update_bodies(&at);
+            while self.next_action_time().is_some_and(|t| *t <= at) {
+                let action = self.scenario.pending.remove(0);
+                if let Err(error) = self.run_action(action) {
+                    self.scenario.last_error = Some(error);
+                }
+            }
+            // spawned bodies get their state
+            self.update_bodies(&at);
+        }
+        self.update_bodies(time);
+    }
+
+    fn run_action(&mut self, action: ScheduledAction) -> std::result::Result<(), SimulationError> {
+        match action.action {
+            ScenarioAction::SpawnBody { body, parent } => {
+                let parent = match parent {
+                    Some(parent) => Some(self.get_body(&parent)?.id),
+                    None => None,
+                };
+                self.add_hierarchy(*body, parent)?;
+            }
+            ScenarioAction::SpawnSpacecraft(mut spacecraft) => {
+                if let Some(primary) = &spacecraft.primary {
+                    let body = self.get_body(primary)?;
+                    let state = &mut spacecraft.state;
+                    state.position = &state.position + self.world_position(body);
+                    state.velocity = &state.velocity + self.world_velocity(body);
+                }
+                spacecraft.state.time.clone_from(&self.time);
+                self.add_spacecraft(*spacecraft)?;
+            }
+            ScenarioAction::Maneuver {
+                spacecraft,
+                delta_v,
+            } => {
+                self.get_spacecraft_mut(&spacecraft)?
+                    .state
+                    .apply_impulse(&delta_v);
+            }
+            ScenarioAction::ChangeMass { body, mass } => {
+                let body = self.get_body_mut(&body)?;
+                body.mass = mass;
+                body.mass_variation = None;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::BodyKind;
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::propagation::{CraftState, ThrustProfile};
+    use crate::scenario::{Scenario, ScenarioAction};
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn scenario_works() {
+        let source = "
+        // a small moon, a beacon, and a probe that burns after ten minutes
+        at 0 spawn_body name=rock kind=asteroid parent=earth mass=1e12 radius=500 orbit_radius=1e7 orbit_period=9900
+        at 0 spawn_body name=beacon mass=1 radius=1 position=1e12,0,0 rotation_period=60
+        at 60 spawn_spacecraft name=probe primary=earth position=7e6,0,0 velocity=0,0,-7546
+        at 600 maneuver spacecraft=probe delta_v=0,0,-100
+        at 3600 change_mass body=rock mass=2e12
+    ";
+        let scenario = Scenario::parse(source).unwrap();
+        assert_eq!(scenario.actions.len(), 5);
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        sim.load_scenario(scenario);
+
+        // spawned as soon as the clock gets there
+        sim.update(&DBig::ZERO);
+        assert_eq!(sim.pending_actions().len(), 3);
+        let rock = sim.get_body("rock").unwrap();
+        let earth = sim.get_body("earth").unwrap();
+        let distance = sim
+            .world_position(rock)
+            .distance_to(&sim.world_position(earth));
+        assert!(approx_eq(&distance, &f64_to_dbig(1e7), &f64_to_dbig(10.0)));
+        assert!(sim.get_body("beacon").is_ok());
+
+        // the probe appears relative to the earth at 60 s and burns at 600 s on the way
+        sim.step_spacecraft(&DBig::from(1200), &DBig::from(60))
+            .unwrap();
+        assert_eq!(sim.pending_actions().len(), 1);
+        // scenario times are read with 32 digits, the states of the bodies follow the time precision
+        let spawn_time = DBig::from(60).with_precision(32).value();
+        let mut check = prepare_sim();
+        check.update(&spawn_time);
+        let earth = check.get_body("earth").unwrap();
+        let spawned = CraftState {
+            time: spawn_time,
+            position: check.world_position(earth) + DecimalVector3d::from_f64(7e6, 0.0, 0.0),
+            velocity: check.world_velocity(earth) + DecimalVector3d::from_f64(0.0, 0.0, -7546.0),
+            propulsion: None,
+        };
+        let step = DBig::from(60);
+        let coast = ThrustProfile::Coast;
+        let path = check
+            .propagate(&spawned, &coast, &DBig::from(540), &step)
+            .unwrap();
+        let mut burned = path.last().unwrap().clone();
+        burned.apply_impulse(&DecimalVector3d::from_f64(0.0, 0.0, -100.0));
+        let path = check
+            .propagate(&burned, &coast, &DBig::from(600), &step)
+            .unwrap();
+        let probe = sim.get_spacecraft("probe").unwrap();
+        assert_eq!(probe.state.time, DBig::from(1200));
+        let miss = probe
+            .state
+            .position
+            .distance_to(&path.last().unwrap().position);
+        assert!(dbig_to_f64(&miss) < 1.0, "{}", dbig_to_f64(&miss));
+
+        sim.update(&DBig::from(4000));
+        assert!(sim.pending_actions().is_empty());
+        let rock = sim.get_body("rock").unwrap();
+        assert_eq!(
+            rock.body.mass_at(&DBig::from(4000)),
+            DBig::from(2_000_000_000_000_i64)
+        );
+        assert_eq!(rock.body.kind, Some(BodyKind::Asteroid));
+        assert!(sim.scenario_error().is_none());
+    }
+
+    #[test]
+    fn failing_scenario_action_is_reported() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        // a failing action is skipped and reported
+        let mut scenario = Scenario::new();
+        scenario.schedule(
+            DBig::from(5000),
+            ScenarioAction::Maneuver {
+                spacecraft: String::from("lander"),
+                delta_v: DecimalVector3d::from_f64(1.0, 0.0, 0.0),
+            },
+        );
+        sim.load_scenario(scenario);
+        sim.update(&DBig::from(6000));
+        assert_eq!(
+            sim.scenario_error(),
+            Some(&SimulationError::UnknownSpacecraft(String::from("lander")))
+        );
+    }
+
+    #[test]
+    fn scenario_parse_errors() {
+        let error = Scenario::parse("at 10 spawn_body name=rock\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: orbit_period is missing");
+        let error = Scenario::parse("\nat 10 explode body=rock").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: unknown action explode");
+        let error = Scenario::parse(
+            "at 0 spawn_body name=rock kind=boulder position=0,0,0 rotation_period=60 mass=1 radius=1",
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "line 1: boulder is not a body kind");
+    }
+}
@@ -0,0 +1,178 @@
+use crate::body::Body;
+use crate::ensemble::{parameter_values_mut, PerturbedParameter};
+use crate::error::SimulationError;
+use crate::simulation::{SimulatedBody, Simulation};
+use dashu_float::ops::Abs;
+use dashu_float::DBig;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+
+static RELATIVE_STEP: LazyLock<DBig> = LazyLock::new(|| DBig::from_str("0.000000000001").unwrap());
+const WORKING_PRECISION: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct SensitivityTracking {
+    pub body_id: i32,
+    pub parameters: Vec<PerturbedParameter>,
+    pub matrix: Vec<[DBig; 6]>, // refreshed on every update
+}
+
+impl Simulation {
+    fn parent_relative_state(
+        &self,
+        time: &DBig,
+        body: &SimulatedBody,
+        definition: Body,
+    ) -> [DBig; 6] {
+        let variant = SimulatedBody {
+            body: Arc::new(definition),
+            ..body.clone()
+        };
+        let (position, velocity) = self.get_body_relative_state(time, &variant);
+        [
+            position.x, position.y, position.z, velocity.x, velocity.y, velocity.z,
+        ]
+    }
+
+    /// columns are the derivatives of the parent-relative position and velocity with respect to
+    /// each scalar of the given parameters (three for `StaticPosition`), by central differences
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn state_sensitivity(
+        &self,
+        body_name: &str,
+        parameters: &[PerturbedParameter],
+    ) -> Result<Vec<[DBig; 6]>, SimulationError> {
+        Ok(self.body_state_sensitivity(self.get_body(body_name)?, parameters))
+    }
+
+    fn body_state_sensitivity(
+        &self,
+        body: &SimulatedBody,
+        parameters: &[PerturbedParameter],
+    ) -> Vec<[DBig; 6]> {
+        let mut columns: Vec<[DBig; 6]> = vec![];
+        for parameter in parameters {
+            let scalars = parameter_values_mut(&mut (*body.body).clone(), *parameter).len();
+            for i in 0..scalars {
+                let mut plus = (*body.body).clone();
+                let mut minus = (*body.body).clone();
+                let value = parameter_values_mut(&mut plus, *parameter)[i]
+                    .clone()
+                    .with_precision(WORKING_PRECISION)
+                    .value();
+                let mut step = value.clone().abs() * &*RELATIVE_STEP;
+                if step == DBig::ZERO {
+                    step.clone_from(&RELATIVE_STEP);
+                }
+                *parameter_values_mut(&mut plus, *parameter)[i] = &value + &step;
+                *parameter_values_mut(&mut minus, *parameter)[i] = &value - &step;
+
+                let state_plus = self.parent_relative_state(&self.time, body, plus);
+                let state_minus = self.parent_relative_state(&self.time, body, minus);
+                let twice_step = step * DBig::from(2);
+                columns.push(std::array::from_fn(|k| {
+                    (&state_plus[k] - &state_minus[k]) / &twice_step
+                }));
+            }
+        }
+        columns
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn enable_sensitivity_tracking(
+        &mut self,
+        body_name: &str,
+        parameters: &[PerturbedParameter],
+    ) -> Result<(), SimulationError> {
+        let body_id = self.get_body(body_name)?.id();
+        let matrix = self.state_sensitivity(body_name, parameters)?;
+        self.sensitivity_tracking
+            .retain(|tracking| tracking.body_id != body_id);
+        self.sensitivity_tracking.push(SensitivityTracking {
+            body_id,
+            parameters: parameters.to_vec(),
+            matrix,
+        });
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn disable_sensitivity_tracking(&mut self, body_name: &str) -> Result<(), SimulationError> {
+        let body_id = self.get_body(body_name)?.id();
+        self.sensitivity_tracking
+            .retain(|tracking| tracking.body_id != body_id);
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn tracked_sensitivity(
+        &self,
+        body_name: &str,
+    ) -> Result<Option<&Vec<[DBig; 6]>>, SimulationError> {
+        let body_id = self.get_body(body_name)?.id();
+        Ok(self
+            .sensitivity_tracking
+            .iter()
+            .find(|tracking| tracking.body_id == body_id)
+            .map(|tracking| &tracking.matrix))
+    }
+
+    pub(crate) fn update_sensitivity_tracking(&mut self) {
+        let mut tracking = std::mem::take(&mut self.sensitivity_tracking);
+        for item in &mut tracking {
+            let body = self.bodies.iter().find(|b| b.id() == item.body_id).unwrap();
+            item.matrix = self.body_state_sensitivity(body, &item.parameters);
+        }
+        self.sensitivity_tracking = tracking;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ensemble::PerturbedParameter;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::prepare_sim;
+    use dashu_float::DBig;
+
+    #[test]
+    fn state_sensitivity_works() {
+        let mut sim = prepare_sim();
+        sim.enable_sensitivity_tracking(
+            "moon",
+            &[PerturbedParameter::OrbitRadius, PerturbedParameter::Mass],
+        )
+        .unwrap();
+        sim.update(&f64_to_dbig(123_123.0));
+
+        let moon = sim.get_body("moon").unwrap();
+        let columns = sim.tracked_sensitivity("moon").unwrap().unwrap();
+        assert_eq!(columns.len(), 2);
+        // position scales linearly with the orbit radius
+        let radius = DBig::from(384_400_000);
+        let expected = [
+            &moon.relative_position.x / &radius,
+            &moon.relative_position.y / &radius,
+            &moon.relative_position.z / &radius,
+            &moon.velocity.x / &radius,
+            &moon.velocity.y / &radius,
+            &moon.velocity.z / &radius,
+        ];
+        for k in 0..6 {
+            assert!(approx_eq(
+                &columns[0][k],
+                &expected[k],
+                &f64_to_dbig(0.000_000_001)
+            ));
+            assert_eq!(columns[1][k], DBig::ZERO);
+        }
+    }
+}
@@ -1,29 +1,100 @@
-use crate::body::{Body, BodyDynamics};
+use crate::atmosphere::AtmosphereTracking;
+use crate::body::{Body, BodyDynamics, BodyKind, FormationFrame};
+use crate::bookmarks::Bookmark;
+use crate::coordinates::body_fixed_axes;
 use crate::decimal_matrix_3d::DecimalMatrix3d;
 use crate::decimal_vector_3d::DecimalVector3d;
-use crate::sin_cos::PIMUL2;
-use dashu_float::ops::SquareRoot;
+use crate::error::SimulationError;
+use crate::export_scale::ExportScale;
+use crate::lockstep::Lockstep;
+use crate::octree::Octree;
+use crate::rollback::History;
+use crate::scenario::ScenarioRun;
+use crate::sensitivity::SensitivityTracking;
+use crate::sin_cos::{dbig_to_f64, PIMUL2};
+use crate::snapshot::Checkpointing;
+use crate::soi::SoiTracking;
+use crate::spacecraft::Spacecraft;
+use crate::triggers::Trigger;
+use crate::uncertainty::UncertaintyTracking;
+use dashu_float::ops::{Abs, SquareRoot};
 use dashu_float::DBig;
+use std::collections::HashMap;
 use std::str::FromStr;
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
 
-static G_CONSTANT: LazyLock<DBig> = LazyLock::new(|| DBig::from_str("0.0000000000667408").unwrap());
+pub(crate) static G_CONSTANT: LazyLock<DBig> =
+    LazyLock::new(|| DBig::from_str("0.0000000000667408").unwrap());
 
 #[derive(Debug, Clone)]
 pub struct SimulatedBody {
-    id: i32,
-    pub body: Body,
+    pub(crate) id: i32,
+    pub body: Arc<Body>,
     pub position: DecimalVector3d,
+    pub relative_position: DecimalVector3d, // offset from the parent
     pub velocity: DecimalVector3d,
     pub orientation: DecimalMatrix3d,
-    parent: Option<i32>, // -1 means no
-    satellites: Vec<i32>,
+    pub(crate) last_update: Option<DBig>,
+    pub(crate) parent: Option<i32>, // the hierarchy itself, the satellites index is derived from it
+    pub(crate) sleeping: bool,      // frozen and left out of updates and queries
+}
+
+#[derive(Debug, Clone)]
+pub struct RaycastHit<'a> {
+    pub body: &'a SimulatedBody,
+    pub distance: DBig,
+    pub point: DecimalVector3d,
+}
+
+impl SimulatedBody {
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn parent(&self) -> Option<i32> {
+        self.parent
+    }
+
+    pub fn sleeping(&self) -> bool {
+        self.sleeping
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Anchor {
+    Body(i32),
+    Point(DecimalVector3d),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionStorage {
+    World,          // position is updated along with relative_position
+    ParentRelative, // only relative_position is updated, world positions are composed on demand
 }
 
 #[derive(Debug)]
 pub struct Simulation {
     pub bodies: Vec<SimulatedBody>,
-    id_counter: i32,
+    pub(crate) time: DBig, // of the last update
+    pub(crate) id_counter: i32,
+    index: Octree,                        // rebuilt on every update
+    satellites: HashMap<i32, Vec<usize>>, // parent id to the indices of its satellites, rebuilt with the index
+    pub(crate) anchor: Option<Anchor>,
+    pub(crate) anchor_threshold: DBig, // in meters, how far the anchor can move before the origin follows
+    pub(crate) origin: DecimalVector3d, // exported positions are relative to this
+    pub(crate) position_storage: PositionStorage,
+    pub(crate) checkpointing: Option<Checkpointing>,
+    pub(crate) sensitivity_tracking: Vec<SensitivityTracking>,
+    pub(crate) uncertainty_tracking: Vec<UncertaintyTracking>,
+    pub(crate) export_scale: Option<ExportScale>,
+    pub(crate) spacecraft: Vec<Spacecraft>,
+    pub(crate) triggers: Vec<Trigger>,
+    pub(crate) soi_tracking: Option<SoiTracking>,
+    pub(crate) atmosphere_tracking: AtmosphereTracking,
+    pub(crate) scenario: ScenarioRun,
+    pub(crate) bookmarks: Vec<Bookmark>, // sorted by time
+    pub(crate) lockstep: Option<Lockstep>,
+    pub(crate) history: Option<History>,
 }
 
 impl Default for Simulation {
@@ -36,31 +107,196 @@ impl Simulation {
     pub fn new() -> Self {
         Simulation {
             bodies: vec![],
+            time: DBig::ZERO,
             id_counter: 0,
+            index: Octree::new(),
+            satellites: HashMap::new(),
+            anchor: None,
+            anchor_threshold: DBig::ZERO,
+            origin: DecimalVector3d::zero(),
+            position_storage: PositionStorage::World,
+            checkpointing: None,
+            sensitivity_tracking: vec![],
+            uncertainty_tracking: vec![],
+            export_scale: None,
+            spacecraft: vec![],
+            triggers: vec![],
+            soi_tracking: None,
+            atmosphere_tracking: AtmosphereTracking::default(),
+            scenario: ScenarioRun::default(),
+            bookmarks: vec![],
+            lockstep: None,
+            history: None,
         }
     }
 
-    pub fn add_hierarchy(&mut self, body: &Body, parent: Option<i32>) -> i32 {
+    // a copy of the bodies alone, for the searches that step a simulation of their own; the
+    // same state a snapshot holds, kept in memory so mass functions come along. The
+    // spacecraft, triggers and the rest of the setup stay behind
+    pub(crate) fn copy_bodies(&self) -> Simulation {
+        let mut sim = Simulation::new();
+        sim.time.clone_from(&self.time);
+        sim.id_counter = self.id_counter;
+        sim.position_storage = self.position_storage;
+        sim.anchor.clone_from(&self.anchor);
+        sim.anchor_threshold.clone_from(&self.anchor_threshold);
+        sim.origin = self.origin.clone();
+        sim.bodies.clone_from(&self.bodies);
+        sim.bookmarks.clone_from(&self.bookmarks);
+        sim.lockstep.clone_from(&self.lockstep);
+        sim.rebuild_index();
+        sim
+    }
+
+    /// the whole hierarchy is checked before anything is inserted
+    ///
+    /// # Errors
+    ///
+    /// `MissingParent` if `parent` isn't in the simulation, `InvalidDynamics` if a body of the
+    /// hierarchy is invalid.
+    pub fn add_hierarchy(
+        &mut self,
+        body: Body,
+        parent: Option<i32>,
+    ) -> Result<i32, SimulationError> {
+        let leader = match parent {
+            Some(parent) => Some(
+                &*self
+                    .get_body_by_id(parent)
+                    .ok_or(SimulationError::MissingParent(parent))?
+                    .body,
+            ),
+            None => None,
+        };
+        Self::validate_hierarchy(&body, leader)?;
+        let new_id = self.insert_hierarchy(body, parent);
+        self.rebuild_index();
+        Ok(new_id)
+    }
+
+    fn validate_hierarchy(body: &Body, parent: Option<&Body>) -> Result<(), SimulationError> {
+        Self::validate(body, parent)?;
+        for satellite in &body.satellites {
+            Self::validate_hierarchy(satellite, Some(body))?;
+        }
+        Ok(())
+    }
+
+    // periods are signed, only zero is meaningless, and directions need a length to normalize
+    fn validate(body: &Body, parent: Option<&Body>) -> Result<(), SimulationError> {
+        let check = |valid: bool, reason: &str| {
+            if valid {
+                Ok(())
+            } else {
+                Err(SimulationError::InvalidDynamics(format!(
+                    "{}: {}",
+                    body.name, reason
+                )))
+            }
+        };
+        check(
+            body.rotation_period != DBig::ZERO,
+            "rotation period can't be zero",
+        )?;
+        check(
+            body.rotation_axis.length_squared() != DBig::ZERO,
+            "rotation axis can't be zero",
+        )?;
+        if let Some(resonance) = &body.resonance {
+            check(
+                resonance.rotations > 0 && resonance.orbits > 0,
+                "resonance needs whole numbers of rotations and orbits",
+            )?;
+            check(
+                matches!(
+                    body.dynamics,
+                    BodyDynamics::Orbiting(_) | BodyDynamics::Barycentric(_)
+                ),
+                "resonance needs an orbit",
+            )?;
+        }
+        if let BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics) =
+            &body.dynamics
+        {
+            check(
+                dynamics.orbit_period != DBig::ZERO,
+                "orbit period can't be zero",
+            )?;
+            check(
+                dynamics.orbit_plane_normal.length_squared() != DBig::ZERO,
+                "orbit plane normal can't be zero",
+            )?;
+            if let Some(ellipse) = &dynamics.ellipse {
+                check(
+                    ellipse.eccentricity >= DBig::ZERO && ellipse.eccentricity < DBig::ONE,
+                    "eccentricity has to be within [0, 1)",
+                )?;
+            }
+        }
+        if let BodyDynamics::Barycentric(_) = &body.dynamics {
+            check(
+                parent.is_some(),
+                "barycentric orbit needs a parent to share it with",
+            )?;
+        }
+        if let BodyDynamics::Formation(dynamics) = &body.dynamics {
+            check(parent.is_some(), "formation needs a leader to follow")?;
+            check(
+                dynamics.frame != FormationFrame::Orbital
+                    || matches!(
+                        parent.map(|p| &p.dynamics),
+                        Some(BodyDynamics::Orbiting(_) | BodyDynamics::Barycentric(_))
+                    ),
+                "orbital formation needs an orbiting leader",
+            )?;
+        }
+        Ok(())
+    }
+
+    fn insert_hierarchy(&mut self, mut body: Body, parent: Option<i32>) -> i32 {
         let new_id = self.id_counter;
         self.id_counter += 1;
-        let mut simulated_body = SimulatedBody {
+        // satellites are moved out of the definition, hierarchy is kept in the simulation
+        let satellites = std::mem::take(&mut body.satellites);
+        // a satellite can't be awake under a sleeping parent, parents within the new hierarchy
+        // are pushed after their satellites and start awake
+        let sleeping = parent
+            .and_then(|parent| self.get_body_by_id(parent))
+            .is_some_and(|parent| parent.sleeping);
+        let simulated_body = SimulatedBody {
             id: new_id,
             parent,
-            satellites: vec![],
-            body: body.clone(),
+            body: Arc::new(body),
             position: DecimalVector3d::zero(),
+            relative_position: DecimalVector3d::zero(),
             velocity: DecimalVector3d::zero(),
             orientation: DecimalMatrix3d::identity(),
+            last_update: None,
+            sleeping,
         };
-        for i in 0..body.satellites.len() {
-            simulated_body
-                .satellites
-                .push(self.add_hierarchy(&body.satellites[i], Some(new_id)));
+        for satellite in satellites {
+            self.insert_hierarchy(satellite, Some(new_id));
         }
         self.bodies.push(simulated_body);
         new_id
     }
 
+    pub(crate) fn rebuild_index(&mut self) {
+        self.satellites.clear();
+        for (i, body) in self.bodies.iter().enumerate() {
+            if let Some(parent) = body.parent {
+                self.satellites.entry(parent).or_default().push(i);
+            }
+        }
+        let items: Vec<(i32, DecimalVector3d)> = self
+            .bodies
+            .iter()
+            .filter(|body| !body.sleeping)
+            .map(|body| (body.id, self.world_position(body)))
+            .collect();
+        self.index = Octree::build(&items);
+    }
+
     fn get_body_by_name(&self, name: &str) -> Option<&SimulatedBody> {
         for i in 0..self.bodies.len() {
             if self.bodies[i].body.name == name {
@@ -71,7 +307,7 @@ impl Simulation {
         None
     }
 
-    fn get_body_by_id(&self, id: i32) -> Option<&SimulatedBody> {
+    pub(crate) fn get_body_by_id(&self, id: i32) -> Option<&SimulatedBody> {
         for i in 0..self.bodies.len() {
             if self.bodies[i].id == id {
                 return Some(&self.bodies[i]);
@@ -81,7 +317,7 @@ impl Simulation {
         None
     }
 
-    fn get_mut_body_by_id(&mut self, id: i32) -> Option<&mut SimulatedBody> {
+    pub(crate) fn get_mut_body_by_id(&mut self, id: i32) -> Option<&mut SimulatedBody> {
         for i in 0..self.bodies.len() {
             if self.bodies[i].id == id {
                 return Some(&mut self.bodies[i]);
@@ -91,7 +327,14 @@ impl Simulation {
         None
     }
 
-    fn resolve_hierarchy_up(&self, body: &SimulatedBody) -> Vec<&SimulatedBody> {
+    fn get_satellites(&self, body: &SimulatedBody) -> Vec<&SimulatedBody> {
+        match self.satellites.get(&body.id) {
+            None => vec![],
+            Some(indices) => indices.iter().map(|&i| &self.bodies[i]).collect(),
+        }
+    }
+
+    pub(crate) fn resolve_hierarchy_up(&self, body: &SimulatedBody) -> Vec<&SimulatedBody> {
         /* how this will look like for example for the moon,
           moon gets into this function, we don't want to add it
           its parent is earth, it gets found, is added to the moon-result
@@ -119,7 +362,7 @@ impl Simulation {
         result
     }
 
-    fn resolve_hierarchy_down(&self, body: &SimulatedBody) -> Vec<&SimulatedBody> {
+    pub(crate) fn resolve_hierarchy_down(&self, body: &SimulatedBody) -> Vec<&SimulatedBody> {
         /* how this will look like for example for the sun,
         sun gets into this function, its satellites are iterated, lets simplify to Venus, Earth, and Mars
         to sun result first added is [Venus]
@@ -131,138 +374,737 @@ impl Simulation {
         but it's good for this purpose here
         */
         let mut result: Vec<&SimulatedBody> = vec![];
-        for i in 0..body.satellites.len() {
-            match self.get_body_by_id(body.satellites[i]) {
-                None => (),
-                Some(sat) => {
-                    result.push(sat);
-                    let mut sub_result = self.resolve_hierarchy_down(sat);
-                    result.append(&mut sub_result);
-                }
-            };
+        for sat in self.get_satellites(body) {
+            result.push(sat);
+            let mut sub_result = self.resolve_hierarchy_down(sat);
+            result.append(&mut sub_result);
         }
         result
     }
 
-    fn get_body_position(&self, time: &DBig, body: &SimulatedBody) -> DecimalVector3d {
-        match body.clone().body.dynamics {
-            BodyDynamics::Static(dynamics) => dynamics.position,
-            BodyDynamics::Orbiting(dynamics) => {
-                let parent = self.get_body_by_id(body.parent.unwrap()).unwrap(); // panic if not fulfilled
-                let orbit_progression = (time / dynamics.orbit_period).fract();
-                let angle = &*PIMUL2 * orbit_progression;
-                let rotation_matrix =
-                    DecimalMatrix3d::axis_angle(&dynamics.orbit_plane_normal, angle);
-                rotation_matrix.apply(&DecimalVector3d::new(
-                    dynamics.orbit_radius,
-                    DBig::ZERO,
-                    DBig::ZERO,
-                )) + &parent.position
+    // offset from the parent, or the world position for bodies without a parent
+    fn get_body_relative_position(&self, time: &DBig, body: &SimulatedBody) -> DecimalVector3d {
+        let mut position = self.get_body_own_position(time, body);
+        // barycentric satellites pull their parent back by their share of the pair's mass
+        for satellite in self.get_satellites(body) {
+            if !matches!(satellite.body.dynamics, BodyDynamics::Barycentric(_)) {
+                continue;
+            }
+            let satellite_mass = satellite.body.mass_at(time);
+            let total_mass = body.body.mass_at(time) + &satellite_mass;
+            if total_mass == DBig::ZERO {
+                continue;
+            }
+            let separation = self.get_body_own_position(time, satellite);
+            position = position - separation * (satellite_mass / total_mass);
+        }
+        position
+    }
+
+    // as given by the dynamics, before barycentric satellites move the body
+    fn get_body_own_position(&self, time: &DBig, body: &SimulatedBody) -> DecimalVector3d {
+        match &body.body.dynamics {
+            BodyDynamics::Static(dynamics) => match body.parent {
+                None => dynamics.position.clone(),
+                Some(parent) => {
+                    &dynamics.position - self.world_position(self.get_body_by_id(parent).unwrap())
+                }
+            },
+            BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics) => {
+                let mean_anomaly = dynamics.orbit_angle(time);
+                let mut apsidal_advance = DBig::ZERO;
+                let mut radius = dynamics.orbit_radius.clone();
+                if let Some(drift) = &dynamics.drift {
+                    apsidal_advance = &drift.apsidal_precession * time;
+                    radius = (radius + &drift.radius_rate * time).max(DBig::ZERO);
+                }
+                let position = match &dynamics.ellipse {
+                    None => {
+                        let rotation_matrix = DecimalMatrix3d::axis_angle(
+                            &dynamics.orbit_plane_normal,
+                            mean_anomaly + apsidal_advance,
+                        );
+                        rotation_matrix.apply(&DecimalVector3d::new(radius, DBig::ZERO, DBig::ZERO))
+                    }
+                    Some(ellipse) => ellipse.position(
+                        &dynamics.orbit_plane_normal,
+                        &radius,
+                        &mean_anomaly,
+                        &apsidal_advance,
+                    ),
+                };
+                match &dynamics.drift {
+                    None => position,
+                    Some(drift) => {
+                        // roots have no parent axis, world +Y is used instead
+                        let axis = match body.parent {
+                            None => DecimalVector3d::new(DBig::ZERO, DBig::ONE, DBig::ZERO),
+                            Some(parent) => self
+                                .get_body_by_id(parent)
+                                .unwrap()
+                                .body
+                                .rotation_axis
+                                .clone(),
+                        };
+                        let node_angle = &drift.nodal_regression * time;
+                        DecimalMatrix3d::axis_angle(&axis, node_angle).apply(&position)
+                    }
+                }
+            }
+            BodyDynamics::Formation(dynamics) => {
+                // validated to have a leader, orbiting for the orbital frame
+                let leader = self.get_body_by_id(body.parent.unwrap()).unwrap();
+                let [x, y, z] = match dynamics.frame {
+                    FormationFrame::Orbital => {
+                        let (position, velocity) = self.get_body_relative_state(time, leader);
+                        let radial = position.normalized();
+                        let normal = position.cross(&velocity).normalized();
+                        let along_track = normal.cross(&radial);
+                        [radial, along_track, normal]
+                    }
+                    FormationFrame::BodyFixed => {
+                        body_fixed_axes(&leader.body, &Self::get_body_orientation(time, leader))
+                    }
+                };
+                x * &dynamics.offset.x + y * &dynamics.offset.y + z * &dynamics.offset.z
+            }
+        }
+    }
+
+    // position and velocity relative to the parent
+    pub(crate) fn get_body_relative_state(
+        &self,
+        time: &DBig,
+        body: &SimulatedBody,
+    ) -> (DecimalVector3d, DecimalVector3d) {
+        let relative_position = self.get_body_relative_position(time, body);
+        let relative_second_ago = self.get_body_relative_position(&(time - DBig::ONE), body);
+        let velocity = &relative_position - relative_second_ago;
+        (relative_position, velocity)
+    }
+
+    fn to_world_position(
+        &self,
+        relative_position: DecimalVector3d,
+        body: &SimulatedBody,
+    ) -> DecimalVector3d {
+        match body.parent {
+            None => relative_position,
+            Some(parent) => {
+                relative_position + self.world_position(self.get_body_by_id(parent).unwrap())
             }
         }
     }
 
     fn get_body_orientation(time: &DBig, body: &SimulatedBody) -> DecimalMatrix3d {
-        let rotation_progression = (time / &body.body.rotation_period).fract();
-        let angle = &*PIMUL2 * rotation_progression;
-        DecimalMatrix3d::axis_angle(&body.body.rotation_axis, angle)
+        let rotation_progression = (time / body.body.spin_period()).fract();
+        let mut angle = &*PIMUL2 * rotation_progression + &body.body.rotation_phase;
+        if let (
+            Some(libration),
+            BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics),
+        ) = (&body.body.libration, &body.body.dynamics)
+        {
+            let mut mean_anomaly = &*PIMUL2 * (time / &dynamics.orbit_period).fract();
+            if let Some(drift) = &dynamics.drift {
+                mean_anomaly += &drift.apsidal_precession * time;
+            }
+            angle -= libration.equation_of_center(&mean_anomaly);
+        }
+        let spin = DecimalMatrix3d::axis_angle(&body.body.rotation_axis, angle);
+        match &body.body.nutation {
+            None => spin,
+            Some(nutation) => {
+                let pole = body.body.orbit_pole();
+                &nutation.rotation(time, &body.body.rotation_axis, &pole) * &spin
+            }
+        }
+    }
+
+    fn needs_update(time: &DBig, body: &SimulatedBody) -> bool {
+        match (&body.last_update, &body.body.update_interval) {
+            (Some(last_update), Some(interval)) => (time - last_update).abs() >= *interval,
+            _ => true,
+        }
     }
 
     pub fn update(&mut self, time: &DBig) {
+        let start = self.time.clone();
+        self.advance_bodies(time);
+        self.check_triggers(&start, None);
+    }
+
+    /// only the bodies whose influence can reach the sphere of `radius` around `point`: the ones
+    /// whose sphere of influence, Hill sphere or surface comes within it, the roots, and the bodies
+    /// above them that their positions are composed from. Judged from where the bodies were at the
+    /// last update, the rest keep their state until a full update. The scenario actions and the
+    /// triggers are left for the full update too, and so are the simulation time, the tracking,
+    /// the checkpoints and the history: only the refreshed bodies are at `time`, each in its own
+    /// last update. Gives the names of the refreshed bodies
+    ///
+    /// # Errors
+    ///
+    /// `InvalidDynamics` if a body has a parent without mass.
+    pub fn update_around(
+        &mut self,
+        point: &DecimalVector3d,
+        radius: &DBig,
+        time: &DBig,
+    ) -> Result<Vec<String>, SimulationError> {
+        let mut scope: Vec<i32> = vec![];
+        for body in &self.bodies {
+            let reaching = if body.parent.is_none() {
+                true
+            } else {
+                let mut influence = body.body.radius.clone();
+                let soi = self.body_sphere_of_influence(body)?;
+                let hill = self.body_hill_radius(body)?;
+                for limit in [soi, hill].into_iter().flatten() {
+                    influence = influence.max(limit);
+                }
+                self.world_position(body).distance_to(point) - influence <= *radius
+            };
+            if reaching {
+                scope.push(body.id);
+                scope.extend(self.resolve_hierarchy_up(body).iter().map(|b| b.id));
+            }
+        }
+        let refreshed = self.update_bodies_in(time, Some(&scope));
+        self.rebuild_index();
+        Ok(refreshed
+            .into_iter()
+            .filter_map(|id| self.get_body_by_id(id))
+            .map(|body| body.body.name.clone())
+            .collect())
+    }
+
+    // the bodies alone, without the scenario actions and trigger checks on the way
+    pub(crate) fn update_bodies(&mut self, time: &DBig) {
+        self.update_bodies_in(time, None);
+        self.time.clone_from(time);
+        self.rebuild_index();
+        self.recenter_origin();
+        self.update_sensitivity_tracking();
+        self.update_uncertainty_tracking();
+        self.checkpoint_if_due();
+        self.record_history();
+    }
+
+    // with a scope only the listed bodies are refreshed, gives the ids of the refreshed ones;
+    // the simulation time and everything that follows it are left to the caller
+    fn update_bodies_in(&mut self, time: &DBig, scope: Option<&[i32]>) -> Vec<i32> {
+        let mut refreshed: Vec<i32> = vec![];
         let mut schedule: Vec<i32> = vec![];
         for i in 0..self.bodies.len() {
             let body = &self.bodies[i];
             match body.body.dynamics {
                 BodyDynamics::Static(_) => {
+                    schedule.push(body.id);
                     let hierarchy = self.resolve_hierarchy_down(body);
                     for body in hierarchy {
                         schedule.push(body.id);
                     }
                 }
-                BodyDynamics::Orbiting(_) => (),
+                BodyDynamics::Orbiting(_)
+                | BodyDynamics::Formation(_)
+                | BodyDynamics::Barycentric(_) => (),
             }
         }
         for item in schedule {
             let body_immutable = self.get_body_by_id(item).unwrap();
+            if body_immutable.sleeping
+                || scope.is_some_and(|scope| !scope.contains(&item))
+                || !Self::needs_update(time, body_immutable)
+            {
+                continue;
+            }
 
-            let position = self.get_body_position(time, body_immutable);
-            let pos_second_ago = self.get_body_position(&(time - DBig::ONE), body_immutable);
-            let velocity = &position - pos_second_ago;
+            let (relative_position, velocity) = self.get_body_relative_state(time, body_immutable);
+            let position = match self.position_storage {
+                PositionStorage::World => {
+                    Some(self.to_world_position(relative_position.clone(), body_immutable))
+                }
+                PositionStorage::ParentRelative => None,
+            };
             let orientation = Self::get_body_orientation(time, body_immutable);
 
             let body = self.get_mut_body_by_id(item).unwrap();
-            body.position = position;
+            if let Some(position) = position {
+                body.position = position;
+            }
+            body.relative_position = relative_position;
             body.velocity = velocity;
             body.orientation = orientation;
+            body.last_update = Some(time.clone());
+            refreshed.push(item);
         }
+        refreshed
     }
 
-    pub fn get_body(&self, body_name: &str) -> &SimulatedBody {
-        self.get_body_by_name(body_name).unwrap()
+    pub fn time(&self) -> &DBig {
+        &self.time
     }
 
+    pub fn set_position_storage(&mut self, position_storage: PositionStorage) {
+        self.position_storage = position_storage;
+        // positions are brought up to date with the new mode on next update
+        for body in &mut self.bodies {
+            body.last_update = None;
+        }
+    }
+
+    pub fn position_storage(&self) -> PositionStorage {
+        self.position_storage
+    }
+
+    /// # Panics
+    ///
+    /// If a parent of the body isn't in this simulation, like for a body taken from another one.
+    pub fn world_position(&self, body: &SimulatedBody) -> DecimalVector3d {
+        match self.position_storage {
+            PositionStorage::World => body.position.clone(),
+            PositionStorage::ParentRelative => match body.parent {
+                None => body.relative_position.clone(),
+                Some(parent) => {
+                    &body.relative_position
+                        + self.world_position(self.get_body_by_id(parent).unwrap())
+                }
+            },
+        }
+    }
+
+    /// velocities are stored relative to the parent, this composes them up the hierarchy
+    ///
+    /// # Panics
+    ///
+    /// If a parent of the body isn't in this simulation, like for a body taken from another one.
+    pub fn world_velocity(&self, body: &SimulatedBody) -> DecimalVector3d {
+        match body.parent {
+            None => body.velocity.clone(),
+            Some(parent) => {
+                &body.velocity + self.world_velocity(self.get_body_by_id(parent).unwrap())
+            }
+        }
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBodyId` if the anchor body isn't in the simulation.
+    pub fn set_anchor(&mut self, anchor: Anchor, threshold: DBig) -> Result<(), SimulationError> {
+        let position = match &anchor {
+            Anchor::Body(id) => self.world_position(
+                self.get_body_by_id(*id)
+                    .ok_or(SimulationError::UnknownBodyId(*id))?,
+            ),
+            Anchor::Point(point) => point.clone(),
+        };
+        self.anchor = Some(anchor);
+        self.anchor_threshold = threshold;
+        self.origin = position;
+        Ok(())
+    }
+
+    pub fn clear_anchor(&mut self) {
+        self.anchor = None;
+        self.origin = DecimalVector3d::zero();
+    }
+
+    pub fn origin(&self) -> &DecimalVector3d {
+        &self.origin
+    }
+
+    fn anchor_position(&self) -> Option<DecimalVector3d> {
+        match &self.anchor {
+            None => None,
+            Some(Anchor::Body(id)) => self
+                .get_body_by_id(*id)
+                .map(|body| self.world_position(body)),
+            Some(Anchor::Point(point)) => Some(point.clone()),
+        }
+    }
+
+    fn recenter_origin(&mut self) {
+        if let Some(anchor_position) = self.anchor_position() {
+            if anchor_position.distance_to(&self.origin) > self.anchor_threshold {
+                self.origin = anchor_position;
+            }
+        }
+    }
+
+    // position relative to the current origin, small enough near the anchor to not jitter in f64
+    pub fn export_position(&self, position: &DecimalVector3d) -> [f64; 3] {
+        let relative = position - &self.origin;
+        [
+            dbig_to_f64(&relative.x),
+            dbig_to_f64(&relative.y),
+            dbig_to_f64(&relative.z),
+        ]
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if there is no body of that name.
+    pub fn get_body(&self, body_name: &str) -> Result<&SimulatedBody, SimulationError> {
+        self.get_body_by_name(body_name)
+            .ok_or_else(|| SimulationError::UnknownBody(body_name.to_string()))
+    }
+
+    // bodies classified as `kind`, unclassified ones never match
+    pub fn bodies_of_kind(&self, kind: BodyKind) -> impl Iterator<Item = &SimulatedBody> {
+        self.bodies
+            .iter()
+            .filter(move |body| body.body.kind == Some(kind))
+    }
+
+    /// the definition is copied on write if it is shared, derived state is refreshed on next update
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if there is no body of that name.
+    pub fn get_body_mut(&mut self, body_name: &str) -> Result<&mut Body, SimulationError> {
+        let id = self.get_body(body_name)?.id;
+        self.get_body_mut_by_id(id)
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBodyId` if there is no body with that id.
+    pub fn get_body_mut_by_id(&mut self, id: i32) -> Result<&mut Body, SimulationError> {
+        let body = self
+            .get_mut_body_by_id(id)
+            .ok_or(SimulationError::UnknownBodyId(id))?;
+        body.last_update = None;
+        Ok(Arc::make_mut(&mut body.body))
+    }
+
+    /// position of `to` as seen from `from`, composed from parent offsets so that
+    /// nearby bodies far from the world origin don't lose precision
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if either body isn't in the simulation.
+    pub fn relative_position(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Result<DecimalVector3d, SimulationError> {
+        let from = self.get_body(from)?;
+        let to = self.get_body(to)?;
+
+        let mut from_chain = vec![from];
+        from_chain.append(&mut self.resolve_hierarchy_up(from));
+        let mut to_chain = vec![to];
+        to_chain.append(&mut self.resolve_hierarchy_up(to));
+
+        let common_ancestor = from_chain
+            .iter()
+            .find(|body| to_chain.iter().any(|other| other.id == body.id))
+            .map(|body| body.id);
+
+        let offset_to_ancestor = |chain: &Vec<&SimulatedBody>| {
+            let mut offset = DecimalVector3d::zero();
+            for body in chain {
+                if Some(body.id) == common_ancestor {
+                    break;
+                }
+                offset = offset + &body.relative_position;
+            }
+            offset
+        };
+        Ok(offset_to_ancestor(&to_chain) - offset_to_ancestor(&from_chain))
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
     pub fn get_surface_velocity(
         &self,
         body_name: &str,
         relative_point: &DecimalVector3d,
-    ) -> DecimalVector3d {
-        let body = self.get_body_by_name(body_name).unwrap();
+    ) -> Result<DecimalVector3d, SimulationError> {
+        let body = self.get_body(body_name)?;
         let axis = &body.body.rotation_axis;
-        let angular_body_vel = &*PIMUL2 / &body.body.rotation_period;
+        let angular_body_vel = &*PIMUL2 / body.body.spin_period();
         let angular_velocity_vector = axis * angular_body_vel;
-        angular_velocity_vector.cross(relative_point)
+        Ok(angular_velocity_vector.cross(relative_point))
     }
 
-    pub fn find_closest_static(&self, point: &DecimalVector3d) -> &SimulatedBody {
-        let mut min_distance = DBig::INFINITY;
-        let mut closest = &self.bodies[0];
-        for i in 0..self.bodies.len() {
-            match self.bodies[i].body.dynamics {
-                BodyDynamics::Static(_) => {
-                    let distance = self.bodies[i].position.distance_to(point);
-                    if distance < min_distance {
-                        closest = &self.bodies[i];
-                        min_distance = distance;
-                    }
+    pub fn find_closest_static(&self, point: &DecimalVector3d) -> Option<&SimulatedBody> {
+        let is_static = |id: i32| {
+            self.get_body_by_id(id)
+                .is_some_and(|body| matches!(body.body.dynamics, BodyDynamics::Static(_)))
+        };
+        self.index
+            .nearest(point, &is_static)
+            .and_then(|(id, _)| self.get_body_by_id(id))
+    }
+
+    /// the attractor at the point: among the roots the one pulling hardest, then down into the
+    /// innermost satellite whose sphere of influence holds the point; None without bodies
+    ///
+    /// # Errors
+    ///
+    /// `InvalidDynamics` if a body along the way has a parent without mass.
+    pub fn find_dominant_body(
+        &self,
+        point: &DecimalVector3d,
+    ) -> Result<Option<&SimulatedBody>, SimulationError> {
+        let distance = |body: &SimulatedBody| point.distance_to(&self.world_position(body));
+        // m_a / d_a^2 against m_b / d_b^2 without dividing, the point can sit on a center
+        let pulls_harder = |a: &SimulatedBody, b: &SimulatedBody| {
+            let (da, db) = (distance(a), distance(b));
+            let pull_a = a.body.mass_at(&self.time) * &db * &db;
+            let pull_b = b.body.mass_at(&self.time) * &da * &da;
+            pull_a.cmp(&pull_b)
+        };
+        let Some(mut current) = self
+            .bodies
+            .iter()
+            .filter(|body| body.parent().is_none() && !body.sleeping)
+            .max_by(|a, b| pulls_harder(a, b))
+        else {
+            return Ok(None);
+        };
+        loop {
+            let mut inside: Option<&SimulatedBody> = None;
+            for body in self.get_satellites(current) {
+                if body.sleeping {
+                    continue;
+                }
+                // satellites always have a parent, so a sphere of influence
+                let Some(limit) = self.body_sphere_of_influence(body)? else {
+                    continue;
+                };
+                if distance(body) < limit && inside.is_none_or(|b| distance(body) < distance(b)) {
+                    inside = Some(body);
                 }
-                BodyDynamics::Orbiting(_) => (),
+            }
+            match inside {
+                Some(body) => current = body,
+                None => return Ok(Some(current)),
             }
         }
-        closest
     }
 
-    pub fn find_closest_body(&self, point: &DecimalVector3d) -> &SimulatedBody {
-        let closest_static = self.find_closest_static(point);
+    // None without bodies to search, any body counts when no static one is there
+    pub fn find_closest_body(&self, point: &DecimalVector3d) -> Option<&SimulatedBody> {
+        let Some(closest_static) = self.find_closest_static(point) else {
+            return self
+                .index
+                .nearest(point, &|_| true)
+                .and_then(|(id, _)| self.get_body_by_id(id));
+        };
         let down_hierarchy = self.resolve_hierarchy_down(closest_static);
-        if down_hierarchy.is_empty() {
-            return closest_static;
-        }
-        let mut min_distance = down_hierarchy[0].position.distance_to(point);
-        let mut closest = &down_hierarchy[0];
-        for item in &down_hierarchy {
-            let distance = item.position.distance_to(point);
-            if distance < min_distance {
-                closest = item;
-                min_distance = distance;
+        let in_hierarchy = |id: i32| down_hierarchy.iter().any(|body| body.id == id);
+        match self.index.nearest(point, &in_hierarchy) {
+            Some((id, _)) => self.get_body_by_id(id),
+            None => Some(closest_static),
+        }
+    }
+
+    pub fn nearest_bodies(&self, point: &DecimalVector3d, k: usize) -> Vec<(&SimulatedBody, DBig)> {
+        self.index
+            .k_nearest(point, k, &|_| true)
+            .into_iter()
+            .filter_map(|(id, distance)| Some((self.get_body_by_id(id)?, distance)))
+            .collect()
+    }
+
+    pub fn bodies_within(
+        &self,
+        point: &DecimalVector3d,
+        radius: &DBig,
+    ) -> Vec<(&SimulatedBody, DBig)> {
+        self.index
+            .within_radius(point, radius)
+            .into_iter()
+            .filter_map(|(id, distance)| Some((self.get_body_by_id(id)?, distance)))
+            .collect()
+    }
+
+    pub fn raycast(
+        &self,
+        origin: &DecimalVector3d,
+        direction: &DecimalVector3d,
+    ) -> Option<RaycastHit<'_>> {
+        let direction = direction.normalized();
+        let mut closest: Option<RaycastHit> = None;
+        for body in self.awake_bodies() {
+            let to_origin = origin - self.world_position(body);
+            let along = to_origin.dot(&direction);
+            // distance to the center measured perpendicular to the ray, stable for huge coordinates
+            let perpendicular = &to_origin - &direction * &along;
+            // radius is lifted to the working precision, squaring it as given could round it
+            let radius = body
+                .body
+                .radius
+                .clone()
+                .with_precision(along.precision())
+                .value();
+            let radius_squared = &radius * &radius;
+            let discriminant = &radius_squared - perpendicular.length_squared();
+            if discriminant < DBig::ZERO {
+                continue;
+            }
+            let half_chord = discriminant.sqrt();
+            let mut distance = -&along - &half_chord;
+            if distance < DBig::ZERO {
+                // origin is inside the sphere
+                distance = -&along + &half_chord;
+            }
+            if distance < DBig::ZERO {
+                continue;
+            }
+            let is_closer = match &closest {
+                None => true,
+                Some(hit) => distance < hit.distance,
+            };
+            if is_closer {
+                closest = Some(RaycastHit {
+                    body,
+                    point: origin + &direction * &distance,
+                    distance,
+                });
             }
         }
         closest
     }
 
-    pub fn calculate_gravity_flux(&self, point: &DecimalVector3d) -> DecimalVector3d {
-        let closest_static = self.find_closest_static(point);
+    pub(crate) fn gravity_sources(
+        &self,
+        point: &DecimalVector3d,
+    ) -> Result<Vec<&SimulatedBody>, SimulationError> {
+        let Some(dominant) = self.find_dominant_body(point)? else {
+            return Ok(vec![]);
+        };
+        // the whole system of the dominant root pulls, not only the dominant body
+        let root = match self.resolve_hierarchy_up(dominant).last() {
+            Some(root) => *root,
+            None => dominant,
+        };
+        let mut hierarchy = self.resolve_hierarchy_down(root);
+        hierarchy.push(root);
+        hierarchy.retain(|body| !body.sleeping);
+        Ok(hierarchy)
+    }
+
+    /// # Errors
+    ///
+    /// `InvalidDynamics` if the dominant body at the point or a parent along the way has no mass.
+    pub fn calculate_gravity_flux(
+        &self,
+        point: &DecimalVector3d,
+    ) -> Result<DecimalVector3d, SimulationError> {
+        Ok(self.gravity_flux_from(point, self.gravity_sources(point)?))
+    }
+
+    // every body pulls, not only the system of the dominant root, so the second star of a wide
+    // binary is felt as well
+    pub fn calculate_total_gravity_flux(&self, point: &DecimalVector3d) -> DecimalVector3d {
+        self.gravity_flux_from(point, self.awake_bodies().collect())
+    }
+
+    fn gravity_flux_from(
+        &self,
+        point: &DecimalVector3d,
+        sources: Vec<&SimulatedBody>,
+    ) -> DecimalVector3d {
         let mut flux = DecimalVector3d::zero();
-        let mut hierarchy = self.resolve_hierarchy_down(closest_static);
-        hierarchy.push(closest_static);
 
-        for item in hierarchy {
+        for item in sources {
             let body = item;
-            let relative = &body.position - point;
+            let relative = self.world_position(body) - point;
             let length_squared = relative.length_squared();
+            // a body doesn't pull at its own center, see gravity_potential_from
+            if length_squared == DBig::ZERO {
+                continue;
+            }
             let length = length_squared.sqrt();
-            let strength = &*G_CONSTANT * &body.body.mass / length_squared;
+            let strength = &*G_CONSTANT * body.body.mass_at(&self.time) / length_squared;
             flux = flux + (relative * (&DBig::ONE / length * strength));
         }
         flux
     }
+
+    /// in J/kg, the sum of -GM/r over the same bodies as `calculate_gravity_flux`, zero far away
+    ///
+    /// # Errors
+    ///
+    /// `InvalidDynamics` if the dominant body at the point or a parent along the way has no mass.
+    pub fn calculate_gravity_potential(
+        &self,
+        point: &DecimalVector3d,
+    ) -> Result<DBig, SimulationError> {
+        Ok(self.gravity_potential_from(point, self.gravity_sources(point)?))
+    }
+
+    // over every body, see calculate_total_gravity_flux
+    pub fn calculate_total_gravity_potential(&self, point: &DecimalVector3d) -> DBig {
+        self.gravity_potential_from(point, self.awake_bodies().collect())
+    }
+
+    fn gravity_potential_from(
+        &self,
+        point: &DecimalVector3d,
+        sources: Vec<&SimulatedBody>,
+    ) -> DBig {
+        let mut potential = DBig::ZERO;
+        for body in sources {
+            let distance = self.world_position(body).distance_to(point);
+            // a point mass has no finite potential at its center, the body is left out there
+            // instead of dividing by zero
+            if distance == DBig::ZERO {
+                continue;
+            }
+            potential -= &*G_CONSTANT * body.body.mass_at(&self.time) / distance;
+        }
+        potential
+    }
+
+    /// gradient of `calculate_gravity_flux` in 1/s², row i holds how the i-th flux component changes
+    /// along x, y and z. Symmetric, applying it to an offset gives the difference in pull across
+    /// it, which is what stretches a body or loads a long structure near a massive one
+    ///
+    /// # Errors
+    ///
+    /// `InvalidDynamics` if the dominant body at the point or a parent along the way has no mass.
+    pub fn calculate_tidal_tensor(
+        &self,
+        point: &DecimalVector3d,
+    ) -> Result<DecimalMatrix3d, SimulationError> {
+        Ok(self.tidal_tensor_from(point, self.gravity_sources(point)?))
+    }
+
+    // over every body, see calculate_total_gravity_flux
+    pub fn calculate_total_tidal_tensor(&self, point: &DecimalVector3d) -> DecimalMatrix3d {
+        self.tidal_tensor_from(point, self.awake_bodies().collect())
+    }
+
+    fn tidal_tensor_from(
+        &self,
+        point: &DecimalVector3d,
+        sources: Vec<&SimulatedBody>,
+    ) -> DecimalMatrix3d {
+        let mut data: [[DBig; 3]; 3] = Default::default();
+        for body in sources {
+            // GM (3 r rT - r² I) / r⁵ with r from the body to the point
+            let relative = point - self.world_position(body);
+            let length_squared = relative.length_squared();
+            if length_squared == DBig::ZERO {
+                continue;
+            }
+            let scale = &*G_CONSTANT * body.body.mass_at(&self.time)
+                / (&length_squared * &length_squared * length_squared.sqrt());
+            let components = [&relative.x, &relative.y, &relative.z];
+            for (i, row) in data.iter_mut().enumerate() {
+                for (j, value) in row.iter_mut().enumerate() {
+                    let mut term = DBig::from(3) * components[i] * components[j];
+                    if i == j {
+                        term -= &length_squared;
+                    }
+                    *value += &scale * term;
+                }
+            }
+        }
+        DecimalMatrix3d { data }
+    }
 }
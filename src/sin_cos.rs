@@ -1,25 +1,17 @@
-use dashu_float::ops::Abs;
+use dashu_float::ops::{Abs, SquareRoot};
 use dashu_float::DBig;
 use std::ops::Deref;
 use std::str::FromStr;
 use std::sync::LazyLock;
 
-static PI: LazyLock<DBig> = LazyLock::new(|| {
+pub static PI: LazyLock<DBig> = LazyLock::new(|| {
     DBig::from_str("3.141592653589793238462643383279502884197169399375105820974944592307816406286")
         .unwrap()
 });
 
-pub static PIMUL2: LazyLock<DBig> = LazyLock::new(|| {
-    DBig::from_str("3.141592653589793238462643383279502884197169399375105820974944592307816406286")
-        .unwrap()
-        * DBig::from(2)
-});
+pub static PIMUL2: LazyLock<DBig> = LazyLock::new(|| &*PI * DBig::from(2));
 
-static PIDIV2: LazyLock<DBig> = LazyLock::new(|| {
-    DBig::from_str("3.141592653589793238462643383279502884197169399375105820974944592307816406286")
-        .unwrap()
-        / DBig::from(2)
-});
+pub static PIDIV2: LazyLock<DBig> = LazyLock::new(|| &*PI / DBig::from(2));
 
 static DBIGTEN: LazyLock<DBig> = LazyLock::new(|| DBig::from(10));
 
@@ -45,10 +37,86 @@ pub fn cos(x: DBig, precision: i64) -> DBig {
     sin(x + PIDIV2.deref(), precision)
 }
 
+// the series run with some digits to spare, a negative precision asks for none
+fn working_digits(precision: i64) -> usize {
+    usize::try_from(precision).unwrap_or(0) + 10
+}
+
+pub fn atan(x: DBig, precision: i64) -> DBig {
+    // lift the argument so the series isn't computed with the precision of the input literal
+    let x = x.with_precision(working_digits(precision)).value();
+    if x < DBig::ZERO {
+        return -atan(-x, precision);
+    }
+    if x > DBig::ONE {
+        return &*PIDIV2 - atan(DBig::ONE / x, precision);
+    }
+    // halve the angle twice, the series converges slowly near 1
+    let mut x = x;
+    for _ in 0..2 {
+        x = &x / (DBig::ONE + (DBig::ONE + &x * &x).sqrt());
+    }
+
+    let mut term = x.clone();
+    let mut result = x.clone();
+    let mut power = x.clone();
+    let mut n = 1;
+    let x_sq = &x * &x;
+
+    let limit = DBIGTEN.powf(&DBig::from(-precision));
+
+    while term.clone().abs() > limit {
+        power = -power * &x_sq;
+        term = &power / DBig::from(2 * n + 1);
+        result += &term;
+        n += 1;
+    }
+
+    result * DBig::from(4)
+}
+
+pub fn atan2(y: DBig, x: DBig, precision: i64) -> DBig {
+    let y = y.with_precision(working_digits(precision)).value();
+    let x = x.with_precision(working_digits(precision)).value();
+    if x > DBig::ZERO {
+        atan(y / x, precision)
+    } else if x < DBig::ZERO {
+        if y < DBig::ZERO {
+            atan(y / x, precision) - &*PI
+        } else {
+            atan(y / x, precision) + &*PI
+        }
+    } else if y > DBig::ZERO {
+        PIDIV2.clone()
+    } else if y < DBig::ZERO {
+        -PIDIV2.clone()
+    } else {
+        DBig::ZERO
+    }
+}
+
+// arguments rounded just past ±1 are taken as ±1
+pub fn asin(x: DBig, precision: i64) -> DBig {
+    let x = x
+        .with_precision(working_digits(precision))
+        .value()
+        .clamp(-DBig::ONE, DBig::ONE);
+    let cos = (DBig::ONE - &x * &x).sqrt();
+    atan2(x, cos, precision)
+}
+
+pub fn acos(x: DBig, precision: i64) -> DBig {
+    &*PIDIV2 - asin(x, precision)
+}
+
 pub fn dbig_to_f64(v: &DBig) -> f64 {
     f64::from_str(v.to_string().as_str()).unwrap()
 }
 
+pub fn approx_eq(a: &DBig, b: &DBig, epsilon: &DBig) -> bool {
+    (a - b).abs() <= *epsilon
+}
+
 pub fn f64_to_dbig(v: f64) -> DBig {
     DBig::from_str(v.to_string().as_str()).unwrap()
 }
@@ -57,10 +125,6 @@ pub fn f64_to_dbig(v: f64) -> DBig {
 mod tests {
     use super::*;
 
-    fn dbig_to_f64(v: &DBig) -> f64 {
-        f64::from_str(v.to_string().as_str()).unwrap()
-    }
-
     #[test]
     fn sin_works() {
         for i in -10..10 {
@@ -70,11 +134,59 @@ mod tests {
                 let sin_dec = sin(dec, 32);
                 let sin_ref = v.sin();
                 // println!("sin({v}) resulted in {sin_dec}, reference is {sin_ref}");
-                assert!((dbig_to_f64(&sin_dec) - sin_ref).abs() < 0.0000000000001);
+                assert!(approx_eq(
+                    &sin_dec,
+                    &f64_to_dbig(sin_ref),
+                    &f64_to_dbig(0.000_000_000_000_1)
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn atan2_works() {
+        for i in -10..10 {
+            for f in -10..10 {
+                let y = f64::from(i) + f64::from(f) / 10.0;
+                let x = f64::from(f) - f64::from(i) / 10.0;
+                let atan_dec = atan2(f64_to_dbig(y), f64_to_dbig(x), 32);
+                let atan_ref = y.atan2(x);
+                assert!(approx_eq(
+                    &atan_dec,
+                    &f64_to_dbig(atan_ref),
+                    &f64_to_dbig(0.000_000_000_000_1)
+                ));
             }
         }
     }
 
+    #[test]
+    fn asin_acos_works() {
+        for i in -10..=10 {
+            let v = f64::from(i) / 10.0;
+            let asin_dec = asin(f64_to_dbig(v), 32);
+            let acos_dec = acos(f64_to_dbig(v), 32);
+            assert!(approx_eq(
+                &asin_dec,
+                &f64_to_dbig(v.asin()),
+                &f64_to_dbig(0.000_000_000_000_1)
+            ));
+            assert!(approx_eq(
+                &acos_dec,
+                &f64_to_dbig(v.acos()),
+                &f64_to_dbig(0.000_000_000_000_1)
+            ));
+        }
+        let past_one = DBig::ONE + DBig::from_str("1e-31").unwrap();
+        assert_eq!(acos(past_one.clone(), 32), DBig::ZERO);
+        let asin_dec = asin(-past_one, 32);
+        assert!(approx_eq(
+            &asin_dec,
+            &f64_to_dbig(-std::f64::consts::FRAC_PI_2),
+            &f64_to_dbig(0.000_000_000_000_1)
+        ));
+    }
+
     #[test]
     fn cos_works() {
         for i in -10..10 {
@@ -84,7 +196,11 @@ mod tests {
                 let cos_dec = cos(dec, 32);
                 let cos_ref = v.cos();
                 // println!("sin({v}) resulted in {sin_dec}, reference is {sin_ref}");
-                assert!((dbig_to_f64(&cos_dec) - cos_ref).abs() < 0.0000000000001);
+                assert!(approx_eq(
+                    &cos_dec,
+                    &f64_to_dbig(cos_ref),
+                    &f64_to_dbig(0.000_000_000_000_1)
+                ));
             }
         }
     }
@@ -0,0 +1,192 @@
+use crate::error::SimulationError;
+use crate::simulation::{SimulatedBody, Simulation};
+
+impl Simulation {
+    /// freezes the body and everything going around it as they are: updates skip them, and the
+    /// spatial queries, dominant body search and gravity leave them out. Looking them up by name
+    /// still works and gives the frozen state, the searches over time refuse them
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn sleep_body(&mut self, body_name: &str) -> Result<(), SimulationError> {
+        let body = self.get_body(body_name)?;
+        let mut ids: Vec<i32> = self
+            .resolve_hierarchy_down(body)
+            .iter()
+            .map(|b| b.id())
+            .collect();
+        ids.push(body.id());
+        self.set_sleeping(&ids, true);
+        Ok(())
+    }
+
+    /// wakes the body with everything going around it, and the bodies above it that its position
+    /// is composed from; they catch up with the simulation time on the next update
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn wake_body(&mut self, body_name: &str) -> Result<(), SimulationError> {
+        let body = self.get_body(body_name)?;
+        let mut ids: Vec<i32> = self
+            .resolve_hierarchy_down(body)
+            .iter()
+            .map(|b| b.id())
+            .collect();
+        ids.extend(self.resolve_hierarchy_up(body).iter().map(|b| b.id()));
+        ids.push(body.id());
+        self.set_sleeping(&ids, false);
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn is_sleeping(&self, body_name: &str) -> Result<bool, SimulationError> {
+        Ok(self.get_body(body_name)?.sleeping)
+    }
+
+    pub fn awake_bodies(&self) -> impl Iterator<Item = &SimulatedBody> {
+        self.bodies.iter().filter(|body| !body.sleeping)
+    }
+
+    // for the searches over time, a frozen body would only give its frozen state at every step
+    pub(crate) fn get_awake_body(
+        &self,
+        body_name: &str,
+    ) -> Result<&SimulatedBody, SimulationError> {
+        let body = self.get_body(body_name)?;
+        if body.sleeping {
+            return Err(SimulationError::InvalidState(format!(
+                "{body_name}: is sleeping"
+            )));
+        }
+        Ok(body)
+    }
+
+    fn set_sleeping(&mut self, ids: &[i32], sleeping: bool) {
+        for body in &mut self.bodies {
+            if ids.contains(&body.id) && body.sleeping != sleeping {
+                body.sleeping = sleeping;
+                if !sleeping {
+                    body.last_update = None;
+                }
+            }
+        }
+        self.rebuild_index();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::simulation::Simulation;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn sleeping_bodies_work() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        sim.sleep_body("earth").unwrap();
+        assert!(sim.is_sleeping("earth").unwrap());
+        assert!(sim.is_sleeping("moon").unwrap());
+        assert!(!sim.is_sleeping("sun").unwrap());
+        assert_eq!(sim.awake_bodies().count(), 1);
+
+        // frozen where they were
+        let earth_before = sim.world_position(sim.get_body("earth").unwrap());
+        let moon_before = sim.world_position(sim.get_body("moon").unwrap());
+        sim.update(&DBig::from(24 * 3600));
+        let earth = sim.get_body("earth").unwrap();
+        assert_eq!(
+            sim.world_position(earth).distance_to(&earth_before),
+            DBig::ZERO
+        );
+        let moon = sim.get_body("moon").unwrap();
+        assert_eq!(
+            sim.world_position(moon).distance_to(&moon_before),
+            DBig::ZERO
+        );
+
+        // left out of the queries, only the sun is there
+        let near_earth = &earth_before + DecimalVector3d::from_f64(1e7, 0.0, 0.0);
+        assert_eq!(sim.nearest_bodies(&near_earth, 3).len(), 1);
+        assert_eq!(sim.find_closest_body(&near_earth).unwrap().body.name, "sun");
+        assert_eq!(
+            sim.find_dominant_body(&near_earth)
+                .unwrap()
+                .unwrap()
+                .body
+                .name,
+            "sun"
+        );
+        assert!(sim
+            .raycast(
+                &(&earth_before + DecimalVector3d::from_f64(0.0, 1e7, 0.0)),
+                &DecimalVector3d::from_f64(0.0, -1.0, 0.0)
+            )
+            .is_none_or(|hit| hit.body.body.name == "sun"));
+        let sun = sim.world_position(sim.get_body("sun").unwrap());
+        let only_sun =
+            6.67408e-11 * 1.98847e30 / dbig_to_f64(&sun.distance_to(&near_earth)).powi(2);
+        let flux = dbig_to_f64(&sim.calculate_gravity_flux(&near_earth).unwrap().length());
+        assert!((flux / only_sun - 1.0).abs() < 1e-9);
+
+        // kept in snapshots
+        let mut buf: Vec<u8> = vec![];
+        sim.write_snapshot(&mut buf).unwrap();
+        let restored = Simulation::read_snapshot(&mut buf.as_slice()).unwrap();
+        assert!(restored.is_sleeping("moon").unwrap());
+
+        // waking the moon wakes the earth it goes around, both catch up on the next update
+        sim.wake_body("moon").unwrap();
+        assert!(!sim.is_sleeping("earth").unwrap());
+        assert!(!sim.is_sleeping("moon").unwrap());
+        sim.update(&DBig::from(24 * 3600));
+        let earth = sim.get_body("earth").unwrap();
+        let earth = sim.world_position(earth);
+        assert!(earth.distance_to(&earth_before) > DBig::ZERO);
+        let near_earth = &earth + DecimalVector3d::from_f64(1e7, 0.0, 0.0);
+        assert_eq!(
+            sim.find_dominant_body(&near_earth)
+                .unwrap()
+                .unwrap()
+                .body
+                .name,
+            "earth"
+        );
+        assert!(matches!(
+            sim.sleep_body("pluto"),
+            Err(SimulationError::UnknownBody(_))
+        ));
+    }
+
+    #[test]
+    fn searches_over_time_refuse_sleeping_bodies() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        sim.sleep_body("moon").unwrap();
+        let sleeping = SimulationError::InvalidState(String::from("moon: is sleeping"));
+        let (start, end, step) = (DBig::ZERO, DBig::from(86400), DBig::from(3600));
+        assert_eq!(
+            sim.eclipse_events("moon", "earth", "sun", &start, &end, &step)
+                .unwrap_err(),
+            sleeping
+        );
+        assert_eq!(
+            sim.find_alignments("moon", "sun", "earth", &start, &end, &DBig::ONE)
+                .unwrap_err(),
+            sleeping
+        );
+        assert_eq!(
+            sim.lunar_phase_calendar("moon", "sun", "earth", &start, &end, &step)
+                .unwrap_err(),
+            sleeping
+        );
+        // the frozen state is still there to look at
+        assert!(sim.body_shadow("moon", "earth", "sun").is_ok());
+    }
+}
@@ -0,0 +1,874 @@
+use crate::body::{
+    Body, BodyDynamics, BodyKind, FormationBodyDynamics, FormationFrame, Libration, MassVariation,
+    Nutation, OrbitEllipse, OrbitingBodyDynamics, SecularDrift, SpinOrbitResonance,
+    StaticBodyDynamics, VisualHints,
+};
+use crate::bookmarks::Bookmark;
+use crate::decimal_matrix_3d::DecimalMatrix3d;
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::lockstep::Lockstep;
+use crate::propagation::{CraftState, Propulsion, ThrustProfile};
+use crate::simulation::{Anchor, PositionStorage, SimulatedBody, Simulation};
+use crate::spacecraft::Spacecraft;
+use dashu_float::{Context, DBig, Repr};
+use dashu_int::IBig;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+const MAGIC: &[u8; 4] = b"PSIM";
+const VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub struct Checkpointing {
+    pub path: PathBuf,
+    pub interval: DBig, // in simulated seconds
+    pub last_checkpoint: Option<DBig>,
+    pub last_error: Option<Error>,
+}
+
+pub(crate) fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn invalid_input(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+// WRITE
+
+pub(crate) fn write_u8<W: Write>(w: &mut W, v: u8) -> Result<()> {
+    w.write_all(&[v])
+}
+
+pub(crate) fn write_u32<W: Write>(w: &mut W, v: u32) -> Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+pub(crate) fn write_i32<W: Write>(w: &mut W, v: i32) -> Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn write_i64<W: Write>(w: &mut W, v: i64) -> Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+// lengths and counts are stored as u32
+pub(crate) fn write_len<W: Write>(w: &mut W, len: usize) -> Result<()> {
+    let len = u32::try_from(len).map_err(|_| invalid_input("too long for a snapshot"))?;
+    write_u32(w, len)
+}
+
+fn write_string<W: Write>(w: &mut W, v: &str) -> Result<()> {
+    write_len(w, v.len())?;
+    w.write_all(v.as_bytes())
+}
+
+// significand, exponent and precision are stored separately, so values come back bit-exact
+pub(crate) fn write_dbig<W: Write>(w: &mut W, v: &DBig) -> Result<()> {
+    write_string(w, &v.repr().significand().to_string())?;
+    let exponent =
+        i64::try_from(v.repr().exponent()).map_err(|_| invalid_input("exponent out of range"))?;
+    write_i64(w, exponent)?;
+    let precision =
+        i64::try_from(v.precision()).map_err(|_| invalid_input("precision out of range"))?;
+    write_i64(w, precision)
+}
+
+fn write_option_dbig<W: Write>(w: &mut W, v: Option<&DBig>) -> Result<()> {
+    match v {
+        None => write_u8(w, 0),
+        Some(v) => {
+            write_u8(w, 1)?;
+            write_dbig(w, v)
+        }
+    }
+}
+
+pub(crate) fn write_vector<W: Write>(w: &mut W, v: &DecimalVector3d) -> Result<()> {
+    write_dbig(w, &v.x)?;
+    write_dbig(w, &v.y)?;
+    write_dbig(w, &v.z)
+}
+
+fn write_matrix<W: Write>(w: &mut W, m: &DecimalMatrix3d) -> Result<()> {
+    for row in &m.data {
+        for v in row {
+            write_dbig(w, v)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_orbit<W: Write>(w: &mut W, dynamics: &OrbitingBodyDynamics) -> Result<()> {
+    write_dbig(w, &dynamics.orbit_radius)?;
+    write_vector(w, &dynamics.orbit_plane_normal)?;
+    write_dbig(w, &dynamics.orbit_period)?;
+    write_dbig(w, &dynamics.mean_anomaly_at_epoch)?;
+    match &dynamics.ellipse {
+        None => write_u8(w, 0)?,
+        Some(ellipse) => {
+            write_u8(w, 1)?;
+            write_dbig(w, &ellipse.eccentricity)?;
+            write_dbig(w, &ellipse.argument_of_periapsis)?;
+        }
+    }
+    match &dynamics.drift {
+        None => write_u8(w, 0),
+        Some(drift) => {
+            write_u8(w, 1)?;
+            write_dbig(w, &drift.nodal_regression)?;
+            write_dbig(w, &drift.apsidal_precession)?;
+            write_dbig(w, &drift.radius_rate)
+        }
+    }
+}
+
+fn write_body<W: Write>(w: &mut W, body: &Body) -> Result<()> {
+    write_string(w, &body.name)?;
+    write_vector(w, &body.rotation_axis)?;
+    write_dbig(w, &body.rotation_period)?;
+    write_dbig(w, &body.rotation_phase)?;
+    match &body.resonance {
+        None => write_u8(w, 0)?,
+        Some(resonance) => {
+            write_u8(w, 1)?;
+            write_u32(w, resonance.rotations)?;
+            write_u32(w, resonance.orbits)?;
+        }
+    }
+    match &body.nutation {
+        None => write_u8(w, 0)?,
+        Some(nutation) => {
+            write_u8(w, 1)?;
+            write_dbig(w, &nutation.longitude_amplitude)?;
+            write_dbig(w, &nutation.obliquity_amplitude)?;
+            write_dbig(w, &nutation.period)?;
+        }
+    }
+    match &body.libration {
+        None => write_u8(w, 0)?,
+        Some(libration) => {
+            write_u8(w, 1)?;
+            write_dbig(w, &libration.eccentricity)?;
+        }
+    }
+    write_dbig(w, &body.mass)?;
+    match &body.mass_variation {
+        None => write_u8(w, 0)?,
+        Some(MassVariation::Linear(rate)) => {
+            write_u8(w, 1)?;
+            write_dbig(w, rate)?;
+        }
+        Some(MassVariation::Function(_)) => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{}: mass functions can't be snapshotted", body.name),
+            ))
+        }
+    }
+    write_dbig(w, &body.radius)?;
+    write_option_dbig(w, body.update_interval.as_ref())?;
+    match &body.visual {
+        None => write_u8(w, 0)?,
+        Some(visual) => {
+            write_u8(w, 1)?;
+            match visual.color {
+                None => write_u8(w, 0)?,
+                Some(color) => {
+                    write_u8(w, 1)?;
+                    w.write_all(&color)?;
+                }
+            }
+            match &visual.texture {
+                None => write_u8(w, 0)?,
+                Some(texture) => {
+                    write_u8(w, 1)?;
+                    write_string(w, texture)?;
+                }
+            }
+            write_u8(w, u8::from(visual.emissive))?;
+        }
+    }
+    write_u8(
+        w,
+        match body.kind {
+            None => 0,
+            Some(BodyKind::Star) => 1,
+            Some(BodyKind::Planet) => 2,
+            Some(BodyKind::DwarfPlanet) => 3,
+            Some(BodyKind::Moon) => 4,
+            Some(BodyKind::Asteroid) => 5,
+            Some(BodyKind::Comet) => 6,
+            Some(BodyKind::ArtificialSatellite) => 7,
+        },
+    )?;
+    match &body.dynamics {
+        BodyDynamics::Static(dynamics) => {
+            write_u8(w, 0)?;
+            write_vector(w, &dynamics.position)
+        }
+        BodyDynamics::Orbiting(dynamics) => {
+            write_u8(w, 1)?;
+            write_orbit(w, dynamics)
+        }
+        BodyDynamics::Formation(dynamics) => {
+            write_u8(w, 2)?;
+            write_vector(w, &dynamics.offset)?;
+            match dynamics.frame {
+                FormationFrame::Orbital => write_u8(w, 0),
+                FormationFrame::BodyFixed => write_u8(w, 1),
+            }
+        }
+        BodyDynamics::Barycentric(dynamics) => {
+            write_u8(w, 3)?;
+            write_orbit(w, dynamics)
+        }
+    }
+}
+
+fn write_simulated_body<W: Write>(w: &mut W, body: &SimulatedBody) -> Result<()> {
+    write_i32(w, body.id)?;
+    match body.parent {
+        None => write_u8(w, 0)?,
+        Some(parent) => {
+            write_u8(w, 1)?;
+            write_i32(w, parent)?;
+        }
+    }
+    write_body(w, &body.body)?;
+    write_vector(w, &body.position)?;
+    write_vector(w, &body.relative_position)?;
+    write_vector(w, &body.velocity)?;
+    write_matrix(w, &body.orientation)?;
+    write_option_dbig(w, body.last_update.as_ref())?;
+    write_u8(w, u8::from(body.sleeping))
+}
+
+fn write_spacecraft<W: Write>(w: &mut W, spacecraft: &Spacecraft) -> Result<()> {
+    write_string(w, &spacecraft.name)?;
+    write_dbig(w, &spacecraft.state.time)?;
+    write_vector(w, &spacecraft.state.position)?;
+    write_vector(w, &spacecraft.state.velocity)?;
+    match &spacecraft.state.propulsion {
+        None => write_u8(w, 0)?,
+        Some(propulsion) => {
+            write_u8(w, 1)?;
+            write_dbig(w, &propulsion.dry_mass)?;
+            write_dbig(w, &propulsion.propellant_mass)?;
+            write_dbig(w, &propulsion.thrust)?;
+            write_dbig(w, &propulsion.specific_impulse)?;
+        }
+    }
+    match &spacecraft.thrust {
+        ThrustProfile::Coast => write_u8(w, 0),
+        ThrustProfile::Prograde {
+            reference,
+            acceleration,
+        } => {
+            write_u8(w, 1)?;
+            write_string(w, reference)?;
+            write_dbig(w, acceleration)
+        }
+        ThrustProfile::Function(_) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{}: thrust functions can't be snapshotted", spacecraft.name),
+        )),
+    }?;
+    match &spacecraft.primary {
+        None => write_u8(w, 0),
+        Some(primary) => {
+            write_u8(w, 1)?;
+            write_string(w, primary)
+        }
+    }
+}
+
+// READ
+
+pub(crate) fn read_u8<R: Read>(r: &mut R) -> Result<u8> {
+    let mut buf = [0u8; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+pub(crate) fn read_u32<R: Read>(r: &mut R) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub(crate) fn read_i32<R: Read>(r: &mut R) -> Result<i32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+fn read_i64<R: Read>(r: &mut R) -> Result<i64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(i64::from_le_bytes(buf))
+}
+
+fn read_string<R: Read>(r: &mut R) -> Result<String> {
+    let len = u64::from(read_u32(r)?);
+    // read through take so a broken length can't allocate more than the data holds
+    let mut buf = vec![];
+    r.by_ref().take(len).read_to_end(&mut buf)?;
+    if u64::try_from(buf.len()).ok() != Some(len) {
+        return Err(invalid_data("string longer than the data"));
+    }
+    String::from_utf8(buf).map_err(|_| invalid_data("invalid utf-8 in string"))
+}
+
+pub(crate) fn read_dbig<R: Read>(r: &mut R) -> Result<DBig> {
+    let significand =
+        IBig::from_str(&read_string(r)?).map_err(|_| invalid_data("invalid significand"))?;
+    let exponent = isize::try_from(read_i64(r)?).map_err(|_| invalid_data("invalid exponent"))?;
+    let repr = Repr::new(significand, exponent);
+    let mut precision =
+        usize::try_from(read_i64(r)?).map_err(|_| invalid_data("invalid precision"))?;
+    // arithmetic can leave more digits than the precision says, the value is kept as it was
+    if precision != 0 && repr.digits() > precision {
+        precision = repr.digits();
+    }
+    Ok(DBig::from_repr(repr, Context::new(precision)))
+}
+
+fn read_option_dbig<R: Read>(r: &mut R) -> Result<Option<DBig>> {
+    match read_u8(r)? {
+        0 => Ok(None),
+        1 => Ok(Some(read_dbig(r)?)),
+        _ => Err(invalid_data("invalid option tag")),
+    }
+}
+
+pub(crate) fn read_vector<R: Read>(r: &mut R) -> Result<DecimalVector3d> {
+    Ok(DecimalVector3d::new(
+        read_dbig(r)?,
+        read_dbig(r)?,
+        read_dbig(r)?,
+    ))
+}
+
+fn read_matrix<R: Read>(r: &mut R) -> Result<DecimalMatrix3d> {
+    let mut matrix = DecimalMatrix3d::identity();
+    for row in &mut matrix.data {
+        for v in row {
+            *v = read_dbig(r)?;
+        }
+    }
+    Ok(matrix)
+}
+
+fn read_orbit<R: Read>(r: &mut R) -> Result<OrbitingBodyDynamics> {
+    Ok(OrbitingBodyDynamics {
+        orbit_radius: read_dbig(r)?,
+        orbit_plane_normal: read_vector(r)?,
+        orbit_period: read_dbig(r)?,
+        mean_anomaly_at_epoch: read_dbig(r)?,
+        ellipse: match read_u8(r)? {
+            0 => None,
+            1 => Some(OrbitEllipse {
+                eccentricity: read_dbig(r)?,
+                argument_of_periapsis: read_dbig(r)?,
+            }),
+            _ => return Err(invalid_data("invalid ellipse tag")),
+        },
+        drift: match read_u8(r)? {
+            0 => None,
+            1 => Some(SecularDrift {
+                nodal_regression: read_dbig(r)?,
+                apsidal_precession: read_dbig(r)?,
+                radius_rate: read_dbig(r)?,
+            }),
+            _ => return Err(invalid_data("invalid drift tag")),
+        },
+    })
+}
+
+fn read_body<R: Read>(r: &mut R) -> Result<Body> {
+    let name = read_string(r)?;
+    let rotation_axis = read_vector(r)?;
+    let rotation_period = read_dbig(r)?;
+    let rotation_phase = read_dbig(r)?;
+    let resonance = match read_u8(r)? {
+        0 => None,
+        1 => Some(SpinOrbitResonance {
+            rotations: read_u32(r)?,
+            orbits: read_u32(r)?,
+        }),
+        _ => return Err(invalid_data("invalid resonance tag")),
+    };
+    let nutation = match read_u8(r)? {
+        0 => None,
+        1 => Some(Nutation {
+            longitude_amplitude: read_dbig(r)?,
+            obliquity_amplitude: read_dbig(r)?,
+            period: read_dbig(r)?,
+        }),
+        _ => return Err(invalid_data("invalid nutation tag")),
+    };
+    let libration = match read_u8(r)? {
+        0 => None,
+        1 => Some(Libration {
+            eccentricity: read_dbig(r)?,
+        }),
+        _ => return Err(invalid_data("invalid libration tag")),
+    };
+    let mass = read_dbig(r)?;
+    let mass_variation = match read_u8(r)? {
+        0 => None,
+        1 => Some(MassVariation::Linear(read_dbig(r)?)),
+        _ => return Err(invalid_data("invalid mass variation tag")),
+    };
+    let radius = read_dbig(r)?;
+    let update_interval = read_option_dbig(r)?;
+    let visual = match read_u8(r)? {
+        0 => None,
+        1 => Some(VisualHints {
+            color: match read_u8(r)? {
+                0 => None,
+                1 => Some([read_u8(r)?, read_u8(r)?, read_u8(r)?]),
+                _ => return Err(invalid_data("invalid color tag")),
+            },
+            texture: match read_u8(r)? {
+                0 => None,
+                1 => Some(read_string(r)?),
+                _ => return Err(invalid_data("invalid texture tag")),
+            },
+            emissive: read_u8(r)? != 0,
+        }),
+        _ => return Err(invalid_data("invalid visual hints tag")),
+    };
+    let kind = match read_u8(r)? {
+        0 => None,
+        1 => Some(BodyKind::Star),
+        2 => Some(BodyKind::Planet),
+        3 => Some(BodyKind::DwarfPlanet),
+        4 => Some(BodyKind::Moon),
+        5 => Some(BodyKind::Asteroid),
+        6 => Some(BodyKind::Comet),
+        7 => Some(BodyKind::ArtificialSatellite),
+        _ => return Err(invalid_data("invalid body kind tag")),
+    };
+    let dynamics = match read_u8(r)? {
+        0 => BodyDynamics::Static(StaticBodyDynamics {
+            position: read_vector(r)?,
+        }),
+        1 => BodyDynamics::Orbiting(read_orbit(r)?),
+        2 => BodyDynamics::Formation(FormationBodyDynamics {
+            offset: read_vector(r)?,
+            frame: match read_u8(r)? {
+                0 => FormationFrame::Orbital,
+                1 => FormationFrame::BodyFixed,
+                _ => return Err(invalid_data("invalid formation frame tag")),
+            },
+        }),
+        3 => BodyDynamics::Barycentric(read_orbit(r)?),
+        _ => return Err(invalid_data("invalid dynamics tag")),
+    };
+    Ok(Body {
+        name,
+        rotation_axis,
+        rotation_period,
+        rotation_phase,
+        resonance,
+        nutation,
+        libration,
+        mass,
+        mass_variation,
+        radius,
+        dynamics,
+        visual,
+        kind,
+        update_interval,
+        satellites: vec![],
+    })
+}
+
+fn read_simulated_body<R: Read>(r: &mut R) -> Result<SimulatedBody> {
+    let id = read_i32(r)?;
+    let parent = match read_u8(r)? {
+        0 => None,
+        1 => Some(read_i32(r)?),
+        _ => return Err(invalid_data("invalid parent tag")),
+    };
+    Ok(SimulatedBody {
+        id,
+        parent,
+        body: Arc::new(read_body(r)?),
+        position: read_vector(r)?,
+        relative_position: read_vector(r)?,
+        velocity: read_vector(r)?,
+        orientation: read_matrix(r)?,
+        last_update: read_option_dbig(r)?,
+        sleeping: read_u8(r)? != 0,
+    })
+}
+
+fn read_spacecraft<R: Read>(r: &mut R) -> Result<Spacecraft> {
+    let name = read_string(r)?;
+    let time = read_dbig(r)?;
+    let position = read_vector(r)?;
+    let velocity = read_vector(r)?;
+    let propulsion = match read_u8(r)? {
+        0 => None,
+        1 => Some(Propulsion {
+            dry_mass: read_dbig(r)?,
+            propellant_mass: read_dbig(r)?,
+            thrust: read_dbig(r)?,
+            specific_impulse: read_dbig(r)?,
+        }),
+        _ => return Err(invalid_data("invalid propulsion tag")),
+    };
+    let thrust = match read_u8(r)? {
+        0 => ThrustProfile::Coast,
+        1 => ThrustProfile::Prograde {
+            reference: read_string(r)?,
+            acceleration: read_dbig(r)?,
+        },
+        _ => return Err(invalid_data("invalid thrust tag")),
+    };
+    let primary = match read_u8(r)? {
+        0 => None,
+        1 => Some(read_string(r)?),
+        _ => return Err(invalid_data("invalid primary tag")),
+    };
+    Ok(Spacecraft {
+        name,
+        state: CraftState {
+            time,
+            position,
+            velocity,
+            propulsion,
+        },
+        thrust,
+        primary,
+    })
+}
+
+impl Simulation {
+    /// # Errors
+    ///
+    /// Any error of the writer, and `InvalidInput` if a name or count is too long for the format.
+    pub fn write_snapshot<W: Write>(&self, w: &mut W) -> Result<()> {
+        self.write_state(w, &self.spacecraft)
+    }
+
+    fn write_state<W: Write>(&self, w: &mut W, spacecraft: &[Spacecraft]) -> Result<()> {
+        w.write_all(MAGIC)?;
+        write_u32(w, VERSION)?;
+        write_dbig(w, &self.time)?;
+        write_i32(w, self.id_counter)?;
+        write_u8(
+            w,
+            match self.position_storage {
+                PositionStorage::World => 0,
+                PositionStorage::ParentRelative => 1,
+            },
+        )?;
+        match &self.anchor {
+            None => write_u8(w, 0)?,
+            Some(Anchor::Body(id)) => {
+                write_u8(w, 1)?;
+                write_i32(w, *id)?;
+            }
+            Some(Anchor::Point(point)) => {
+                write_u8(w, 2)?;
+                write_vector(w, point)?;
+            }
+        }
+        write_dbig(w, &self.anchor_threshold)?;
+        write_vector(w, &self.origin)?;
+        write_len(w, self.bodies.len())?;
+        for body in &self.bodies {
+            write_simulated_body(w, body)?;
+        }
+        write_len(w, spacecraft.len())?;
+        for spacecraft in spacecraft {
+            write_spacecraft(w, spacecraft)?;
+        }
+        write_len(w, self.bookmarks.len())?;
+        for bookmark in &self.bookmarks {
+            write_string(w, &bookmark.name)?;
+            write_dbig(w, &bookmark.time)?;
+        }
+        match &self.lockstep {
+            None => write_u8(w, 0)?,
+            Some(lockstep) => {
+                write_u8(w, 1)?;
+                write_dbig(w, &lockstep.step)?;
+                let precision = u32::try_from(lockstep.precision)
+                    .map_err(|_| invalid_input("lockstep precision out of range"))?;
+                write_u32(w, precision)?;
+                write_dbig(w, &lockstep.start)?;
+                let tick = i64::try_from(lockstep.tick)
+                    .map_err(|_| invalid_input("lockstep tick out of range"))?;
+                write_i64(w, tick)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// Any error of the reader, and `InvalidData` if it isn't a snapshot of this version or is
+    /// malformed.
+    pub fn read_snapshot<R: Read>(r: &mut R) -> Result<Simulation> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a planetsim snapshot"));
+        }
+        if read_u32(r)? != VERSION {
+            return Err(invalid_data("unsupported snapshot version"));
+        }
+
+        let mut sim = Simulation::new();
+        sim.time = read_dbig(r)?;
+        sim.id_counter = read_i32(r)?;
+        sim.position_storage = match read_u8(r)? {
+            0 => PositionStorage::World,
+            1 => PositionStorage::ParentRelative,
+            _ => return Err(invalid_data("invalid position storage tag")),
+        };
+        sim.anchor = match read_u8(r)? {
+            0 => None,
+            1 => Some(Anchor::Body(read_i32(r)?)),
+            2 => Some(Anchor::Point(read_vector(r)?)),
+            _ => return Err(invalid_data("invalid anchor tag")),
+        };
+        sim.anchor_threshold = read_dbig(r)?;
+        sim.origin = read_vector(r)?;
+        let count = read_u32(r)?;
+        for _ in 0..count {
+            sim.bodies.push(read_simulated_body(r)?);
+        }
+        let count = read_u32(r)?;
+        for _ in 0..count {
+            sim.spacecraft.push(read_spacecraft(r)?);
+        }
+        let count = read_u32(r)?;
+        for _ in 0..count {
+            sim.bookmarks.push(Bookmark {
+                name: read_string(r)?,
+                time: read_dbig(r)?,
+            });
+        }
+        sim.lockstep = match read_u8(r)? {
+            0 => None,
+            1 => Some(Lockstep {
+                step: read_dbig(r)?,
+                precision: usize::try_from(read_u32(r)?)
+                    .map_err(|_| invalid_data("invalid lockstep precision"))?,
+                start: read_dbig(r)?,
+                tick: u64::try_from(read_i64(r)?)
+                    .map_err(|_| invalid_data("invalid lockstep tick"))?,
+            }),
+            _ => return Err(invalid_data("invalid lockstep tag")),
+        };
+        sim.rebuild_index();
+        Ok(sim)
+    }
+
+    /// # Errors
+    ///
+    /// The errors of `write_snapshot` and of creating, syncing or renaming the file.
+    pub fn save_snapshot(&self, path: &Path) -> Result<()> {
+        // written next to the target first, so a crash mid-write never leaves a broken snapshot;
+        // the suffix goes after the whole name so targets differing only in extension don't clash
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+        let result = self
+            .write_snapshot_file(&temporary)
+            .and_then(|()| std::fs::rename(&temporary, path));
+        if result.is_err() {
+            // the snapshot at `path` is untouched, nothing half-written is left next to it
+            let _ = std::fs::remove_file(&temporary);
+        }
+        result
+    }
+
+    // synced before it is renamed into place, or a crash could still leave the rename pointing
+    // at data that never reached the disk
+    fn write_snapshot_file(&self, path: &Path) -> Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_snapshot(&mut w)?;
+        w.into_inner()
+            .map_err(std::io::IntoInnerError::into_error)?
+            .sync_all()
+    }
+
+    /// # Errors
+    ///
+    /// The errors of opening the file and of `read_snapshot`.
+    pub fn resume_from(path: &Path) -> Result<Simulation> {
+        let mut r = BufReader::new(File::open(path)?);
+        Self::read_snapshot(&mut r)
+    }
+
+    // a snapshot is saved during update whenever `interval` of simulated time has passed
+    pub fn enable_checkpointing(&mut self, path: &Path, interval: DBig) {
+        self.checkpointing = Some(Checkpointing {
+            path: path.to_path_buf(),
+            interval,
+            last_checkpoint: None,
+            last_error: None,
+        });
+    }
+
+    pub fn disable_checkpointing(&mut self) {
+        self.checkpointing = None;
+    }
+
+    // errors don't interrupt the simulation, the last one is kept here until the next success
+    pub fn checkpoint_error(&self) -> Option<&Error> {
+        self.checkpointing
+            .as_ref()
+            .and_then(|checkpointing| checkpointing.last_error.as_ref())
+    }
+
+    pub(crate) fn checkpoint_if_due(&mut self) {
+        let Some(checkpointing) = &self.checkpointing else {
+            return;
+        };
+        let due = match &checkpointing.last_checkpoint {
+            None => true,
+            Some(last) => &self.time - last >= checkpointing.interval,
+        };
+        if !due {
+            return;
+        }
+        let result = self.save_snapshot(&checkpointing.path);
+        let time = self.time.clone();
+        let checkpointing = self.checkpointing.as_mut().unwrap();
+        match result {
+            Ok(()) => {
+                checkpointing.last_checkpoint = Some(time);
+                checkpointing.last_error = None;
+            }
+            Err(error) => checkpointing.last_error = Some(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::sin_cos::f64_to_dbig;
+    use crate::tests::prepare_sim;
+    use dashu_float::DBig;
+
+    #[test]
+    fn dbig_roundtrip_keeps_precision() {
+        let values = [
+            DBig::from_str("1000").unwrap().with_precision(76).value(),
+            DBig::from_str("-0.0000000000667408").unwrap(),
+            DBig::from(384_400_000),
+            DBig::ZERO,
+        ];
+        for v in values {
+            let mut buf: Vec<u8> = vec![];
+            write_dbig(&mut buf, &v).unwrap();
+            let read = read_dbig(&mut buf.as_slice()).unwrap();
+            assert_eq!(read, v);
+            assert_eq!(read.precision(), v.precision());
+        }
+    }
+
+    #[test]
+    fn broken_lengths_are_rejected() {
+        // a string claiming 4 GB with only a few bytes behind it
+        let mut buf: Vec<u8> = vec![];
+        write_u32(&mut buf, u32::MAX).unwrap();
+        buf.extend_from_slice(b"1000");
+        let error = read_dbig(&mut buf.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "string longer than the data");
+
+        let mut buf: Vec<u8> = vec![];
+        write_dbig(&mut buf, &DBig::from(1000)).unwrap();
+        // the precision is the last field
+        let at = buf.len() - 8;
+        buf[at..].copy_from_slice(&(-1i64).to_le_bytes());
+        let error = read_dbig(&mut buf.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "invalid precision");
+    }
+
+    #[test]
+    fn snapshot_roundtrip_works() {
+        let mut sim = prepare_sim();
+        sim.update(&f64_to_dbig(123_123.0));
+
+        let mut buf: Vec<u8> = vec![];
+        sim.write_snapshot(&mut buf).unwrap();
+        let mut resumed = Simulation::read_snapshot(&mut buf.as_slice()).unwrap();
+        assert_eq!(resumed.time(), sim.time());
+        assert_eq!(
+            resumed.get_body("moon").unwrap().position.x,
+            sim.get_body("moon").unwrap().position.x
+        );
+
+        sim.update(&f64_to_dbig(223_123.0));
+        resumed.update(&f64_to_dbig(223_123.0));
+        assert_eq!(
+            resumed.get_body("moon").unwrap().position.z,
+            sim.get_body("moon").unwrap().position.z
+        );
+    }
+
+    // per process, so concurrent test runs don't share files
+    fn temporary_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("planetsim_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn checkpointing_works() {
+        let path = temporary_path("checkpointing_works.bin");
+        let mut sim = prepare_sim();
+        sim.enable_checkpointing(&path, DBig::from(3600));
+        sim.update(&f64_to_dbig(123_123.0));
+        sim.update(&f64_to_dbig(123_124.0));
+        assert!(sim.checkpoint_error().is_none());
+
+        let resumed = Simulation::resume_from(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(resumed.time(), &f64_to_dbig(123_123.0));
+        assert_eq!(resumed.bodies.len(), 3);
+    }
+
+    #[test]
+    fn save_snapshot_keeps_neighbouring_files() {
+        // with_extension would have written both of these through neighbours.tmp
+        let first = temporary_path("neighbours.a");
+        let second = temporary_path("neighbours.b");
+        let other = temporary_path("neighbours.tmp");
+        std::fs::write(&other, b"unrelated").unwrap();
+        let sim = prepare_sim();
+        sim.save_snapshot(&first).unwrap();
+        sim.save_snapshot(&second).unwrap();
+        assert_eq!(std::fs::read(&other).unwrap(), b"unrelated");
+        assert!(!temporary_path("neighbours.a.tmp").exists());
+        for path in [&first, &second] {
+            assert_eq!(Simulation::resume_from(path).unwrap().bodies.len(), 3);
+            std::fs::remove_file(path).unwrap();
+        }
+        std::fs::remove_file(&other).unwrap();
+    }
+
+    #[test]
+    fn failed_save_snapshot_leaves_no_temporary_file() {
+        // a directory can't be replaced by the rename
+        let path = temporary_path("failed_save");
+        std::fs::create_dir(&path).unwrap();
+        assert!(prepare_sim().save_snapshot(&path).is_err());
+        assert!(!temporary_path("failed_save.tmp").exists());
+        std::fs::remove_dir(&path).unwrap();
+    }
+}
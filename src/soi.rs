@@ -0,0 +1,238 @@
+use crate::error::SimulationError;
+use crate::propagation::CraftState;
+use crate::simulation::Simulation;
+use crate::spacecraft::Spacecraft;
+use dashu_float::DBig;
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+pub struct SoiTransition {
+    pub spacecraft: String,
+    pub from: String,
+    pub to: String,
+    pub time: DBig,        // refined between the steps
+    pub state: CraftState, // at the crossing, relative to the body entered
+}
+
+pub type SoiCallback = dyn Fn(&SoiTransition) + Send + Sync;
+
+pub(crate) struct SoiTracking {
+    auto_reparent: bool,
+    callback: Arc<SoiCallback>,
+}
+
+impl fmt::Debug for SoiTracking {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SoiTracking({})", self.auto_reparent)
+    }
+}
+
+impl Simulation {
+    // reports every spacecraft crossing from the sphere of influence of one body into another
+    // while stepping, in time order. With `auto_reparent` the primary of the spacecraft follows
+    // the body it is in
+    pub fn enable_soi_tracking(&mut self, auto_reparent: bool, callback: Arc<SoiCallback>) {
+        self.soi_tracking = Some(SoiTracking {
+            auto_reparent,
+            callback,
+        });
+    }
+
+    pub fn disable_soi_tracking(&mut self) {
+        self.soi_tracking = None;
+    }
+
+    /// the spacecraft state relative to its primary
+    ///
+    /// # Errors
+    ///
+    /// `UnknownSpacecraft` if there is no spacecraft of that name, `InvalidDynamics` if the body it
+    /// is around or a parent along the way has no mass.
+    pub fn spacecraft_relative_state(&self, name: &str) -> Result<CraftState, SimulationError> {
+        let spacecraft = self.get_spacecraft(name)?;
+        match &spacecraft.primary {
+            None => Ok(spacecraft.state.clone()),
+            Some(primary) => Ok(self.relative_craft_state(&spacecraft.state, primary)?),
+        }
+    }
+
+    pub(crate) fn relative_craft_state(
+        &self,
+        state: &CraftState,
+        body_name: &str,
+    ) -> Result<CraftState, SimulationError> {
+        let body = self.get_body(body_name)?;
+        Ok(CraftState {
+            position: &state.position - self.world_position(body),
+            velocity: &state.velocity - self.world_velocity(body),
+            ..state.clone()
+        })
+    }
+
+    // names of the bodies each spacecraft is in, empty while tracking is off
+    pub(crate) fn spacecraft_regions(&self) -> Result<Vec<Option<String>>, SimulationError> {
+        if self.soi_tracking.is_none() {
+            return Ok(vec![]);
+        }
+        self.spacecraft
+            .iter()
+            .map(|craft| {
+                let body = self.find_dominant_body(&craft.state.position)?;
+                Ok(body.map(|body| body.body.name.clone()))
+            })
+            .collect()
+    }
+
+    // runs after a spacecraft step from `start`, with the regions and spacecraft from before it
+    pub(crate) fn check_soi_transitions(
+        &mut self,
+        start: &DBig,
+        spacecraft: &[Spacecraft],
+        step: &DBig,
+        regions: Vec<Option<String>>,
+    ) -> Result<(), SimulationError> {
+        let Some(tracking) = &self.soi_tracking else {
+            return Ok(());
+        };
+        let (auto_reparent, callback) = (tracking.auto_reparent, tracking.callback.clone());
+        let mut probe: Option<Simulation> = None;
+        let mut transitions: Vec<(usize, SoiTransition)> = vec![];
+        for (i, from) in regions.into_iter().enumerate() {
+            let Some(from) = from else {
+                continue;
+            };
+            let name = &self.spacecraft[i].name;
+            let now = self.find_dominant_body(&self.spacecraft[i].state.position)?;
+            if now.is_none_or(|body| body.body.name == from) {
+                continue;
+            }
+
+            let probe = probe.get_or_insert_with(|| self.copy_bodies());
+            let moving = Some((spacecraft, step));
+            let outside = |probe: &Simulation| {
+                // the probe carries the same spacecraft
+                let craft = probe.get_spacecraft(name).unwrap();
+                // the regions were found before the step, so the same bodies don't fail here
+                matches!(
+                    probe.find_dominant_body(&craft.state.position),
+                    Ok(Some(body)) if body.body.name != from
+                )
+            };
+            let time = self.refine_crossing(probe, start, moving, outside);
+            self.move_probe(probe, &time, moving);
+            let craft = probe.get_spacecraft(name).unwrap();
+            let Some(to) = probe.find_dominant_body(&craft.state.position)? else {
+                continue;
+            };
+            let to = to.body.name.clone();
+            transitions.push((
+                i,
+                SoiTransition {
+                    spacecraft: name.clone(),
+                    state: probe.relative_craft_state(&craft.state, &to).unwrap(),
+                    from,
+                    to,
+                    time,
+                },
+            ));
+        }
+
+        transitions.sort_by(|a, b| a.1.time.cmp(&b.1.time));
+        for (i, transition) in transitions {
+            if auto_reparent {
+                self.spacecraft[i].primary = Some(transition.to.clone());
+            }
+            callback(&transition);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::au::au_to_meters;
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::propagation::{CraftState, ThrustProfile};
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::soi::SoiTransition;
+    use crate::spacecraft::Spacecraft;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn soi_transitions_work() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let limit = dbig_to_f64(&sim.sphere_of_influence("earth").unwrap().unwrap());
+        let earth = sim.get_body("earth").unwrap();
+        let (earth_position, earth_velocity) =
+            (sim.world_position(earth), sim.world_velocity(earth));
+        // leaving north of the ecliptic, away from the moon
+        sim.add_spacecraft(Spacecraft {
+            name: String::from("probe"),
+            state: CraftState {
+                time: DBig::ZERO,
+                position: &earth_position + DecimalVector3d::from_f64(0.0, limit - 2.5e7, 0.0),
+                velocity: &earth_velocity + DecimalVector3d::from_f64(0.0, 3000.0, 0.0),
+                propulsion: None,
+            },
+            thrust: ThrustProfile::Coast,
+            primary: Some(String::from("earth")),
+        })
+        .unwrap();
+        let transitions: Arc<Mutex<Vec<SoiTransition>>> = Arc::new(Mutex::new(vec![]));
+        let record = {
+            let transitions = transitions.clone();
+            Arc::new(move |transition: &SoiTransition| {
+                transitions.lock().unwrap().push(transition.clone());
+            })
+        };
+        sim.enable_soi_tracking(true, record);
+        sim.step_spacecraft(&DBig::from(20000), &DBig::from(2000))
+            .unwrap();
+
+        let transitions = transitions.lock().unwrap();
+        assert_eq!(transitions.len(), 1);
+        let transition = &transitions[0];
+        assert_eq!(
+            (transition.from.as_str(), transition.to.as_str()),
+            ("earth", "sun")
+        );
+        let time = dbig_to_f64(&transition.time);
+        assert!(time > 7000.0 && time < 9000.0, "{}", time);
+        let au = au_to_meters(DBig::ONE);
+        assert!(approx_eq(
+            &transition.state.position.length(),
+            &au,
+            &(&au * f64_to_dbig(0.01))
+        ));
+
+        // the craft now reports against the sun
+        assert_eq!(
+            sim.get_spacecraft("probe").unwrap().primary.as_deref(),
+            Some("sun")
+        );
+        let relative = sim.spacecraft_relative_state("probe").unwrap();
+        let sun = sim.get_body("sun").unwrap();
+        let probe = sim.get_spacecraft("probe").unwrap();
+        assert!(relative.position.approx_eq(
+            &(&probe.state.position - sim.world_position(sun)),
+            &f64_to_dbig(1e-6)
+        ));
+
+        // the crossing happens where the distance to the earth reaches its sphere of influence
+        let mut check = prepare_sim();
+        check.update(&transition.time);
+        let earth = check.get_body("earth").unwrap();
+        let sun = check.get_body("sun").unwrap();
+        let crossing = &transition.state.position + check.world_position(sun);
+        let reach = crossing.distance_to(&check.world_position(earth));
+        assert!(
+            approx_eq(&reach, &f64_to_dbig(limit), &DBig::from(1000)),
+            "{}",
+            dbig_to_f64(&reach) - limit
+        );
+    }
+}
@@ -0,0 +1,377 @@
+use crate::error::{check_positive, SimulationError};
+use crate::propagation::{CraftState, ThrustProfile};
+use crate::simulation::Simulation;
+use dashu_float::ops::Abs;
+use dashu_float::DBig;
+
+// a craft flying free under the gravity of the bodies instead of on rails, it doesn't pull on
+// them. The thrust profile is followed until it is changed
+#[derive(Debug, Clone)]
+pub struct Spacecraft {
+    pub name: String,
+    pub state: CraftState,
+    pub thrust: ThrustProfile,
+    pub primary: Option<String>, // body the relative state is given against, None for the world
+}
+
+impl Simulation {
+    /// names are unique among the spacecraft, bodies and spacecraft can share them
+    ///
+    /// # Errors
+    ///
+    /// `InvalidDynamics` if the name is taken, `UnknownBody` if its thrust reference or primary
+    /// isn't in the simulation.
+    pub fn add_spacecraft(&mut self, spacecraft: Spacecraft) -> Result<(), SimulationError> {
+        if self.spacecraft.iter().any(|s| s.name == spacecraft.name) {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{}: a spacecraft with this name already exists",
+                spacecraft.name
+            )));
+        }
+        if let ThrustProfile::Prograde { reference, .. } = &spacecraft.thrust {
+            self.get_body(reference)?;
+        }
+        if let Some(primary) = &spacecraft.primary {
+            self.get_body(primary)?;
+        }
+        self.spacecraft.push(spacecraft);
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownSpacecraft` if there is no spacecraft of that name.
+    pub fn remove_spacecraft(&mut self, name: &str) -> Result<Spacecraft, SimulationError> {
+        let index = self
+            .spacecraft
+            .iter()
+            .position(|s| s.name == name)
+            .ok_or_else(|| SimulationError::UnknownSpacecraft(name.to_string()))?;
+        Ok(self.spacecraft.remove(index))
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownSpacecraft` if there is no spacecraft of that name.
+    pub fn get_spacecraft(&self, name: &str) -> Result<&Spacecraft, SimulationError> {
+        self.spacecraft
+            .iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| SimulationError::UnknownSpacecraft(name.to_string()))
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownSpacecraft` if there is no spacecraft of that name.
+    pub fn get_spacecraft_mut(&mut self, name: &str) -> Result<&mut Spacecraft, SimulationError> {
+        self.spacecraft
+            .iter_mut()
+            .find(|s| s.name == name)
+            .ok_or_else(|| SimulationError::UnknownSpacecraft(name.to_string()))
+    }
+
+    pub fn spacecraft(&self) -> &[Spacecraft] {
+        &self.spacecraft
+    }
+
+    /// propagates every spacecraft from its own state time to `time`, with the bodies updated
+    /// along every `max_step` so the triggers see the motion of both; stepping backwards only
+    /// updates the bodies and leaves the spacecraft where they are
+    ///
+    /// # Errors
+    ///
+    /// `InvalidArgument` if `max_step` isn't positive, `UnknownBody` if a prograde thrust refers to
+    /// a body that isn't in the simulation, `InvalidDynamics` if a body a craft is around has a
+    /// parent without mass.
+    pub fn step_spacecraft(&mut self, time: &DBig, max_step: &DBig) -> Result<(), SimulationError> {
+        self.step_spacecraft_guarded(time, max_step, None)
+    }
+
+    /// `step_spacecraft` for large time warps: every step is shortened so no craft can cover the
+    /// gap to the nearest surface, atmosphere interface or sphere of influence boundary within
+    /// it, down to `min_step`. Events then land within a short step instead of being jumped over
+    ///
+    /// # Errors
+    ///
+    /// The errors of `step_spacecraft`, and `InvalidArgument` if `min_step` isn't positive.
+    pub fn warp_spacecraft(
+        &mut self,
+        time: &DBig,
+        max_step: &DBig,
+        min_step: &DBig,
+    ) -> Result<(), SimulationError> {
+        check_positive(min_step, "minimum step")?;
+        self.step_spacecraft_guarded(time, max_step, Some(min_step))
+    }
+
+    fn step_spacecraft_guarded(
+        &mut self,
+        time: &DBig,
+        max_step: &DBig,
+        min_step: Option<&DBig>,
+    ) -> Result<(), SimulationError> {
+        check_positive(max_step, "step")?;
+        if *time <= self.time {
+            self.update(time);
+            return Ok(());
+        }
+        while self.time < *time {
+            let start = self.time.clone();
+            let step = match min_step {
+                None => max_step.clone(),
+                Some(min_step) => self.guarded_step(max_step, min_step)?,
+            };
+            let step = &step;
+            // actions land on a step boundary, so maneuvers happen at their time
+            let mut next = (&start + step).min(time.clone());
+            if let Some(action_time) = self.next_action_time().filter(|t| **t > start) {
+                next = next.min(action_time.clone());
+            }
+            let spacecraft = self.spacecraft.clone();
+            let regions = self.spacecraft_regions()?;
+            let atmosphere = self.atmosphere_regions();
+            let mut states = vec![];
+            for craft in &spacecraft {
+                states.push(self.spacecraft_state_at(craft, &next, step)?);
+            }
+            for (craft, state) in self.spacecraft.iter_mut().zip(states) {
+                craft.state = state;
+            }
+            self.advance_bodies(&next);
+            self.check_triggers(&start, Some((&spacecraft, step)));
+            self.check_soi_transitions(&start, &spacecraft, step, regions)?;
+            self.check_atmosphere_crossings(&start, &spacecraft, step, atmosphere);
+        }
+        Ok(())
+    }
+
+    // the longest step within the limits that no craft can cross a boundary in, at its current
+    // speed relative to the body; a quarter of the time is kept as margin for the acceleration
+    fn guarded_step(&self, max_step: &DBig, min_step: &DBig) -> Result<DBig, SimulationError> {
+        let mut step = max_step.clone();
+        for craft in &self.spacecraft {
+            for body in self.awake_bodies() {
+                let distance = craft.state.position.distance_to(&self.world_position(body));
+                let speed = (&craft.state.velocity - self.world_velocity(body)).length();
+                if speed == DBig::ZERO {
+                    continue;
+                }
+                let mut boundaries = vec![body.body.radius.clone()];
+                if let Some(altitude) = self.atmosphere_interface(&body.body.name) {
+                    boundaries.push(&body.body.radius + altitude);
+                }
+                if let Some(limit) = self.sphere_of_influence(&body.body.name)? {
+                    boundaries.push(limit);
+                }
+                for boundary in boundaries {
+                    let gap = (&distance - boundary).abs();
+                    step = step.min(gap / &speed / DBig::from(4));
+                }
+            }
+        }
+        Ok(step.max(min_step.clone()))
+    }
+
+    // the craft propagated from its state to `time`, or as it is when already past it
+    pub(crate) fn spacecraft_state_at(
+        &self,
+        craft: &Spacecraft,
+        time: &DBig,
+        max_step: &DBig,
+    ) -> Result<CraftState, SimulationError> {
+        let duration = time - &craft.state.time;
+        if duration <= DBig::ZERO {
+            return Ok(craft.state.clone());
+        }
+        let path = self.propagate(&craft.state, &craft.thrust, &duration, max_step)?;
+        Ok(path.into_iter().last().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atmosphere::{AtmosphereCrossing, AtmosphereEvent};
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::propagation::{CraftState, ThrustProfile};
+    use crate::simulation::Simulation;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::spacecraft::Spacecraft;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+    use std::sync::{Arc, Mutex};
+
+    fn circular_start(sim: &Simulation) -> CraftState {
+        let earth = sim.get_body("earth").unwrap();
+        let (earth_position, earth_velocity) =
+            (sim.world_position(earth), sim.world_velocity(earth));
+        let speed = (6.674e-11 * 5.97219e24 / 7e6f64).sqrt();
+        CraftState {
+            time: DBig::ZERO,
+            position: &earth_position + DecimalVector3d::from_f64(7e6, 0.0, 0.0),
+            velocity: &earth_velocity + DecimalVector3d::from_f64(0.0, 0.0, -speed),
+            propulsion: None,
+        }
+    }
+
+    fn probe(state: CraftState) -> Spacecraft {
+        Spacecraft {
+            name: String::from("probe"),
+            state,
+            thrust: ThrustProfile::Coast,
+            primary: None,
+        }
+    }
+
+    #[test]
+    fn spacecraft_registry_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let start = circular_start(&sim);
+        sim.add_spacecraft(probe(start.clone())).unwrap();
+        assert_eq!(
+            sim.add_spacecraft(probe(start)),
+            Err(SimulationError::InvalidDynamics(String::from(
+                "probe: a spacecraft with this name already exists"
+            )))
+        );
+        assert_eq!(
+            sim.get_spacecraft("lander").unwrap_err(),
+            SimulationError::UnknownSpacecraft(String::from("lander"))
+        );
+        assert_eq!(sim.remove_spacecraft("probe").unwrap().name, "probe");
+        assert!(sim.spacecraft().is_empty());
+        assert!(sim.remove_spacecraft("probe").is_err());
+    }
+
+    #[test]
+    fn stepping_follows_propagation() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let start = circular_start(&sim);
+        sim.add_spacecraft(probe(start.clone())).unwrap();
+        // stepping follows the path of propagating the state directly, up to rounding at the steps
+        let duration = DBig::from(600);
+        let step = DBig::from(60);
+        let expected = sim
+            .propagate(&start, &ThrustProfile::Coast, &duration, &step)
+            .unwrap();
+        sim.step_spacecraft(&duration, &step).unwrap();
+        assert_eq!(sim.time, DBig::from(600));
+        let probe = sim.get_spacecraft("probe").unwrap();
+        assert_eq!(probe.state.time, DBig::from(600));
+        let miss = probe.state.position.distance_to(&expected[10].position);
+        assert!(dbig_to_f64(&miss) < 1e-2, "{}", dbig_to_f64(&miss));
+        let earth = sim.get_body("earth").unwrap();
+        let altitude = probe.state.position.distance_to(&sim.world_position(earth));
+        assert!(approx_eq(
+            &altitude,
+            &f64_to_dbig(7e6),
+            &f64_to_dbig(7000.0)
+        ));
+
+        assert_eq!(
+            sim.step_spacecraft(&DBig::from(1200), &DBig::ZERO),
+            Err(SimulationError::InvalidArgument(String::from(
+                "the step has to be positive"
+            )))
+        );
+        assert_eq!(sim.time, DBig::from(600));
+
+        // a reference swapped in after adding is only checked once the craft moves
+        sim.get_spacecraft_mut("probe").unwrap().thrust = ThrustProfile::Prograde {
+            reference: String::from("pluto"),
+            acceleration: DBig::ONE,
+        };
+        assert_eq!(
+            sim.step_spacecraft(&DBig::from(660), &step),
+            Err(SimulationError::UnknownBody(String::from("pluto")))
+        );
+    }
+
+    #[test]
+    fn spacecraft_survive_snapshots() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        sim.add_spacecraft(probe(circular_start(&sim))).unwrap();
+        // the spacecraft survive a snapshot, thrust functions can't be written
+        let mut snapshot: Vec<u8> = vec![];
+        sim.write_snapshot(&mut snapshot).unwrap();
+        let restored = Simulation::read_snapshot(&mut snapshot.as_slice()).unwrap();
+        let copy = restored.get_spacecraft("probe").unwrap();
+        let probe = sim.get_spacecraft("probe").unwrap();
+        assert_eq!(copy.state.position.x, probe.state.position.x);
+        assert_eq!(restored.spacecraft().len(), 1);
+
+        sim.get_spacecraft_mut("probe").unwrap().thrust =
+            ThrustProfile::Function(Arc::new(|_: &Simulation, _: &CraftState| {
+                DecimalVector3d::zero()
+            }));
+        let error = sim.write_snapshot(&mut vec![]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        // searches running on their own copy of the bodies don't mind it
+        sim.step_spacecraft(&DBig::from(60), &DBig::from(60))
+            .unwrap();
+        sim.remove_spacecraft("probe").unwrap();
+        sim.write_snapshot(&mut vec![]).unwrap();
+    }
+
+    #[test]
+    fn warp_stepping_works() {
+        // a fast lunar flyby dipping 50 km into a 100 km interface for under three minutes
+        let flyby = |sim: &mut Simulation| {
+            sim.update(&DBig::ZERO);
+            let moon = sim.get_body("moon").unwrap();
+            let (moon_position, moon_velocity) =
+                (sim.world_position(moon), sim.world_velocity(moon));
+            sim.add_spacecraft(Spacecraft {
+                name: String::from("probe"),
+                state: CraftState {
+                    time: DBig::ZERO,
+                    position: &moon_position + DecimalVector3d::from_f64(1e7, 0.0, 1.9728e6),
+                    velocity: &moon_velocity + DecimalVector3d::from_f64(-5000.0, 0.0, 0.0),
+                    propulsion: None,
+                },
+                thrust: ThrustProfile::Coast,
+                primary: Some(String::from("moon")),
+            })
+            .unwrap();
+            sim.set_atmosphere_interface("moon", DBig::from(100_000))
+                .unwrap();
+            let events: Arc<Mutex<Vec<AtmosphereEvent>>> = Arc::new(Mutex::new(vec![]));
+            let record = {
+                let events = events.clone();
+                Arc::new(move |event: &AtmosphereEvent| events.lock().unwrap().push(event.clone()))
+            };
+            sim.set_atmosphere_callback(Some(record));
+            events
+        };
+
+        // hour long steps start and end outside, the pass is jumped over
+        let mut sim = prepare_sim();
+        let events = flyby(&mut sim);
+        sim.step_spacecraft(&DBig::from(7200), &DBig::from(3600))
+            .unwrap();
+        assert!(events.lock().unwrap().is_empty());
+
+        let mut sim = prepare_sim();
+        let events = flyby(&mut sim);
+        sim.warp_spacecraft(&DBig::from(7200), &DBig::from(3600), &DBig::from(60))
+            .unwrap();
+        assert_eq!(sim.time(), &DBig::from(7200));
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].crossing, AtmosphereCrossing::Entry);
+        assert_eq!(events[1].crossing, AtmosphereCrossing::Exit);
+        let inside = dbig_to_f64(&(&events[1].time - &events[0].time));
+        assert!(inside > 60.0 && inside < 300.0);
+
+        assert_eq!(
+            sim.warp_spacecraft(&DBig::from(9000), &DBig::from(3600), &DBig::ZERO),
+            Err(SimulationError::InvalidArgument(String::from(
+                "the minimum step has to be positive"
+            )))
+        );
+        assert_eq!(sim.time(), &DBig::from(7200));
+    }
+}
@@ -0,0 +1,84 @@
+use crate::body::BodyDynamics;
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::simulation::Simulation;
+use dashu_float::DBig;
+
+#[derive(Debug, Clone)]
+pub struct SimulationStats {
+    pub body_count: usize,
+    pub hierarchy_depth: usize, // 1 for a system of roots only, 0 for an empty simulation
+    pub total_mass: DBig,       // at the current time
+    pub center_of_mass: DecimalVector3d, // zero for a massless system
+    pub static_count: usize,
+    pub orbiting_count: usize,
+    pub formation_count: usize,
+}
+
+impl Simulation {
+    pub fn stats(&self) -> SimulationStats {
+        let mut hierarchy_depth = 0;
+        let mut total_mass = DBig::ZERO;
+        let mut weighted_position = DecimalVector3d::zero();
+        let mut static_count = 0;
+        let mut orbiting_count = 0;
+        let mut formation_count = 0;
+        for body in &self.bodies {
+            hierarchy_depth = hierarchy_depth.max(self.resolve_hierarchy_up(body).len() + 1);
+            let mass = body.body.mass_at(&self.time);
+            weighted_position = weighted_position + self.world_position(body) * &mass;
+            total_mass += mass;
+            match body.body.dynamics {
+                BodyDynamics::Static(_) => static_count += 1,
+                BodyDynamics::Orbiting(_) | BodyDynamics::Barycentric(_) => orbiting_count += 1,
+                BodyDynamics::Formation(_) => formation_count += 1,
+            }
+        }
+        let center_of_mass = if total_mass == DBig::ZERO {
+            DecimalVector3d::zero()
+        } else {
+            weighted_position / &total_mass
+        };
+
+        SimulationStats {
+            body_count: self.bodies.len(),
+            hierarchy_depth,
+            total_mass,
+            center_of_mass,
+            static_count,
+            orbiting_count,
+            formation_count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simulation::Simulation;
+    use crate::tests::prepare_sim;
+    use dashu_float::DBig;
+
+    #[test]
+    fn stats_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::from(3600));
+        let stats = sim.stats();
+        assert_eq!(stats.body_count, 3);
+        assert_eq!(stats.hierarchy_depth, 3);
+        assert_eq!(stats.static_count, 1);
+        assert_eq!(stats.orbiting_count, 2);
+        assert_eq!(stats.formation_count, 0);
+
+        let mut expected_mass = DBig::ZERO;
+        for body in &sim.bodies {
+            expected_mass += &body.body.mass;
+        }
+        assert_eq!(stats.total_mass, expected_mass);
+        // the sun dominates, so the center of mass is within its radius
+        let sun = sim.world_position(sim.get_body("sun").unwrap());
+        assert!(stats.center_of_mass.distance_to(&sun) < sim.get_body("sun").unwrap().body.radius);
+
+        let empty = Simulation::new().stats();
+        assert_eq!(empty.body_count, 0);
+        assert_eq!(empty.hierarchy_depth, 0);
+    }
+}
@@ -0,0 +1,268 @@
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::phase_angle::wrap_angle;
+use crate::simulation::{SimulatedBody, Simulation};
+use crate::sin_cos::{asin, atan2, cos, sin, PI, PIMUL2};
+use dashu_float::ops::Abs;
+use dashu_float::DBig;
+
+const HOURS_PER_DAY: u32 = 24;
+
+// longitude zero at time zero for a unit rotation axis, world +X, or +Z when the axis leans more
+// towards X than Z, flattened onto the equator
+pub(crate) fn reference_meridian(axis: &DecimalVector3d) -> DecimalVector3d {
+    let reference = if axis.x.clone().abs() <= axis.z.clone().abs() {
+        DecimalVector3d::new(DBig::ONE, DBig::ZERO, DBig::ZERO)
+    } else {
+        DecimalVector3d::new(DBig::ZERO, DBig::ZERO, DBig::ONE)
+    };
+    (&reference - axis * axis.dot(&reference)).normalized()
+}
+
+// world directions [up, east, north] at a geographic location on the body at its current
+// orientation, in radians. The north pole is the rotation axis, longitude zero is the reference
+// meridian turned by the rotation phase and longitudes grow towards the east, the direction a
+// positive rotation period turns the surface
+pub(crate) fn surface_frame(
+    body: &SimulatedBody,
+    latitude: &DBig,
+    longitude: &DBig,
+) -> [DecimalVector3d; 3] {
+    let axis = body.body.rotation_axis.normalized();
+    let meridian = reference_meridian(&axis);
+    let quarter = axis.cross(&meridian);
+
+    let (sin_latitude, cos_latitude) = (sin(latitude.clone(), 32), cos(latitude.clone(), 32));
+    let (sin_longitude, cos_longitude) = (sin(longitude.clone(), 32), cos(longitude.clone(), 32));
+    let outward = &meridian * &cos_longitude + &quarter * &sin_longitude;
+
+    let up = &outward * &cos_latitude + &axis * &sin_latitude;
+    let east = &quarter * &cos_longitude - &meridian * &sin_longitude;
+    let north = &axis * &cos_latitude - &outward * &sin_latitude;
+    [
+        body.orientation.apply(&up),
+        body.orientation.apply(&east),
+        body.orientation.apply(&north),
+    ]
+}
+
+// inverse of the surface frame, (latitude, longitude) in radians of a world direction from the
+// body center at its current orientation, longitude within (-pi, pi]
+pub(crate) fn geographic_coordinates(
+    body: &SimulatedBody,
+    direction: &DecimalVector3d,
+) -> (DBig, DBig) {
+    let [meridian, quarter, axis] = surface_frame(body, &DBig::ZERO, &DBig::ZERO);
+    let direction = direction.normalized();
+    (
+        asin(direction.dot(&axis), 32),
+        atan2(direction.dot(&quarter), direction.dot(&meridian), 32),
+    )
+}
+
+impl Simulation {
+    /// velocity of the surface point at the body's radius, from the rotation alone or, with
+    /// `include_orbital`, the full inertial velocity including the motion of the body itself
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn get_surface_velocity_at(
+        &self,
+        body_name: &str,
+        latitude: &DBig,
+        longitude: &DBig,
+        include_orbital: bool,
+    ) -> Result<DecimalVector3d, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let [up, _, _] = surface_frame(body, latitude, longitude);
+        let velocity = self.get_surface_velocity(body_name, &(up * &body.body.radius))?;
+        Ok(if include_orbital {
+            velocity + self.world_velocity(body)
+        } else {
+            velocity
+        })
+    }
+
+    /// world position and total inertial velocity (rotation and orbital motion) of a point at
+    /// `altitude` meters above the radius, what an object standing there starts with
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn surface_point_state(
+        &self,
+        body_name: &str,
+        latitude: &DBig,
+        longitude: &DBig,
+        altitude: &DBig,
+    ) -> Result<(DecimalVector3d, DecimalVector3d), SimulationError> {
+        let body = self.get_body(body_name)?;
+        let [up, _, _] = surface_frame(body, latitude, longitude);
+        let offset = up * (&body.body.radius + altitude);
+        let velocity = self.get_surface_velocity(body_name, &offset)? + self.world_velocity(body);
+        Ok((self.world_position(body) + offset, velocity))
+    }
+
+    // longitude of the point directly under the star, in radians within (-pi, pi]
+    fn subsolar_longitude(
+        &self,
+        body_name: &str,
+        star_name: &str,
+    ) -> Result<DBig, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let star = self.get_body(star_name)?;
+        let direction = self.world_position(star) - self.world_position(body);
+        Ok(geographic_coordinates(body, &direction).1)
+    }
+
+    /// local solar time at a longitude in hours within [0, 24) of a solar day, noon when the star
+    /// crosses the meridian; on the equator the terminator is at 6 and 18. A retrograde spin turns
+    /// the surface towards the west, so the hours run the other way around in longitude
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body or the star isn't in the simulation.
+    pub fn local_solar_time(
+        &self,
+        body_name: &str,
+        star_name: &str,
+        longitude: &DBig,
+    ) -> Result<DBig, SimulationError> {
+        let mut hour_angle = longitude - self.subsolar_longitude(body_name, star_name)?;
+        if self.get_body(body_name)?.body.spin_period() < DBig::ZERO {
+            hour_angle = -hour_angle;
+        }
+        let day_fraction = wrap_angle(hour_angle + &*PI) / &*PIMUL2;
+        Ok(day_fraction * DBig::from(HOURS_PER_DAY))
+    }
+
+    /// inverse of the local solar time, the longitude in radians within [-pi, pi) where it is
+    /// `hours` right now
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body or the star isn't in the simulation.
+    pub fn solar_time_longitude(
+        &self,
+        body_name: &str,
+        star_name: &str,
+        hours: &DBig,
+    ) -> Result<DBig, SimulationError> {
+        let mut hour_angle = hours * &*PIMUL2 / DBig::from(HOURS_PER_DAY) - &*PI;
+        if self.get_body(body_name)?.body.spin_period() < DBig::ZERO {
+            hour_angle = -hour_angle;
+        }
+        let longitude = self.subsolar_longitude(body_name, star_name)? + hour_angle;
+        Ok(wrap_angle(longitude + &*PI) - &*PI)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simulation::Simulation;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn surface_velocity_at_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::from(3600));
+        let equator = sim
+            .get_surface_velocity_at("earth", &DBig::ZERO, &DBig::ZERO, false)
+            .unwrap();
+        assert!(approx_eq(
+            &equator.length(),
+            &f64_to_dbig(463.31),
+            &f64_to_dbig(0.01)
+        ));
+        let pole = sim
+            .get_surface_velocity_at(
+                "earth",
+                &f64_to_dbig(std::f64::consts::FRAC_PI_2),
+                &DBig::ZERO,
+                false,
+            )
+            .unwrap();
+        assert!(dbig_to_f64(&pole.length()) < 1e-6);
+        let latitude = f64_to_dbig(std::f64::consts::FRAC_PI_3);
+        let velocity = sim
+            .get_surface_velocity_at("earth", &latitude, &DBig::ZERO, false)
+            .unwrap();
+        assert!(approx_eq(
+            &velocity.length(),
+            &f64_to_dbig(463.31 / 2.0),
+            &f64_to_dbig(0.01)
+        ));
+
+        let inertial = sim
+            .get_surface_velocity_at("earth", &DBig::ZERO, &DBig::ZERO, true)
+            .unwrap();
+        let orbital = sim.world_velocity(sim.get_body("earth").unwrap());
+        let difference = &inertial - &orbital - &equator;
+        assert!(dbig_to_f64(&difference.length()) < 1e-6);
+    }
+
+    #[test]
+    fn local_solar_time_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let hours = |sim: &Simulation, longitude: f64| {
+            let time = sim
+                .local_solar_time("earth", "sun", &f64_to_dbig(longitude))
+                .unwrap();
+            dbig_to_f64(&time)
+        };
+        // noon on the far side, midnight at longitude zero, the evening terminator a quarter east
+        assert!((hours(&sim, std::f64::consts::PI) - 12.0).abs() < 1e-4);
+        assert!(hours(&sim, 0.0) < 1e-4 || hours(&sim, 0.0) > 24.0 - 1e-4);
+        assert!((hours(&sim, -std::f64::consts::FRAC_PI_2) - 18.0).abs() < 1e-4);
+
+        let longitude = sim
+            .solar_time_longitude("earth", "sun", &DBig::from(18))
+            .unwrap();
+        assert!(approx_eq(
+            &longitude,
+            &f64_to_dbig(-std::f64::consts::FRAC_PI_2),
+            &f64_to_dbig(1e-6)
+        ));
+        let longitude = sim
+            .solar_time_longitude("earth", "sun", &DBig::from(9))
+            .unwrap();
+        assert!((hours(&sim, dbig_to_f64(&longitude)) - 9.0).abs() < 1e-4);
+
+        // the sun rises at longitude zero a quarter of a day later
+        sim.update(&DBig::from(6 * 3600));
+        assert!((hours(&sim, 0.0) - 6.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn surface_point_state_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::from(3600));
+        let altitude = DBig::from(1000);
+        let (position, velocity) = sim
+            .surface_point_state("earth", &DBig::ZERO, &DBig::ZERO, &altitude)
+            .unwrap();
+        let earth = sim.get_body("earth").unwrap();
+        let offset = &position - sim.world_position(earth);
+        assert!(approx_eq(
+            &offset.length(),
+            &f64_to_dbig(6_372_000.0),
+            &f64_to_dbig(1e-3)
+        ));
+
+        let rotation = &velocity - sim.world_velocity(earth);
+        assert!(approx_eq(
+            &rotation.length(),
+            &f64_to_dbig(463.31 * 6_372_000.0 / 6_371_000.0),
+            &f64_to_dbig(0.01)
+        ));
+        assert!(approx_eq(
+            &rotation.dot(&offset),
+            &DBig::ZERO,
+            &f64_to_dbig(1e-3)
+        ));
+    }
+}
@@ -1,88 +1,1242 @@
 use crate::au::au_to_meters;
-use crate::body::{Body, BodyDynamics, OrbitingBodyDynamics, StaticBodyDynamics};
+use crate::body::{
+    tilted_axis, Body, BodyDynamics, BodyKind, FormationBodyDynamics, FormationFrame, Libration,
+    MassVariation, Nutation, OrbitingBodyDynamics, SecularDrift, SpinOrbitResonance,
+    StaticBodyDynamics, VisualHints,
+};
+use crate::decimal_matrix_3d::{quat_approx_eq, DecimalMatrix3d};
 use crate::decimal_vector_3d::DecimalVector3d;
-use crate::simulation::Simulation;
-use crate::sin_cos::f64_to_dbig;
+use crate::error::SimulationError;
+use crate::simulation::{Anchor, PositionStorage, Simulation};
+use crate::sin_cos::{approx_eq, f64_to_dbig};
 use dashu_float::DBig;
 use std::str::FromStr;
-use std::time::Instant;
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    fn prepare_sim() -> Simulation {
-        let ten_to_24 = DBig::from_str("1000000000000000000000000").unwrap();
-
-        let moon = Body {
-            name: String::from_str("moon").unwrap(),
-            dynamics: BodyDynamics::Orbiting(OrbitingBodyDynamics {
-                orbit_radius: DBig::from(384400000),
-                orbit_period: DBig::from(27 * 24 * 3600),
-                orbit_plane_normal: DecimalVector3d::from_f64(0.0, 1.0, 0.1).normalized(),
-            }),
-            mass: f64_to_dbig(0.073) * &ten_to_24,
-            satellites: vec![],
-            rotation_axis: DecimalVector3d::from_f64(0.3, 1.0, 0.2).normalized(),
-            rotation_period: DBig::from(27 * 24 * 3600),
-        };
+use std::sync::Arc;
 
-        let earth = Body {
-            name: String::from_str("earth").unwrap(),
-            dynamics: BodyDynamics::Orbiting(OrbitingBodyDynamics {
-                orbit_radius: au_to_meters(f64_to_dbig(1.0)),
-                orbit_period: DBig::from(365 * 24 * 3600),
-                orbit_plane_normal: DecimalVector3d::from_f64(0.1, 1.0, 0.0).normalized(),
-            }),
-            mass: f64_to_dbig(5.97219) * &ten_to_24,
-            satellites: vec![moon.clone()],
-            rotation_axis: DecimalVector3d::from_f64(0.0, 1.0, 0.0).normalized(),
-            rotation_period: DBig::from(24 * 3600),
-        };
+pub(crate) fn prepare_sim() -> Simulation {
+    let ten_to_24 = DBig::from_str("1000000000000000000000000").unwrap();
+
+    let moon = Body {
+        name: String::from_str("moon").unwrap(),
+        dynamics: BodyDynamics::Orbiting(OrbitingBodyDynamics {
+            orbit_radius: DBig::from(384_400_000),
+            orbit_period: DBig::from(27 * 24 * 3600),
+            orbit_plane_normal: DecimalVector3d::from_f64(0.0, 1.0, 0.1).normalized(),
+            mean_anomaly_at_epoch: DBig::ZERO,
+            ellipse: None,
+            drift: None,
+        }),
+        update_interval: None,
+        mass_variation: None,
+        nutation: None,
+        libration: None,
+        mass: f64_to_dbig(0.073) * &ten_to_24,
+        radius: DBig::from(1_737_400),
+        satellites: vec![],
+        rotation_axis: DecimalVector3d::from_f64(0.3, 1.0, 0.2).normalized(),
+        rotation_period: DBig::from(27 * 24 * 3600),
+        rotation_phase: DBig::ZERO,
+        resonance: None,
+        visual: None,
+        kind: Some(BodyKind::Moon),
+    };
+
+    let earth = Body {
+        name: String::from_str("earth").unwrap(),
+        dynamics: BodyDynamics::Orbiting(OrbitingBodyDynamics {
+            orbit_radius: au_to_meters(f64_to_dbig(1.0)),
+            orbit_period: DBig::from(365 * 24 * 3600),
+            orbit_plane_normal: DecimalVector3d::from_f64(0.1, 1.0, 0.0).normalized(),
+            mean_anomaly_at_epoch: DBig::ZERO,
+            ellipse: None,
+            drift: None,
+        }),
+        update_interval: None,
+        mass_variation: None,
+        nutation: None,
+        libration: None,
+        mass: f64_to_dbig(5.97219) * &ten_to_24,
+        radius: DBig::from(6_371_000),
+        satellites: vec![moon],
+        rotation_axis: DecimalVector3d::from_f64(0.0, 1.0, 0.0).normalized(),
+        rotation_period: DBig::from(24 * 3600),
+        rotation_phase: DBig::ZERO,
+        resonance: None,
+        visual: None,
+        kind: Some(BodyKind::Planet),
+    };
+
+    let sun = Body {
+        name: String::from_str("sun").unwrap(),
+        dynamics: BodyDynamics::Static(StaticBodyDynamics {
+            position: DecimalVector3d::from_str(
+                "64959787070023434667",
+                "23454569021239234304",
+                "29349283489",
+            )
+            .unwrap(),
+        }),
+        update_interval: None,
+        mass_variation: None,
+        nutation: None,
+        libration: None,
+        mass: f64_to_dbig(1_988_470.0) * &ten_to_24,
+        radius: DBig::from(696_340_000),
+        satellites: vec![earth],
+        rotation_axis: DecimalVector3d::from_f64(0.0, 1.0, 0.0).normalized(),
+        rotation_period: DBig::from(7 * 24 * 3600),
+        rotation_phase: DBig::ZERO,
+        resonance: None,
+        visual: None,
+        kind: Some(BodyKind::Star),
+    };
+
+    let mut sim = Simulation::new();
+    sim.add_hierarchy(sun, None).unwrap();
+    sim
+}
+
+pub(crate) fn dbig_to_f64(v: &DBig) -> f64 {
+    f64::from_str(v.to_string().as_str()).unwrap()
+}
+
+#[test]
+fn gravity_flux_works() {
+    let mut sim = prepare_sim();
+    sim.update(&f64_to_dbig(123_123.0));
+    let earth_now = sim.get_body("earth").unwrap();
+    let surface = DecimalVector3d::new(earth_now.body.radius.clone(), DBig::ZERO, DBig::ZERO);
+    let flux = sim
+        .calculate_gravity_flux(&(&earth_now.position + surface))
+        .unwrap();
+    // println!("flux is {}", flux.length());
+    assert!(approx_eq(
+        &flux.length(),
+        &f64_to_dbig(9.82),
+        &f64_to_dbig(0.01)
+    ));
+}
+
+#[test]
+fn surface_velocity_works() {
+    let sim = prepare_sim();
+    let radius = sim.get_body("earth").unwrap().body.radius.clone();
+    let surf_vel = sim
+        .get_surface_velocity(
+            "earth",
+            &DecimalVector3d::new(radius, DBig::ZERO, DBig::ZERO),
+        )
+        .unwrap();
+    // println!("surf_vel is {}", surf_vel.length());
+    assert!(approx_eq(
+        &surf_vel.length(),
+        &f64_to_dbig(463.31),
+        &f64_to_dbig(0.01)
+    ));
+}
+
+#[test]
+fn body_mut_works() {
+    let mut sim = prepare_sim();
+    sim.get_body_mut("earth").unwrap().rotation_period = DBig::from(48 * 3600);
+    let radius = sim.get_body("earth").unwrap().body.radius.clone();
+    let surf_vel = sim
+        .get_surface_velocity(
+            "earth",
+            &DecimalVector3d::new(radius, DBig::ZERO, DBig::ZERO),
+        )
+        .unwrap();
+    assert!(approx_eq(
+        &surf_vel.length(),
+        &f64_to_dbig(231.65),
+        &f64_to_dbig(0.01)
+    ));
+
+    let moon_id = sim.get_body("moon").unwrap().id();
+    sim.get_body_mut_by_id(moon_id).unwrap().mass = DBig::ZERO;
+    assert_eq!(sim.get_body("moon").unwrap().body.mass, DBig::ZERO);
+}
+
+#[test]
+fn update_interval_works() {
+    let mut sim = prepare_sim();
+    sim.get_body_mut("moon").unwrap().update_interval = Some(DBig::from(10 * 24 * 3600));
+    sim.update(&DBig::from(0));
+    let moon_before = sim.get_body("moon").unwrap().position.clone();
+    let earth_before = sim.get_body("earth").unwrap().position.clone();
+
+    sim.update(&DBig::from(3600));
+    assert_eq!(sim.get_body("moon").unwrap().position.x, moon_before.x);
+    assert_ne!(sim.get_body("earth").unwrap().position.x, earth_before.x);
+
+    sim.update(&DBig::from(10 * 24 * 3600));
+    assert_ne!(sim.get_body("moon").unwrap().position.x, moon_before.x);
+}
+
+#[test]
+fn nearest_bodies_works() {
+    let mut sim = prepare_sim();
+    sim.update(&f64_to_dbig(123_123.0));
+    let earth_now = sim.get_body("earth").unwrap();
+    let point = &earth_now.position + DecimalVector3d::from_f64(6_371_000.0, 0.0, 0.0);
+
+    let nearest = sim.nearest_bodies(&point, 2);
+    assert_eq!(nearest.len(), 2);
+    assert_eq!(nearest[0].0.body.name, "earth");
+    assert_eq!(nearest[1].0.body.name, "moon");
+    assert!(approx_eq(
+        &nearest[0].1,
+        &f64_to_dbig(6_371_000.0),
+        &f64_to_dbig(0.01)
+    ));
+
+    let within = sim.bodies_within(&point, &DBig::from(500_000_000));
+    assert_eq!(within.len(), 2);
+    let within = sim.bodies_within(&point, &DBig::from(1_000_000));
+    assert!(within.is_empty());
+}
+
+#[test]
+fn raycast_works() {
+    let mut sim = prepare_sim();
+    sim.update(&f64_to_dbig(123_123.0));
+    let earth_now = sim.get_body("earth").unwrap();
+    let origin = &earth_now.position + DecimalVector3d::from_f64(100_000_000.0, 0.0, 0.0);
+
+    let hit = sim
+        .raycast(&origin, &DecimalVector3d::from_f64(-1.0, 0.0, 0.0))
+        .unwrap();
+    assert_eq!(hit.body.body.name, "earth");
+    assert!(approx_eq(
+        &hit.distance,
+        &f64_to_dbig(93_629_000.0),
+        &f64_to_dbig(0.01)
+    ));
+    let altitude = hit.point.distance_to(&earth_now.position);
+    assert!(approx_eq(
+        &altitude,
+        &f64_to_dbig(6_371_000.0),
+        &f64_to_dbig(0.01)
+    ));
+
+    let miss = sim.raycast(&origin, &DecimalVector3d::from_f64(1.0, 0.0, 0.0));
+    assert!(miss.is_none());
+}
+
+#[test]
+fn anchor_works() {
+    let mut sim = prepare_sim();
+    sim.update(&f64_to_dbig(123_123.0));
+    let earth_id = sim.get_body("earth").unwrap().id();
+    sim.set_anchor(Anchor::Body(earth_id), DBig::from(1_000_000_000))
+        .unwrap();
+
+    let moon_position = sim.get_body("moon").unwrap().position.clone();
+    let exported = sim.export_position(&moon_position);
+    let expected = &moon_position - &sim.get_body("earth").unwrap().position;
+    assert!(approx_eq(
+        &f64_to_dbig(exported[0]),
+        &expected.x,
+        &f64_to_dbig(0.000_001)
+    ));
+    assert!(approx_eq(
+        &f64_to_dbig(exported[1]),
+        &expected.y,
+        &f64_to_dbig(0.000_001)
+    ));
+    assert!(approx_eq(
+        &f64_to_dbig(exported[2]),
+        &expected.z,
+        &f64_to_dbig(0.000_001)
+    ));
+
+    // earth moves less than the threshold in 10 seconds, origin stays
+    let origin_before = sim.origin().clone();
+    sim.update(&f64_to_dbig(123_133.0));
+    assert_eq!(sim.origin().x, origin_before.x);
+
+    // and more than the threshold in a day
+    sim.update(&f64_to_dbig(123_123.0 + 24.0 * 3600.0));
+    assert_eq!(sim.origin().x, sim.get_body("earth").unwrap().position.x);
+}
+
+#[test]
+fn relative_position_works() {
+    let mut sim = prepare_sim();
+    sim.update(&f64_to_dbig(123_123.0));
+
+    let moon_from_earth = sim.relative_position("earth", "moon").unwrap();
+    assert_eq!(
+        moon_from_earth.x,
+        sim.get_body("moon").unwrap().relative_position.x
+    );
+    let earth_from_moon = sim.relative_position("moon", "earth").unwrap();
+    assert_eq!(earth_from_moon.y, -moon_from_earth.y.clone());
+
+    let expected = &sim.get_body("moon").unwrap().position - &sim.get_body("sun").unwrap().position;
+    let moon_from_sun = sim.relative_position("sun", "moon").unwrap();
+    assert!(moon_from_sun.approx_eq(&expected, &f64_to_dbig(0.000_001)));
+}
+
+#[test]
+fn parent_relative_storage_works() {
+    let mut world_sim = prepare_sim();
+    world_sim.update(&f64_to_dbig(123_123.0));
+
+    let mut sim = prepare_sim();
+    sim.set_position_storage(PositionStorage::ParentRelative);
+    sim.update(&f64_to_dbig(123_123.0));
+
+    let moon = sim.get_body("moon").unwrap();
+    assert_eq!(moon.position.x, DBig::ZERO);
+    let expected = &world_sim.get_body("moon").unwrap().position;
+    assert!(sim
+        .world_position(moon)
+        .approx_eq(expected, &f64_to_dbig(0.000_001)));
+    assert_eq!(
+        sim.find_closest_body(expected).unwrap().body.name,
+        world_sim.find_closest_body(expected).unwrap().body.name
+    );
+}
+
+#[test]
+fn mass_variation_works() {
+    let mut sim = prepare_sim();
+    sim.update(&DBig::ZERO);
+    let point = sim.world_position(sim.get_body("sun").unwrap())
+        + DecimalVector3d::from_f64(1e10, 0.0, 0.0);
+    let flux_before = sim.calculate_gravity_flux(&point).unwrap().length();
+
+    // the sun loses half of its mass over a day
+    let sun_mass = sim.get_body("sun").unwrap().body.mass.clone();
+    let rate = -(&sun_mass / DBig::from(2 * 24 * 3600));
+    sim.get_body_mut("sun").unwrap().mass_variation = Some(MassVariation::Linear(rate));
+    sim.update(&DBig::from(24 * 3600));
+    let flux_after = sim.calculate_gravity_flux(&point).unwrap().length();
+    assert!(approx_eq(
+        &(flux_after / flux_before),
+        &f64_to_dbig(0.5),
+        &f64_to_dbig(1e-6)
+    ));
+    assert_eq!(
+        sim.get_body("sun")
+            .unwrap()
+            .body
+            .mass_at(&DBig::from(365 * 24 * 3600)),
+        DBig::ZERO
+    );
+
+    let mut buf: Vec<u8> = vec![];
+    sim.write_snapshot(&mut buf).unwrap();
+    let resumed = Simulation::read_snapshot(&mut buf.as_slice()).unwrap();
+    assert_eq!(
+        resumed
+            .get_body("sun")
+            .unwrap()
+            .body
+            .mass_at(&DBig::from(3600)),
+        sim.get_body("sun").unwrap().body.mass_at(&DBig::from(3600))
+    );
+
+    sim.get_body_mut("moon").unwrap().mass_variation =
+        Some(MassVariation::Function(Arc::new(|time: &DBig| {
+            time * DBig::from(1000)
+        })));
+    assert_eq!(
+        sim.get_body("moon").unwrap().body.mass_at(&DBig::from(2)),
+        DBig::from(2000)
+    );
+    assert!(sim.write_snapshot(&mut vec![]).is_err());
+}
+
+#[test]
+fn secular_drift_works() {
+    let mut sim = prepare_sim();
+    let period = DBig::from(27 * 24 * 3600);
+    let drift = SecularDrift {
+        nodal_regression: DBig::from_str("0.0000001").unwrap(),
+        apsidal_precession: DBig::ZERO,
+        radius_rate: DBig::from_str("-0.01").unwrap(),
+    };
+    if let BodyDynamics::Orbiting(dynamics) = &mut sim.get_body_mut("moon").unwrap().dynamics {
+        dynamics.orbit_plane_normal = DecimalVector3d::from_f64(0.0, 1.0, 0.0);
+        dynamics.drift = Some(drift);
+    }
+
+    // after a full orbit the moon is back on +X, except that the node turned about the earth axis
+    sim.update(&period);
+    let relative = &sim.get_body("moon").unwrap().relative_position;
+    let radius = 384_400_000.0 - 0.01 * 27.0 * 24.0 * 3600.0;
+    let node_angle = 0.000_000_1 * 27.0 * 24.0 * 3600.0;
+    assert!(approx_eq(
+        &relative.length(),
+        &f64_to_dbig(radius),
+        &f64_to_dbig(1e-6)
+    ));
+    assert!(approx_eq(
+        &relative.x,
+        &f64_to_dbig(radius * f64::cos(node_angle)),
+        &f64_to_dbig(1e-3)
+    ));
+    assert!(approx_eq(
+        &relative.z,
+        &f64_to_dbig(-(radius * f64::sin(node_angle))),
+        &f64_to_dbig(1e-3)
+    ));
+
+    let mut buf: Vec<u8> = vec![];
+    sim.write_snapshot(&mut buf).unwrap();
+    let mut resumed = Simulation::read_snapshot(&mut buf.as_slice()).unwrap();
+    resumed.update(&period);
+    assert_eq!(
+        resumed.get_body("moon").unwrap().relative_position.x,
+        sim.get_body("moon").unwrap().relative_position.x
+    );
+}
+
+#[test]
+fn approx_eq_works() {
+    let epsilon = f64_to_dbig(0.001);
+    let a = DecimalVector3d::from_f64(1.0, 2.0, 3.0);
+    assert!(a.approx_eq(&DecimalVector3d::from_f64(1.0005, 2.0, 2.9995), &epsilon));
+    assert!(!a.approx_eq(&DecimalVector3d::from_f64(1.0, 2.002, 3.0), &epsilon));
+    assert!(approx_eq(&DBig::ONE, &f64_to_dbig(1.0001), &epsilon));
+
+    let axis = DecimalVector3d::from_f64(0.0, 1.0, 0.0);
+    let rotation = DecimalMatrix3d::axis_angle(&axis, f64_to_dbig(0.5));
+    let nearly = DecimalMatrix3d::axis_angle(&axis, f64_to_dbig(0.5001));
+    assert!(rotation.approx_eq(&nearly, &epsilon));
+    assert!(!rotation.approx_eq(&DecimalMatrix3d::identity(), &epsilon));
+
+    let q = rotation.as_quat();
+    let negated = [-q[0].clone(), -q[1].clone(), -q[2].clone(), -q[3].clone()];
+    assert!(quat_approx_eq(&q, &negated, &epsilon));
+    let other = DecimalMatrix3d::axis_angle(&axis, f64_to_dbig(1.5)).as_quat();
+    assert!(!quat_approx_eq(&q, &other, &epsilon));
+}
+
+#[test]
+fn zero_orbit_period_is_rejected() {
+    let mut sim = prepare_sim();
+    let mut moon = (*sim.get_body("moon").unwrap().body).clone();
+    if let BodyDynamics::Orbiting(dynamics) = &mut moon.dynamics {
+        dynamics.orbit_period = DBig::ZERO;
+    }
+    let count = sim.bodies.len();
+    assert_eq!(
+        sim.add_hierarchy(moon, None),
+        Err(SimulationError::InvalidDynamics(String::from(
+            "moon: orbit period can't be zero"
+        )))
+    );
+    assert_eq!(sim.bodies.len(), count);
+}
+
+#[test]
+fn simulation_errors_work() {
+    let mut sim = prepare_sim();
+    sim.update(&DBig::ZERO);
+    let unknown = SimulationError::UnknownBody(String::from("pluto"));
+    assert_eq!(sim.get_body("pluto").err(), Some(unknown.clone()));
+    assert_eq!(sim.get_body_mut("pluto").err(), Some(unknown.clone()));
+    assert_eq!(
+        sim.relative_position("earth", "pluto").err(),
+        Some(unknown.clone())
+    );
+    assert_eq!(sim.orbital_speed("pluto").err(), Some(unknown));
+    assert_eq!(
+        sim.get_body_mut_by_id(1000).err(),
+        Some(SimulationError::UnknownBodyId(1000))
+    );
+    assert_eq!(
+        sim.set_anchor(Anchor::Body(1000), DBig::ONE),
+        Err(SimulationError::UnknownBodyId(1000))
+    );
+    let nowhere = DecimalVector3d::zero();
+    assert!(Simulation::new().find_closest_static(&nowhere).is_none());
+    assert!(Simulation::new().find_closest_body(&nowhere).is_none());
+
+    let moon = (*sim.get_body("moon").unwrap().body).clone();
+    assert_eq!(
+        sim.add_hierarchy(moon, Some(1000)),
+        Err(SimulationError::MissingParent(1000))
+    );
+    assert!(matches!(
+        sim.phase_angle("earth", "moon"),
+        Err(SimulationError::InvalidDynamics(_))
+    ));
+    assert_eq!(
+        DecimalVector3d::from_str("1", "two", "3").err(),
+        Some(SimulationError::Parse(String::from("two")))
+    );
+    assert_eq!(
+        SimulationError::UnknownBody(String::from("pluto")).to_string(),
+        "unknown body pluto"
+    );
+}
+
+#[test]
+fn tilted_axis_works() {
+    let obliquity = f64_to_dbig(23.44f64.to_radians());
+    let (sin, cos) = 23.44f64.to_radians().sin_cos();
+    let pole = DecimalVector3d::from_f64(0.0, 1.0, 0.0);
+    let epsilon = f64_to_dbig(1e-9);
+    let axis = tilted_axis(&pole, &obliquity, &DBig::ZERO);
+    assert!(axis.approx_eq(&DecimalVector3d::from_f64(sin, cos, 0.0), &epsilon));
+    // a quarter turn around in the direction of motion
+    let quarter = f64_to_dbig(std::f64::consts::FRAC_PI_2);
+    let axis = tilted_axis(&pole, &obliquity, &quarter);
+    assert!(axis.approx_eq(&DecimalVector3d::from_f64(0.0, cos, -sin), &epsilon));
 
-        let sun = Body {
-            name: String::from_str("sun").unwrap(),
-            dynamics: BodyDynamics::Static(StaticBodyDynamics {
-                position: DecimalVector3d::from_str(
-                    "64959787070023434667",
-                    "23454569021239234304",
-                    "29349283489",
-                ),
-            }),
-            mass: f64_to_dbig(1988470.0) * &ten_to_24,
-            satellites: vec![earth.clone()],
-            rotation_axis: DecimalVector3d::from_f64(0.0, 1.0, 0.0).normalized(),
-            rotation_period: DBig::from(7 * 24 * 3600),
+    let sim = prepare_sim();
+    let BodyDynamics::Orbiting(dynamics) = &sim.get_body("earth").unwrap().body.dynamics else {
+        panic!();
+    };
+    let mut dynamics = dynamics.clone();
+    let normal = dynamics.orbit_plane_normal.clone();
+    let angle_between = |a: &DecimalVector3d, b: &DecimalVector3d| {
+        let a = [dbig_to_f64(&a.x), dbig_to_f64(&a.y), dbig_to_f64(&a.z)];
+        let b = [dbig_to_f64(&b.x), dbig_to_f64(&b.y), dbig_to_f64(&b.z)];
+        let dot = a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+        let length = |v: [f64; 3]| (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+        (dot / length(a) / length(b)).acos()
+    };
+    let axis = dynamics.tilted_axis(&obliquity, &quarter);
+    let angle = angle_between(&axis, &normal);
+    assert!((angle - 23.44f64.to_radians()).abs() < 1e-9);
+    dynamics.orbit_period = -dynamics.orbit_period;
+    let axis = dynamics.tilted_axis(&obliquity, &quarter);
+    let angle = angle_between(&axis, &normal);
+    assert!((angle - (180.0 - 23.44f64).to_radians()).abs() < 1e-9);
+}
+
+#[test]
+fn nutation_works() {
+    let mut sim = prepare_sim();
+    let obliquity = 23.44f64.to_radians();
+    let amplitude = 0.01;
+    let period = 1_000_000;
+    let pole = sim.get_body("earth").unwrap().body.orbit_pole();
+    let earth = sim.get_body_mut("earth").unwrap();
+    earth.rotation_axis = tilted_axis(&pole, &f64_to_dbig(obliquity), &DBig::ZERO);
+    earth.nutation = Some(Nutation {
+        longitude_amplitude: f64_to_dbig(amplitude),
+        obliquity_amplitude: f64_to_dbig(amplitude),
+        period: DBig::from(period),
+    });
+    let to_f64 = |v: &DecimalVector3d| [dbig_to_f64(&v.x), dbig_to_f64(&v.y), dbig_to_f64(&v.z)];
+    let angle = |a: [f64; 3], b: [f64; 3]| {
+        let dot = a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+        let length = |v: [f64; 3]| (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+        (dot / length(a) / length(b)).clamp(-1.0, 1.0).acos()
+    };
+    let mean_axis = to_f64(&sim.get_body("earth").unwrap().body.rotation_axis);
+    let pole = to_f64(&pole);
+
+    // the obliquity term peaks at time zero
+    sim.update(&DBig::ZERO);
+    let earth = sim.get_body("earth").unwrap();
+    let axis = to_f64(&earth.orientation.apply(&earth.body.rotation_axis));
+    assert!((angle(axis, pole) - (obliquity + amplitude)).abs() < 1e-6);
+
+    // a quarter later the axis has turned about the pole instead
+    sim.update(&DBig::from(period / 4));
+    let earth = sim.get_body("earth").unwrap();
+    let axis = to_f64(&earth.orientation.apply(&earth.body.rotation_axis));
+    assert!((angle(axis, pole) - obliquity).abs() < 1e-6);
+    assert!((angle(axis, mean_axis) - amplitude * obliquity.sin()).abs() < 1e-6);
+
+    let mut buf: Vec<u8> = vec![];
+    sim.write_snapshot(&mut buf).unwrap();
+    let resumed = Simulation::read_snapshot(&mut buf.as_slice()).unwrap();
+    let nutation = resumed
+        .get_body("earth")
+        .unwrap()
+        .body
+        .nutation
+        .clone()
+        .unwrap();
+    assert_eq!(nutation.period, DBig::from(period));
+}
+
+#[test]
+fn libration_works() {
+    let mut sim = prepare_sim();
+    let period = 27 * 24 * 3600;
+    let normal = DecimalVector3d::from_f64(0.0, 1.0, 0.0);
+    let moon = sim.get_body_mut("moon").unwrap();
+    if let BodyDynamics::Orbiting(dynamics) = &mut moon.dynamics {
+        dynamics.orbit_plane_normal = normal.clone();
+    }
+    moon.rotation_axis = normal;
+    moon.rotation_period = DBig::from(period);
+    moon.libration = Some(Libration {
+        eccentricity: f64_to_dbig(0.0549),
+    });
+
+    // direction to the earth in the moon's own frame
+    let sub_earth = |sim: &Simulation| {
+        let moon = sim.get_body("moon").unwrap();
+        let direction = -&moon.relative_position;
+        let component = |i: usize| {
+            let [x, y, z] = moon.orientation.data[i].clone();
+            dbig_to_f64(&direction.dot(&DecimalVector3d::new(x, y, z)))
         };
+        component(2).atan2(component(0))
+    };
+    sim.update(&DBig::ZERO);
+    let start = sub_earth(&sim);
+    sim.update(&DBig::from(period / 4));
+    let quarter = sub_earth(&sim);
+    sim.update(&DBig::from(period / 2));
+    let half = sub_earth(&sim);
+    assert!(((quarter - start).abs() - 2.0 * 0.0549).abs() < 1e-4);
+    assert!((half - start).abs() < 1e-6);
+}
+
+#[test]
+fn eccentric_orbit_works() {
+    let mut sim = prepare_sim();
+    let semi_major_axis = 1.5e11f64;
+    let mu = 6.67408e-11 * 1_988_470.0e24;
+    let period = 2.0 * std::f64::consts::PI * (semi_major_axis.powi(3) / mu).sqrt();
+    let mut comet = (*sim.get_body("moon").unwrap().body).clone();
+    comet.name = String::from("comet");
+    comet.dynamics = BodyDynamics::Orbiting(OrbitingBodyDynamics::from_elements(
+        f64_to_dbig(semi_major_axis),
+        f64_to_dbig(0.3),
+        &f64_to_dbig(0.2),
+        &DBig::ONE,
+        f64_to_dbig(0.5),
+        f64_to_dbig(period),
+    ));
+    let sun = sim.get_body("sun").unwrap().id();
+    sim.add_hierarchy(comet.clone(), Some(sun)).unwrap();
+
+    // periapsis at time zero, apoapsis half a period later
+    let distance = |sim: &Simulation| {
+        let offset = sim.relative_position("sun", "comet").unwrap();
+        dbig_to_f64(&offset.length())
+    };
+    sim.update(&DBig::ZERO);
+    assert!((distance(&sim) / (semi_major_axis * 0.7) - 1.0).abs() < 1e-12);
+    sim.update(&f64_to_dbig(period / 2.0));
+    assert!((distance(&sim) / (semi_major_axis * 1.3) - 1.0).abs() < 1e-12);
+    let true_anomaly = sim.true_anomaly("comet").unwrap().unwrap();
+    assert!(approx_eq(
+        &true_anomaly,
+        &f64_to_dbig(std::f64::consts::PI),
+        &f64_to_dbig(1e-9)
+    ));
 
-        let mut sim = Simulation::new();
-        sim.add_hierarchy(sun, None);
-        sim
+    // the state away from the apsides gives back the elements
+    sim.update(&f64_to_dbig(period / 8.0));
+    let elements = sim.osculating_elements("comet").unwrap().unwrap();
+    assert!(approx_eq(
+        &elements.semi_major_axis,
+        &f64_to_dbig(semi_major_axis),
+        &f64_to_dbig(semi_major_axis * 1e-5)
+    ));
+    assert!(approx_eq(
+        &elements.eccentricity,
+        &f64_to_dbig(0.3),
+        &f64_to_dbig(1e-5)
+    ));
+    assert!(approx_eq(
+        &elements.inclination,
+        &f64_to_dbig(0.2),
+        &f64_to_dbig(1e-5)
+    ));
+    assert!(approx_eq(
+        &elements.ascending_node,
+        &DBig::ONE,
+        &f64_to_dbig(1e-5)
+    ));
+    assert!(approx_eq(
+        &elements.argument_of_periapsis,
+        &f64_to_dbig(0.5),
+        &f64_to_dbig(1e-5)
+    ));
+    let true_anomaly = dbig_to_f64(&sim.true_anomaly("comet").unwrap().unwrap());
+    let mean_anomaly = dbig_to_f64(&sim.mean_anomaly("comet").unwrap().unwrap());
+    assert!((mean_anomaly - std::f64::consts::FRAC_PI_4).abs() < 1e-9);
+    assert!(true_anomaly > mean_anomaly);
+
+    let mut snapshot: Vec<u8> = vec![];
+    sim.write_snapshot(&mut snapshot).unwrap();
+    let restored = Simulation::read_snapshot(&mut snapshot.as_slice()).unwrap();
+    let position = |sim: &Simulation| sim.relative_position("sun", "comet").unwrap();
+    assert!(position(&restored).approx_eq(&position(&sim), &f64_to_dbig(1e-3)));
+
+    if let BodyDynamics::Orbiting(dynamics) = &mut comet.dynamics {
+        dynamics.ellipse.as_mut().unwrap().eccentricity = DBig::ONE;
     }
+    assert!(matches!(
+        sim.add_hierarchy(comet, Some(sun)),
+        Err(SimulationError::InvalidDynamics(_))
+    ));
+}
 
-    fn dbig_to_f64(v: &DBig) -> f64 {
-        f64::from_str(v.to_string().as_str()).unwrap()
+#[test]
+fn resonance_works() {
+    let mut sim = prepare_sim();
+    let period = 27 * 24 * 3600;
+    let normal = DecimalVector3d::from_f64(0.0, 1.0, 0.0);
+    let moon = sim.get_body_mut("moon").unwrap();
+    if let BodyDynamics::Orbiting(dynamics) = &mut moon.dynamics {
+        dynamics.orbit_plane_normal = normal.clone();
     }
+    moon.rotation_axis = normal;
+    moon.resonance = Some(SpinOrbitResonance {
+        rotations: 3,
+        orbits: 2,
+    });
+    assert_eq!(moon.spin_period(), DBig::from(period * 2 / 3));
 
-    #[test]
-    fn gravity_flux_works() {
-        let mut sim = prepare_sim();
-        sim.update(f64_to_dbig(123123.0));
-        let earth_now = sim.get_body("earth");
-        let start = Instant::now();
-        let flux = sim.calculate_gravity_flux(
-            &(&earth_now.position + DecimalVector3d::from_f64(6371000.0, 0.0, 0.0)),
+    // the same face looks at the earth every other orbit, the opposite one in between
+    let sub_earth = |sim: &Simulation| {
+        let moon = sim.get_body("moon").unwrap();
+        let direction = -&moon.relative_position;
+        let component = |i: usize| {
+            let [x, y, z] = moon.orientation.data[i].clone();
+            dbig_to_f64(&direction.dot(&DecimalVector3d::new(x, y, z)))
+        };
+        component(2).atan2(component(0))
+    };
+    sim.update(&DBig::ZERO);
+    let start = sub_earth(&sim);
+    sim.update(&DBig::from(period));
+    let one = sub_earth(&sim);
+    sim.update(&DBig::from(2 * period));
+    let two = sub_earth(&sim);
+    assert!(((one - start).abs() - std::f64::consts::PI).abs() < 1e-6);
+    assert!((two - start).abs() < 1e-6);
+
+    // the resonance follows the orbit
+    if let BodyDynamics::Orbiting(dynamics) = &mut sim.get_body_mut("moon").unwrap().dynamics {
+        dynamics.orbit_period = DBig::from(period * 2);
+    }
+    assert_eq!(
+        sim.get_body("moon").unwrap().body.spin_period(),
+        DBig::from(period * 4 / 3)
+    );
+}
+
+#[test]
+fn formation_works() {
+    let mut sim = prepare_sim();
+    let follower = |name: &str, offset: DecimalVector3d, frame: FormationFrame| Body {
+        name: String::from(name),
+        dynamics: BodyDynamics::Formation(FormationBodyDynamics { offset, frame }),
+        update_interval: None,
+        mass_variation: None,
+        nutation: None,
+        libration: None,
+        mass: DBig::from(1000),
+        radius: DBig::from(10),
+        satellites: vec![],
+        rotation_axis: DecimalVector3d::from_f64(0.0, 1.0, 0.0),
+        rotation_period: DBig::from(3600),
+        rotation_phase: DBig::ZERO,
+        resonance: None,
+        visual: None,
+        kind: None,
+    };
+    let earth_id = sim.get_body("earth").unwrap().id();
+    let trailing = DecimalVector3d::from_f64(0.0, -1e9, 0.0);
+    sim.add_hierarchy(
+        follower("trailer", trailing.clone(), FormationFrame::Orbital),
+        Some(earth_id),
+    )
+    .unwrap();
+    let above = DecimalVector3d::from_f64(4.2e7, 0.0, 0.0);
+    sim.add_hierarchy(
+        follower("station", above, FormationFrame::BodyFixed),
+        Some(earth_id),
+    )
+    .unwrap();
+    assert_eq!(sim.stats().formation_count, 2);
+
+    for time in [0, 7 * 24 * 3600 + 1234] {
+        sim.update(&DBig::from(time));
+        // a million kilometers behind the earth on its orbit
+        let earth = sim.get_body("earth").unwrap();
+        let sun = sim.get_body("sun").unwrap();
+        let earth_velocity = sim.world_velocity(earth) - sim.world_velocity(sun);
+        let offset =
+            sim.world_position(sim.get_body("trailer").unwrap()) - sim.world_position(earth);
+        assert!(approx_eq(&offset.length(), &f64_to_dbig(1e9), &DBig::ONE));
+        // the fixture orbit breathes a little, only the tangential part of the velocity counts
+        let radial = (sim.world_position(earth) - sim.world_position(sun)).normalized();
+        let tangential = &earth_velocity - &radial * earth_velocity.dot(&radial);
+        let along = offset.dot(&tangential.normalized());
+        assert!(
+            approx_eq(&along, &f64_to_dbig(-1e9), &DBig::ONE),
+            "{}",
+            along
         );
-        // println!("flux is {}", flux.length());
-        assert!((dbig_to_f64(&flux.length()) - 9.82).abs() < 0.01);
+
+        // hovering over the same spot on the surface
+        let station = sim.world_position(sim.get_body("station").unwrap());
+        let geodetic = sim.world_to_geodetic("earth", &[station]).unwrap();
+        assert!(approx_eq(
+            &geodetic[0].latitude,
+            &DBig::ZERO,
+            &f64_to_dbig(1e-9)
+        ));
+        assert!(approx_eq(
+            &geodetic[0].longitude,
+            &DBig::ZERO,
+            &f64_to_dbig(1e-9)
+        ));
+        assert!(approx_eq(
+            &geodetic[0].altitude,
+            &f64_to_dbig(4.2e7 - 6_371_000.0),
+            &f64_to_dbig(1e-3)
+        ));
     }
 
-    #[test]
-    fn surface_velocity_works() {
-        let mut sim = prepare_sim();
-        let surf_vel =
-            sim.get_surface_velocity("earth", &DecimalVector3d::from_f64(6371000.0, 0.0, 0.0));
-        // println!("surf_vel is {}", surf_vel.length());
-        assert!((dbig_to_f64(&surf_vel.length()) - 463.31).abs() < 0.01);
+    let mut snapshot: Vec<u8> = vec![];
+    sim.write_snapshot(&mut snapshot).unwrap();
+    let mut restored = Simulation::read_snapshot(&mut snapshot.as_slice()).unwrap();
+    restored.update(&DBig::from(3600));
+    sim.update(&DBig::from(3600));
+    let position = |sim: &Simulation| sim.world_position(sim.get_body("trailer").unwrap());
+    assert!(position(&restored).approx_eq(&position(&sim), &f64_to_dbig(1e-6)));
+
+    assert_eq!(
+        sim.add_hierarchy(
+            follower("loner", trailing.clone(), FormationFrame::BodyFixed),
+            None
+        )
+        .unwrap_err(),
+        SimulationError::InvalidDynamics(String::from("loner: formation needs a leader to follow"))
+    );
+    let sun_id = sim.get_body("sun").unwrap().id();
+    assert_eq!(
+        sim.add_hierarchy(
+            follower("lagger", trailing, FormationFrame::Orbital),
+            Some(sun_id)
+        )
+        .unwrap_err(),
+        SimulationError::InvalidDynamics(String::from(
+            "lagger: orbital formation needs an orbiting leader"
+        ))
+    );
+}
+
+#[test]
+fn dominant_body_works() {
+    let mut sim = prepare_sim();
+    sim.update(&DBig::ZERO);
+    let earth_limit = dbig_to_f64(&sim.sphere_of_influence("earth").unwrap().unwrap());
+    let moon_limit = dbig_to_f64(&sim.sphere_of_influence("moon").unwrap().unwrap());
+    assert!((earth_limit - 9.24e8).abs() < 1e7);
+    assert!((moon_limit - 6.6e7).abs() < 1e6);
+    assert!(sim.sphere_of_influence("sun").unwrap().is_none());
+
+    let dominant = |sim: &Simulation, point: &DecimalVector3d| {
+        sim.find_dominant_body(point)
+            .unwrap()
+            .unwrap()
+            .body
+            .name
+            .clone()
+    };
+    let earth = sim.world_position(sim.get_body("earth").unwrap());
+    let moon = sim.world_position(sim.get_body("moon").unwrap());
+    assert_eq!(dominant(&sim, &moon), "moon");
+    assert_eq!(
+        dominant(&sim, &(&earth + DecimalVector3d::from_f64(0.0, 1e8, 0.0))),
+        "earth"
+    );
+    assert_eq!(
+        dominant(&sim, &(&earth + DecimalVector3d::from_f64(0.0, 1e9, 0.0))),
+        "sun"
+    );
+
+    // a light root right next to the earth doesn't take over from the sun's system
+    let pebble = Body {
+        name: String::from("pebble"),
+        dynamics: BodyDynamics::Static(StaticBodyDynamics {
+            position: &earth + DecimalVector3d::from_f64(0.0, 2e9, 0.0),
+        }),
+        update_interval: None,
+        mass_variation: None,
+        nutation: None,
+        libration: None,
+        mass: DBig::from(1000),
+        radius: DBig::from(10),
+        satellites: vec![],
+        rotation_axis: DecimalVector3d::from_f64(0.0, 1.0, 0.0),
+        rotation_period: DBig::from(3600),
+        rotation_phase: DBig::ZERO,
+        resonance: None,
+        visual: None,
+        kind: None,
+    };
+    sim.add_hierarchy(pebble, None).unwrap();
+    sim.update(&DBig::ZERO);
+    let near_pebble = &earth + DecimalVector3d::from_f64(0.0, 1.9e9, 0.0);
+    assert_eq!(
+        sim.find_closest_static(&near_pebble).unwrap().body.name,
+        "pebble"
+    );
+    assert_eq!(dominant(&sim, &near_pebble), "sun");
+    let flux = sim.calculate_gravity_flux(&near_pebble).unwrap();
+    let sun = sim.world_position(sim.get_body("sun").unwrap());
+    assert!(flux.dot(&(&sun - &near_pebble)) > DBig::ZERO);
+}
+
+#[test]
+fn barycentric_dynamics_works() {
+    let body = |name: &str, mass: f64, radius: u32, dynamics: BodyDynamics| Body {
+        name: String::from(name),
+        dynamics,
+        update_interval: None,
+        mass_variation: None,
+        nutation: None,
+        libration: None,
+        mass: f64_to_dbig(mass),
+        radius: DBig::from(radius),
+        satellites: vec![],
+        rotation_axis: DecimalVector3d::from_f64(0.0, 1.0, 0.0),
+        rotation_period: DBig::from(551_856),
+        rotation_phase: DBig::ZERO,
+        resonance: None,
+        visual: None,
+        kind: None,
+    };
+    let charon_orbit = OrbitingBodyDynamics {
+        orbit_radius: DBig::from(19_591_000),
+        orbit_plane_normal: DecimalVector3d::from_f64(0.0, 1.0, 0.0),
+        orbit_period: DBig::from(551_856),
+        mean_anomaly_at_epoch: DBig::ZERO,
+        ellipse: None,
+        drift: None,
+    };
+    let center = DecimalVector3d::from_f64(5.9e12, 0.0, 1.0e11);
+    let mut pluto = body(
+        "pluto",
+        1.303e22,
+        1_188_300,
+        BodyDynamics::Static(StaticBodyDynamics {
+            position: center.clone(),
+        }),
+    );
+    pluto.satellites.push(body(
+        "charon",
+        1.586e21,
+        606_000,
+        BodyDynamics::Barycentric(charon_orbit.clone()),
+    ));
+    let mut sim = Simulation::new();
+    sim.add_hierarchy(pluto, None).unwrap();
+    sim.update(&DBig::from(100_000).with_precision(40).value());
+
+    let pluto = sim.get_body("pluto").unwrap();
+    let charon = sim.get_body("charon").unwrap();
+    let (m1, m2) = (f64_to_dbig(1.303e22), f64_to_dbig(1.586e21));
+    let barycenter =
+        (sim.world_position(pluto) * &m1 + sim.world_position(charon) * &m2) / (&m1 + &m2);
+    assert!(dbig_to_f64(&barycenter.distance_to(&center)) < 1e-3);
+    let separation = sim
+        .world_position(charon)
+        .distance_to(&sim.world_position(pluto));
+    assert!(approx_eq(
+        &separation,
+        &f64_to_dbig(19_591_000.0),
+        &f64_to_dbig(1e-3)
+    ));
+    // pluto swings around a center well outside of itself
+    let wobble = dbig_to_f64(&sim.world_position(pluto).distance_to(&center));
+    assert!((wobble - 19_591_000.0 * 1.586 / 14.616).abs() < 1.0);
+    let momentum = sim.world_velocity(pluto) * &m1 + sim.world_velocity(charon) * &m2;
+    assert!(dbig_to_f64(&(momentum.length() / (&m1 * sim.world_velocity(pluto).length()))) < 1e-3);
+
+    let mut buf = vec![];
+    sim.write_snapshot(&mut buf).unwrap();
+    let resumed = Simulation::read_snapshot(&mut buf.as_slice()).unwrap();
+    assert!(matches!(
+        resumed.get_body("charon").unwrap().body.dynamics,
+        BodyDynamics::Barycentric(_)
+    ));
+
+    let mut root = Simulation::new();
+    let lonely = body(
+        "charon",
+        1.586e21,
+        606_000,
+        BodyDynamics::Barycentric(charon_orbit),
+    );
+    assert!(matches!(
+        root.add_hierarchy(lonely, None),
+        Err(SimulationError::InvalidDynamics(_))
+    ));
+}
+
+#[test]
+fn total_gravity_flux_works() {
+    let mut sim = prepare_sim();
+    sim.update(&DBig::ZERO);
+    let sun_position = sim.world_position(sim.get_body("sun").unwrap());
+    let offset = DecimalVector3d::from_f64(0.0, 0.0, 1e15);
+    let companion = Body {
+        name: String::from("companion"),
+        dynamics: BodyDynamics::Static(StaticBodyDynamics {
+            position: &sun_position + &offset,
+        }),
+        update_interval: None,
+        mass_variation: None,
+        nutation: None,
+        libration: None,
+        mass: f64_to_dbig(1.98847e30),
+        radius: DBig::from(696_340_000),
+        satellites: vec![],
+        rotation_axis: DecimalVector3d::from_f64(0.0, 1.0, 0.0),
+        rotation_period: DBig::from(7 * 24 * 3600),
+        rotation_phase: DBig::ZERO,
+        resonance: None,
+        visual: None,
+        kind: None,
+    };
+    sim.add_hierarchy(companion, None).unwrap();
+    sim.update(&DBig::ZERO);
+
+    let point = &sun_position + DecimalVector3d::from_f64(1e12, 0.0, 0.0);
+    let single = sim.calculate_gravity_flux(&point).unwrap();
+    let total = sim.calculate_total_gravity_flux(&point);
+    let companion_pull = &total - &single;
+    let expected = 6.674e-11 * 1.98847e30 / (1e15f64 * 1e15 + 1e12 * 1e12);
+    assert!(approx_eq(
+        &companion_pull.length(),
+        &f64_to_dbig(expected),
+        &f64_to_dbig(expected * 1e-3)
+    ));
+    // towards the companion
+    assert!(dbig_to_f64(&companion_pull.z) > 0.0);
+}
+
+#[test]
+fn visual_hints_work() {
+    let mut sim = prepare_sim();
+    let earth_visual = VisualHints {
+        color: Some([70, 110, 180]),
+        texture: Some(String::from("planets/earth")),
+        emissive: false,
+    };
+    sim.get_body_mut("earth").unwrap().visual = Some(earth_visual.clone());
+    sim.get_body_mut("sun").unwrap().visual = Some(VisualHints {
+        color: Some([255, 240, 200]),
+        texture: None,
+        emissive: true,
+    });
+
+    let mut buf = vec![];
+    sim.write_snapshot(&mut buf).unwrap();
+    let resumed = Simulation::read_snapshot(&mut buf.as_slice()).unwrap();
+    let earth = resumed.get_body("earth").unwrap();
+    assert_eq!(earth.body.visual, Some(earth_visual));
+    assert!(
+        resumed
+            .get_body("sun")
+            .unwrap()
+            .body
+            .visual
+            .as_ref()
+            .unwrap()
+            .emissive
+    );
+    assert_eq!(resumed.get_body("moon").unwrap().body.visual, None);
+
+    let mut buf: Vec<u8> = vec![];
+    sim.write_czml(
+        &mut buf,
+        "hints",
+        "2000-01-01T12:00:00Z",
+        &DBig::ZERO,
+        &DBig::ZERO,
+        &DBig::ONE,
+    )
+    .unwrap();
+    let czml = String::from_utf8(buf).unwrap();
+    assert!(czml.contains("\"rgba\":[70,110,180,255]"));
+    assert!(czml.contains("\"properties\":{\"emissive\":false,\"texture\":\"planets/earth\"}"));
+    assert!(czml.contains("\"properties\":{\"emissive\":true}"));
+
+    let mut buf: Vec<u8> = vec![];
+    sim.write_celestia_ssc(&mut buf, &DBig::from(2_451_545))
+        .unwrap();
+    let ssc = String::from_utf8(buf).unwrap();
+    assert!(ssc.contains("    Texture \"planets/earth\"\n"));
+    assert!(
+        ssc.contains("    Color [ 0.27450980392156865 0.43137254901960786 0.7058823529411765 ]\n")
+    );
+    assert!(!ssc.contains("Emissive"));
+}
+
+#[test]
+fn gravity_potential_works() {
+    let mut sim = prepare_sim();
+    sim.update(&f64_to_dbig(123_123.0));
+    let earth = sim.get_body("earth").unwrap();
+    let surface = sim.world_position(earth) + DecimalVector3d::from_f64(6_371_000.0, 0.0, 0.0);
+    let potential = sim.calculate_gravity_potential(&surface).unwrap();
+    // the sun's well is about 14 times deeper than the earth's at 1 AU
+    let earth_term = -6.674e-11 * 5.97219e24 / 6_371_000.0;
+    let sun_term = -6.674e-11 * 1.98847e30 / 1.496e11;
+    assert!(approx_eq(
+        &potential,
+        &f64_to_dbig(earth_term + sun_term),
+        &f64_to_dbig((earth_term + sun_term).abs() * 1e-2)
+    ));
+    assert!(potential < DBig::ZERO);
+
+    // escape speed from the earth alone, sqrt(-2 * its share of the potential)
+    let sun = sim.world_position(sim.get_body("sun").unwrap());
+    let moon = sim.world_position(sim.get_body("moon").unwrap());
+    let others = -6.674e-11 * 1.98847e30 / dbig_to_f64(&sun.distance_to(&surface))
+        - 6.674e-11 * 0.073e24 / dbig_to_f64(&moon.distance_to(&surface));
+    let escape = (-2.0 * (dbig_to_f64(&potential) - others)).sqrt();
+    assert!((escape - 11186.0).abs() < 5.0);
+
+    // a single root, every body is in its system
+    let total = sim.calculate_total_gravity_potential(&surface);
+    assert!(approx_eq(&total, &potential, &f64_to_dbig(1e-12)));
+
+    // at the center of the sun only the other bodies count
+    let at_sun = sim.calculate_total_gravity_potential(&sun);
+    let planets = -6.674e-11 * 5.97219e24 / dbig_to_f64(&sun.distance_to(&surface))
+        - 6.674e-11 * 0.073e24 / dbig_to_f64(&sun.distance_to(&moon));
+    assert!(approx_eq(
+        &at_sun,
+        &f64_to_dbig(planets),
+        &f64_to_dbig(planets.abs() * 1e-2)
+    ));
+    let pull = sim.calculate_gravity_flux(&sun).unwrap().length();
+    assert!(dbig_to_f64(&pull) < 1e-7);
+    sim.calculate_tidal_tensor(&sun).unwrap();
+}
+
+#[test]
+fn tidal_tensor_works() {
+    let mut sim = prepare_sim();
+    sim.update(&DBig::ZERO);
+    let earth = sim.world_position(sim.get_body("earth").unwrap());
+    let point = &earth + DecimalVector3d::from_f64(1e7, 0.0, 0.0);
+    let tensor = sim.calculate_tidal_tensor(&point).unwrap();
+    let strength = 6.67408e-11 * 5.97219e24 / 1e21;
+    let value = |i: usize, j: usize| dbig_to_f64(&tensor.data[i][j]);
+    // stretched along the line to the earth, squeezed across it
+    assert!((value(0, 0) / (2.0 * strength) - 1.0).abs() < 1e-4);
+    assert!((value(1, 1) / -strength - 1.0).abs() < 1e-4);
+    assert!((value(2, 2) / -strength - 1.0).abs() < 1e-4);
+    assert!((value(0, 0) + value(1, 1) + value(2, 2)).abs() < strength * 1e-6);
+    for i in 0..3 {
+        for j in 0..3 {
+            assert!((value(i, j) - value(j, i)).abs() < strength * 1e-9);
+        }
+    }
+
+    // the difference in pull across a short offset
+    let offset = DecimalVector3d::from_f64(100.0, 50.0, -20.0);
+    let difference = sim.calculate_gravity_flux(&(&point + &offset)).unwrap()
+        - sim.calculate_gravity_flux(&point).unwrap();
+    // symmetric, so the row-vector product of apply is the same
+    let predicted = tensor.apply(&offset);
+    assert!(
+        dbig_to_f64(&(difference - &predicted).length()) < dbig_to_f64(&predicted.length()) * 1e-4
+    );
+
+    // every body is in the system of the sun here, so both sums cover the same bodies
+    let total = sim.calculate_total_tidal_tensor(&point);
+    assert!(total.approx_eq(&tensor, &DBig::from_str("1e-30").unwrap()));
+}
+
+#[test]
+fn update_around_works() {
+    let mut sim = prepare_sim();
+    sim.enable_history(4).unwrap();
+    sim.update(&DBig::ZERO);
+    let sun = sim.world_position(sim.get_body("sun").unwrap());
+    let earth_before = sim.world_position(sim.get_body("earth").unwrap());
+
+    // the earth and the moon don't reach that far from the earth orbit, only the root is refreshed
+    let refreshed = sim
+        .update_around(&sun, &DBig::from(1000), &DBig::from(3600))
+        .unwrap();
+    assert_eq!(refreshed, vec![String::from("sun")]);
+    // the rest of the bodies are still at 0, so is the simulation and its history
+    assert_eq!(sim.time(), &DBig::ZERO);
+    assert_eq!(sim.history_times(), vec![&DBig::ZERO]);
+    let earth = sim.get_body("earth").unwrap();
+    assert_eq!(
+        sim.world_position(earth).distance_to(&earth_before),
+        DBig::ZERO
+    );
+
+    // near the moon the bodies it is composed from come along, as a full update gives them
+    let moon = sim.world_position(sim.get_body("moon").unwrap());
+    let time = DBig::from(7200);
+    let mut refreshed = sim
+        .update_around(&moon, &DBig::from(1_000_000), &time)
+        .unwrap();
+    refreshed.sort();
+    assert_eq!(refreshed, vec!["earth", "moon", "sun"]);
+    let mut full = prepare_sim();
+    full.update(&time);
+    for name in ["earth", "moon"] {
+        let scoped = sim.world_position(sim.get_body(name).unwrap());
+        let expected = full.world_position(full.get_body(name).unwrap());
+        assert_eq!(scoped.distance_to(&expected), DBig::ZERO);
     }
 }
+
+#[test]
+fn update_around_massless_bodies_works() {
+    let mut sim = prepare_sim();
+    sim.update(&DBig::ZERO);
+    sim.get_body_mut("moon").unwrap().mass = DBig::ZERO;
+    let moon = sim.world_position(sim.get_body("moon").unwrap());
+    let mut refreshed = sim
+        .update_around(&moon, &DBig::from(1_000_000), &DBig::from(60))
+        .unwrap();
+    refreshed.sort();
+    assert_eq!(refreshed, vec!["earth", "moon", "sun"]);
+
+    sim.get_body_mut("sun").unwrap().mass = DBig::ZERO;
+    assert_eq!(
+        sim.update_around(&moon, &DBig::from(1_000_000), &DBig::from(120)),
+        Err(SimulationError::InvalidDynamics(String::from(
+            "earth: the parent has no mass"
+        )))
+    );
+}
+
+#[test]
+fn bodies_of_kind_works() {
+    let mut sim = prepare_sim();
+    sim.update(&DBig::ZERO);
+    let names = |sim: &Simulation, kind: BodyKind| {
+        sim.bodies_of_kind(kind)
+            .map(|body| body.body.name.clone())
+            .collect::<Vec<String>>()
+    };
+    assert_eq!(names(&sim, BodyKind::Star), vec!["sun"]);
+    assert_eq!(names(&sim, BodyKind::Moon), vec!["moon"]);
+    assert!(names(&sim, BodyKind::Comet).is_empty());
+
+    sim.get_body_mut("moon").unwrap().kind = Some(BodyKind::ArtificialSatellite);
+    sim.get_body_mut("earth").unwrap().kind = None;
+    assert!(names(&sim, BodyKind::Planet).is_empty());
+
+    let mut buf: Vec<u8> = vec![];
+    sim.write_snapshot(&mut buf).unwrap();
+    let resumed = Simulation::read_snapshot(&mut buf.as_slice()).unwrap();
+    assert_eq!(names(&resumed, BodyKind::ArtificialSatellite), vec!["moon"]);
+    assert_eq!(resumed.get_body("earth").unwrap().body.kind, None);
+    assert_eq!(
+        resumed.get_body("sun").unwrap().body.kind,
+        Some(BodyKind::Star)
+    );
+}
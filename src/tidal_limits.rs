@@ -0,0 +1,187 @@
+use crate::body::BodyDynamics;
+use crate::error::SimulationError;
+use crate::simulation::{SimulatedBody, Simulation};
+use crate::sin_cos::PI;
+use dashu_float::DBig;
+
+const PRECISION: usize = 32;
+
+// in meters from the primary center, a rigid satellite holds together closer in than a fluid one
+// that deforms under the tides, rings sit inside the fluid limit
+#[derive(Debug, Clone)]
+pub struct RocheLimit {
+    pub rigid: DBig,
+    pub fluid: DBig,
+}
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+impl Simulation {
+    /// Hill sphere, a (1 - e) * (m / 3M)^(1/3); stable satellites stay well inside, about half
+    /// of it for prograde orbits
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation, `InvalidDynamics` if its parent has no
+    /// mass.
+    pub fn hill_radius(&self, body_name: &str) -> Result<Option<DBig>, SimulationError> {
+        self.body_hill_radius(self.get_body(body_name)?)
+    }
+
+    pub(crate) fn body_hill_radius(
+        &self,
+        body: &SimulatedBody,
+    ) -> Result<Option<DBig>, SimulationError> {
+        let Some(parent) = body.parent() else {
+            return Ok(None);
+        };
+        let parent = self
+            .get_body_by_id(parent)
+            .ok_or(SimulationError::MissingParent(parent))?;
+        let mass = lift(&body.body.mass_at(&self.time));
+        let parent_mass = lift(&parent.body.mass_at(&self.time));
+        if parent_mass <= DBig::ZERO {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{}: the parent has no mass",
+                body.body.name
+            )));
+        }
+        // nothing is held by a massless body
+        if mass <= DBig::ZERO {
+            return Ok(Some(DBig::ZERO));
+        }
+        let distance = match &body.body.dynamics {
+            BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics) => {
+                let eccentricity = match &dynamics.ellipse {
+                    Some(ellipse) => lift(&ellipse.eccentricity),
+                    None => DBig::ZERO,
+                };
+                lift(&dynamics.orbit_radius) * (DBig::ONE - eccentricity)
+            }
+            BodyDynamics::Static(_) | BodyDynamics::Formation(_) => self
+                .world_position(body)
+                .distance_to(&self.world_position(parent)),
+        };
+        let ratio = mass / (parent_mass * DBig::from(3));
+        Ok(Some(distance * (ratio.ln() / DBig::from(3)).exp()))
+    }
+
+    /// distances from the primary center where a satellite of `satellite_density` in kg/m³ is
+    /// torn apart by the tides, with the primary density from its mass and radius
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the primary isn't in the simulation, `InvalidDynamics` if it has no radius
+    /// or the satellite density isn't positive.
+    pub fn roche_limit(
+        &self,
+        primary_name: &str,
+        satellite_density: &DBig,
+    ) -> Result<RocheLimit, SimulationError> {
+        let primary = self.get_body(primary_name)?;
+        let radius = lift(&primary.body.radius);
+        let satellite_density = lift(satellite_density);
+        if radius <= DBig::ZERO || satellite_density <= DBig::ZERO {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{primary_name}: the Roche limit needs a radius and a satellite density"
+            )));
+        }
+        let mass = lift(&primary.body.mass_at(&self.time));
+        // a massless primary raises no tides
+        if mass <= DBig::ZERO {
+            return Ok(RocheLimit {
+                rigid: DBig::ZERO,
+                fluid: DBig::ZERO,
+            });
+        }
+        let volume = DBig::from(4) * &*PI * &radius * &radius * &radius / DBig::from(3);
+        let density = mass / volume;
+        let ratio = density / satellite_density;
+        let cube_root = |v: DBig| (v.ln() / DBig::from(3)).exp();
+        // R (2 ρM / ρm)^(1/3) and 2.44 R (ρM / ρm)^(1/3)
+        Ok(RocheLimit {
+            rigid: &radius * cube_root(&ratio * DBig::from(2)),
+            fluid: radius * cube_root(ratio) * DBig::from(244) / DBig::from(100),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::SimulationError;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    #[test]
+    fn hill_radius_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let earth = dbig_to_f64(&sim.hill_radius("earth").unwrap().unwrap());
+        assert!((earth - 1.4966e9).abs() < 1e6);
+        // the moon fits well inside, within the prograde stability limit
+        let moon_orbit = 384_400_000.0;
+        assert!(moon_orbit < earth / 2.0);
+        assert!(sim.hill_radius("sun").unwrap().is_none());
+        assert!(matches!(
+            sim.hill_radius("pluto"),
+            Err(SimulationError::UnknownBody(_))
+        ));
+    }
+
+    #[test]
+    fn roche_limit_works() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let limit = sim.roche_limit("earth", &DBig::from(3344)).unwrap();
+        let earth_density = 5.97219e24 / (4.0 / 3.0 * std::f64::consts::PI * 6_371_000_f64.powi(3));
+        let ratio = earth_density / 3344.0;
+        let rigid = 6_371_000.0 * (2.0 * ratio).cbrt();
+        let fluid = 2.44 * 6_371_000.0 * ratio.cbrt();
+        assert!(approx_eq(
+            &limit.rigid,
+            &f64_to_dbig(rigid),
+            &f64_to_dbig(rigid * 1e-9)
+        ));
+        assert!(approx_eq(
+            &limit.fluid,
+            &f64_to_dbig(fluid),
+            &f64_to_dbig(fluid * 1e-9)
+        ));
+        // the moon is far out of reach, about 18000 km for a fluid moon
+        assert!(dbig_to_f64(&limit.fluid) < 384_400_000.0 / 20.0);
+        assert!(limit.rigid < limit.fluid);
+        // a denser satellite holds together closer in
+        let iron = sim.roche_limit("earth", &DBig::from(7874)).unwrap();
+        assert!(iron.fluid < limit.fluid);
+
+        assert_eq!(
+            sim.roche_limit("earth", &DBig::ZERO).unwrap_err(),
+            SimulationError::InvalidDynamics(String::from(
+                "earth: the Roche limit needs a radius and a satellite density"
+            ))
+        );
+        assert!(matches!(
+            sim.roche_limit("pluto", &DBig::from(3344)),
+            Err(SimulationError::UnknownBody(_))
+        ));
+    }
+
+    #[test]
+    fn massless_bodies_have_no_tidal_limits() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        sim.get_body_mut("moon").unwrap().mass = DBig::ZERO;
+        assert_eq!(sim.hill_radius("moon").unwrap(), Some(DBig::ZERO));
+        let limit = sim.roche_limit("moon", &DBig::from(3344)).unwrap();
+        assert_eq!(limit.rigid, DBig::ZERO);
+        assert_eq!(limit.fluid, DBig::ZERO);
+        sim.get_body_mut("earth").unwrap().mass = DBig::ZERO;
+        assert_eq!(
+            sim.hill_radius("moon").unwrap_err(),
+            SimulationError::InvalidDynamics(String::from("moon: the parent has no mass"))
+        );
+    }
+}
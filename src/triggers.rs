@@ -0,0 +1,390 @@
+use crate::error::SimulationError;
+use crate::simulation::Simulation;
+use crate::spacecraft::Spacecraft;
+use dashu_float::DBig;
+use std::fmt;
+use std::sync::Arc;
+
+const PRECISION: usize = 40;
+const REFINE_ITERATIONS: usize = 32;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+// names are only unique within each kind
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TriggerSubject {
+    Body(String),
+    Spacecraft(String),
+}
+
+// signed distance to the event, evaluated on a simulation updated to the checked time with the
+// spacecraft moved there as well
+pub type TriggerFunction = dyn Fn(&Simulation) -> DBig + Send + Sync;
+
+// a trigger fires when its condition starts to hold, and again after it stopped holding for an
+// update. Distances are in meters, between centers
+#[derive(Clone)]
+pub enum TriggerCondition {
+    AltitudeBelow {
+        subject: TriggerSubject,
+        body: String,
+        altitude: DBig, // above the body radius
+    },
+    DistanceBelow {
+        subject: TriggerSubject,
+        body: String,
+        distance: DBig,
+    },
+    EnteringSphereOfInfluence {
+        subject: TriggerSubject,
+        body: String, // can't be a root
+    },
+    // holds while the function is zero or negative
+    Function(Arc<TriggerFunction>),
+}
+
+impl fmt::Debug for TriggerCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TriggerCondition::AltitudeBelow {
+                subject,
+                body,
+                altitude,
+            } => write!(f, "AltitudeBelow({subject:?}, {body}, {altitude})"),
+            TriggerCondition::DistanceBelow {
+                subject,
+                body,
+                distance,
+            } => write!(f, "DistanceBelow({subject:?}, {body}, {distance})"),
+            TriggerCondition::EnteringSphereOfInfluence { subject, body } => {
+                write!(f, "EnteringSphereOfInfluence({subject:?}, {body})")
+            }
+            TriggerCondition::Function(_) => write!(f, "Function"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TriggerEvent {
+    pub trigger: String,
+    pub time: DBig, // when the condition started to hold, refined between the updates
+}
+
+pub type TriggerCallback = dyn Fn(&TriggerEvent) + Send + Sync;
+
+pub(crate) struct Trigger {
+    name: String,
+    condition: TriggerCondition,
+    callback: Arc<TriggerCallback>,
+    value: Option<DBig>, // at the last update, None while the condition can't be evaluated
+}
+
+impl fmt::Debug for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Trigger({}, {:?})", self.name, self.condition)
+    }
+}
+
+impl Simulation {
+    /// the condition is checked on every update and spacecraft step, the callback gets the
+    /// refined time of each crossing in time order. Only the states at the updates are sampled, a
+    /// condition that starts and stops holding between two of them is missed
+    ///
+    /// # Errors
+    ///
+    /// `InvalidDynamics` if the name is taken, and the errors of `trigger_value`.
+    pub fn add_trigger(
+        &mut self,
+        name: &str,
+        condition: TriggerCondition,
+        callback: Arc<TriggerCallback>,
+    ) -> Result<(), SimulationError> {
+        if self.triggers.iter().any(|t| t.name == name) {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{name}: a trigger with this name already exists"
+            )));
+        }
+        let value = self.trigger_value(&condition)?;
+        self.triggers.push(Trigger {
+            name: name.to_string(),
+            condition,
+            callback,
+            value: Some(value),
+        });
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownTrigger` if there is no trigger of that name.
+    pub fn remove_trigger(&mut self, name: &str) -> Result<(), SimulationError> {
+        let index = self
+            .triggers
+            .iter()
+            .position(|t| t.name == name)
+            .ok_or_else(|| SimulationError::UnknownTrigger(name.to_string()))?;
+        self.triggers.remove(index);
+        Ok(())
+    }
+
+    /// the condition holds at zero and below, at the current state
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` or `UnknownSpacecraft` if the condition names one that isn't in the
+    /// simulation, `InvalidDynamics` for the sphere of influence of a root or of a body whose
+    /// parent has no mass.
+    pub fn trigger_value(&self, condition: &TriggerCondition) -> Result<DBig, SimulationError> {
+        match condition {
+            TriggerCondition::AltitudeBelow {
+                subject,
+                body,
+                altitude,
+            } => {
+                let radius = lift(&self.get_body(body)?.body.radius);
+                Ok(self.subject_distance(subject, body)? - radius - lift(altitude))
+            }
+            TriggerCondition::DistanceBelow {
+                subject,
+                body,
+                distance,
+            } => Ok(self.subject_distance(subject, body)? - lift(distance)),
+            TriggerCondition::EnteringSphereOfInfluence { subject, body } => {
+                let Some(limit) = self.sphere_of_influence(body)? else {
+                    return Err(SimulationError::InvalidDynamics(format!(
+                        "{body}: roots have no sphere of influence"
+                    )));
+                };
+                Ok(self.subject_distance(subject, body)? - limit)
+            }
+            TriggerCondition::Function(function) => Ok(function(self)),
+        }
+    }
+
+    fn subject_distance(
+        &self,
+        subject: &TriggerSubject,
+        body_name: &str,
+    ) -> Result<DBig, SimulationError> {
+        let position = match subject {
+            TriggerSubject::Body(name) => self.world_position(self.get_body(name)?),
+            TriggerSubject::Spacecraft(name) => self.get_spacecraft(name)?.state.position.clone(),
+        };
+        let center = self.world_position(self.get_body(body_name)?);
+        Ok(lift(&position.distance_to(&center)))
+    }
+
+    // runs after the bodies reached the current time from `start`. `moving` holds the spacecraft
+    // as they were at the start and the step to propagate them with, for updates that moved them
+    pub(crate) fn check_triggers(&mut self, start: &DBig, moving: Option<(&[Spacecraft], &DBig)>) {
+        if self.triggers.is_empty() {
+            return;
+        }
+        let mut probe: Option<Simulation> = None;
+        let mut events: Vec<(DBig, usize)> = vec![];
+        for i in 0..self.triggers.len() {
+            let value = self.trigger_value(&self.triggers[i].condition).ok();
+            let previous = std::mem::replace(&mut self.triggers[i].value, value.clone());
+            let (Some(previous), Some(value)) = (previous, value) else {
+                continue;
+            };
+            if previous > DBig::ZERO && value <= DBig::ZERO {
+                let probe = probe.get_or_insert_with(|| self.copy_bodies());
+                let condition = &self.triggers[i].condition;
+                let time = self.refine_crossing(probe, start, moving, |probe| {
+                    matches!(probe.trigger_value(condition), Ok(value) if value <= DBig::ZERO)
+                });
+                events.push((time, i));
+            }
+        }
+        events.sort_by(|a, b| a.0.cmp(&b.0));
+        for (time, i) in events {
+            let event = TriggerEvent {
+                trigger: self.triggers[i].name.clone(),
+                time,
+            };
+            (self.triggers[i].callback)(&event);
+        }
+    }
+
+    // bisects between `start` and the current time on the probe, a copy of the bodies, for the
+    // earliest time `holds` is true; it has to be false at the start and true now
+    pub(crate) fn refine_crossing<F>(
+        &self,
+        probe: &mut Simulation,
+        start: &DBig,
+        moving: Option<(&[Spacecraft], &DBig)>,
+        holds: F,
+    ) -> DBig
+    where
+        F: Fn(&Simulation) -> bool,
+    {
+        let (mut low, mut high) = (lift(start), lift(&self.time));
+        for _ in 0..REFINE_ITERATIONS {
+            let middle = (&low + &high) / DBig::from(2);
+            self.move_probe(probe, &middle, moving);
+            if holds(probe) {
+                high = middle;
+            } else {
+                low = middle;
+            }
+        }
+        high
+    }
+
+    // the probe with its bodies at `time` and the spacecraft brought along when they are moving
+    pub(crate) fn move_probe(
+        &self,
+        probe: &mut Simulation,
+        time: &DBig,
+        moving: Option<(&[Spacecraft], &DBig)>,
+    ) {
+        probe.update(time);
+        probe.spacecraft = match moving {
+            None => self.spacecraft.clone(),
+            Some((spacecraft, step)) => spacecraft
+                .iter()
+                .map(|craft| Spacecraft {
+                    // stepping already propagated them this far, the references exist
+                    state: self.spacecraft_state_at(craft, time, step).unwrap(),
+                    ..craft.clone()
+                })
+                .collect(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::error::SimulationError;
+    use crate::propagation::{CraftState, ThrustProfile};
+    use crate::simulation::Simulation;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::spacecraft::Spacecraft;
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use crate::triggers::{TriggerCallback, TriggerCondition, TriggerEvent, TriggerSubject};
+    use dashu_float::DBig;
+    use std::sync::{Arc, Mutex};
+
+    fn recorder() -> (Arc<Mutex<Vec<TriggerEvent>>>, Arc<TriggerCallback>) {
+        let events: Arc<Mutex<Vec<TriggerEvent>>> = Arc::new(Mutex::new(vec![]));
+        let record = {
+            let events = events.clone();
+            Arc::new(move |event: &TriggerEvent| events.lock().unwrap().push(event.clone()))
+        };
+        (events, record)
+    }
+
+    #[test]
+    fn function_trigger_fires_between_updates() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let (events, record) = recorder();
+        let deadline =
+            TriggerCondition::Function(Arc::new(|sim: &Simulation| DBig::from(1000) - sim.time()));
+        sim.add_trigger("deadline", deadline, record).unwrap();
+        sim.update(&DBig::from(1500));
+        sim.update(&DBig::from(2000));
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].trigger, "deadline");
+        assert!(approx_eq(
+            &events[0].time,
+            &f64_to_dbig(1000.0),
+            &f64_to_dbig(1e-3)
+        ));
+    }
+
+    #[test]
+    fn trigger_registration_errors() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let (_, record) = recorder();
+        let deadline =
+            TriggerCondition::Function(Arc::new(|sim: &Simulation| DBig::from(1000) - sim.time()));
+        sim.add_trigger("deadline", deadline.clone(), record.clone())
+            .unwrap();
+        assert_eq!(
+            sim.add_trigger("deadline", deadline, record.clone()),
+            Err(SimulationError::InvalidDynamics(String::from(
+                "deadline: a trigger with this name already exists"
+            )))
+        );
+        sim.remove_trigger("deadline").unwrap();
+        assert_eq!(
+            sim.remove_trigger("deadline"),
+            Err(SimulationError::UnknownTrigger(String::from("deadline")))
+        );
+        assert_eq!(
+            sim.add_trigger(
+                "arrival",
+                TriggerCondition::EnteringSphereOfInfluence {
+                    subject: TriggerSubject::Body(String::from("moon")),
+                    body: String::from("sun"),
+                },
+                record,
+            ),
+            Err(SimulationError::InvalidDynamics(String::from(
+                "sun: roots have no sphere of influence"
+            )))
+        );
+    }
+
+    #[test]
+    fn altitude_trigger_fires_for_descending_craft() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        let (events, record) = recorder();
+        // a craft below circular speed falls towards its periapsis
+        let earth = sim.get_body("earth").unwrap();
+        let (earth_position, earth_velocity) =
+            (sim.world_position(earth), sim.world_velocity(earth));
+        let earth_radius = dbig_to_f64(&earth.body.radius);
+        let speed = 0.95 * (6.674e-11 * 5.97219e24 / 7e6f64).sqrt();
+        let start = CraftState {
+            time: DBig::ZERO,
+            position: &earth_position + DecimalVector3d::from_f64(7e6, 0.0, 0.0),
+            velocity: &earth_velocity + DecimalVector3d::from_f64(0.0, 0.0, -speed),
+            propulsion: None,
+        };
+        sim.add_spacecraft(Spacecraft {
+            name: String::from("probe"),
+            state: start.clone(),
+            thrust: ThrustProfile::Coast,
+            primary: None,
+        })
+        .unwrap();
+        let descent = TriggerCondition::AltitudeBelow {
+            subject: TriggerSubject::Spacecraft(String::from("probe")),
+            body: String::from("earth"),
+            altitude: f64_to_dbig(6.9e6 - earth_radius),
+        };
+        sim.add_trigger("descent", descent, record).unwrap();
+        let step = DBig::from(60);
+        sim.step_spacecraft(&DBig::from(1800), &step).unwrap();
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        let time = events[0].time.clone();
+        assert!(time > DBig::ZERO && time < DBig::from(1800));
+
+        let mut check = prepare_sim();
+        check.update(&time);
+        let path = check
+            .propagate(&start, &ThrustProfile::Coast, &time, &step)
+            .unwrap();
+        let earth = check.get_body("earth").unwrap();
+        let radius = path
+            .last()
+            .unwrap()
+            .position
+            .distance_to(&check.world_position(earth));
+        assert!(
+            approx_eq(&radius, &f64_to_dbig(6.9e6), &DBig::ONE),
+            "{}",
+            dbig_to_f64(&radius)
+        );
+    }
+}
@@ -0,0 +1,370 @@
+use crate::body::BodyDynamics;
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::error::SimulationError;
+use crate::kepler::propagate_kepler;
+use crate::simulation::Simulation;
+use crate::sin_cos::PIMUL2;
+use dashu_float::ops::{Abs, SquareRoot};
+use dashu_float::DBig;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+static RELATIVE_STEP: LazyLock<DBig> = LazyLock::new(|| DBig::from_str("0.000001").unwrap());
+const PRECISION: usize = 40;
+const JACOBI_SWEEPS: usize = 16;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+// covariance of the parent-relative position and velocity of a body, known at the epoch and
+// carried along by the state transition matrix of the two-body motion around the parent
+#[derive(Debug, Clone)]
+pub struct UncertaintyTracking {
+    pub body_id: i32,
+    pub epoch: DBig,
+    pub initial: [[DBig; 6]; 6],    // at the epoch
+    pub covariance: [[DBig; 6]; 6], // refreshed on every update
+    state: [DBig; 6],               // parent-relative position and velocity at the epoch
+    mu: DBig,                       // implied by the orbit radius and period
+}
+
+#[derive(Debug, Clone)]
+pub struct UncertaintyEllipsoid {
+    pub center: DecimalVector3d,    // world position of the body
+    pub axes: [DecimalVector3d; 3], // semi-axes at the requested sigma, longest first
+}
+
+fn zero_matrix<const N: usize>() -> [[DBig; N]; N] {
+    std::array::from_fn(|_| std::array::from_fn(|_| DBig::ZERO))
+}
+
+// derivatives of the state after `time` with respect to the state at the epoch, by central
+// differences of the Kepler propagation; linear, so valid while the uncertainty stays small
+fn transition_matrix(mu: &DBig, state: &[DBig; 6], time: &DBig) -> [[DBig; 6]; 6] {
+    let propagate = |s: &[DBig; 6]| {
+        let (position, velocity) = propagate_kepler(
+            mu,
+            &DecimalVector3d::new(s[0].clone(), s[1].clone(), s[2].clone()),
+            &DecimalVector3d::new(s[3].clone(), s[4].clone(), s[5].clone()),
+            time,
+        );
+        [
+            position.x, position.y, position.z, velocity.x, velocity.y, velocity.z,
+        ]
+    };
+    // steps scaled to the position and the velocity, so every column is resolved alike
+    let scale = |range: &[DBig]| {
+        range
+            .iter()
+            .map(|v| v * v)
+            .fold(DBig::ZERO, |sum, v| sum + v)
+            .sqrt()
+            * &*RELATIVE_STEP
+    };
+    let steps = [scale(&state[..3]), scale(&state[3..])];
+    let mut matrix = zero_matrix();
+    for column in 0..6 {
+        let step = &steps[column / 3];
+        let mut plus = state.clone();
+        let mut minus = state.clone();
+        plus[column] += step;
+        minus[column] -= step;
+        let (plus, minus) = (propagate(&plus), propagate(&minus));
+        let twice_step = step * DBig::from(2);
+        for row in 0..6 {
+            matrix[row][column] = (&plus[row] - &minus[row]) / &twice_step;
+        }
+    }
+    matrix
+}
+
+// phi * covariance * phi^T
+fn transform(phi: &[[DBig; 6]; 6], covariance: &[[DBig; 6]; 6]) -> [[DBig; 6]; 6] {
+    let mut left: [[DBig; 6]; 6] = zero_matrix();
+    for i in 0..6 {
+        for j in 0..6 {
+            left[i][j] = (0..6).fold(DBig::ZERO, |sum, k| sum + &phi[i][k] * &covariance[k][j]);
+        }
+    }
+    let mut result = zero_matrix();
+    for i in 0..6 {
+        for j in 0..6 {
+            result[i][j] = (0..6).fold(DBig::ZERO, |sum, k| sum + &left[i][k] * &phi[j][k]);
+        }
+    }
+    result
+}
+
+// eigenvalues and unit eigenvectors of a symmetric matrix by cyclic Jacobi rotations
+#[allow(clippy::many_single_char_names)] // named as in the formulas
+fn symmetric_eigen(matrix: &[[DBig; 3]; 3]) -> [(DBig, DecimalVector3d); 3] {
+    let mut a: [[DBig; 3]; 3] =
+        std::array::from_fn(|i| std::array::from_fn(|j| lift(&matrix[i][j])));
+    let mut v: [[DBig; 3]; 3] = zero_matrix();
+    for (i, row) in v.iter_mut().enumerate() {
+        row[i] = DBig::ONE;
+    }
+    for _ in 0..JACOBI_SWEEPS {
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == DBig::ZERO {
+                continue;
+            }
+            let tau = (&a[q][q] - &a[p][p]) / (DBig::from(2) * &a[p][q]);
+            let t = (DBig::ONE + &tau * &tau).sqrt() + tau.clone().abs();
+            let t = if tau < DBig::ZERO {
+                -DBig::ONE / t
+            } else {
+                DBig::ONE / t
+            };
+            let c = DBig::ONE / (DBig::ONE + &t * &t).sqrt();
+            let s = &t * &c;
+            let rotate = |x: &DBig, y: &DBig| (&c * x - &s * y, &s * x + &c * y);
+            for row in &mut a {
+                (row[p], row[q]) = rotate(&row[p], &row[q]);
+            }
+            let rows: [(DBig, DBig); 3] = std::array::from_fn(|k| rotate(&a[p][k], &a[q][k]));
+            for (k, (x, y)) in rows.into_iter().enumerate() {
+                (a[p][k], a[q][k]) = (x, y);
+            }
+            for row in &mut v {
+                (row[p], row[q]) = rotate(&row[p], &row[q]);
+            }
+        }
+    }
+    std::array::from_fn(|i| {
+        (
+            a[i][i].clone(),
+            DecimalVector3d::new(v[0][i].clone(), v[1][i].clone(), v[2][i].clone()),
+        )
+    })
+}
+
+impl Simulation {
+    fn tracked_covariance(&self, tracking: &UncertaintyTracking) -> [[DBig; 6]; 6] {
+        let time = &self.time - &tracking.epoch;
+        if time == DBig::ZERO {
+            return tracking.initial.clone();
+        }
+        let phi = transition_matrix(&tracking.mu, &tracking.state, &time);
+        transform(&phi, &tracking.initial)
+    }
+
+    /// `covariance` is of the position and velocity relative to the parent at the current time,
+    /// in m^2, m^2/s and m^2/s^2; only bodies orbiting a parent can be tracked
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation, `InvalidDynamics` if it doesn't orbit or
+    /// the covariance isn't symmetric.
+    pub fn enable_uncertainty_tracking(
+        &mut self,
+        body_name: &str,
+        covariance: [[DBig; 6]; 6],
+    ) -> Result<(), SimulationError> {
+        let body = self.get_body(body_name)?;
+        let dynamics = match &body.body.dynamics {
+            BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics) => dynamics,
+            BodyDynamics::Static(_) | BodyDynamics::Formation(_) => {
+                return Err(SimulationError::InvalidDynamics(format!(
+                    "{body_name}: uncertainty needs an orbiting body"
+                )))
+            }
+        };
+        if (0..6).any(|i| (0..i).any(|j| covariance[i][j] != covariance[j][i])) {
+            return Err(SimulationError::InvalidDynamics(format!(
+                "{body_name}: the covariance has to be symmetric"
+            )));
+        }
+        let radius = lift(&dynamics.orbit_radius);
+        let period = lift(&dynamics.orbit_period);
+        let mu = &*PIMUL2 * &*PIMUL2 * &radius * &radius * &radius / (&period * &period);
+        let (position, velocity) = self.get_body_relative_state(&self.time, body);
+        let tracking = UncertaintyTracking {
+            body_id: body.id(),
+            epoch: self.time.clone(),
+            initial: covariance.clone(),
+            covariance,
+            state: [
+                position.x, position.y, position.z, velocity.x, velocity.y, velocity.z,
+            ],
+            mu,
+        };
+        self.uncertainty_tracking
+            .retain(|item| item.body_id != tracking.body_id);
+        self.uncertainty_tracking.push(tracking);
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn disable_uncertainty_tracking(&mut self, body_name: &str) -> Result<(), SimulationError> {
+        let body_id = self.get_body(body_name)?.id();
+        self.uncertainty_tracking
+            .retain(|tracking| tracking.body_id != body_id);
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn state_covariance(
+        &self,
+        body_name: &str,
+    ) -> Result<Option<&[[DBig; 6]; 6]>, SimulationError> {
+        let body_id = self.get_body(body_name)?.id();
+        Ok(self
+            .uncertainty_tracking
+            .iter()
+            .find(|tracking| tracking.body_id == body_id)
+            .map(|tracking| &tracking.covariance))
+    }
+
+    /// the position uncertainty at the current time, `sigma` standard deviations out; None when
+    /// the body isn't tracked
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn uncertainty_ellipsoid(
+        &self,
+        body_name: &str,
+        sigma: &DBig,
+    ) -> Result<Option<UncertaintyEllipsoid>, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let Some(covariance) = self.state_covariance(body_name)? else {
+            return Ok(None);
+        };
+        let block: [[DBig; 3]; 3] =
+            std::array::from_fn(|i| std::array::from_fn(|j| covariance[i][j].clone()));
+        let mut axes = symmetric_eigen(&block);
+        axes.sort_by(|a, b| b.0.cmp(&a.0));
+        let sigma = lift(sigma);
+        Ok(Some(UncertaintyEllipsoid {
+            center: self.world_position(body),
+            // rounding can leave the flat directions of a singular covariance slightly negative
+            axes: axes.map(|(variance, direction)| {
+                direction * (variance.max(DBig::ZERO).sqrt() * &sigma)
+            }),
+        }))
+    }
+
+    pub(crate) fn update_uncertainty_tracking(&mut self) {
+        let mut tracking = std::mem::take(&mut self.uncertainty_tracking);
+        for item in &mut tracking {
+            item.covariance = self.tracked_covariance(item);
+        }
+        self.uncertainty_tracking = tracking;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::SimulationError;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use dashu_float::DBig;
+
+    // 1 km in position and 1 cm/s in velocity along every axis
+    fn covariance() -> [[DBig; 6]; 6] {
+        std::array::from_fn(|i| {
+            std::array::from_fn(|j| match (i == j, i < 3) {
+                (false, _) => DBig::ZERO,
+                (true, true) => DBig::from(1_000_000),
+                (true, false) => f64_to_dbig(0.0001),
+            })
+        })
+    }
+
+    #[test]
+    fn initial_ellipsoid_is_the_covariance() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        sim.enable_uncertainty_tracking("moon", covariance())
+            .unwrap();
+        let ellipsoid = sim
+            .uncertainty_ellipsoid("moon", &DBig::ONE)
+            .unwrap()
+            .unwrap();
+        let moon = sim.get_body("moon").unwrap();
+        assert!(ellipsoid.center.distance_to(&sim.world_position(moon)) == DBig::ZERO);
+        for axis in &ellipsoid.axes {
+            assert!(approx_eq(
+                &axis.length(),
+                &f64_to_dbig(1000.0),
+                &f64_to_dbig(1e-6)
+            ));
+        }
+    }
+
+    #[test]
+    fn uncertainty_grows_along_the_track() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        sim.enable_uncertainty_tracking("moon", covariance())
+            .unwrap();
+        // after a full orbit the spread along the track has grown, across the plane it is back
+        sim.update(&DBig::from(27 * 24 * 3600));
+        let stored = sim.state_covariance("moon").unwrap().unwrap();
+        for (i, row) in stored.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                let (a, b) = (dbig_to_f64(value), dbig_to_f64(&stored[j][i]));
+                assert!((a - b).abs() <= 1e-9 * a.abs().max(1.0));
+            }
+        }
+        let ellipsoid = sim
+            .uncertainty_ellipsoid("moon", &DBig::ONE)
+            .unwrap()
+            .unwrap();
+        let lengths = ellipsoid
+            .axes
+            .clone()
+            .map(|axis| dbig_to_f64(&axis.length()));
+        assert!(lengths[0] >= lengths[1] && lengths[1] >= lengths[2]);
+        assert!(lengths[0] > 50000.0, "{lengths:?}");
+        assert!(lengths[2] < 2000.0, "{lengths:?}");
+        let moon = sim.get_body("moon").unwrap();
+        let along = ellipsoid.axes[0]
+            .normalized()
+            .dot(&moon.velocity.normalized());
+        assert!(dbig_to_f64(&along).abs() > 0.99);
+        let wider = sim
+            .uncertainty_ellipsoid("moon", &DBig::from(3))
+            .unwrap()
+            .unwrap();
+        assert!(approx_eq(
+            &wider.axes[0].length(),
+            &f64_to_dbig(3.0 * lengths[0]),
+            &f64_to_dbig(1e-3)
+        ));
+    }
+
+    #[test]
+    fn uncertainty_tracking_errors() {
+        let mut sim = prepare_sim();
+        sim.update(&DBig::ZERO);
+        assert_eq!(
+            sim.enable_uncertainty_tracking("sun", covariance()),
+            Err(SimulationError::InvalidDynamics(String::from(
+                "sun: uncertainty needs an orbiting body"
+            )))
+        );
+        let mut skewed = covariance();
+        skewed[0][1] = DBig::ONE;
+        assert_eq!(
+            sim.enable_uncertainty_tracking("earth", skewed),
+            Err(SimulationError::InvalidDynamics(String::from(
+                "earth: the covariance has to be symmetric"
+            )))
+        );
+        sim.enable_uncertainty_tracking("moon", covariance())
+            .unwrap();
+        sim.disable_uncertainty_tracking("moon").unwrap();
+        assert!(sim.state_covariance("moon").unwrap().is_none());
+        assert!(sim
+            .uncertainty_ellipsoid("moon", &DBig::ONE)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,74 @@
+use crate::body::BodyDynamics;
+use crate::error::SimulationError;
+use crate::simulation::{Simulation, G_CONSTANT};
+use dashu_float::ops::SquareRoot;
+use dashu_float::DBig;
+
+const PRECISION: usize = 32;
+
+fn lift(v: &DBig) -> DBig {
+    v.clone().with_precision(PRECISION).value()
+}
+
+// speed at distance `radius` on an orbit with the given semi-major axis, v^2 = mu (2/r - 1/a),
+// negative semi-major axes give hyperbolas, speeds below zero energy are clamped to zero
+pub fn vis_viva_speed(mu: &DBig, radius: &DBig, semi_major_axis: &DBig) -> DBig {
+    let radius = lift(radius);
+    let speed_squared = lift(mu) * (DBig::from(2) / &radius - DBig::ONE / lift(semi_major_axis));
+    speed_squared.max(DBig::ZERO).sqrt()
+}
+
+impl Simulation {
+    /// speed around the parent at the current distance from it, the semi-major axis is the orbit
+    /// radius including its drift; None for static bodies and roots
+    ///
+    /// # Errors
+    ///
+    /// `UnknownBody` if the body isn't in the simulation.
+    pub fn orbital_speed(&self, body_name: &str) -> Result<Option<DBig>, SimulationError> {
+        let body = self.get_body(body_name)?;
+        let (BodyDynamics::Orbiting(dynamics) | BodyDynamics::Barycentric(dynamics), Some(parent)) =
+            (&body.body.dynamics, body.parent())
+        else {
+            return Ok(None);
+        };
+        let parent = self
+            .get_body_by_id(parent)
+            .ok_or(SimulationError::UnknownBodyId(parent))?;
+        let mu = &*G_CONSTANT * lift(&parent.body.mass_at(&self.time));
+        let radius = (self.world_position(body) - self.world_position(parent)).length();
+        let mut semi_major_axis = dynamics.orbit_radius.clone();
+        if let Some(drift) = &dynamics.drift {
+            semi_major_axis = (semi_major_axis + &drift.radius_rate * &self.time).max(DBig::ZERO);
+        }
+        Ok(Some(vis_viva_speed(&mu, &radius, &semi_major_axis)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::{dbig_to_f64, prepare_sim};
+    use crate::vis_viva::vis_viva_speed;
+    use dashu_float::DBig;
+
+    #[test]
+    fn vis_viva_works() {
+        let speed = vis_viva_speed(&DBig::from(4), &DBig::ONE, &DBig::ONE);
+        assert!(approx_eq(&speed, &f64_to_dbig(2.0), &f64_to_dbig(1e-12)));
+        // hyperbola, negative semi-major axis
+        let speed = vis_viva_speed(&DBig::ONE, &DBig::ONE, &DBig::from(-1));
+        assert!(approx_eq(
+            &speed,
+            &f64_to_dbig(3.0f64.sqrt()),
+            &f64_to_dbig(1e-12)
+        ));
+
+        let mut sim = prepare_sim();
+        sim.update(&DBig::from(3600));
+        let speed = dbig_to_f64(&sim.orbital_speed("moon").unwrap().unwrap());
+        let circular = (6.67408e-11 * 5.97219e24 / 384_400_000.0_f64).sqrt();
+        assert!((speed - circular).abs() / circular < 1e-6);
+        assert!(sim.orbital_speed("sun").unwrap().is_none());
+    }
+}
@@ -0,0 +1,124 @@
+use crate::decimal_matrix_3d::DecimalMatrix3d;
+use crate::decimal_vector_3d::DecimalVector3d;
+use crate::simulation::{SimulatedBody, Simulation};
+use crate::sin_cos::{asin, cos, sin, PI};
+use dashu_float::ops::{Abs, SquareRoot};
+use dashu_float::DBig;
+use std::ops::Deref;
+
+#[derive(Debug, Clone)]
+pub struct VisibleBody<'a> {
+    pub body: &'a SimulatedBody,
+    pub distance: DBig,
+    pub angular_diameter: DBig, // in radians
+}
+
+impl Simulation {
+    // the observer looks along its local -Z with +Y up, fov is vertical and in radians
+    pub fn visible_bodies(
+        &self,
+        observer_position: &DecimalVector3d,
+        observer_orientation: &DecimalMatrix3d,
+        fov: &DBig,
+        aspect_ratio: &DBig,
+    ) -> Vec<VisibleBody<'_>> {
+        let forward = observer_orientation.apply(&DecimalVector3d::from_f64(0.0, 0.0, -1.0));
+        let up = observer_orientation.apply(&DecimalVector3d::from_f64(0.0, 1.0, 0.0));
+        let right = observer_orientation.apply(&DecimalVector3d::from_f64(1.0, 0.0, 0.0));
+
+        let half_fov = fov / DBig::from(2);
+        let vertical_cos = cos(half_fov.clone(), 32);
+        let vertical_sin = sin(half_fov, 32);
+        let horizontal_tan = aspect_ratio * &vertical_sin / &vertical_cos;
+        let horizontal_hypot = (DBig::ONE + &horizontal_tan * &horizontal_tan).sqrt();
+        let horizontal_cos = DBig::ONE / &horizontal_hypot;
+        let horizontal_sin = &horizontal_tan / &horizontal_hypot;
+
+        let mut result: Vec<VisibleBody> = vec![];
+        for body in self.awake_bodies() {
+            let relative = self.world_position(body) - observer_position;
+            let radius = &body.body.radius;
+            let depth = relative.dot(&forward);
+            if depth < -radius {
+                continue;
+            }
+            // signed distances to the side planes, positive means outside
+            let vertical = relative.dot(&up).abs() * &vertical_cos - &depth * &vertical_sin;
+            let horizontal =
+                relative.dot(&right).abs() * &horizontal_cos - &depth * &horizontal_sin;
+            if vertical > *radius || horizontal > *radius {
+                continue;
+            }
+
+            let distance = relative.length();
+            let angular_diameter = if distance > *radius {
+                asin(radius / &distance, 32) * DBig::from(2)
+            } else {
+                PI.deref().clone()
+            };
+            result.push(VisibleBody {
+                body,
+                distance,
+                angular_diameter,
+            });
+        }
+        result.sort_by(|a, b| a.distance.cmp(&b.distance));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal_matrix_3d::DecimalMatrix3d;
+    use crate::decimal_vector_3d::DecimalVector3d;
+    use crate::sin_cos::{approx_eq, f64_to_dbig};
+    use crate::tests::prepare_sim;
+
+    #[test]
+    fn visible_bodies_works() {
+        let mut sim = prepare_sim();
+        sim.update(&f64_to_dbig(123_123.0));
+        let earth_now = sim.get_body("earth").unwrap();
+        let observer = &earth_now.position + DecimalVector3d::from_f64(0.0, 0.0, 100_000_000.0);
+        let fov = f64_to_dbig(60.0_f64.to_radians());
+
+        let visible = sim.visible_bodies(
+            &observer,
+            &DecimalMatrix3d::identity(),
+            &fov,
+            &f64_to_dbig(1.5),
+        );
+        assert_eq!(visible.len(), 1);
+        assert_eq!(visible[0].body.body.name, "earth");
+        let expected = 2.0 * (6_371_000.0_f64 / 100_000_000.0).asin();
+        assert!(approx_eq(
+            &visible[0].angular_diameter,
+            &f64_to_dbig(expected),
+            &f64_to_dbig(0.000_000_000_1)
+        ));
+
+        let looking_away = DecimalMatrix3d::axis_angle(
+            &DecimalVector3d::from_f64(0.0, 1.0, 0.0),
+            f64_to_dbig(std::f64::consts::PI),
+        );
+        let visible = sim.visible_bodies(&observer, &looking_away, &fov, &f64_to_dbig(1.5));
+        assert!(visible.iter().all(|v| v.body.body.name != "earth"));
+    }
+
+    #[test]
+    fn sleeping_bodies_are_not_visible() {
+        let mut sim = prepare_sim();
+        sim.update(&f64_to_dbig(123_123.0));
+        let earth = sim.get_body("earth").unwrap();
+        let observer = &earth.position + DecimalVector3d::from_f64(0.0, 0.0, 100_000_000.0);
+        let fov = f64_to_dbig(60.0_f64.to_radians());
+        sim.sleep_body("earth").unwrap();
+        let visible = sim.visible_bodies(
+            &observer,
+            &DecimalMatrix3d::identity(),
+            &fov,
+            &f64_to_dbig(1.5),
+        );
+        assert!(visible.is_empty());
+    }
+}